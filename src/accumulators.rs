@@ -0,0 +1,338 @@
+// src/accumulators.rs
+//! Streaming summaries kept in `glob` by `hist_add` and `topk_add`, and
+//! used by `--schema`.
+//!
+//! All use bounded memory however many values are added: `Histogram` is a
+//! DDSketch (quantiles within 1% relative error), `TopK` uses the
+//! Space-Saving algorithm (it reports only counts it can guarantee, and
+//! may leave out keys once more distinct keys than it tracks were added)
+//! and `HyperLogLog` estimates distinct counts within a few percent.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// Relative accuracy of `Histogram::quantile`
+const RELATIVE_ACCURACY: f64 = 0.01;
+
+/// Quantile sketch with logarithmically sized buckets
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Histogram {
+    pub count: u64,
+    pub sum: f64,
+    pub min: f64,
+    pub max: f64,
+    zeros: u64,
+    /// Bucket index -> count, for positive and (by magnitude) negative values
+    positive: BTreeMap<i32, u64>,
+    negative: BTreeMap<i32, u64>,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram {
+            count: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            zeros: 0,
+            positive: BTreeMap::new(),
+            negative: BTreeMap::new(),
+        }
+    }
+}
+
+fn gamma() -> f64 {
+    (1.0 + RELATIVE_ACCURACY) / (1.0 - RELATIVE_ACCURACY)
+}
+
+/// Values this close to zero share the zero bucket
+const MIN_MAGNITUDE: f64 = 1e-9;
+
+impl Histogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, value: f64) {
+        if value.is_nan() {
+            return;
+        }
+        self.count += 1;
+        self.sum += value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        if value.abs() < MIN_MAGNITUDE {
+            self.zeros += 1;
+            return;
+        }
+        let index = (value.abs().ln() / gamma().ln()).ceil() as i32;
+        let buckets = if value > 0.0 {
+            &mut self.positive
+        } else {
+            &mut self.negative
+        };
+        *buckets.entry(index).or_insert(0) += 1;
+    }
+
+    /// Estimated value at quantile `q` (0.0 to 1.0); `None` when empty
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = (q.clamp(0.0, 1.0) * (self.count - 1) as f64).round() as u64;
+        // Exact at the ends
+        if rank == 0 {
+            return Some(self.min);
+        }
+        if rank == self.count - 1 {
+            return Some(self.max);
+        }
+        let bucket_value = |index: i32| 2.0 * gamma().powi(index) / (gamma() + 1.0);
+
+        // Walk from the most negative value up to the largest
+        let mut seen = 0;
+        let mut estimate = None;
+        for (index, count) in self.negative.iter().rev() {
+            seen += count;
+            if seen > rank {
+                estimate = Some(-bucket_value(*index));
+                break;
+            }
+        }
+        if estimate.is_none() {
+            seen += self.zeros;
+            if seen > rank {
+                estimate = Some(0.0);
+            }
+        }
+        if estimate.is_none() {
+            for (index, count) in &self.positive {
+                seen += count;
+                if seen > rank {
+                    estimate = Some(bucket_value(*index));
+                    break;
+                }
+            }
+        }
+        Some(estimate.unwrap_or(self.max).clamp(self.min, self.max))
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum / self.count as f64)
+    }
+}
+
+/// Approximate most frequent keys
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TopK {
+    /// Number of keys reported
+    pub k: usize,
+    /// (key, count, possible overcount), at most `capacity()` entries
+    counters: Vec<(String, u64, u64)>,
+}
+
+impl TopK {
+    pub fn new(k: usize) -> Self {
+        TopK {
+            k: k.max(1),
+            counters: Vec::new(),
+        }
+    }
+
+    /// Keys tracked; more than `k` so that rising keys are not lost
+    fn capacity(&self) -> usize {
+        (self.k * 4).max(20)
+    }
+
+    pub fn add(&mut self, key: &str) {
+        if let Some(counter) = self.counters.iter_mut().find(|(k, _, _)| k == key) {
+            counter.1 += 1;
+            return;
+        }
+        if self.counters.len() < self.capacity() {
+            self.counters.push((key.to_string(), 1, 0));
+            return;
+        }
+        // Replace the smallest counter; the newcomer inherits its count
+        if let Some(smallest) = self.counters.iter_mut().min_by_key(|(_, count, _)| *count) {
+            let floor = smallest.1;
+            *smallest = (key.to_string(), floor + 1, floor);
+        }
+    }
+
+    /// The `k` most frequent keys with their counts, most frequent first.
+    /// Counts are exact until more distinct keys than `capacity()` were
+    /// added; after that a key is reported with the count it is known to
+    /// have (its count less what it inherited), and only when that is more
+    /// than an untracked key could have.
+    pub fn top(&self) -> Vec<(String, u64)> {
+        let floor = if self.counters.len() < self.capacity() {
+            0
+        } else {
+            self.counters
+                .iter()
+                .map(|(_, count, _)| *count)
+                .min()
+                .unwrap_or(0)
+        };
+        let mut counters: Vec<_> = self
+            .counters
+            .iter()
+            .map(|(key, count, overcount)| (key.clone(), count - overcount))
+            .filter(|(_, count)| *count > floor)
+            .collect();
+        counters.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        counters.truncate(self.k);
+        counters
+    }
+}
+
+/// Register index bits of `HyperLogLog` (4096 registers, about 1.6% error)
+const HLL_BITS: u32 = 12;
+
+/// Distinct count estimator
+#[derive(Debug, Clone, PartialEq)]
+pub struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        HyperLogLog {
+            registers: vec![0; 1 << HLL_BITS],
+        }
+    }
+}
+
+impl HyperLogLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add<T: Hash + ?Sized>(&mut self, value: &T) {
+        // DefaultHasher::new() uses fixed keys, so estimates are repeatable
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+        let index = (hash >> (64 - HLL_BITS)) as usize;
+        let rank = ((hash << HLL_BITS).leading_zeros() + 1).min(64 - HLL_BITS + 1) as u8;
+        if rank > self.registers[index] {
+            self.registers[index] = rank;
+        }
+    }
+
+    pub fn estimate(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self.registers.iter().map(|&r| 2f64.powi(-(r as i32))).sum();
+        let raw = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|&&r| r == 0).count();
+        // Linear counting is more accurate for small cardinalities
+        let estimate = if raw <= 2.5 * m && zeros > 0 {
+            m * (m / zeros as f64).ln()
+        } else {
+            raw
+        };
+        estimate.round() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_quantiles() {
+        let mut hist = Histogram::new();
+        assert_eq!(hist.quantile(0.5), None);
+        for i in 1..=1000 {
+            hist.add(i as f64);
+        }
+        assert_eq!(hist.count, 1000);
+        assert_eq!(hist.quantile(0.0), Some(1.0));
+        assert_eq!(hist.quantile(1.0), Some(1000.0));
+        for (q, expected) in [(0.5, 500.0), (0.9, 900.0), (0.99, 990.0)] {
+            let estimate = hist.quantile(q).unwrap();
+            assert!(
+                (estimate - expected).abs() / expected <= 0.02,
+                "{} -> {}",
+                q,
+                estimate
+            );
+        }
+        assert_eq!(hist.mean(), Some(500.5));
+    }
+
+    #[test]
+    fn test_histogram_negative_and_zero() {
+        let mut hist = Histogram::new();
+        for value in [-10.0, -1.0, 0.0, 0.0, 5.0] {
+            hist.add(value);
+        }
+        assert_eq!(hist.quantile(0.0), Some(-10.0));
+        assert_eq!(hist.quantile(0.5), Some(0.0));
+        assert!((hist.quantile(0.25).unwrap() + 1.0).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_topk_keeps_heavy_hitters() {
+        let mut top = TopK::new(2);
+        for i in 0..1000 {
+            top.add(&format!("rare{}", i));
+            if i % 2 == 0 {
+                top.add("alice");
+            }
+            if i % 4 == 0 {
+                top.add("bob");
+            }
+        }
+        let top = top.top();
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0].0, "alice");
+        assert_eq!(top[1].0, "bob");
+        assert_eq!(top[0].1, 500);
+    }
+
+    #[test]
+    fn test_topk_does_not_inflate_distinct_keys() {
+        // Within capacity every count is exact
+        let mut top = TopK::new(5);
+        for i in 0..10 {
+            top.add(&i.to_string());
+        }
+        assert_eq!(top.top().len(), 5);
+        assert!(top.top().iter().all(|(_, count)| *count == 1));
+
+        // Past capacity no key is known to beat an untracked one
+        let mut top = TopK::new(5);
+        for i in 0..40 {
+            top.add(&i.to_string());
+        }
+        assert_eq!(top.top(), Vec::new());
+
+        for _ in 0..3 {
+            top.add("39");
+        }
+        assert_eq!(top.top(), vec![("39".to_string(), 4)]);
+    }
+
+    #[test]
+    fn test_hyperloglog_estimates() {
+        let mut hll = HyperLogLog::new();
+        assert_eq!(hll.estimate(), 0);
+        for i in 0..10 {
+            hll.add(&format!("user{}", i % 5));
+        }
+        assert_eq!(hll.estimate(), 5);
+        for i in 0..100_000 {
+            hll.add(&i);
+        }
+        let estimate = hll.estimate() as f64;
+        assert!(
+            (estimate - 100_005.0).abs() / 100_005.0 < 0.05,
+            "{}",
+            estimate
+        );
+    }
+}
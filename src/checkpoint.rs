@@ -0,0 +1,298 @@
+// src/checkpoint.rs
+//! Resumable processing of large files (`--checkpoint path`, `--resume`).
+//!
+//! While input files are read, the byte offset of the next unprocessed
+//! record, the line counters and a `glob` snapshot are saved every
+//! `--checkpoint-interval` seconds and once more at the end of the run.
+//! Output is flushed before each save, so after a crash `--resume`
+//! reprocesses at most the records since the last checkpoint
+//! (at-least-once). Re-running with `--resume` over a file that has
+//! grown since processes only the new lines.
+//!
+//! The checkpoint also records the identity of the file it belongs to
+//! (inode, size and a hash of its first bytes), so a rotated or
+//! truncated file is refused rather than read from a bogus offset.
+
+use crate::state_file::write_json_atomically;
+use crate::variables::GlobalVariables;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Format version written to new checkpoints
+pub const CHECKPOINT_VERSION: u64 = 1;
+
+/// Bytes at the start of a file hashed to recognise it
+const HEAD_BYTES: u64 = 4096;
+
+/// How far into the current input processing has got
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputPosition {
+    /// Byte offset of the next unread record
+    pub offset: u64,
+    /// Physical lines before `offset`
+    pub lines: usize,
+}
+
+/// Where to pick up a file: its position plus the LINENUM/RECNUM counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResumePoint {
+    pub position: InputPosition,
+    pub line_number: usize,
+    pub record_count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileIdentity {
+    /// Unix inode; `None` on other platforms
+    pub inode: Option<u64>,
+    pub size: u64,
+    /// Number of leading bytes covered by `head_hash`
+    pub head_len: u64,
+    pub head_hash: String,
+}
+
+impl FileIdentity {
+    pub fn of(path: &Path) -> Result<FileIdentity, String> {
+        let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let metadata = file
+            .metadata()
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        let head_len = metadata.len().min(HEAD_BYTES);
+        Ok(FileIdentity {
+            inode: inode(&metadata),
+            size: metadata.len(),
+            head_len,
+            head_hash: head_hash(file, head_len)
+                .map_err(|e| format!("{}: {}", path.display(), e))?,
+        })
+    }
+
+    /// Check that `path` is still this file, possibly grown, and is at least
+    /// `offset` bytes long
+    pub fn verify(&self, path: &Path, offset: u64) -> Result<(), String> {
+        let current = FileIdentity::of(path)?;
+        let problem = if self.inode.is_some() && current.inode != self.inode {
+            Some("it was replaced (different inode)")
+        } else if current.size < offset {
+            Some("it is shorter than the checkpoint offset (truncated?)")
+        } else if current.size < self.head_len
+            || head_hash(
+                File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?,
+                self.head_len,
+            )
+            .map_err(|e| format!("{}: {}", path.display(), e))?
+                != self.head_hash
+        {
+            Some("its contents changed")
+        } else {
+            None
+        };
+        match problem {
+            Some(problem) => Err(format!(
+                "cannot resume '{}': {}; remove the checkpoint to start over",
+                path.display(),
+                problem
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn inode(metadata: &std::fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.ino())
+}
+
+#[cfg(not(unix))]
+fn inode(_metadata: &std::fs::Metadata) -> Option<u64> {
+    None
+}
+
+fn head_hash(file: File, len: u64) -> std::io::Result<String> {
+    let mut head = Vec::with_capacity(len as usize);
+    file.take(len).read_to_end(&mut head)?;
+    Ok(crate::pipeline::global_functions::to_hex(&Sha256::digest(
+        &head,
+    )))
+}
+
+/// A saved checkpoint: everything before `position` in `file` is done
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub version: u64,
+    pub saved_at: String,
+    pub file: PathBuf,
+    pub identity: FileIdentity,
+    pub position: InputPosition,
+    /// LINENUM and RECNUM of the last processed record
+    pub line_number: usize,
+    pub record_count: usize,
+    pub glob: Map<String, Value>,
+}
+
+impl Checkpoint {
+    /// Read a checkpoint; `None` if none has been written yet
+    pub fn load(path: &Path) -> Result<Option<Checkpoint>, String> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(format!(
+                    "failed to read checkpoint '{}': {}",
+                    path.display(),
+                    e
+                ))
+            }
+        };
+        let document: Value = serde_json::from_str(&text)
+            .map_err(|e| format!("failed to parse checkpoint '{}': {}", path.display(), e))?;
+        match document.get("version").and_then(Value::as_u64) {
+            Some(CHECKPOINT_VERSION) => {}
+            Some(v) => {
+                return Err(format!(
+                    "checkpoint '{}' has unsupported version {} (expected {})",
+                    path.display(),
+                    v,
+                    CHECKPOINT_VERSION
+                ))
+            }
+            None => {
+                return Err(format!(
+                    "'{}' is not a stelp checkpoint (missing version)",
+                    path.display()
+                ))
+            }
+        }
+        serde_json::from_value(document)
+            .map(Some)
+            .map_err(|e| format!("invalid checkpoint '{}': {}", path.display(), e))
+    }
+
+    pub fn resume_point(&self) -> ResumePoint {
+        ResumePoint {
+            position: self.position,
+            line_number: self.line_number,
+            record_count: self.record_count,
+        }
+    }
+}
+
+/// Writes checkpoints for the file currently being processed
+pub struct Checkpointer {
+    path: PathBuf,
+    interval: Duration,
+    last_saved: Instant,
+    file: Option<(PathBuf, FileIdentity)>,
+}
+
+impl Checkpointer {
+    pub fn new(path: &Path, interval: Duration) -> Self {
+        Checkpointer {
+            path: path.to_path_buf(),
+            interval,
+            last_saved: Instant::now(),
+            file: None,
+        }
+    }
+
+    /// Start checkpointing positions in `file`
+    pub fn set_file(&mut self, file: &Path) -> Result<(), String> {
+        self.file = Some((file.to_path_buf(), FileIdentity::of(file)?));
+        Ok(())
+    }
+
+    pub fn is_due(&self) -> bool {
+        self.file.is_some() && self.last_saved.elapsed() >= self.interval
+    }
+
+    pub fn save(&mut self, point: ResumePoint, globals: &GlobalVariables) -> Result<(), String> {
+        let Some((file, identity)) = &self.file else {
+            return Ok(());
+        };
+        let checkpoint = Checkpoint {
+            version: CHECKPOINT_VERSION,
+            saved_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            file: file.clone(),
+            identity: identity.clone(),
+            position: point.position,
+            line_number: point.line_number,
+            record_count: point.record_count,
+            glob: globals.to_json(|_| true),
+        };
+        let document = serde_json::to_value(&checkpoint).map_err(|e| e.to_string())?;
+        write_json_atomically(&self.path, &document).map_err(|e| {
+            format!(
+                "failed to write checkpoint '{}': {}",
+                self.path.display(),
+                e
+            )
+        })?;
+        self.last_saved = Instant::now();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_identity_accepts_growth_and_rejects_rewrites() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("app.log");
+        fs::write(&path, "one\ntwo\n").unwrap();
+        let identity = FileIdentity::of(&path).unwrap();
+        assert_eq!(identity.head_len, 8);
+
+        // Appending keeps the identity
+        fs::write(&path, "one\ntwo\nthree\n").unwrap();
+        assert!(identity.verify(&path, 8).is_ok());
+        assert!(identity
+            .verify(&path, 100)
+            .unwrap_err()
+            .contains("truncated"));
+
+        fs::write(&path, "uno\ndos\ntres\n").unwrap();
+        assert!(identity
+            .verify(&path, 8)
+            .unwrap_err()
+            .contains("contents changed"));
+    }
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let log = dir.path().join("app.log");
+        let path = dir.path().join("app.ckpt");
+        fs::write(&log, "a\nb\n").unwrap();
+
+        assert!(Checkpoint::load(&path).unwrap().is_none());
+
+        let globals = GlobalVariables::new();
+        globals.increment_counter("seen");
+        let mut checkpointer = Checkpointer::new(&path, Duration::from_secs(60));
+        assert!(!checkpointer.is_due());
+        checkpointer.set_file(&log).unwrap();
+        let point = ResumePoint {
+            position: InputPosition {
+                offset: 2,
+                lines: 1,
+            },
+            line_number: 1,
+            record_count: 1,
+        };
+        checkpointer.save(point, &globals).unwrap();
+
+        let checkpoint = Checkpoint::load(&path).unwrap().unwrap();
+        assert_eq!(checkpoint.file, log);
+        assert_eq!(checkpoint.resume_point(), point);
+        assert_eq!(checkpoint.glob["seen"], 1);
+    }
+}
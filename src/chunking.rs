@@ -167,6 +167,7 @@ pub fn parse_chunk_strategy(strategy_spec: &str) -> Result<ChunkStrategy, String
 }
 
 #[cfg(test)]
+#[allow(clippy::needless_update)]
 mod tests {
     use super::*;
     use std::io::Cursor;
@@ -0,0 +1,84 @@
+// src/dead_letter.rs
+//! Dead-letter output (`--dead-letter path`).
+//!
+//! Every input line rejected by a parser or a failing processing step is
+//! written as one JSON object per line, so it can be reprocessed later:
+//!
+//! `{"file":"app.log","line_number":12,"step":"eval_1","error":"...","line":"..."}`
+
+use crate::error::ProcessingError;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+pub struct DeadLetterWriter {
+    writer: BufWriter<File>,
+    current_input: Option<(usize, String)>,
+    count: usize,
+}
+
+impl DeadLetterWriter {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let file = File::create(path)?;
+        Ok(DeadLetterWriter {
+            writer: BufWriter::with_capacity(65536, file),
+            current_input: None,
+            count: 0,
+        })
+    }
+
+    /// Remember the raw input line for the record about to be processed
+    pub fn set_current_input(&mut self, line_number: usize, line: &str) {
+        match &mut self.current_input {
+            Some((number, text)) => {
+                *number = line_number;
+                text.clear();
+                text.push_str(line);
+            }
+            None => self.current_input = Some((line_number, line.to_string())),
+        }
+    }
+
+    /// Take the raw input line remembered by `set_current_input`
+    pub fn take_current_input(&mut self) -> Option<(usize, String)> {
+        self.current_input.take()
+    }
+
+    pub fn write_entry(
+        &mut self,
+        file: Option<&str>,
+        line_number: usize,
+        step: &str,
+        error: &str,
+        line: Option<&str>,
+    ) -> io::Result<()> {
+        let entry = serde_json::json!({
+            "file": file,
+            "line_number": line_number,
+            "step": step,
+            "error": error,
+            "line": line,
+        });
+        writeln!(self.writer, "{}", entry)?;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Number of entries written so far
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Split a processing error into the failing step name and its message
+pub fn error_step_and_message(err: &ProcessingError) -> (String, String) {
+    match err {
+        ProcessingError::ScriptError { step, source, .. } => (step.clone(), source.to_string()),
+        ProcessingError::IoError(e) => ("io".to_string(), e.to_string()),
+        ProcessingError::OutputError(msg) => ("output".to_string(), msg.clone()),
+    }
+}
@@ -112,20 +112,20 @@ impl LogfmtFormatter {
     fn format_key_value_pair_into(&self, key: &str, value: &str, output: &mut String) {
         // Write colored key
         if !self.colors.key.is_empty() {
-            output.push_str(&self.colors.key);
+            output.push_str(self.colors.key);
         }
         output.push_str(key);
         if !self.colors.key.is_empty() {
-            output.push_str(&self.colors.reset);
+            output.push_str(self.colors.reset);
         }
 
         // Write colored equals
         if !self.colors.equals.is_empty() {
-            output.push_str(&self.colors.equals);
+            output.push_str(self.colors.equals);
         }
         output.push('=');
         if !self.colors.equals.is_empty() {
-            output.push_str(&self.colors.reset);
+            output.push_str(self.colors.reset);
         }
 
         // Write colored value
@@ -166,7 +166,7 @@ impl LogfmtFormatter {
 
         // Reset color
         if !color.is_empty() {
-            output.push_str(&self.colors.reset);
+            output.push_str(self.colors.reset);
         }
     }
 
@@ -191,7 +191,7 @@ impl LogfmtFormatter {
 
         // Reset color
         if !color.is_empty() {
-            output.push_str(&self.colors.reset);
+            output.push_str(self.colors.reset);
         }
     }
 
@@ -241,7 +241,7 @@ impl LogfmtFormatter {
 
     /// Check if key is likely a log level field
     fn is_level_field(&self, key: &str) -> bool {
-        self.level_keys.iter().any(|&lk| lk == key)
+        self.level_keys.contains(&key)
     }
 
     /// Check if value needs to be quoted per logfmt rules
@@ -289,20 +289,20 @@ impl LogfmtFormatter {
     fn format_json_key_value_into(&self, key: &str, value: &Value, output: &mut String) {
         // Write colored key
         if !self.colors.key.is_empty() {
-            output.push_str(&self.colors.key);
+            output.push_str(self.colors.key);
         }
         output.push_str(key);
         if !self.colors.key.is_empty() {
-            output.push_str(&self.colors.reset);
+            output.push_str(self.colors.reset);
         }
 
         // Write colored equals
         if !self.colors.equals.is_empty() {
-            output.push_str(&self.colors.equals);
+            output.push_str(self.colors.equals);
         }
         output.push('=');
         if !self.colors.equals.is_empty() {
-            output.push_str(&self.colors.reset);
+            output.push_str(self.colors.reset);
         }
 
         // Format value directly from JSON
@@ -370,7 +370,7 @@ impl LogfmtFormatter {
 
         // Reset color
         if !color.is_empty() {
-            output.push_str(&self.colors.reset);
+            output.push_str(self.colors.reset);
         }
     }
 
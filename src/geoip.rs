@@ -0,0 +1,129 @@
+// src/geoip.rs
+//! Offline GeoIP/ASN lookups from local MaxMind-format (`.mmdb`) databases,
+//! loaded once with `--geoip` and shared by `geoip()` and `--combined-geoip`.
+
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+static DATABASES: OnceCell<GeoIp> = OnceCell::new();
+
+/// The subset of GeoIP2/GeoLite2 City, Country and ASN records we report
+#[derive(Deserialize, Default)]
+struct GeoRecord {
+    country: Option<Country>,
+    city: Option<Names>,
+    location: Option<Location>,
+    autonomous_system_number: Option<u32>,
+    autonomous_system_organization: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Country {
+    iso_code: Option<String>,
+    names: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Deserialize)]
+struct Names {
+    names: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Deserialize)]
+struct Location {
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+}
+
+fn english(names: Option<BTreeMap<String, String>>) -> Option<String> {
+    names.and_then(|mut names| names.remove("en"))
+}
+
+/// One or more databases queried together (e.g. a City and an ASN database)
+pub struct GeoIp {
+    readers: Vec<(PathBuf, maxminddb::Reader<Vec<u8>>)>,
+}
+
+impl GeoIp {
+    pub fn open(paths: &[PathBuf]) -> Result<GeoIp, String> {
+        let readers = paths
+            .iter()
+            .map(|path| {
+                maxminddb::Reader::open_readfile(path)
+                    .map(|reader| (path.clone(), reader))
+                    .map_err(|e| {
+                        format!("failed to open GeoIP database '{}': {}", path.display(), e)
+                    })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(GeoIp { readers })
+    }
+
+    /// Merged fields from every database that knows the address: country,
+    /// country_name, city, latitude, longitude, asn and as_org.
+    /// `None` if no database has an entry for it.
+    pub fn lookup(&self, ip: IpAddr) -> Result<Option<Map<String, Value>>, String> {
+        let mut fields = Map::new();
+        let mut found = false;
+        for (path, reader) in &self.readers {
+            // An IPv4-only database would misread the bits of an IPv6 address
+            if ip.is_ipv6() && reader.metadata.ip_version == 4 {
+                continue;
+            }
+            let record: GeoRecord = match reader.lookup(ip) {
+                Ok(record) => record,
+                Err(maxminddb::MaxMindDBError::AddressNotFoundError(_)) => continue,
+                Err(e) => return Err(format!("{}: {}", path.display(), e)),
+            };
+            found = true;
+            let mut set = |key: &str, value: Option<Value>| {
+                if let Some(value) = value {
+                    fields.insert(key.to_string(), value);
+                }
+            };
+            if let Some(country) = record.country {
+                set("country", country.iso_code.map(Value::from));
+                set("country_name", english(country.names).map(Value::from));
+            }
+            set(
+                "city",
+                record.city.and_then(|c| english(c.names)).map(Value::from),
+            );
+            if let Some(location) = record.location {
+                set("latitude", location.latitude.map(Value::from));
+                set("longitude", location.longitude.map(Value::from));
+            }
+            set("asn", record.autonomous_system_number.map(Value::from));
+            set(
+                "as_org",
+                record.autonomous_system_organization.map(Value::from),
+            );
+        }
+        Ok(found.then_some(fields))
+    }
+}
+
+/// Load the process-wide databases (from `--geoip`); call at most once
+pub fn init(paths: &[PathBuf]) -> Result<(), String> {
+    let databases = GeoIp::open(paths)?;
+    DATABASES
+        .set(databases)
+        .map_err(|_| "GeoIP databases already loaded".to_string())
+}
+
+/// The databases loaded with `init`, if any
+pub fn databases() -> Option<&'static GeoIp> {
+    DATABASES.get()
+}
+
+/// `lookup` against the loaded databases, for callers that only have a string
+pub fn lookup_str(ip: &str) -> Result<Option<Map<String, Value>>, String> {
+    let databases = databases().ok_or("no GeoIP database loaded (use --geoip FILE)")?;
+    match crate::net::parse_ip(ip) {
+        Some(ip) => databases.lookup(ip),
+        None => Ok(None),
+    }
+}
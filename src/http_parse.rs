@@ -0,0 +1,284 @@
+// src/http_parse.rs
+//! URL, query-string and User-Agent parsing shared by the `parse_url()`,
+//! `parse_qs()` and `parse_user_agent()` builtins and `-f combined --combined-expand`.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::{json, Map, Value};
+
+/// Split a URL into scheme, host, port, path, query (as a dict) and fragment.
+/// Relative references such as `/search?q=x` are accepted; missing parts are null.
+pub fn parse_url(text: &str) -> Value {
+    // RFC 3986, appendix B
+    static URL: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(
+            r"^(?:([A-Za-z][A-Za-z0-9+.-]*):)?(?://([^/?#]*))?([^?#]*)(?:\?([^#]*))?(?:#(.*))?$",
+        )
+        .unwrap()
+    });
+
+    let captures = URL.captures(text.trim()).expect("every string matches");
+    let (host, port) = match captures.get(2) {
+        Some(authority) => split_authority(authority.as_str()),
+        None => (None, None),
+    };
+    json!({
+        "scheme": captures.get(1).map(|m| m.as_str().to_lowercase()),
+        "host": host,
+        "port": port,
+        "path": captures.get(3).map_or("", |m| m.as_str()),
+        "query": parse_qs(captures.get(4).map_or("", |m| m.as_str())),
+        "fragment": captures.get(5).map(|m| m.as_str()),
+    })
+}
+
+/// `user:pass@host:port` -> (host, port); IPv6 hosts lose their brackets
+fn split_authority(authority: &str) -> (Option<String>, Option<u16>) {
+    let host_port = authority.rsplit_once('@').map_or(authority, |(_, h)| h);
+    let (host, port) = if let Some(rest) = host_port.strip_prefix('[') {
+        match rest.split_once(']') {
+            Some((host, tail)) => (host, tail.strip_prefix(':')),
+            None => (rest, None),
+        }
+    } else {
+        match host_port.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (host_port, None),
+        }
+    };
+    let host = (!host.is_empty()).then(|| host.to_lowercase());
+    (host, port.and_then(|p| p.parse().ok()))
+}
+
+/// Decode a query string (with or without the leading '?') into a dict.
+/// Repeated keys collect their values into a list.
+pub fn parse_qs(text: &str) -> Map<String, Value> {
+    let mut params = Map::new();
+    let text = text.strip_prefix('?').unwrap_or(text);
+    for pair in text.split(['&', ';']).filter(|p| !p.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let (key, value) = (form_decode(key), Value::String(form_decode(value)));
+        match params.get_mut(&key) {
+            Some(Value::Array(values)) => values.push(value),
+            Some(existing) => *existing = Value::Array(vec![existing.take(), value]),
+            None => {
+                params.insert(key, value);
+            }
+        }
+    }
+    params
+}
+
+fn form_decode(text: &str) -> String {
+    percent_encoding::percent_decode_str(&text.replace('+', " "))
+        .decode_utf8_lossy()
+        .into_owned()
+}
+
+/// Known crawlers and automated clients, checked before browsers.
+/// Group 1 is the version, or the name when the table name is empty.
+static BOTS: Lazy<Vec<(Regex, &'static str)>> = Lazy::new(|| {
+    [
+        (r"Googlebot(?:-\w+)?/(\d+(?:\.\d+)*)", "Googlebot"),
+        (r"bingbot/(\d+(?:\.\d+)*)", "Bingbot"),
+        (r"YandexBot/(\d+(?:\.\d+)*)", "YandexBot"),
+        (r"Baiduspider(?:-\w+)?/(\d+(?:\.\d+)*)", "Baiduspider"),
+        (r"DuckDuckBot/(\d+(?:\.\d+)*)", "DuckDuckBot"),
+        (r"Applebot/(\d+(?:\.\d+)*)", "Applebot"),
+        (
+            r"facebookexternalhit/(\d+(?:\.\d+)*)",
+            "facebookexternalhit",
+        ),
+        (r"Twitterbot/(\d+(?:\.\d+)*)", "Twitterbot"),
+        (r"AhrefsBot/(\d+(?:\.\d+)*)", "AhrefsBot"),
+        (r"SemrushBot/(\d+(?:\.\d+)*)", "SemrushBot"),
+        (r"GPTBot/(\d+(?:\.\d+)*)", "GPTBot"),
+        (r"HeadlessChrome/(\d+(?:\.\d+)*)", "HeadlessChrome"),
+        (r"^curl/(\d+(?:\.\d+)*)", "curl"),
+        (r"^Wget/(\d+(?:\.\d+)*)", "Wget"),
+        (r"^python-requests/(\d+(?:\.\d+)*)", "python-requests"),
+        (r"^Python-urllib/(\d+(?:\.\d+)*)", "Python-urllib"),
+        (r"^Go-http-client/(\d+(?:\.\d+)*)", "Go-http-client"),
+        (r"^okhttp/(\d+(?:\.\d+)*)", "okhttp"),
+        (r"^Java/(\d+(?:\.\d+)*)", "Java"),
+        (r"^PostmanRuntime/(\d+(?:\.\d+)*)", "PostmanRuntime"),
+        // Anything else that calls itself a bot: the name is captured too
+        (
+            r"(?i)\b([\w-]*(?:bot|crawler|spider))\b(?:/(\d+(?:\.\d+)*))?",
+            "",
+        ),
+    ]
+    .into_iter()
+    .map(|(pattern, name)| (Regex::new(pattern).unwrap(), name))
+    .collect()
+});
+
+/// Browsers, most specific first (Edge and Opera also claim to be Chrome,
+/// Chrome claims to be Safari)
+static BROWSERS: Lazy<Vec<(Regex, &'static str)>> = Lazy::new(|| {
+    [
+        (r"(?:Edg|Edge|EdgA|EdgiOS)/(\d+(?:\.\d+)*)", "Edge"),
+        (r"(?:OPR|OPiOS)/(\d+(?:\.\d+)*)", "Opera"),
+        (r"Opera/.*Version/(\d+(?:\.\d+)*)", "Opera"),
+        (r"SamsungBrowser/(\d+(?:\.\d+)*)", "Samsung Internet"),
+        (r"YaBrowser/(\d+(?:\.\d+)*)", "Yandex Browser"),
+        (r"Vivaldi/(\d+(?:\.\d+)*)", "Vivaldi"),
+        (r"(?:Firefox|FxiOS)/(\d+(?:\.\d+)*)", "Firefox"),
+        (r"(?:Chrome|CriOS)/(\d+(?:\.\d+)*)", "Chrome"),
+        (r"Version/(\d+(?:\.\d+)*).*Safari/", "Safari"),
+        (r"MSIE (\d+(?:\.\d+)*)", "Internet Explorer"),
+        (r"Trident/.*rv:(\d+(?:\.\d+)*)", "Internet Explorer"),
+    ]
+    .into_iter()
+    .map(|(pattern, name)| (Regex::new(pattern).unwrap(), name))
+    .collect()
+});
+
+/// Operating systems; the version group may use '_' as separator (iOS, macOS)
+static OPERATING_SYSTEMS: Lazy<Vec<(Regex, &'static str)>> = Lazy::new(|| {
+    [
+        (r"Windows Phone(?: OS)? (\d+(?:\.\d+)*)", "Windows Phone"),
+        (r"Windows NT (\d+\.\d+)", "Windows"),
+        (r"Android (\d+(?:\.\d+)*)", "Android"),
+        (r"Android()", "Android"),
+        (r"(?:iPhone|CPU) OS (\d+(?:_\d+)*)", "iOS"),
+        (r"iPad.*OS (\d+(?:_\d+)*)", "iOS"),
+        (r"Mac OS X (\d+(?:[._]\d+)*)", "macOS"),
+        (r"CrOS \S+ (\d+(?:\.\d+)*)", "Chrome OS"),
+        (r"Linux()", "Linux"),
+    ]
+    .into_iter()
+    .map(|(pattern, name)| (Regex::new(pattern).unwrap(), name))
+    .collect()
+});
+
+/// Classify a User-Agent header: browser, version, os, os_version,
+/// device ("desktop", "mobile", "tablet", "bot" or "other") and is_bot
+pub fn parse_user_agent(text: &str) -> Value {
+    let first_match = |table: &[(Regex, &'static str)]| {
+        table.iter().find_map(|(regex, name)| {
+            regex.captures(text).map(|c| {
+                let (name, version) = match name.is_empty() {
+                    true => (&c[1], c.get(2)),
+                    false => (*name, c.get(1)),
+                };
+                let version = version.map(|m| m.as_str()).filter(|v| !v.is_empty());
+                (name.to_string(), version.map(|v| v.replace('_', ".")))
+            })
+        })
+    };
+
+    let bot = first_match(&BOTS);
+    let is_bot = bot.is_some();
+    let (browser, version) = bot
+        .or_else(|| first_match(&BROWSERS))
+        .map_or((None, None), |(name, version)| (Some(name), version));
+
+    let (os, os_version) =
+        first_match(&OPERATING_SYSTEMS).map_or((None, None), |(name, version)| {
+            let version = match (name.as_str(), version) {
+                ("Windows", Some(nt)) => Some(windows_version(&nt).to_string()),
+                (_, version) => version,
+            };
+            (Some(name), version)
+        });
+
+    let device = if is_bot {
+        "bot"
+    } else if text.contains("iPad")
+        || text.contains("Tablet")
+        || (os.as_deref() == Some("Android") && !text.contains("Mobile"))
+    {
+        "tablet"
+    } else if text.contains("Mobi")
+        || text.contains("iPhone")
+        || os.as_deref() == Some("Windows Phone")
+    {
+        "mobile"
+    } else if os.is_some() {
+        "desktop"
+    } else {
+        "other"
+    };
+
+    json!({
+        "browser": browser,
+        "version": version,
+        "os": os,
+        "os_version": os_version,
+        "device": device,
+        "is_bot": is_bot,
+    })
+}
+
+/// Marketing name for a Windows NT version
+fn windows_version(nt: &str) -> &str {
+    match nt {
+        "10.0" => "10",
+        "6.3" => "8.1",
+        "6.2" => "8",
+        "6.1" => "7",
+        "6.0" => "Vista",
+        "5.1" | "5.2" => "XP",
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_url() {
+        let url = parse_url("HTTPS://user:pw@Example.com:8443/a/b?x=1&y=2&x=3#top");
+        assert_eq!(url["scheme"], "https");
+        assert_eq!(url["host"], "example.com");
+        assert_eq!(url["port"], 8443);
+        assert_eq!(url["path"], "/a/b");
+        assert_eq!(url["query"], json!({"x": ["1", "3"], "y": "2"}));
+        assert_eq!(url["fragment"], "top");
+
+        let relative = parse_url("/search?q=hello+world%21");
+        assert_eq!(relative["host"], Value::Null);
+        assert_eq!(relative["path"], "/search");
+        assert_eq!(relative["query"]["q"], "hello world!");
+
+        assert_eq!(parse_url("http://[::1]:80/")["host"], "::1");
+    }
+
+    #[test]
+    fn test_parse_user_agent() {
+        let chrome = parse_user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.6099.109 Safari/537.36");
+        assert_eq!(chrome["browser"], "Chrome");
+        assert_eq!(chrome["version"], "120.0.6099.109");
+        assert_eq!(chrome["os"], "Windows");
+        assert_eq!(chrome["os_version"], "10");
+        assert_eq!(chrome["device"], "desktop");
+
+        let iphone = parse_user_agent("Mozilla/5.0 (iPhone; CPU iPhone OS 17_1 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.1 Mobile/15E148 Safari/604.1");
+        assert_eq!(iphone["browser"], "Safari");
+        assert_eq!(iphone["os"], "iOS");
+        assert_eq!(iphone["os_version"], "17.1");
+        assert_eq!(iphone["device"], "mobile");
+
+        let edge = parse_user_agent("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36 Edg/120.0.2210.91");
+        assert_eq!(edge["browser"], "Edge");
+        assert_eq!(edge["os"], "macOS");
+        assert_eq!(edge["os_version"], "10.15.7");
+
+        let google = parse_user_agent(
+            "Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)",
+        );
+        assert_eq!(google["browser"], "Googlebot");
+        assert_eq!(google["version"], "2.1");
+        assert_eq!(google["is_bot"], true);
+        assert_eq!(google["device"], "bot");
+
+        let generic = parse_user_agent("Mozilla/5.0 (compatible; MegaIndex-crawler/1.3)");
+        assert_eq!(generic["browser"], "MegaIndex-crawler");
+        assert_eq!(generic["version"], "1.3");
+
+        assert_eq!(parse_user_agent("curl/8.4.0")["browser"], "curl");
+        assert_eq!(parse_user_agent("")["device"], "other");
+    }
+}
@@ -477,6 +477,8 @@ pub struct CombinedParser {
     extended_regex: Regex,
     standard_combined_regex: Regex,
     common_regex: Regex,
+    expand: bool,
+    geoip: bool,
 }
 
 impl CombinedParser {
@@ -500,9 +502,25 @@ impl CombinedParser {
             extended_regex,
             standard_combined_regex,
             common_regex,
+            expand: false,
+            geoip: false,
         }
     }
 
+    /// Also add `geo` (geoip() of the client `ip`) to each record; needs
+    /// databases loaded with `crate::geoip::init`
+    pub fn with_geoip(mut self, geoip: bool) -> Self {
+        self.geoip = geoip;
+        self
+    }
+
+    /// Also add `request` (parse_url() of the request path) and `user_agent`
+    /// (parse_user_agent() of the User-Agent) to each record
+    pub fn with_expansion(mut self, expand: bool) -> Self {
+        self.expand = expand;
+        self
+    }
+
     fn parse_request(request: &str) -> (Option<String>, Option<String>, Option<String>) {
         let parts: Vec<&str> = request.splitn(3, ' ').collect();
         match parts.len() {
@@ -534,6 +552,34 @@ impl Default for CombinedParser {
 
 impl LineParser for CombinedParser {
     fn parse_line(&self, line: &str) -> Result<serde_json::Value, String> {
+        let mut record = self.parse_fields(line)?;
+        if self.expand {
+            if let serde_json::Value::Object(map) = &mut record {
+                if let Some(path) = map.get("path").and_then(|v| v.as_str()) {
+                    let url = crate::http_parse::parse_url(path);
+                    map.insert("request".to_string(), url);
+                }
+                if let Some(ua) = map.get("ua").and_then(|v| v.as_str()) {
+                    let agent = crate::http_parse::parse_user_agent(ua);
+                    map.insert("user_agent".to_string(), agent);
+                }
+            }
+        }
+        if self.geoip {
+            if let serde_json::Value::Object(map) = &mut record {
+                if let Some(ip) = map.get("ip").and_then(|v| v.as_str()) {
+                    if let Some(geo) = crate::geoip::lookup_str(ip)? {
+                        map.insert("geo".to_string(), serde_json::Value::Object(geo));
+                    }
+                }
+            }
+        }
+        Ok(record)
+    }
+}
+
+impl CombinedParser {
+    fn parse_fields(&self, line: &str) -> Result<serde_json::Value, String> {
         let line = line.trim();
 
         // Try Extended Apache format first (IP hostname - user port [timestamp] "request" "query" status size "referer" "user_agent" timing...)
@@ -817,6 +863,8 @@ impl LineParser for CombinedParser {
 pub struct InputFormatWrapper<'a> {
     format: Option<&'a InputFormat>,
     chunk_config: Option<ChunkConfig>,
+    combined_expand: bool,
+    combined_geoip: bool,
 }
 
 impl<'a> InputFormatWrapper<'a> {
@@ -824,9 +872,23 @@ impl<'a> InputFormatWrapper<'a> {
         Self {
             format,
             chunk_config: None,
+            combined_expand: false,
+            combined_geoip: false,
         }
     }
 
+    /// Expand request and User-Agent fields of combined logs (see `CombinedParser::with_expansion`)
+    pub fn with_combined_expansion(mut self, expand: bool) -> Self {
+        self.combined_expand = expand;
+        self
+    }
+
+    /// Add GeoIP fields to combined logs (see `CombinedParser::with_geoip`)
+    pub fn with_combined_geoip(mut self, geoip: bool) -> Self {
+        self.combined_geoip = geoip;
+        self
+    }
+
     pub fn with_chunking(mut self, chunk_config: ChunkConfig) -> Self {
         self.chunk_config = Some(chunk_config);
         self
@@ -906,6 +968,7 @@ impl<'a> InputFormatWrapper<'a> {
     }
 
     /// Generic streaming processor for all line-based structured formats
+    #[allow(clippy::too_many_arguments)]
     fn process_line_based_format_streaming<R: BufRead, W: Write, P: LineParser>(
         &self,
         mut reader: R,
@@ -921,6 +984,7 @@ impl<'a> InputFormatWrapper<'a> {
 
         // Initialize streaming context
         pipeline.init_streaming_context(filename);
+        let stats_before = pipeline.get_stats().clone();
 
         // Initialize local stats
         let mut file_stats = crate::context::ProcessingStats::default();
@@ -941,13 +1005,15 @@ impl<'a> InputFormatWrapper<'a> {
         }
 
         let error_strategy = pipeline.get_config().error_strategy.clone();
-        let mut line_number = 0;
+        let profiling = pipeline.is_profiling();
+        let mut line_number = pipeline.input_position().lines;
 
         // Handle headers if format requires them (CSV/TSV)
         if has_headers {
             let mut header_line = String::new();
-            reader.read_line(&mut header_line)?;
-            line_number = 1; // Start at 1 since we already read the header line
+            let bytes = reader.read_line(&mut header_line)?;
+            pipeline.advance_input(bytes as u64, 1);
+            line_number += 1; // Count the header line we already read
 
             if header_line.trim().is_empty() {
                 return Err(format!("{} file is empty", format_name).into());
@@ -958,8 +1024,17 @@ impl<'a> InputFormatWrapper<'a> {
         }
 
         // STREAMING: Process each line immediately instead of collecting
-        for line_result in reader.lines() {
-            let line = line_result?;
+        // read_line rather than lines() so the byte offset is known
+        let mut line = String::new();
+        loop {
+            pipeline.checkpoint_if_due(output);
+            line.clear();
+            let bytes = reader.read_line(&mut line)?;
+            if bytes == 0 {
+                break;
+            }
+            pipeline.advance_input(bytes as u64, 1);
+            crate::pipeline::stream::trim_line_ending(&mut line);
             line_number += 1;
             file_stats.lines_seen += 1; // Track all lines seen (including unparseable)
             let line_content = line.trim();
@@ -969,7 +1044,12 @@ impl<'a> InputFormatWrapper<'a> {
             }
 
             // Parse line using the provided parser and create structured record
-            let record = match parser.parse_line(line_content) {
+            let parse_started = profiling.then(Instant::now);
+            let parsed = parser.parse_line(line_content);
+            if let Some(started) = parse_started {
+                pipeline.record_parse_time(format_name, started.elapsed(), parsed.is_ok());
+            }
+            let record = match parsed {
                 Ok(data) => crate::context::RecordData::structured(data),
                 Err(parse_error) => {
                     // Handle parsing error according to error strategy
@@ -989,6 +1069,12 @@ impl<'a> InputFormatWrapper<'a> {
                             // - Disabling error collection during streaming
                             // - Using a bounded circular buffer
                             file_stats.errors += 1;
+                            pipeline.write_dead_letter(
+                                line_number,
+                                &format!("parse:{}", format_name.to_lowercase()),
+                                &parse_error,
+                                Some(line_content),
+                            );
                             file_stats.parse_errors.push(crate::context::ParseErrorInfo {
                                 line_number,
                                 format_name: format_name.to_string(),
@@ -1001,6 +1087,7 @@ impl<'a> InputFormatWrapper<'a> {
             };
 
             // STREAMING: Process this single record immediately
+            pipeline.set_current_input(line_number, line_content);
             let should_continue = pipeline.process_single_record_streaming(record, output)?;
             if !should_continue {
                 break; // Exit or broken pipe
@@ -1017,18 +1104,10 @@ impl<'a> InputFormatWrapper<'a> {
             }
         }
 
-        // Copy final stats from pipeline
-        let pipeline_stats = pipeline.get_stats();
-        file_stats.records_processed = pipeline_stats.records_processed;
-        file_stats.records_output = pipeline_stats.records_output;
-        file_stats.records_skipped = pipeline_stats.records_skipped;
-        file_stats.errors += pipeline_stats.errors; // Add to existing parse errors
+        // Add what the pipeline counted for this file (on top of parse errors
+        // and BEGIN/END output counted above)
+        file_stats.take_file_delta(&stats_before, pipeline.get_stats_mut());
         file_stats.processing_time = start_time.elapsed();
-        // Copy enhanced stats from pipeline
-        file_stats.earliest_timestamp = pipeline_stats.earliest_timestamp;
-        file_stats.latest_timestamp = pipeline_stats.latest_timestamp;
-        file_stats.keys_seen = pipeline_stats.keys_seen.clone();
-        file_stats.levels_seen = pipeline_stats.levels_seen.clone();
 
         Ok(file_stats)
     }
@@ -1047,6 +1126,7 @@ impl<'a> InputFormatWrapper<'a> {
         
         // Initialize streaming context
         pipeline.init_streaming_context(filename);
+        let stats_before = pipeline.get_stats().clone();
         
         // Initialize local stats
         let mut file_stats = crate::context::ProcessingStats::default();
@@ -1077,10 +1157,31 @@ impl<'a> InputFormatWrapper<'a> {
         // Get headers and convert to owned strings
         let headers: Vec<String> = csv_reader.headers()?.iter().map(|h| h.to_string()).collect();
         
-        let mut line_number = 1; // Starting after header
+        // The header line; on resume the reader starts with a copy of it
+        // followed by the file from the checkpoint offset
+        let mut consumed = csv_reader.position().clone();
+        pipeline.advance_input(consumed.byte(), consumed.line() as usize - 1);
+        let mut line_number = pipeline.input_position().lines; // Starting after header
         
-        // STREAMING: Process each record immediately using csv crate's iterator
-        for record_result in csv_reader.records() {
+        let profiling = pipeline.is_profiling();
+        let mut record = csv::StringRecord::new();
+
+        // STREAMING: Process each record immediately, tracking the reader's
+        // byte position for checkpoints
+        loop {
+            pipeline.checkpoint_if_due(output);
+            let parse_started = profiling.then(Instant::now);
+            let record_result = match csv_reader.read_record(&mut record) {
+                Ok(false) => break,
+                Ok(true) => Ok(&record),
+                Err(e) => Err(e),
+            };
+            let position = csv_reader.position();
+            pipeline.advance_input(
+                position.byte() - consumed.byte(),
+                (position.line() - consumed.line()) as usize,
+            );
+            consumed = position.clone();
             line_number += 1;
             file_stats.lines_seen += 1; // Track all lines seen (including unparseable)
             
@@ -1096,7 +1197,17 @@ impl<'a> InputFormatWrapper<'a> {
                             ).into());
                         }
                         crate::config::ErrorStrategy::Skip => {
+                            if let Some(started) = parse_started {
+                                pipeline.record_parse_time(format_name, started.elapsed(), false);
+                            }
                             file_stats.errors += 1;
+                            // The csv reader doesn't keep the raw text of rejected rows
+                            pipeline.write_dead_letter(
+                                line_number,
+                                &format!("parse:{}", format_name.to_lowercase()),
+                                &parse_error.to_string(),
+                                None,
+                            );
                             file_stats.parse_errors.push(crate::context::ParseErrorInfo {
                                 line_number,
                                 format_name: format_name.to_string(),
@@ -1114,11 +1225,17 @@ impl<'a> InputFormatWrapper<'a> {
                 map.insert(header.clone(), serde_json::Value::String(value.to_string()));
             }
             let json_value = serde_json::Value::Object(map);
+            if let Some(started) = parse_started {
+                pipeline.record_parse_time(format_name, started.elapsed(), true);
+            }
             
             // Create structured record
             let structured_record = crate::context::RecordData::structured(json_value);
             
             // STREAMING: Process this single record immediately
+            if pipeline.has_dead_letter() {
+                pipeline.set_current_input(line_number, &Self::csv_row_to_line(record, is_tsv));
+            }
             let should_continue = pipeline.process_single_record_streaming(structured_record, output)?;
             if !should_continue {
                 break; // Exit or broken pipe
@@ -1135,22 +1252,27 @@ impl<'a> InputFormatWrapper<'a> {
             }
         }
         
-        // Copy final stats from pipeline
-        let pipeline_stats = pipeline.get_stats();
-        file_stats.records_processed = pipeline_stats.records_processed;
-        file_stats.records_output = pipeline_stats.records_output;
-        file_stats.records_skipped = pipeline_stats.records_skipped;
-        file_stats.errors += pipeline_stats.errors; // Add to existing parse errors
+        // Add what the pipeline counted for this file (on top of parse errors
+        // and BEGIN/END output counted above)
+        file_stats.take_file_delta(&stats_before, pipeline.get_stats_mut());
         file_stats.processing_time = start_time.elapsed();
-        // Copy enhanced stats from pipeline
-        file_stats.earliest_timestamp = pipeline_stats.earliest_timestamp;
-        file_stats.latest_timestamp = pipeline_stats.latest_timestamp;
-        file_stats.keys_seen = pipeline_stats.keys_seen.clone();
-        file_stats.levels_seen = pipeline_stats.levels_seen.clone();
         
         Ok(file_stats)
     }
 
+    /// Re-serialize a CSV/TSV row, e.g. for dead-letter output
+    fn csv_row_to_line(record: &csv::StringRecord, is_tsv: bool) -> String {
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(if is_tsv { b'\t' } else { b',' })
+            .terminator(csv::Terminator::Any(b'\n'))
+            .from_writer(Vec::new());
+        if writer.write_record(record).is_err() {
+            return record.iter().collect::<Vec<_>>().join(if is_tsv { "\t" } else { "," });
+        }
+        let bytes = writer.into_inner().unwrap_or_default();
+        String::from_utf8_lossy(&bytes).trim_end_matches('\n').to_string()
+    }
+
     fn process_csv<R: BufRead, W: Write>(
         &self,
         reader: R,
@@ -1206,7 +1328,9 @@ impl<'a> InputFormatWrapper<'a> {
         output: &mut W,
         filename: Option<&str>,
     ) -> Result<crate::context::ProcessingStats, Box<dyn std::error::Error>> {
-        let parser = CombinedParser::new();
+        let parser = CombinedParser::new()
+            .with_expansion(self.combined_expand)
+            .with_geoip(self.combined_geoip);
         self.process_line_based_format_streaming(
             reader, pipeline, output, filename,
             parser, "combined", false // No headers for combined log format
@@ -1,14 +1,29 @@
 // src/lib.rs
+pub mod accumulators;
+pub mod checkpoint;
 pub mod chunking;
 pub mod colors;
+pub mod dead_letter;
 pub mod error;
 pub mod flatten;
 pub mod formatters;
+pub mod geoip;
+pub mod http_parse;
 pub mod input_format;
+pub mod net;
 pub mod output_format;
+pub mod output_tee;
+pub mod output_template;
 pub mod pattern_extraction;
 pub mod pipeline;
+pub mod pipeline_builder;
+pub mod pipeline_file;
 pub mod processors;
+pub mod profile;
+pub mod repl;
+pub mod state_file;
+pub mod stats_report;
+pub mod test_runner;
 pub mod tty;
 pub mod variables;
 
@@ -16,10 +31,12 @@ pub use error::*;
 pub use pipeline::*;
 
 pub use pipeline::config::{ErrorStrategy, PipelineConfig};
-pub use pipeline::context::{ProcessResult, ProcessingStats, RecordContext, RecordData};
+pub use pipeline::context::{
+    ProcessResult, ProcessingStats, ProcessorStats, RecordContext, RecordData,
+};
 pub use pipeline::processors::{
     DeriveProcessor, ExtractProcessor, FilterProcessor, LevelFilterProcessor, StarlarkProcessor,
 };
 pub use pipeline::levelmap::LevelMapProcessor;
 pub use pipeline::stream::{RecordProcessor, StreamPipeline};
-pub use processors::WindowProcessor;
+pub use processors::{RedactProcessor, TypeCoercionProcessor, WindowProcessor};
@@ -1,33 +1,37 @@
-use clap::{ArgAction, ArgMatches, CommandFactory, FromArgMatches, Parser};
+use clap::{ArgAction, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
 use is_terminal::IsTerminal;
 use std::fs::File;
-use std::io::{self, BufReader, Write};
-use std::path::PathBuf;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 
-use stelp::chunking::{parse_chunk_strategy, ChunkConfig};
+use stelp::checkpoint::{Checkpoint, Checkpointer, ResumePoint};
 use stelp::config::{ErrorStrategy, PipelineConfig, TIMESTAMP_KEYS, LEVEL_KEYS, MESSAGE_KEYS};
 use stelp::context::ProcessingStats;
 use stelp::input_format::{InputFormat, InputFormatWrapper};
+use stelp::dead_letter::DeadLetterWriter;
 use stelp::output_format::OutputFormat;
+use stelp::repl::{run_interactive, Repl, DEFAULT_RECORD_LIMIT};
+use stelp::state_file::StateFile;
+use stelp::stats_report::{FileStats, StatsFormat, StatsReport};
+use stelp::test_runner::{run_tests, TestOptions};
+use stelp::output_tee::{TeeSink, TeeSpec};
+use stelp::output_template::{OutputTemplate, PartitionedWriter};
+use stelp::pipeline_builder::PipelineBuilder;
+use stelp::pipeline_file::{NameList, PipelineFile, PipelineStep};
+use stelp::processors::patterns::DEFAULT_PATTERNS_TOP;
+use stelp::processors::sample::parse_rate;
+use stelp::processors::{RedactMode, RedactPattern, SchemaFormat, ValidateMode};
 use stelp::StreamPipeline;
-use stelp::{
-    DeriveProcessor, ExtractProcessor, FilterProcessor, LevelMapProcessor, StarlarkProcessor, WindowProcessor,
-};
-
-#[derive(Debug, Clone)]
-enum PipelineStep {
-    Extract(String),
-    Eval(String),
-    Filter(String),
-    Derive(String),
-    ScriptFile(PathBuf),
-}
 
 #[derive(Parser)]
 #[command(name = "stelp")]
 #[command(about = "Process text streams with Starlark scripts (Starlark Event and Line Processor)")]
 #[command(version)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     // === CORE PROCESSING ===
     /// Pipeline evaluation expressions (executed in order)
     #[arg(short = 'e', long = "eval", action = ArgAction::Append)]
@@ -37,10 +41,22 @@ struct Args {
     #[arg(short = 's', long = "script")]
     script_file: Option<PathBuf>,
 
+    /// Pipeline file (YAML, or Starlark with stage() calls) declaring formats, steps and options
+    #[arg(short = 'P', long = "pipeline", value_name = "FILE", conflicts_with = "script_file")]
+    pipeline_file: Option<PathBuf>,
+
+    /// Steps read from the pipeline file, run before command-line steps
+    #[arg(skip)]
+    file_steps: Vec<PipelineStep>,
+
     /// Include Starlark files (processed in order)
     #[arg(short = 'I', long = "include", action = ArgAction::Append)]
     includes: Vec<PathBuf>,
 
+    /// Directory to search for load("//...") modules (repeatable, searched before $STELP_PATH)
+    #[arg(long = "lib-path", action = ArgAction::Append)]
+    lib_paths: Vec<PathBuf>,
+
     /// BEGIN expression - Run before processing any input lines
     #[arg(long = "begin")]
     begin: Option<String>,
@@ -62,6 +78,47 @@ struct Args {
     #[arg(short = 'd', long = "derive", action = ArgAction::Append)]
     derives: Vec<String>,
 
+    // === SAMPLING ===
+    /// Keep each record with probability RATE (e.g. 0.01 or 1%)
+    #[arg(long = "sample", value_name = "RATE", value_parser = parse_rate, action = ArgAction::Append)]
+    samples: Vec<f64>,
+
+    /// Keep all records for a fraction of the values of FIELD (e.g. user:0.1), chosen by hash
+    #[arg(long = "sample-by", value_name = "FIELD:RATE", action = ArgAction::Append)]
+    sample_bys: Vec<String>,
+
+    /// Keep a uniform random sample of N records, output when the last input ends
+    #[arg(long = "reservoir", value_name = "N", action = ArgAction::Append)]
+    reservoirs: Vec<usize>,
+
+    /// Let through at most N records per PERIOD, per value of FIELD if given (e.g. 100/s:user)
+    #[arg(long = "rate-limit", value_name = "N/PERIOD[:FIELD]", action = ArgAction::Append)]
+    rate_limits: Vec<String>,
+
+    /// Random seed for --sample and --reservoir, and salt for --sample-by
+    #[arg(long = "seed", value_name = "N")]
+    seed: Option<u64>,
+
+    /// Drop records whose values for these fields were already seen (comma-separated)
+    #[arg(long = "dedupe-by", value_name = "FIELDS", action = ArgAction::Append)]
+    dedupe_bys: Vec<String>,
+
+    /// Drop lines (or records) that were already seen
+    #[arg(long = "dedupe-line")]
+    dedupe_line: bool,
+
+    /// Only treat records as duplicates within DURATION (e.g. 10m) of the first one
+    #[arg(long = "dedupe-window", value_name = "DURATION")]
+    dedupe_window: Option<String>,
+
+    /// Remember at most N dedupe keys, forgetting the least recently seen
+    #[arg(long = "dedupe-keys", value_name = "N")]
+    dedupe_keys: Option<usize>,
+
+    /// Instead of dropping repeats, emit the first record with a repeat_count when its window closes
+    #[arg(long = "dedupe-count")]
+    dedupe_count: bool,
+
     // === INPUT/OUTPUT FORMATS ===
     /// Input files to process (default: stdin if none provided)
     #[arg(value_name = "FILE")]
@@ -75,6 +132,18 @@ struct Args {
     #[arg(short = 'o', long = "output")]
     output_file: Option<PathBuf>,
 
+    /// Write each record to a file computed from its fields, e.g. 'out/{service}/{ts:%Y-%m-%d}.jsonl'
+    #[arg(long = "output-template", value_name = "TEMPLATE", conflicts_with = "output_file")]
+    output_template: Option<String>,
+
+    /// Maximum number of files kept open by --output-template
+    #[arg(long = "output-max-open", value_name = "N", default_value_t = 64)]
+    output_max_open: usize,
+
+    /// Also write output to another file: 'path:format[:filter-expr]' (repeatable)
+    #[arg(long = "tee", value_name = "SPEC", action = ArgAction::Append)]
+    tees: Vec<String>,
+
     /// Output format
     #[arg(short = 'F', long = "output-format", value_enum)]
     output_format: Option<OutputFormat>,
@@ -108,15 +177,111 @@ struct Args {
     #[arg(long = "window")]
     window_size: Option<usize>,
 
+    /// Keep records from the last DURATION (e.g. 10m) for window functions
+    #[arg(long = "window-time", value_name = "DURATION")]
+    window_time: Option<String>,
+
+    /// Keep a separate window for each value of this field
+    #[arg(long = "window-by", value_name = "FIELD")]
+    window_by: Option<String>,
+
+    /// Keep at most N --window-by windows, dropping the least recently used
+    #[arg(long = "window-keys", value_name = "N", requires = "window_by")]
+    window_keys: Option<usize>,
+
+    /// Keep at most N glob keys, evicting the least recently used
+    #[arg(long = "glob-limit", value_name = "N")]
+    glob_limit: Option<usize>,
+
+    /// Load glob from this JSON file before BEGIN and save it after END
+    #[arg(long = "state-file", value_name = "FILE")]
+    state_file: Option<PathBuf>,
+
+    /// Only persist glob keys starting with these prefixes (comma-separated)
+    #[arg(long = "state-keys", value_name = "PREFIXES", requires = "state_file")]
+    state_keys: Option<String>,
+
+    /// Also save the state file every N seconds (for long-running `tail -f` input)
+    #[arg(long = "state-interval", value_name = "SECS", requires = "state_file")]
+    state_interval: Option<u64>,
+
+    /// Periodically save the input position and glob to this file
+    #[arg(long = "checkpoint", value_name = "FILE")]
+    checkpoint: Option<PathBuf>,
+
+    /// Continue from the --checkpoint file instead of the start of the input
+    #[arg(long, requires = "checkpoint")]
+    resume: bool,
+
+    /// Seconds between checkpoints [default: 10]
+    #[arg(long = "checkpoint-interval", value_name = "SECS", requires = "checkpoint")]
+    checkpoint_interval: Option<u64>,
+
     /// Fail on first error instead of skipping lines
     #[arg(long)]
     fail_fast: bool,
 
+    /// Skip failing lines even if the pipeline file sets fail-fast
+    #[arg(long = "no-fail-fast", conflicts_with = "fail_fast")]
+    no_fail_fast: bool,
+
+    /// Write rejected input lines (parse errors, failing steps) as JSONL to this file
+    #[arg(long = "dead-letter", value_name = "FILE")]
+    dead_letter: Option<PathBuf>,
+
+    /// Convert fields to types: 'status:int,latency:duration,ts:timestamp,ok:bool' (also float, str)
+    #[arg(long = "types", value_name = "SPEC")]
+    types: Option<String>,
+
+    /// Convert numeric-looking string fields to numbers
+    #[arg(long = "infer-types")]
+    infer_types: bool,
+
+    /// Check structured records against a JSON Schema file
+    #[arg(long = "validate", value_name = "SCHEMA")]
+    validate: Option<PathBuf>,
+
+    /// What to do with records that violate --validate
+    #[arg(long = "validate-mode", value_enum, value_name = "MODE", default_value_t = ValidateMode::Reject)]
+    validate_mode: ValidateMode,
+
+    /// With -f combined, add parsed `request` (URL) and `user_agent` fields
+    #[arg(long = "combined-expand")]
+    combined_expand: bool,
+
+    /// MaxMind-format database (.mmdb) for geoip() lookups (repeatable, e.g. City + ASN)
+    #[arg(long = "geoip", value_name = "FILE", action = ArgAction::Append)]
+    geoip: Vec<PathBuf>,
+
+    /// With -f combined, add a `geo` field for the client ip (requires --geoip)
+    #[arg(long = "combined-geoip", requires = "geoip")]
+    combined_geoip: bool,
+
+    /// Redact matches of these built-in patterns from all output (comma-separated, see --list-patterns)
+    #[arg(long = "redact", value_name = "PATTERNS")]
+    redact: Option<String>,
+
+    /// How redacted values are rewritten
+    #[arg(long = "redact-mode", value_enum, default_value_t = RedactMode::Mask)]
+    redact_mode: RedactMode,
+
+    /// Key for --redact-mode hash/pseudonymize
+    #[arg(long = "redact-salt", value_name = "SALT", default_value = "")]
+    redact_salt: String,
+
+    /// File of extra 'name = regex' patterns to redact (repeatable)
+    #[arg(long = "redact-patterns", value_name = "FILE", action = ArgAction::Append)]
+    redact_patterns: Vec<PathBuf>,
+
     // === OUTPUT CONTROL ===
     /// Print only values, not keys (plain output mode)
     #[arg(short = 'p', long = "plain")]
     plain: bool,
 
+    /// Print keys and values even if the pipeline file sets plain
+    #[arg(long = "no-plain", conflicts_with = "plain")]
+    no_plain: bool,
+
     /// Show only records with these log levels (comma-separated)
     #[arg(short = 'l', long = "levels")]
     levels: Option<String>,
@@ -129,6 +294,30 @@ struct Args {
     #[arg(long = "levelmap", short = 'M', help = "Output first char of log levels only to give a big picture overview")]
     levelmap: bool,
 
+    /// Cluster messages into templates and report the most frequent ones, with counts and samples
+    #[arg(long = "patterns", conflicts_with = "pattern_field")]
+    patterns: bool,
+
+    /// Number of templates reported by --patterns (0 for all)
+    #[arg(long = "patterns-top", value_name = "N", default_value_t = DEFAULT_PATTERNS_TOP)]
+    patterns_top: usize,
+
+    /// Add each record's message template id to this field
+    #[arg(long = "pattern-field", value_name = "NAME")]
+    pattern_field: Option<String>,
+
+    /// Profile the fields of structured records: a report per field, a JSON Schema or --types
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "report",
+        conflicts_with = "patterns"
+    )]
+    schema: Option<SchemaFormat>,
+
     /// Force colored output even when not on TTY
     #[arg(long = "color", action = ArgAction::SetTrue)]
     force_color: bool,
@@ -137,9 +326,28 @@ struct Args {
     #[arg(long = "no-color", action = ArgAction::SetTrue)]
     no_color: bool,
 
-    /// Show processing statistics
+    /// Show processing statistics (text, json or logfmt; default text)
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "text"
+    )]
+    stats: Option<StatsFormat>,
+
+    /// Write the stats report to a file (format from --stats, else from the extension, else JSON)
+    #[arg(long = "stats-file", value_name = "FILE")]
+    stats_file: Option<PathBuf>,
+
+    /// Print per-step timings (parse, each processor, format) to stderr
     #[arg(long)]
-    stats: bool,
+    profile: bool,
+
+    /// Number of slowest records listed by --profile
+    #[arg(long = "profile-top", value_name = "N", default_value_t = 10)]
+    profile_top: usize,
 
     /// Debug mode - show processing details
     #[arg(long)]
@@ -150,20 +358,55 @@ struct Args {
     list_patterns: bool,
 }
 
+#[derive(Subcommand)]
+enum Command {
+    /// Run pipeline regression tests (golden input/expected cases and *_test.star files)
+    Test(TestArgs),
+    /// Explore records interactively and build a pipeline step by step
+    Repl(ReplArgs),
+}
+
+/// `stelp test`: run golden pipeline cases and Starlark test files
+#[derive(clap::Args)]
+struct TestArgs {
+    /// Test directories, case pipeline files or *_test.star files [default: .]
+    paths: Vec<PathBuf>,
+
+    /// Rewrite expected output files from the actual output
+    #[arg(long)]
+    update: bool,
+
+    /// Directory to search for load("//...") modules (repeatable, searched before $STELP_PATH)
+    #[arg(long = "lib-path", action = ArgAction::Append)]
+    lib_paths: Vec<PathBuf>,
+}
+
 impl Args {
     fn validate(&self) -> Result<(), String> {
         let has_script_file = self.script_file.is_some();
         let has_extract = self.extract_pattern.is_some();
         let has_evals = !self.evals.is_empty();
+        let has_file_steps = !self.file_steps.is_empty();
         let has_filters = !self.filters.is_empty();
         let has_derives = !self.derives.is_empty();
+        let has_sampling = !self.samples.is_empty()
+            || !self.sample_bys.is_empty()
+            || !self.reservoirs.is_empty()
+            || !self.rate_limits.is_empty()
+            || !self.dedupe_bys.is_empty()
+            || self.dedupe_line;
         let has_begin_end = self.begin.is_some() || self.end.is_some();
         let has_input_format = self.input_format.is_some();
-        let has_output_format = self.output_format.is_some();
+        let has_output_format = self.output_format.is_some() || self.output_template.is_some();
         let has_chunking =
             self.chunk_lines.is_some() || self.chunk_start.is_some() || self.chunk_delim.is_some();
         let has_level_filters = self.levels.is_some() || self.exclude_levels.is_some();
         let has_levelmap = self.levelmap;
+        let has_patterns = self.patterns || self.pattern_field.is_some();
+        let has_schema = self.schema.is_some();
+        let has_redact = self.redact.is_some() || !self.redact_patterns.is_empty();
+        let has_types = self.types.is_some() || self.infer_types;
+        let has_validate = self.validate.is_some();
         let has_input_files = !self.input_files.is_empty();
 
         // Check for mutually exclusive chunking options
@@ -185,7 +428,7 @@ impl Args {
             // Check if we have structured input format either explicitly or auto-detected
             let has_structured_format = has_input_format || 
                 (has_input_files && self.input_files.iter().any(|file| {
-                    InputFormat::from_extension(file).map_or(false, |format| {
+                    InputFormat::from_extension(file).is_some_and(|format| {
                         matches!(format, InputFormat::Jsonl | InputFormat::Csv | InputFormat::Tsv | 
                                InputFormat::Logfmt | InputFormat::Syslog | InputFormat::Combined | 
                                InputFormat::Fields)
@@ -217,9 +460,11 @@ impl Args {
         }
 
         let has_any_processing =
-            has_extract || has_evals || has_filters || has_derives || has_begin_end;
+            has_extract || has_evals || has_filters || has_derives || has_begin_end || has_file_steps
+            || has_sampling;
         let has_format_or_utility =
-            has_input_format || has_output_format || has_chunking || has_level_filters || has_levelmap;
+            has_input_format || has_output_format || has_chunking || has_level_filters || has_levelmap
+            || has_redact || has_types || has_patterns || has_schema || has_validate;
 
         match (has_script_file, has_any_processing, has_format_or_utility, has_input_files) {
             (true, true, _, _) => Err("Cannot use --script with other processing options".to_string()),
@@ -263,6 +508,47 @@ impl Args {
             }
         }
 
+        // Sampling steps with their indices
+        if let Some(indices) = matches.indices_of("samples") {
+            let values: Vec<&f64> = matches.get_many::<f64>("samples").unwrap().collect();
+            for (pos, index) in indices.enumerate() {
+                steps_with_indices.push((index, PipelineStep::Sample(*values[pos])));
+            }
+        }
+        if let Some(indices) = matches.indices_of("sample_bys") {
+            let values: Vec<&String> = matches.get_many::<String>("sample_bys").unwrap().collect();
+            for (pos, index) in indices.enumerate() {
+                steps_with_indices.push((index, PipelineStep::SampleBy(values[pos].clone())));
+            }
+        }
+        if let Some(indices) = matches.indices_of("reservoirs") {
+            let values: Vec<&usize> = matches.get_many::<usize>("reservoirs").unwrap().collect();
+            for (pos, index) in indices.enumerate() {
+                steps_with_indices.push((index, PipelineStep::Reservoir(*values[pos])));
+            }
+        }
+        if let Some(indices) = matches.indices_of("rate_limits") {
+            let values: Vec<&String> = matches.get_many::<String>("rate_limits").unwrap().collect();
+            for (pos, index) in indices.enumerate() {
+                steps_with_indices.push((index, PipelineStep::RateLimit(values[pos].clone())));
+            }
+        }
+
+        if let Some(indices) = matches.indices_of("dedupe_bys") {
+            let values: Vec<&String> = matches.get_many::<String>("dedupe_bys").unwrap().collect();
+            for (pos, index) in indices.enumerate() {
+                steps_with_indices.push((
+                    index,
+                    PipelineStep::DedupeBy(NameList::Csv(values[pos].clone())),
+                ));
+            }
+        }
+        if self.dedupe_line {
+            if let Some(index) = matches.index_of("dedupe_line") {
+                steps_with_indices.push((index, PipelineStep::DedupeLine));
+            }
+        }
+
         // Handle extract pattern - it doesn't have an index, so we place it first
         if let Some(extract_pattern) = &self.extract_pattern {
             steps_with_indices.push((0, PipelineStep::Extract(extract_pattern.clone())));
@@ -276,29 +562,75 @@ impl Args {
         // Sort by original command line position
         steps_with_indices.sort_by_key(|(index, _)| *index);
 
-        // Extract just the steps
-        Ok(steps_with_indices
-            .into_iter()
-            .map(|(_, step)| step)
+        // Pipeline file steps run first, then the command-line steps
+        Ok(self
+            .file_steps
+            .iter()
+            .cloned()
+            .chain(steps_with_indices.into_iter().map(|(_, step)| step))
             .collect())
     }
 
-    fn get_chunk_config(&self) -> Result<Option<ChunkConfig>, String> {
-        if let Some(lines) = self.chunk_lines {
-            Ok(Some(ChunkConfig {
-                strategy: parse_chunk_strategy(&format!("lines:{}", lines))?,
-            }))
-        } else if let Some(pattern) = &self.chunk_start {
-            Ok(Some(ChunkConfig {
-                strategy: parse_chunk_strategy(&format!("start-pattern:{}", pattern))?,
-            }))
-        } else if let Some(delimiter) = &self.chunk_delim {
-            Ok(Some(ChunkConfig {
-                strategy: parse_chunk_strategy(&format!("delimiter:{}", delimiter))?,
-            }))
-        } else {
-            Ok(None)
+    /// Fill in settings from a pipeline file; options given on the command line win
+    fn apply_pipeline_file(&mut self, file: PipelineFile) -> Result<(), String> {
+        use clap::ValueEnum;
+
+        if self.input_format.is_none() {
+            if let Some(name) = &file.input_format {
+                self.input_format = Some(
+                    InputFormat::from_str(name, true)
+                        .map_err(|_| format!("unknown input format '{}'", name))?,
+                );
+            }
+        }
+        if self.output_format.is_none() {
+            if let Some(name) = &file.output_format {
+                self.output_format = Some(
+                    OutputFormat::from_str(name, true)
+                        .map_err(|_| format!("unknown output format '{}'", name))?,
+                );
+            }
         }
+
+        self.begin = self.begin.take().or(file.begin);
+        self.end = self.end.take().or(file.end);
+        self.window_size = self.window_size.or(file.window);
+        self.window_time = self.window_time.take().or(file.window_time);
+        self.window_by = self.window_by.take().or(file.window_by);
+        self.window_keys = self.window_keys.or(file.window_keys);
+
+        // Chunking strategies are exclusive, so only take the file's if none was given
+        if self.chunk_lines.is_none() && self.chunk_start.is_none() && self.chunk_delim.is_none() {
+            self.chunk_lines = file.chunk_lines;
+            self.chunk_start = file.chunk_start;
+            self.chunk_delim = file.chunk_delim;
+        }
+
+        self.levels = self.levels.take().or(file.levels.map(|l| l.to_csv()));
+        self.exclude_levels = self
+            .exclude_levels
+            .take()
+            .or(file.exclude_levels.map(|l| l.to_csv()));
+        self.keys = self.keys.take().or(file.keys.map(|k| k.to_csv()));
+        self.remove_keys = self
+            .remove_keys
+            .take()
+            .or(file.remove_keys.map(|k| k.to_csv()));
+
+        self.types = self.types.take().or(file.types.map(|t| t.to_csv()));
+        self.infer_types |= file.infer_types;
+        self.lib_paths.extend(file.lib_path);
+        self.plain |= file.plain && !self.no_plain;
+        self.fail_fast |= file.fail_fast && !self.no_fail_fast;
+        self.glob_limit = self.glob_limit.or(file.glob_limit);
+        self.state_file = self.state_file.take().or(file.state_file);
+        self.state_keys = self.state_keys.take().or(file.state_keys.map(|k| k.to_csv()));
+        self.seed = self.seed.or(file.seed);
+        self.dedupe_window = self.dedupe_window.take().or(file.dedupe_window);
+        self.dedupe_keys = self.dedupe_keys.or(file.dedupe_keys);
+        self.dedupe_count |= file.dedupe_count;
+        self.file_steps = file.steps;
+        Ok(())
     }
 
     /// Determine whether to use colors based on flags and environment
@@ -355,38 +687,32 @@ impl Args {
     }
 }
 
-/// Format duration in seconds to human-readable string
-fn format_duration(seconds: i64) -> String {
-    if seconds < 60 {
-        format!("0:00:{:02}", seconds)
-    } else if seconds < 3600 {
-        let minutes = seconds / 60;
-        let secs = seconds % 60;
-        format!("0:{:02}:{:02}", minutes, secs)
-    } else {
-        let hours = seconds / 3600;
-        let minutes = (seconds % 3600) / 60;
-        let secs = seconds % 60;
-        format!("{}:{:02}:{:02}", hours, minutes, secs)
+/// Patterns named by --redact followed by those from --redact-patterns files
+fn build_redact_patterns(args: &Args) -> Result<Vec<RedactPattern>, String> {
+    let mut patterns = Vec::new();
+    for path in &args.redact_patterns {
+        patterns.extend(RedactPattern::load_file(path)?);
     }
-}
-
-/// Build the final script by concatenating includes and user script
-fn build_final_script(includes: &[PathBuf], user_script: &str) -> Result<String, String> {
-    let mut final_script = String::new();
-
-    // Add includes in order
-    for include_path in includes {
-        let include_content = std::fs::read_to_string(include_path)
-            .map_err(|e| format!("Include file '{}' not found: {}", include_path.display(), e))?;
-        final_script.push_str(&include_content);
-        final_script.push_str("\n\n");
+    let mut selected = Vec::new();
+    for name in args.redact.iter().flat_map(|list| list.split(',')) {
+        let name = name.trim();
+        if name.is_empty() || selected.iter().any(|p: &RedactPattern| p.name == name) {
+            continue;
+        }
+        let pattern = patterns
+            .iter()
+            .find(|p| p.name == name)
+            .cloned()
+            .or_else(|| RedactPattern::builtin(name))
+            .ok_or_else(|| format!("--redact: unknown pattern '{}' (see --list-patterns)", name))?;
+        selected.push(pattern);
     }
-
-    // Add user script
-    final_script.push_str(user_script);
-
-    Ok(final_script)
+    for pattern in patterns {
+        if !selected.iter().any(|p| p.name == pattern.name) {
+            selected.push(pattern);
+        }
+    }
+    Ok(selected)
 }
 
 /// Determine whether to use colors based on flags and environment
@@ -401,13 +727,102 @@ fn determine_color_usage(args: &Args) -> bool {
     }
 }
 
+/// `stelp repl`: step through records and try expressions interactively
+#[derive(clap::Args)]
+struct ReplArgs {
+    /// Input file to explore
+    file: PathBuf,
+
+    /// Input format (auto-detected from the file extension)
+    #[arg(short = 'f', long = "input-format")]
+    input_format: Option<InputFormat>,
+
+    /// Number of records to load
+    #[arg(short = 'n', long = "records", default_value_t = DEFAULT_RECORD_LIMIT)]
+    records: usize,
+
+    /// Directory to search for load("//...") modules (repeatable, searched before $STELP_PATH)
+    #[arg(long = "lib-path", action = ArgAction::Append)]
+    lib_paths: Vec<PathBuf>,
+}
+
+/// Handle `stelp repl ...`
+fn run_repl_command(args: ReplArgs) -> ! {
+    let result = Repl::load(&args.file, args.input_format, args.records, &args.lib_paths)
+        .and_then(|mut repl| run_interactive(&mut repl));
+    if let Err(e) = result {
+        eprintln!("stelp: {}", e);
+        std::process::exit(1);
+    }
+    std::process::exit(0);
+}
+
+/// Handle `stelp test ...` and exit with 0 if every test passed
+fn run_test_command(args: TestArgs) -> ! {
+    let paths = if args.paths.is_empty() {
+        vec![PathBuf::from(".")]
+    } else {
+        args.paths
+    };
+    let options = TestOptions {
+        update: args.update,
+        lib_paths: args.lib_paths,
+    };
+
+    match run_tests(&paths, &options, &mut io::stdout()) {
+        Ok(summary) => std::process::exit(if summary.success() { 0 } else { 1 }),
+        Err(e) => {
+            eprintln!("stelp: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Open an input file positioned at `start`. CSV/TSV parsers need the header
+/// line first, so when resuming it is replayed in front of the remaining data
+/// and `start` is moved back to account for it.
+fn open_input(path: &Path, start: &mut ResumePoint, replay_header: bool) -> io::Result<Box<dyn Read>> {
+    let mut file = File::open(path)?;
+    let offset = start.position.offset;
+    if offset == 0 {
+        return Ok(Box::new(file));
+    }
+    let mut header = Vec::new();
+    if replay_header {
+        BufReader::new(&file).read_until(b'\n', &mut header)?;
+        start.position.offset -= header.len() as u64;
+        start.position.lines -= 1;
+    }
+    file.seek(SeekFrom::Start(offset))?;
+    Ok(Box::new(io::Cursor::new(header).chain(file)))
+}
+
 fn main() {
     let matches = Args::command().get_matches();
-    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| {
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| {
         eprintln!("stelp: argument parsing failed: {}", e);
         std::process::exit(1);
     });
 
+    // Subcommands; everything else is a regular run
+    match args.command.take() {
+        Some(Command::Test(test_args)) => run_test_command(test_args),
+        Some(Command::Repl(repl_args)) => run_repl_command(repl_args),
+        None => {}
+    }
+
+    if let Some(path) = args.pipeline_file.clone() {
+        PipelineFile::load(&path)
+            .and_then(|file| {
+                args.apply_pipeline_file(file)
+                    .map_err(|e| format!("invalid pipeline file '{}': {}", path.display(), e))
+            })
+            .unwrap_or_else(|e| {
+                eprintln!("stelp: {}", e);
+                std::process::exit(1);
+            });
+    }
+
     // Handle --list-patterns option early
     if args.list_patterns {
         use stelp::pipeline::global_functions::get_pattern_list;
@@ -415,6 +830,9 @@ fn main() {
         for (name, description) in get_pattern_list() {
             println!("{:<15} - {}", name, description);
         }
+        for (name, description) in stelp::processors::redact::redact_only_patterns() {
+            println!("{:<15} - {} (--redact only)", name, description);
+        }
         std::process::exit(0);
     }
 
@@ -431,6 +849,13 @@ fn main() {
         }
     }
 
+    if !args.geoip.is_empty() {
+        if let Err(e) = stelp::geoip::init(&args.geoip) {
+            eprintln!("stelp: {}", e);
+            std::process::exit(1);
+        }
+    }
+
     // Build pipeline steps first (before moving parts of args)
     let steps = args.get_pipeline_steps(&matches).unwrap_or_else(|e| {
         eprintln!("stelp: failed to parse pipeline steps: {}", e);
@@ -451,26 +876,83 @@ fn main() {
         }
     };
 
-    // Extract chunking config before moving args
-    let chunk_config = args.get_chunk_config().unwrap_or_else(|e| {
+    // Processors, chunking and output format defaults come from the same options
+    let redact_patterns = if args.redact.is_some() || !args.redact_patterns.is_empty() {
+        build_redact_patterns(&args).unwrap_or_else(|e| {
+            eprintln!("stelp: {}", e);
+            std::process::exit(1);
+        })
+    } else {
+        Vec::new()
+    };
+    let builder = PipelineBuilder {
+        input_format: input_format.clone(),
+        steps,
+        includes: args.includes.clone(),
+        lib_paths: args.lib_paths.clone(),
+        base_dir: PathBuf::from("."),
+        begin: args.begin.clone(),
+        end: args.end.clone(),
+        pass_through: !args.input_files.is_empty(),
+        types: args.types.clone(),
+        infer_types: args.infer_types,
+        validate: args.validate.clone(),
+        validate_mode: args.validate_mode,
+        levels: args.levels.clone(),
+        exclude_levels: args.exclude_levels.clone(),
+        levelmap: args.levelmap.then(|| determine_color_usage(&args)),
+        patterns_top: args.patterns.then_some(args.patterns_top),
+        pattern_field: args.pattern_field.clone(),
+        schema: args.schema,
+        window_size: args.window_size,
+        window_time: args.window_time.clone(),
+        window_by: args.window_by.clone(),
+        window_keys: args.window_keys,
+        dedupe_window: args.dedupe_window.clone(),
+        dedupe_keys: args.dedupe_keys,
+        dedupe_count: args.dedupe_count,
+        seed: args.seed,
+        redact_patterns,
+        redact_mode: args.redact_mode,
+        redact_salt: args.redact_salt.clone(),
+        chunk_lines: args.chunk_lines,
+        chunk_start: args.chunk_start.clone(),
+        chunk_delim: args.chunk_delim.clone(),
+    };
+
+    let chunk_config = builder.chunk_config().unwrap_or_else(|e| {
         eprintln!("stelp: {}", e);
         std::process::exit(1);
     });
 
+    // Checkpoints are byte offsets into files, one record per line
+    if args.checkpoint.is_some() {
+        if args.input_files.is_empty() {
+            eprintln!("stelp: --checkpoint needs input files (stdin cannot be resumed)");
+            std::process::exit(1);
+        }
+        if chunk_config.is_some() {
+            eprintln!("stelp: --checkpoint cannot be combined with chunking");
+            std::process::exit(1);
+        }
+    }
+
     // Extract color preference before moving args
     let color_preference = args.determine_color_usage();
 
+    let output_template = args.output_template.as_deref().map(|template| {
+        OutputTemplate::parse(template).unwrap_or_else(|e| {
+            eprintln!("stelp: {}", e);
+            std::process::exit(1);
+        })
+    });
+
     // Build configuration with smart output format defaulting
-    let output_format = match args.output_format {
-        Some(format) => format, // User explicitly specified output format
-        None => {
-            // Default based on input format and plain mode
-            match input_format {
-                Some(InputFormat::Line) => OutputFormat::Line, // Text input defaults to text output
-                _ => OutputFormat::Logfmt, // All structured formats default to logfmt (plain mode affects rendering, not format choice)
-            }
-        }
-    };
+    let template_format = output_template.as_ref().and_then(|t| t.output_format());
+    let output_format = args
+        .output_format
+        .or(template_format) // Explicit format, or inferred from the template's extension
+        .unwrap_or_else(|| builder.default_output_format());
 
     let keys = args.build_keys_list();
 
@@ -497,200 +979,138 @@ fn main() {
         remove_keys,
         color_preference,
         plain: args.plain,
+        glob_limit: args.glob_limit,
     };
 
+    let tee_sinks: Vec<TeeSink> = args
+        .tees
+        .iter()
+        .map(|spec| {
+            TeeSpec::parse(spec)
+                .and_then(|spec| TeeSink::open(spec, config.remove_keys.clone(), config.plain))
+                .unwrap_or_else(|e| {
+                    eprintln!("stelp: {}", e);
+                    std::process::exit(1);
+                })
+        })
+        .collect();
+
+    let partitioned_output = output_template.map(|template| {
+        PartitionedWriter::new(
+            template,
+            config.output_format,
+            config.keys.clone(),
+            config.remove_keys.clone(),
+            config.plain,
+            args.output_max_open,
+        )
+    });
+
     // Create pipeline
     let mut pipeline = StreamPipeline::new(config);
+    if let Some(writer) = partitioned_output {
+        pipeline.set_partitioned_output(writer);
+    }
+    for sink in tee_sinks {
+        pipeline.add_tee_sink(sink);
+    }
+    if args.profile {
+        pipeline.enable_profiling(args.profile_top);
+    }
+    if let Some(path) = &args.dead_letter {
+        let writer = DeadLetterWriter::create(path).unwrap_or_else(|e| {
+            eprintln!(
+                "stelp: failed to create dead-letter file '{}': {}",
+                path.display(),
+                e
+            );
+            std::process::exit(1);
+        });
+        pipeline.set_dead_letter(writer);
+    }
 
     // Create input format wrapper with optional chunking
     let format_wrapper = if let Some(config) = chunk_config {
         InputFormatWrapper::new(input_format.as_ref()).with_chunking(config)
     } else {
         InputFormatWrapper::new(input_format.as_ref())
-    };
+    }
+    .with_combined_expansion(args.combined_expand)
+    .with_combined_geoip(args.combined_geoip);
 
-    // Add level filter processor if specified
-    if args.levels.is_some() || args.exclude_levels.is_some() {
-        let level_filter = stelp::LevelFilterProcessor::new(
-            "level_filter",
-            args.levels.as_deref(),
-            args.exclude_levels.as_deref(),
-        );
-        pipeline.add_processor(Box::new(level_filter));
-    }
-
-    // Add levelmap processor if requested
-    if args.levelmap {
-        let use_color = determine_color_usage(&args);
-        let levelmap_processor = LevelMapProcessor::new("levelmap", use_color);
-        pipeline.add_processor(Box::new(levelmap_processor));
-    }
-
-    // Add processors to pipeline in order
-    for (i, step) in steps.iter().enumerate() {
-        match step {
-            PipelineStep::Extract(pattern) => {
-                let processor = ExtractProcessor::new(&format!("extract_{}", i + 1), pattern)
-                    .unwrap_or_else(|e| {
-                        eprintln!("stelp: failed to compile extract-vars pattern: {}", e);
-                        std::process::exit(1);
-                    });
-                let final_processor: Box<dyn stelp::pipeline::stream::RecordProcessor> =
-                    if let Some(window_size) = args.window_size {
-                        Box::new(WindowProcessor::new(window_size, Box::new(processor)))
-                    } else {
-                        Box::new(processor)
-                    };
-                pipeline.add_processor(final_processor);
-            }
-            PipelineStep::Eval(eval_expr) => {
-                let final_script =
-                    build_final_script(&args.includes, eval_expr).unwrap_or_else(|e| {
-                        eprintln!("stelp: {}", e);
-                        std::process::exit(1);
-                    });
-                let processor =
-                    StarlarkProcessor::from_script(&format!("eval_{}", i + 1), &final_script)
-                        .unwrap_or_else(|e| {
-                            eprintln!("stelp: failed to compile eval expression {}: {}", i + 1, e);
-                            std::process::exit(1);
-                        });
-                let final_processor: Box<dyn stelp::pipeline::stream::RecordProcessor> =
-                    if let Some(window_size) = args.window_size {
-                        Box::new(WindowProcessor::new(window_size, Box::new(processor)))
-                    } else {
-                        Box::new(processor)
-                    };
-                pipeline.add_processor(final_processor);
+    if let Err(e) = builder.add_processors(&mut pipeline) {
+        eprintln!("stelp: {}", e);
+        std::process::exit(1);
+    }
+
+    // Load persisted glob before BEGIN runs
+    if let Some(path) = &args.state_file {
+        let prefixes = args
+            .state_keys
+            .as_deref()
+            .map(|keys| {
+                keys.split(',')
+                    .map(|k| k.trim().to_string())
+                    .filter(|k| !k.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let state = StateFile::new(path, prefixes)
+            .with_interval(args.state_interval.map(std::time::Duration::from_secs));
+        match pipeline.set_state_file(state) {
+            Ok(loaded) if args.debug => {
+                eprintln!("stelp: loaded {} glob keys from {}", loaded, path.display())
             }
-            PipelineStep::Filter(filter_expr) => {
-                let final_script =
-                    build_final_script(&args.includes, filter_expr).unwrap_or_else(|e| {
-                        eprintln!("stelp: {}", e);
-                        std::process::exit(1);
-                    });
-                let processor =
-                    FilterProcessor::from_expression(&format!("filter_{}", i + 1), &final_script)
-                        .unwrap_or_else(|e| {
-                            eprintln!(
-                                "stelp: failed to compile filter expression {}: {}",
-                                i + 1,
-                                e
-                            );
-                            std::process::exit(1);
-                        });
-                let final_processor: Box<dyn stelp::pipeline::stream::RecordProcessor> =
-                    if let Some(window_size) = args.window_size {
-                        Box::new(WindowProcessor::new(window_size, Box::new(processor)))
-                    } else {
-                        Box::new(processor)
-                    };
-                pipeline.add_processor(final_processor);
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("stelp: {}", e);
+                std::process::exit(1);
             }
-            PipelineStep::Derive(derive_expr) => {
-                let final_script =
-                    build_final_script(&args.includes, derive_expr).unwrap_or_else(|e| {
-                        eprintln!("stelp: {}", e);
+        }
+    }
+
+    // Set up checkpoints; a resumed glob replaces the state file's, being newer
+    let mut resume_from: Option<(usize, Checkpoint)> = None;
+    if let Some(path) = &args.checkpoint {
+        let interval = std::time::Duration::from_secs(args.checkpoint_interval.unwrap_or(10));
+        pipeline.set_checkpointer(Checkpointer::new(path, interval));
+        if args.resume {
+            match Checkpoint::load(path) {
+                Ok(Some(checkpoint)) => {
+                    let Some(index) = args.input_files.iter().position(|f| *f == checkpoint.file)
+                    else {
+                        eprintln!(
+                            "stelp: checkpoint '{}' is for '{}', which is not an input file",
+                            path.display(),
+                            checkpoint.file.display()
+                        );
                         std::process::exit(1);
-                    });
-                let processor =
-                    DeriveProcessor::from_script(&format!("derive_{}", i + 1), &final_script)
-                        .unwrap_or_else(|e| {
-                            eprintln!(
-                                "stelp: failed to compile derive expression {}: {}",
-                                i + 1,
-                                e
-                            );
-                            std::process::exit(1);
-                        });
-                let final_processor: Box<dyn stelp::pipeline::stream::RecordProcessor> =
-                    if let Some(window_size) = args.window_size {
-                        Box::new(WindowProcessor::new(window_size, Box::new(processor)))
-                    } else {
-                        Box::new(processor)
                     };
-                pipeline.add_processor(final_processor);
-            }
-            PipelineStep::ScriptFile(script_path) => {
-                let script_content = std::fs::read_to_string(script_path).unwrap_or_else(|e| {
-                    eprintln!(
-                        "stelp: failed to read script file '{}': {}",
-                        script_path.display(),
-                        e
-                    );
-                    std::process::exit(1);
-                });
-                let final_script = build_final_script(&args.includes, &script_content)
-                    .unwrap_or_else(|e| {
-                        eprintln!("stelp: {}", e);
-                        std::process::exit(1);
-                    });
-                let processor = StarlarkProcessor::from_script(
-                    &format!("script:{}", script_path.display()),
-                    &final_script,
-                )
-                .unwrap_or_else(|e| {
-                    eprintln!("stelp: failed to compile script file: {}", e);
+                    pipeline.get_global_vars().clear();
+                    pipeline.get_global_vars().load_json(&checkpoint.glob, |_| true);
+                    if args.debug {
+                        eprintln!(
+                            "stelp: resuming {} at byte {} (line {})",
+                            checkpoint.file.display(),
+                            checkpoint.position.offset,
+                            checkpoint.line_number
+                        );
+                    }
+                    resume_from = Some((index, checkpoint));
+                }
+                Ok(None) => {
+                    if args.debug {
+                        eprintln!("stelp: no checkpoint at {}, starting over", path.display());
+                    }
+                }
+                Err(e) => {
+                    eprintln!("stelp: {}", e);
                     std::process::exit(1);
-                });
-                let final_processor: Box<dyn stelp::pipeline::stream::RecordProcessor> =
-                    if let Some(window_size) = args.window_size {
-                        Box::new(WindowProcessor::new(window_size, Box::new(processor)))
-                    } else {
-                        Box::new(processor)
-                    };
-                pipeline.add_processor(final_processor);
+                }
             }
         }
     }
 
-    // Add default identity processor if no processing steps were provided AND actual processing is needed
-    // Skip identity processor for pure format conversion operations (no BEGIN/END scripts)
-    let needs_processing = args.begin.is_some() || args.end.is_some();
-    
-    if steps.is_empty() && !args.input_files.is_empty() && needs_processing {
-        // For structured formats, a simple identity transform that preserves the data
-        // For line format, just pass through the line
-        let identity_script = match input_format.as_ref().unwrap_or(&InputFormat::Line) {
-            InputFormat::Line => "line",  // Pass through the line as-is
-            _ => "data",  // Pass through structured data (gets formatted by output formatter)
-        };
-        
-        let processor = StarlarkProcessor::from_script("identity", identity_script)
-            .unwrap_or_else(|e| {
-                eprintln!("stelp: failed to compile default identity processor: {}", e);
-                std::process::exit(1);
-            });
-        pipeline.add_processor(Box::new(processor));
-    }
-
-    // Add BEGIN processor if specified
-    if let Some(begin_expr) = &args.begin {
-        let final_script = build_final_script(&args.includes, begin_expr).unwrap_or_else(|e| {
-            eprintln!("stelp: {}", e);
-            std::process::exit(1);
-        });
-        let processor =
-            StarlarkProcessor::from_script("BEGIN", &final_script).unwrap_or_else(|e| {
-                eprintln!("stelp: failed to compile BEGIN expression: {}", e);
-                std::process::exit(1);
-            });
-        pipeline.set_begin_processor(Box::new(processor));
-    }
-
-    // Add END processor if specified
-    if let Some(end_expr) = &args.end {
-        let final_script = build_final_script(&args.includes, end_expr).unwrap_or_else(|e| {
-            eprintln!("stelp: {}", e);
-            std::process::exit(1);
-        });
-        let processor = StarlarkProcessor::from_script("END", &final_script).unwrap_or_else(|e| {
-            eprintln!("stelp: failed to compile END expression: {}", e);
-            std::process::exit(1);
-        });
-        pipeline.set_end_processor(Box::new(processor));
-    }
-
     // Set up output
     let mut output: Box<dyn Write> = if let Some(output_path) = &args.output_file {
         let file = File::create(output_path).unwrap_or_else(|e| {
@@ -708,6 +1128,7 @@ fn main() {
 
     // Process input files or stdin
     let mut total_stats = ProcessingStats::default();
+    let mut file_stats: Vec<FileStats> = Vec::new();
 
     if args.input_files.is_empty() {
         // No input files specified, read from stdin
@@ -721,15 +1142,40 @@ fn main() {
                 eprintln!("stelp: processing stdin failed: {}", e);
                 std::process::exit(1);
             });
-        total_stats = stats;
+        total_stats.merge(&stats);
+        file_stats.push(FileStats {
+            file: "<stdin>".to_string(),
+            stats,
+        });
     } else {
         // Process each input file
-        for (file_index, input_path) in args.input_files.iter().enumerate() {
+        for (index, input_path) in args.input_files.iter().enumerate() {
+            let mut start = ResumePoint::default();
+            if let Some((resume_index, checkpoint)) = &resume_from {
+                if index < *resume_index {
+                    if args.debug {
+                        eprintln!("stelp: skipping {} (before checkpoint)", input_path.display());
+                    }
+                    continue;
+                }
+                if index == *resume_index {
+                    if let Err(e) = checkpoint
+                        .identity
+                        .verify(input_path, checkpoint.position.offset)
+                    {
+                        eprintln!("stelp: {}", e);
+                        std::process::exit(1);
+                    }
+                    start = checkpoint.resume_point();
+                }
+            }
+
             if args.debug {
                 eprintln!("stelp: processing file: {}", input_path.display());
             }
 
-            let file = File::open(input_path).unwrap_or_else(|e| {
+            let replay_header = matches!(input_format, Some(InputFormat::Csv | InputFormat::Tsv));
+            let file = open_input(input_path, &mut start, replay_header).unwrap_or_else(|e| {
                 eprintln!(
                     "stelp: failed to open input file '{}': {}",
                     input_path.display(),
@@ -737,6 +1183,11 @@ fn main() {
                 );
                 std::process::exit(1);
             });
+            if let Err(e) = pipeline.start_input(Some(input_path), start) {
+                eprintln!("stelp: {}", e);
+                std::process::exit(1);
+            }
+            pipeline.set_last_input(index + 1 == args.input_files.len());
             let input = BufReader::with_capacity(65536, file);
 
             let filename = input_path.to_string_lossy();
@@ -752,45 +1203,28 @@ fn main() {
                 });
 
             // Accumulate statistics
-            if file_index == 0 {
-                total_stats = stats;
-            } else {
-                total_stats.records_processed += stats.records_processed;
-                total_stats.records_output += stats.records_output;
-                total_stats.records_skipped += stats.records_skipped;
-                total_stats.errors += stats.errors;
-                total_stats.processing_time += stats.processing_time;
-                total_stats.parse_errors.extend(stats.parse_errors);
-                // Accumulate enhanced stats
-                total_stats.lines_seen += stats.lines_seen;
-                total_stats.keys_seen.extend(stats.keys_seen);
-                total_stats.levels_seen.extend(stats.levels_seen);
-                // Update timestamp range
-                if let Some(earliest) = stats.earliest_timestamp {
-                    if let Some(total_earliest) = total_stats.earliest_timestamp {
-                        if earliest < total_earliest {
-                            total_stats.earliest_timestamp = Some(earliest);
-                        }
-                    } else {
-                        total_stats.earliest_timestamp = Some(earliest);
-                    }
-                }
-                if let Some(latest) = stats.latest_timestamp {
-                    if let Some(total_latest) = total_stats.latest_timestamp {
-                        if latest > total_latest {
-                            total_stats.latest_timestamp = Some(latest);
-                        }
-                    } else {
-                        total_stats.latest_timestamp = Some(latest);
-                    }
-                }
-            }
+            total_stats.merge(&stats);
+            file_stats.push(FileStats {
+                file: filename.to_string(),
+                stats,
+            });
 
             // Reset pipeline state between files (but keep globals)
             pipeline.reset_processors();
         }
     }
 
+    if let Err(e) = pipeline.finish_output() {
+        eprintln!("stelp: failed to write output files: {}", e);
+        std::process::exit(1);
+    }
+
+    // END has run; persist glob for the next run
+    if let Err(e) = pipeline.save_state() {
+        eprintln!("stelp: {}", e);
+        std::process::exit(1);
+    }
+
     // Ensure output is flushed
     if let Err(e) = output.flush() {
         if e.kind() == io::ErrorKind::BrokenPipe {
@@ -801,6 +1235,12 @@ fn main() {
         }
     }
 
+    // All output is written; the next --resume starts after everything read
+    if let Err(e) = pipeline.save_checkpoint() {
+        eprintln!("stelp: {}", e);
+        std::process::exit(1);
+    }
+
     // Report CSV warnings about missing keys
     pipeline.get_output_formatter().report_csv_warnings();
 
@@ -823,85 +1263,31 @@ fn main() {
         }
     }
 
-    // Print performance stats if requested
-    if args.stats {
-        let processing_ms = total_stats.processing_time.as_secs_f64() * 1000.0;
-        let records_per_sec = if processing_ms > 0.0 {
-            (total_stats.records_processed as f64) / (processing_ms / 1000.0)
-        } else {
-            0.0
+    // Print or write the stats report if requested
+    if args.stats.is_some() || args.stats_file.is_some() {
+        let report = StatsReport {
+            total: &total_stats,
+            files: &file_stats,
+            processors: pipeline.get_processor_stats(),
         };
-
-        // Basic stats line
-        if total_stats.lines_seen > 0 {
-            let percentage = if total_stats.lines_seen > 0 {
-                (total_stats.records_processed as f64 / total_stats.lines_seen as f64) * 100.0
-            } else {
-                0.0
-            };
-            eprintln!(
-                "Records shown: {} ({:.0}% of {} lines seen)",
-                total_stats.records_processed,
-                percentage,
-                total_stats.lines_seen
-            );
-        } else {
-            eprintln!("Records shown: {}", total_stats.records_processed);
-        }
-
-        // Time span information if we have timestamps
-        if let (Some(earliest), Some(latest)) = (total_stats.earliest_timestamp, total_stats.latest_timestamp) {
-            use chrono::DateTime;
-            if let (Some(earliest_dt), Some(latest_dt)) = (
-                DateTime::from_timestamp(earliest, 0),
-                DateTime::from_timestamp(latest, 0)
-            ) {
-                let duration_seconds = latest - earliest;
-                let records_per_sec = if duration_seconds > 0 {
-                    total_stats.records_processed as f64 / duration_seconds as f64
-                } else {
-                    0.0
-                };
-                
-                let duration = format_duration(duration_seconds);
-                eprintln!(
-                    "Time span shown: {} to {}  ({}, {:.1} records/s)",
-                    earliest_dt.to_rfc3339(),
-                    latest_dt.to_rfc3339(),
-                    duration,
-                    records_per_sec
-                );
+        let result = match &args.stats_file {
+            Some(path) => {
+                let format = args
+                    .stats
+                    .unwrap_or_else(|| StatsFormat::from_extension(path));
+                File::create(path).and_then(|mut file| report.write(&mut file, format))
             }
+            None => report.write(&mut io::stderr(), args.stats.unwrap_or_default()),
+        };
+        if let Err(e) = result {
+            eprintln!("stelp: failed to write stats: {}", e);
         }
+    }
 
-        // Keys seen (if any structured data was processed)
-        if !total_stats.keys_seen.is_empty() {
-            let mut keys: Vec<_> = total_stats.keys_seen.iter().cloned().collect();
-            keys.sort();
-            eprintln!("Keys seen: {}", keys.join(","));
-        }
-
-        // Log levels seen (if any were detected)
-        if !total_stats.levels_seen.is_empty() {
-            let mut levels: Vec<_> = total_stats.levels_seen.iter().collect();
-            levels.sort_by_key(|(level, _)| level.as_str());
-            let level_summary: Vec<String> = levels
-                .iter()
-                .map(|(level, key)| format!("{} (keys: {})", level, key))
-                .collect();
-            eprintln!("Log levels seen: {}", level_summary.join(", "));
-        }
-
-        // Performance details
-        eprintln!(
-            "Performance: {} records processed, {} output, {} skipped, {} errors in {:.2}ms ({:.0} records/s)",
-            total_stats.records_processed,
-            total_stats.records_output,
-            total_stats.records_skipped,
-            total_stats.errors,
-            processing_ms,
-            records_per_sec
-        );
+    if let Some(profiler) = pipeline.get_profiler() {
+        if let Err(e) = profiler.write_report(&mut io::stderr()) {
+            eprintln!("stelp: failed to write profile: {}", e);
+        }
     }
 
     // Determine exit code based on results
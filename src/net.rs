@@ -0,0 +1,198 @@
+// src/net.rs
+//! IP address and CIDR helpers behind `ip_in()`, `is_private()`,
+//! `cidr_set()` and friends.
+
+use std::net::IpAddr;
+
+/// Parse an address, accepting IPv4-mapped IPv6 (`::ffff:1.2.3.4`) as IPv4
+pub fn parse_ip(text: &str) -> Option<IpAddr> {
+    let ip: IpAddr = text.trim().parse().ok()?;
+    Some(match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        v4 => v4,
+    })
+}
+
+/// Address as an integer plus its bit width (32 or 128)
+fn ip_bits(ip: IpAddr) -> (u128, u8) {
+    match ip {
+        IpAddr::V4(v4) => (u32::from(v4) as u128, 32),
+        IpAddr::V6(v6) => (u128::from(v6), 128),
+    }
+}
+
+pub fn ip_to_int(ip: IpAddr) -> u128 {
+    ip_bits(ip).0
+}
+
+/// A network such as `10.0.0.0/8` or `fe80::/10`; a bare address is a /32 or /128
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    first: u128,
+    last: u128,
+    width: u8,
+}
+
+impl Cidr {
+    pub fn parse(text: &str) -> Result<Cidr, String> {
+        let text = text.trim();
+        let (addr, prefix) = match text.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (text, None),
+        };
+        let ip = parse_ip(addr).ok_or_else(|| format!("invalid network '{}'", text))?;
+        let (bits, width) = ip_bits(ip);
+        // A mapped IPv6 prefix like ::ffff:10.0.0.0/104 counts from the IPv6 width
+        let offset = if width == 32 && addr.contains(':') {
+            96
+        } else {
+            0
+        };
+        let prefix = match prefix {
+            Some(p) => p
+                .parse::<u8>()
+                .ok()
+                .and_then(|p| p.checked_sub(offset))
+                .filter(|&p| p <= width)
+                .ok_or_else(|| format!("invalid prefix length in '{}'", text))?,
+            None => width,
+        };
+        let host_mask = if prefix == 0 {
+            u128::MAX >> (128 - width as u32)
+        } else {
+            (1u128 << (width - prefix)) - 1
+        };
+        Ok(Cidr {
+            first: bits & !host_mask,
+            last: bits | host_mask,
+            width,
+        })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let (bits, width) = ip_bits(ip);
+        width == self.width && self.first <= bits && bits <= self.last
+    }
+}
+
+/// Many networks merged into sorted, non-overlapping ranges for
+/// O(log n) membership tests
+#[derive(Debug, Clone, Default)]
+pub struct CidrSet {
+    v4: Vec<(u128, u128)>,
+    v6: Vec<(u128, u128)>,
+}
+
+impl CidrSet {
+    pub fn new<'a>(networks: impl IntoIterator<Item = &'a str>) -> Result<CidrSet, String> {
+        let mut set = CidrSet::default();
+        for network in networks {
+            let cidr = Cidr::parse(network)?;
+            let ranges = if cidr.width == 32 {
+                &mut set.v4
+            } else {
+                &mut set.v6
+            };
+            ranges.push((cidr.first, cidr.last));
+        }
+        merge_ranges(&mut set.v4);
+        merge_ranges(&mut set.v6);
+        Ok(set)
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let (bits, width) = ip_bits(ip);
+        let ranges = if width == 32 { &self.v4 } else { &self.v6 };
+        // Last range starting at or before the address
+        let index = ranges.partition_point(|&(first, _)| first <= bits);
+        index > 0 && bits <= ranges[index - 1].1
+    }
+
+    /// Number of merged ranges
+    pub fn len(&self) -> usize {
+        self.v4.len() + self.v6.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn merge_ranges(ranges: &mut Vec<(u128, u128)>) {
+    ranges.sort_unstable();
+    let mut merged: Vec<(u128, u128)> = Vec::with_capacity(ranges.len());
+    for &(first, last) in ranges.iter() {
+        match merged.last_mut() {
+            Some(previous) if first <= previous.1.saturating_add(1) => {
+                previous.1 = previous.1.max(last)
+            }
+            _ => merged.push((first, last)),
+        }
+    }
+    *ranges = merged;
+}
+
+/// Networks that are not globally routable: RFC 1918, loopback, link-local,
+/// shared address space (CGNAT), unique-local and unspecified addresses
+const PRIVATE_NETWORKS: &[&str] = &[
+    "0.0.0.0/32",
+    "10.0.0.0/8",
+    "100.64.0.0/10",
+    "127.0.0.0/8",
+    "169.254.0.0/16",
+    "172.16.0.0/12",
+    "192.168.0.0/16",
+    "::/128",
+    "::1/128",
+    "fc00::/7",
+    "fe80::/10",
+];
+
+pub fn is_private(ip: IpAddr) -> bool {
+    static PRIVATE: once_cell::sync::Lazy<CidrSet> =
+        once_cell::sync::Lazy::new(|| CidrSet::new(PRIVATE_NETWORKS.iter().copied()).unwrap());
+    PRIVATE.contains(ip)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(text: &str) -> IpAddr {
+        parse_ip(text).unwrap()
+    }
+
+    #[test]
+    fn test_cidr_contains() {
+        let net = Cidr::parse("10.1.0.0/16").unwrap();
+        assert!(net.contains(ip("10.1.255.255")));
+        assert!(!net.contains(ip("10.2.0.0")));
+        assert!(net.contains(ip("::ffff:10.1.2.3")));
+        assert!(Cidr::parse("0.0.0.0/0").unwrap().contains(ip("8.8.8.8")));
+        assert!(!Cidr::parse("::/0").unwrap().contains(ip("8.8.8.8")));
+        assert!(Cidr::parse("2001:db8::/32")
+            .unwrap()
+            .contains(ip("2001:db8::1")));
+        assert!(Cidr::parse("10.0.0.0/33").is_err());
+        assert!(Cidr::parse("bogus/8").is_err());
+    }
+
+    #[test]
+    fn test_cidr_set_merges_ranges() {
+        let set = CidrSet::new(["10.0.0.0/9", "10.128.0.0/9", "192.168.1.7", "fe80::/10"]).unwrap();
+        assert_eq!(set.len(), 3);
+        assert!(set.contains(ip("10.200.0.1")));
+        assert!(set.contains(ip("192.168.1.7")));
+        assert!(!set.contains(ip("192.168.1.8")));
+        assert!(set.contains(ip("fe80::1")));
+        assert!(!set.contains(ip("11.0.0.0")));
+    }
+
+    #[test]
+    fn test_is_private() {
+        assert!(is_private(ip("172.20.1.1")));
+        assert!(is_private(ip("fd00::1")));
+        assert!(!is_private(ip("172.32.0.1")));
+        assert!(!is_private(ip("2606:4700::1111")));
+    }
+}
@@ -45,6 +45,17 @@ impl std::str::FromStr for OutputFormat {
 
 
 impl OutputFormat {
+    /// Infer output format from a file extension
+    pub fn from_extension(path: &std::path::Path) -> Option<OutputFormat> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "jsonl" | "ndjson" => Some(OutputFormat::Jsonl),
+            "csv" => Some(OutputFormat::Csv),
+            "tsv" => Some(OutputFormat::Tsv),
+            "logfmt" => Some(OutputFormat::Logfmt),
+            _ => None,
+        }
+    }
+
     /// Check if this output format needs nested data to be flattened
     pub fn needs_flattening(&self) -> bool {
         matches!(self, 
@@ -381,27 +392,27 @@ impl OutputFormatter {
             writeln!(output, "{}", formatted)?;
         } else {
             // Normal logfmt mode - fast path when no keys specified
-            if self.keys.is_none() {
-                let formatted = formatter.format_record(&filtered_record);
-                writeln!(output, "{}", formatted)?;
-            } else {
+            if let Some(keys) = &self.keys {
                 // Determine effective key order for this record when keys are specified
-                let key_order = if let RecordData::Structured(data) = &filtered_record {
-                    if let serde_json::Value::Object(obj) = data {
-                        // Filter key list to only keys that exist in this record
-                        let effective_keys: Vec<String> = self.keys.as_ref().unwrap().iter()
-                            .filter(|key| obj.contains_key(*key))
-                            .cloned()
-                            .collect();
-                        Some(effective_keys)
-                    } else {
-                        None
-                    }
+                let key_order = if let RecordData::Structured(serde_json::Value::Object(obj)) =
+                    &filtered_record
+                {
+                    // Filter key list to only keys that exist in this record
+                    let effective_keys: Vec<String> = keys
+                        .iter()
+                        .filter(|key| obj.contains_key(*key))
+                        .cloned()
+                        .collect();
+                    Some(effective_keys)
                 } else {
                     None
                 };
-                
-                let formatted = formatter.format_record_with_key_order(&filtered_record, key_order.as_ref().map(|k| k.as_slice()));
+
+                let formatted =
+                    formatter.format_record_with_key_order(&filtered_record, key_order.as_deref());
+                writeln!(output, "{}", formatted)?;
+            } else {
+                let formatted = formatter.format_record(&filtered_record);
                 writeln!(output, "{}", formatted)?;
             }
         }
@@ -0,0 +1,176 @@
+// src/output_tee.rs
+//! Additional output sinks (`--tee 'path:format[:filter-expr]'`).
+//!
+//! Every record written to the main output is also offered to each tee sink.
+//! A sink has its own formatter (and therefore its own CSV header state), an
+//! optional key selection and an optional filter expression.
+
+use crate::error::ProcessingError;
+use crate::output_format::{OutputFormat, OutputFormatter};
+use crate::pipeline::context::{ProcessResult, RecordContext, RecordData};
+use crate::pipeline::processors::FilterProcessor;
+use crate::pipeline::stream::RecordProcessor;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+/// Parsed `--tee` specification
+#[derive(Debug, Clone, PartialEq)]
+pub struct TeeSpec {
+    pub path: PathBuf,
+    pub format: OutputFormat,
+    pub keys: Option<Vec<String>>,
+    pub filter: Option<String>,
+}
+
+impl TeeSpec {
+    /// Parse `path:format[:filter-expr]`.
+    ///
+    /// The format may carry a key selection: `csv[ts,level,msg]`. The filter
+    /// expression is everything after the second colon, so it may contain colons.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut parts = spec.splitn(3, ':');
+        let path = parts.next().unwrap_or_default().trim();
+        let format_part = parts
+            .next()
+            .ok_or_else(|| format!("Invalid --tee '{}': expected 'path:format[:filter]'", spec))?
+            .trim();
+        let filter = parts
+            .next()
+            .map(|f| f.trim().to_string())
+            .filter(|f| !f.is_empty());
+
+        if path.is_empty() {
+            return Err(format!("Invalid --tee '{}': missing path", spec));
+        }
+
+        let (format_name, keys) = match format_part.split_once('[') {
+            Some((name, rest)) => {
+                let key_list = rest
+                    .strip_suffix(']')
+                    .ok_or_else(|| format!("Invalid --tee '{}': unclosed '[' in key list", spec))?;
+                let keys: Vec<String> = key_list
+                    .split(',')
+                    .map(|k| k.trim().to_string())
+                    .filter(|k| !k.is_empty())
+                    .collect();
+                (name.trim(), if keys.is_empty() { None } else { Some(keys) })
+            }
+            None => (format_part, None),
+        };
+
+        let format = format_name
+            .parse::<OutputFormat>()
+            .map_err(|e| format!("Invalid --tee '{}': {}", spec, e))?;
+
+        Ok(TeeSpec {
+            path: PathBuf::from(path),
+            format,
+            keys,
+            filter,
+        })
+    }
+}
+
+/// An open tee output
+pub struct TeeSink {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    formatter: OutputFormatter,
+    filter: Option<FilterProcessor>,
+}
+
+impl TeeSink {
+    pub fn open(
+        spec: TeeSpec,
+        remove_keys: Option<Vec<String>>,
+        plain: bool,
+    ) -> Result<Self, String> {
+        let filter = match &spec.filter {
+            Some(expr) => Some(
+                FilterProcessor::from_expression(&format!("tee:{}", spec.path.display()), expr)
+                    .map_err(|e| {
+                        format!("Invalid --tee filter for '{}': {}", spec.path.display(), e)
+                    })?,
+            ),
+            None => None,
+        };
+        let file = File::create(&spec.path).map_err(|e| {
+            format!(
+                "failed to create tee output '{}': {}",
+                spec.path.display(),
+                e
+            )
+        })?;
+
+        Ok(TeeSink {
+            formatter: OutputFormatter::new_with_plain(
+                spec.format,
+                spec.keys,
+                remove_keys,
+                Some(false),
+                plain,
+            ),
+            path: spec.path,
+            writer: BufWriter::with_capacity(65536, file),
+            filter,
+        })
+    }
+
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    /// Write the record if it passes this sink's filter
+    pub fn write_record(
+        &mut self,
+        record: &RecordData,
+        ctx: &RecordContext,
+    ) -> Result<(), ProcessingError> {
+        if let Some(filter) = &mut self.filter {
+            match filter.process(record, ctx) {
+                ProcessResult::Transform(_) => {}
+                ProcessResult::Error(err) => return Err(err),
+                _ => return Ok(()),
+            }
+        }
+        self.formatter.write_record(&mut self.writer, record)
+    }
+
+    pub fn finish(&mut self) -> Result<(), ProcessingError> {
+        self.writer.flush()?;
+        self.formatter.report_csv_warnings();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tee_spec() {
+        let spec = TeeSpec::parse("errors.jsonl:jsonl:data[\"level\"] == \"error\"").unwrap();
+        assert_eq!(spec.path, PathBuf::from("errors.jsonl"));
+        assert_eq!(spec.format, OutputFormat::Jsonl);
+        assert_eq!(spec.keys, None);
+        assert_eq!(spec.filter.as_deref(), Some("data[\"level\"] == \"error\""));
+
+        let spec = TeeSpec::parse("summary.csv:csv[ts, level]").unwrap();
+        assert_eq!(spec.format, OutputFormat::Csv);
+        assert_eq!(spec.keys, Some(vec!["ts".to_string(), "level".to_string()]));
+        assert_eq!(spec.filter, None);
+
+        // Filters may contain colons
+        let spec = TeeSpec::parse("x.jsonl:jsonl:{\"a\": 1}[\"a\"] == 1").unwrap();
+        assert_eq!(spec.filter.as_deref(), Some("{\"a\": 1}[\"a\"] == 1"));
+    }
+
+    #[test]
+    fn test_parse_tee_spec_errors() {
+        assert!(TeeSpec::parse("out.jsonl").is_err());
+        assert!(TeeSpec::parse(":jsonl").is_err());
+        assert!(TeeSpec::parse("out.txt:xml").is_err());
+        assert!(TeeSpec::parse("out.csv:csv[a,b").is_err());
+    }
+}
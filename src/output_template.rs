@@ -0,0 +1,397 @@
+// src/output_template.rs
+//! Partitioned output: route each record to a file computed from its fields.
+//!
+//! A template such as `out/{service}/{ts:%Y-%m-%d}.jsonl` is rendered per record.
+//! `{field}` looks up a (dotted) field, `{field:FORMAT}` parses the field as a
+//! timestamp and formats it with strftime. Use `{{` and `}}` for literal braces.
+
+use crate::error::ProcessingError;
+use crate::output_format::{OutputFormat, OutputFormatter};
+use crate::pipeline::context::RecordData;
+use crate::processors::coerce::parse_datetime;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Placeholder used when a template field is missing or cannot be rendered
+const MISSING_VALUE: &str = "unknown";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    Field {
+        path: Vec<String>,
+        time_format: Option<String>,
+    },
+}
+
+/// Parsed output path template
+#[derive(Debug, Clone)]
+pub struct OutputTemplate {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+impl OutputTemplate {
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut spec = String::new();
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        spec.push(c);
+                    }
+                    if !closed {
+                        return Err(format!("Unclosed '{{' in output template '{}'", template));
+                    }
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Self::parse_field(&spec, template)?);
+                }
+                '}' => {
+                    return Err(format!("Unmatched '}}' in output template '{}'", template));
+                }
+                _ => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        if !segments.iter().any(|s| matches!(s, Segment::Field { .. })) {
+            return Err(format!(
+                "Output template '{}' has no {{field}} placeholders",
+                template
+            ));
+        }
+
+        Ok(OutputTemplate {
+            source: template.to_string(),
+            segments,
+        })
+    }
+
+    fn parse_field(spec: &str, template: &str) -> Result<Segment, String> {
+        let (name, time_format) = match spec.split_once(':') {
+            Some((name, fmt)) => (name.trim(), Some(fmt.to_string())),
+            None => (spec.trim(), None),
+        };
+        if name.is_empty() {
+            return Err(format!(
+                "Empty field name in output template '{}'",
+                template
+            ));
+        }
+        if let Some(fmt) = &time_format {
+            use chrono::format::{Item, StrftimeItems};
+            if StrftimeItems::new(fmt).any(|item| matches!(item, Item::Error)) {
+                return Err(format!(
+                    "Invalid time format '{}' in output template '{}'",
+                    fmt, template
+                ));
+            }
+        }
+        Ok(Segment::Field {
+            path: name.split('.').map(|s| s.to_string()).collect(),
+            time_format,
+        })
+    }
+
+    /// Output format implied by the template's file extension, if any
+    pub fn output_format(&self) -> Option<OutputFormat> {
+        OutputFormat::from_extension(Path::new(&self.source))
+    }
+
+    /// Render the file path for a record
+    pub fn render(&self, record: &RecordData) -> PathBuf {
+        let mut path = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => path.push_str(text),
+                Segment::Field {
+                    path: field,
+                    time_format,
+                } => {
+                    let value = match record {
+                        RecordData::Structured(data) => lookup(data, field),
+                        RecordData::Text(_) => None,
+                    };
+                    let rendered = match (value, time_format) {
+                        (Some(value), Some(fmt)) => parse_timestamp(value)
+                            .map(|dt| dt.format(fmt).to_string())
+                            .unwrap_or_else(|| MISSING_VALUE.to_string()),
+                        (Some(value), None) => sanitize(&value_to_string(value)),
+                        (None, _) => MISSING_VALUE.to_string(),
+                    };
+                    path.push_str(&rendered);
+                }
+            }
+        }
+        PathBuf::from(path)
+    }
+}
+
+fn lookup<'a>(data: &'a serde_json::Value, path: &[String]) -> Option<&'a serde_json::Value> {
+    let mut current = data;
+    for part in path {
+        current = match current {
+            serde_json::Value::Object(obj) => obj.get(part)?,
+            serde_json::Value::Array(arr) => arr.get(part.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    if current.is_null() {
+        None
+    } else {
+        Some(current)
+    }
+}
+
+fn value_to_string(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Make a field value safe to use as a single path component
+fn sanitize(value: &str) -> String {
+    let cleaned: String = value
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || "-_.@+=,".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    match cleaned.as_str() {
+        "" | "." | ".." => "_".to_string(),
+        _ => cleaned,
+    }
+}
+
+fn parse_timestamp(value: &serde_json::Value) -> Option<chrono::DateTime<chrono::Utc>> {
+    match value {
+        serde_json::Value::Number(n) => parse_datetime(&n.to_string()),
+        serde_json::Value::String(s) => parse_datetime(s),
+        _ => None,
+    }
+}
+
+struct OpenFile {
+    writer: BufWriter<File>,
+    last_used: u64,
+}
+
+/// Writes records to files computed from an `OutputTemplate`.
+///
+/// Keeps at most `max_open` files open, closing the least recently used one
+/// when the limit is reached. Each file keeps its `OutputFormatter` while
+/// closed, so that reopening appends without repeating CSV/TSV headers and
+/// its warnings are reported once, by `finish`.
+pub struct PartitionedWriter {
+    template: OutputTemplate,
+    format: OutputFormat,
+    keys: Option<Vec<String>>,
+    remove_keys: Option<Vec<String>>,
+    plain: bool,
+    max_open: usize,
+    open_files: HashMap<PathBuf, OpenFile>,
+    /// Files created by this writer, open or not, with their formatters
+    created: HashMap<PathBuf, OutputFormatter>,
+    tick: u64,
+}
+
+impl PartitionedWriter {
+    pub fn new(
+        template: OutputTemplate,
+        format: OutputFormat,
+        keys: Option<Vec<String>>,
+        remove_keys: Option<Vec<String>>,
+        plain: bool,
+        max_open: usize,
+    ) -> Self {
+        PartitionedWriter {
+            template,
+            format,
+            keys,
+            remove_keys,
+            plain,
+            max_open: max_open.max(1),
+            open_files: HashMap::new(),
+            created: HashMap::new(),
+            tick: 0,
+        }
+    }
+
+    pub fn write_record(&mut self, record: &RecordData) -> Result<(), ProcessingError> {
+        let path = self.template.render(record);
+        self.tick += 1;
+
+        if !self.open_files.contains_key(&path) {
+            self.open(&path)?;
+        }
+
+        let file = self
+            .open_files
+            .get_mut(&path)
+            .expect("partition file was just opened");
+        file.last_used = self.tick;
+        let formatter = self
+            .created
+            .get_mut(&path)
+            .expect("open partition files have a formatter");
+        formatter.write_record(&mut file.writer, record)
+    }
+
+    fn open(&mut self, path: &Path) -> Result<(), ProcessingError> {
+        if self.open_files.len() >= self.max_open {
+            self.evict_least_recently_used()?;
+        }
+
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent).map_err(|e| {
+                    ProcessingError::OutputError(format!(
+                        "failed to create directory '{}': {}",
+                        parent.display(),
+                        e
+                    ))
+                })?;
+            }
+        }
+
+        // Files created earlier in this run are appended to after eviction;
+        // otherwise start fresh like `-o` does.
+        let reopening = self.created.contains_key(path);
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(reopening)
+            .truncate(!reopening)
+            .open(path)
+            .map_err(|e| {
+                ProcessingError::OutputError(format!(
+                    "failed to open output file '{}': {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+
+        if !reopening {
+            let formatter = OutputFormatter::new_with_plain(
+                self.format,
+                self.keys.clone(),
+                self.remove_keys.clone(),
+                Some(false),
+                self.plain,
+            );
+            self.created.insert(path.to_path_buf(), formatter);
+        }
+
+        self.open_files.insert(
+            path.to_path_buf(),
+            OpenFile {
+                writer: BufWriter::with_capacity(65536, file),
+                last_used: self.tick,
+            },
+        );
+        Ok(())
+    }
+
+    fn evict_least_recently_used(&mut self) -> Result<(), ProcessingError> {
+        let oldest = self
+            .open_files
+            .iter()
+            .min_by_key(|(_, file)| file.last_used)
+            .map(|(path, _)| path.clone());
+        if let Some(path) = oldest {
+            if let Some(mut file) = self.open_files.remove(&path) {
+                file.writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of files currently held open
+    pub fn open_file_count(&self) -> usize {
+        self.open_files.len()
+    }
+
+    /// Flush all open files and report CSV warnings per output file
+    pub fn finish(&mut self) -> Result<(), ProcessingError> {
+        for (_, mut file) in self.open_files.drain() {
+            file.writer.flush()?;
+        }
+        let mut paths: Vec<&PathBuf> = self.created.keys().collect();
+        paths.sort();
+        for path in paths {
+            self.created[path].report_csv_warnings();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_render_fields_and_timestamps() {
+        let template = OutputTemplate::parse("out/{service}/{ts:%Y-%m-%d}.jsonl").unwrap();
+        let record = RecordData::Structured(json!({
+            "service": "api",
+            "ts": "2024-03-05T10:00:00Z"
+        }));
+        assert_eq!(
+            template.render(&record),
+            PathBuf::from("out/api/2024-03-05.jsonl")
+        );
+        assert_eq!(template.output_format(), Some(OutputFormat::Jsonl));
+    }
+
+    #[test]
+    fn test_render_missing_and_unsafe_values() {
+        let template = OutputTemplate::parse("{user.name}-{zone}.log").unwrap();
+        let record = RecordData::Structured(json!({"user": {"name": "../etc/passwd"}}));
+        assert_eq!(
+            template.render(&record),
+            PathBuf::from(".._etc_passwd-unknown.log")
+        );
+        let record = RecordData::Structured(json!({"user": {"name": ".."}}));
+        assert_eq!(template.render(&record), PathBuf::from("_-unknown.log"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(OutputTemplate::parse("out.jsonl").is_err());
+        assert!(OutputTemplate::parse("out/{service.jsonl").is_err());
+        assert!(OutputTemplate::parse("out/{}.jsonl").is_err());
+        assert!(OutputTemplate::parse("{ts:%Q}.jsonl").is_err());
+        let template = OutputTemplate::parse("{{x}}-{a}").unwrap();
+        let record = RecordData::Structured(json!({"a": 1}));
+        assert_eq!(template.render(&record), PathBuf::from("{x}-1"));
+    }
+}
@@ -1,8 +1,10 @@
 //! Pipeline module: entry point for submodules
 pub mod config;
 pub mod context;
+pub mod fstring;
 pub mod glob_dict;
 pub mod global_functions;
 pub mod levelmap;
+pub mod module_loader;
 pub mod processors;
 pub mod stream;
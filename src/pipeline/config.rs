@@ -20,6 +20,7 @@ pub struct PipelineConfig {
     pub remove_keys: Option<Vec<String>>,
     pub color_preference: Option<bool>, // None = auto-detect, Some(true/false) = forced
     pub plain: bool,                    // Print only values, not keys
+    pub glob_limit: Option<usize>,      // Max `glob` keys, evicting least recently used
 }
 
 impl Default for PipelineConfig {
@@ -36,6 +37,7 @@ impl Default for PipelineConfig {
             remove_keys: None,
             color_preference: None, // Auto-detect
             plain: false,
+            glob_limit: None,
         }
     }
 }
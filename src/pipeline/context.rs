@@ -2,7 +2,7 @@ use crate::error::ProcessingError;
 use crate::variables::GlobalVariables;
 use serde_json;
 use std::cell::RefCell;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::time::Duration;
 
 /// A record that flows through the pipeline - either text or structured data
@@ -106,7 +106,85 @@ pub struct ProcessingStats {
     pub lines_seen: usize, // Total input lines (including unparseable ones)
 }
 
+/// Per-step counters, keyed by `RecordProcessor::name()`
+#[derive(Debug, Clone, Default)]
+pub struct ProcessorStats {
+    pub name: String,
+    pub records_in: usize,
+    pub records_out: usize,
+    pub records_skipped: usize,
+    pub errors: usize,
+    pub time: Duration,
+    /// Processor-specific counts, see `RecordProcessor::counters`
+    pub counters: BTreeMap<String, usize>,
+}
+
+impl ProcessorStats {
+    pub fn new(name: &str) -> Self {
+        ProcessorStats {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Count the outcome of one call to the processor
+    pub fn record_result(&mut self, result: &ProcessResult, elapsed: Duration) {
+        self.records_in += 1;
+        self.time += elapsed;
+        match result {
+            ProcessResult::Transform(_) => self.records_out += 1,
+            ProcessResult::FanOut(records) => self.records_out += records.len(),
+            ProcessResult::TransformWithEmissions { primary, emissions } => {
+                self.records_out += primary.is_some() as usize + emissions.len();
+            }
+            ProcessResult::Skip => self.records_skipped += 1,
+            ProcessResult::Exit { data, .. } => self.records_out += data.is_some() as usize,
+            ProcessResult::Error(_) => self.errors += 1,
+        }
+    }
+}
+
 impl ProcessingStats {
+    /// Add another (per-file) stats object into this one
+    pub fn merge(&mut self, other: &ProcessingStats) {
+        self.records_processed += other.records_processed;
+        self.records_output += other.records_output;
+        self.records_skipped += other.records_skipped;
+        self.errors += other.errors;
+        self.processing_time += other.processing_time;
+        self.parse_errors.extend(other.parse_errors.iter().cloned());
+        self.lines_seen += other.lines_seen;
+        self.keys_seen.extend(other.keys_seen.iter().cloned());
+        self.levels_seen.extend(
+            other
+                .levels_seen
+                .iter()
+                .map(|(level, key)| (level.clone(), key.clone())),
+        );
+        if let Some(earliest) = other.earliest_timestamp {
+            self.update_timestamp_range(earliest);
+        }
+        if let Some(latest) = other.latest_timestamp {
+            self.update_timestamp_range(latest);
+        }
+    }
+
+    /// Fill this per-file stats object from the pipeline's cumulative stats.
+    ///
+    /// Record counters are taken as the difference to `before` (a snapshot
+    /// taken when the file started). The structured-data summaries are moved
+    /// out of `pipeline_stats`, so they start empty for the next file.
+    pub fn take_file_delta(&mut self, before: &ProcessingStats, pipeline_stats: &mut ProcessingStats) {
+        self.records_processed += pipeline_stats.records_processed - before.records_processed;
+        self.records_output += pipeline_stats.records_output - before.records_output;
+        self.records_skipped += pipeline_stats.records_skipped - before.records_skipped;
+        self.errors += pipeline_stats.errors - before.errors;
+        self.earliest_timestamp = pipeline_stats.earliest_timestamp.take();
+        self.latest_timestamp = pipeline_stats.latest_timestamp.take();
+        self.keys_seen = std::mem::take(&mut pipeline_stats.keys_seen);
+        self.levels_seen = std::mem::take(&mut pipeline_stats.levels_seen);
+    }
+
     /// Update stats with structured data record
     pub fn update_with_structured_data(&mut self, data: &serde_json::Value) {
         use crate::pipeline::config::{TIMESTAMP_KEYS, LEVEL_KEYS};
@@ -225,6 +303,14 @@ impl PipelineContext {
             file_name: None,
         }
     }
+
+    /// Bound `glob` to `limit` keys (see `GlobalVariables::with_limit`)
+    pub fn with_glob_limit(limit: Option<usize>) -> Self {
+        PipelineContext {
+            global_vars: limit.map_or_else(GlobalVariables::new, GlobalVariables::with_limit),
+            ..Self::new()
+        }
+    }
 }
 
 // Thread-local storage for parsed data (add this to your existing thread-locals)
@@ -0,0 +1,887 @@
+// src/pipeline/fstring.rs
+//! F-string preprocessing.
+//!
+//! Starlark's native f-strings only accept plain identifiers (`f"{name}"`).
+//! This stage rewrites f-strings that use anything else (subscripts, method
+//! calls, arithmetic, `!r`/`!s` conversions or format specs) into ordinary
+//! string concatenation before the script is parsed:
+//!
+//! ```text
+//! f"User: {data['user']} took {ms / 1000:.2f}s"
+//! ("User: " + format_value((data['user'])) + " took " + format_value((ms / 1000), ".2f") + "s")
+//! ```
+//!
+//! F-strings that Starlark already understands are left untouched. The
+//! rewrite never adds or removes lines, and a per-line column map translates
+//! positions in error messages back to the original source.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+/// Result of preprocessing a script
+#[derive(Debug, Clone)]
+pub struct FStringRewrite {
+    pub source: String,
+    original_lines: Vec<String>,
+    /// For each line of `source`, the original column of every character
+    column_map: Vec<Vec<usize>>,
+    rewritten: bool,
+}
+
+#[derive(Debug)]
+enum Part {
+    /// Literal characters with their indices in the original source
+    Literal(Vec<(char, usize)>),
+    Field {
+        expr: (usize, usize),
+        conversion: Option<char>,
+        spec: Option<Vec<Part>>,
+    },
+}
+
+struct Emitter<'a> {
+    cols: &'a [usize],
+    out: String,
+    column_map: Vec<Vec<usize>>,
+}
+
+impl Emitter<'_> {
+    fn push_original(&mut self, c: char, index: usize) {
+        self.out.push(c);
+        if c == '\n' {
+            self.column_map.push(Vec::new());
+        } else {
+            self.column_map.last_mut().unwrap().push(self.cols[index]);
+        }
+    }
+
+    /// Push generated text, attributing it to the original character at `anchor`
+    fn push_generated(&mut self, text: &str, anchor: usize) {
+        let col = self.cols[anchor.min(self.cols.len().saturating_sub(1))];
+        for c in text.chars() {
+            self.out.push(c);
+            self.column_map.last_mut().unwrap().push(col);
+        }
+    }
+}
+
+/// Rewrite f-strings that Starlark cannot handle natively
+pub fn preprocess_fstrings(script: &str) -> FStringRewrite {
+    let chars: Vec<char> = script.chars().collect();
+    let mut cols = Vec::with_capacity(chars.len());
+    let mut col = 0;
+    for &c in &chars {
+        cols.push(col);
+        col = if c == '\n' { 0 } else { col + 1 };
+    }
+
+    let mut emitter = Emitter {
+        cols: &cols,
+        out: String::with_capacity(script.len()),
+        column_map: vec![Vec::new()],
+    };
+    let mut rewritten = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '#' {
+            while i < chars.len() && chars[i] != '\n' {
+                emitter.push_original(chars[i], i);
+                i += 1;
+            }
+        } else if c == '"' || c == '\'' {
+            let end = skip_string(&chars, i).unwrap_or(chars.len());
+            for (j, &c) in chars.iter().enumerate().take(end).skip(i) {
+                emitter.push_original(c, j);
+            }
+            i = end;
+        } else if c.is_alphanumeric() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            let is_prefix =
+                i < chars.len() && (chars[i] == '"' || chars[i] == '\'') && is_string_prefix(&word);
+
+            if is_prefix && word.to_lowercase().contains('f') {
+                let raw = word.to_lowercase().contains('r');
+                if let Some((parts, end)) = parse_fstring(&chars, i) {
+                    if needs_rewrite(&chars, &parts) {
+                        emit_fstring(&mut emitter, &chars, &parts, start, i, raw);
+                        rewritten = true;
+                        i = end;
+                        continue;
+                    }
+                }
+            }
+
+            for (j, &c) in chars.iter().enumerate().take(i).skip(start) {
+                emitter.push_original(c, j);
+            }
+            if is_prefix {
+                let end = skip_string(&chars, i).unwrap_or(chars.len());
+                for (j, &c) in chars.iter().enumerate().take(end).skip(i) {
+                    emitter.push_original(c, j);
+                }
+                i = end;
+            }
+        } else {
+            emitter.push_original(c, i);
+            i += 1;
+        }
+    }
+
+    FStringRewrite {
+        source: emitter.out,
+        original_lines: script.split('\n').map(|l| l.to_string()).collect(),
+        column_map: emitter.column_map,
+        rewritten,
+    }
+}
+
+fn is_string_prefix(word: &str) -> bool {
+    let lower = word.to_lowercase();
+    matches!(lower.as_str(), "r" | "b" | "f" | "rb" | "br" | "fr" | "rf")
+}
+
+/// Index just past the string literal starting at the quote at `start`
+fn skip_string(chars: &[char], start: usize) -> Option<usize> {
+    let quote = chars[start];
+    let triple = chars.get(start + 1) == Some(&quote) && chars.get(start + 2) == Some(&quote);
+    let mut i = if triple { start + 3 } else { start + 1 };
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\\' {
+            i += 2;
+            continue;
+        }
+        if c == quote {
+            if !triple {
+                return Some(i + 1);
+            }
+            if chars.get(i + 1) == Some(&quote) && chars.get(i + 2) == Some(&quote) {
+                return Some(i + 3);
+            }
+        }
+        if c == '\n' && !triple {
+            return None;
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Parse the f-string whose opening quote is at `start`.
+///
+/// Returns the parts and the index just past the closing quote, or `None`
+/// for anything malformed, which is then left for Starlark to report.
+fn parse_fstring(chars: &[char], start: usize) -> Option<(Vec<Part>, usize)> {
+    let quote = chars[start];
+    let triple = chars.get(start + 1) == Some(&quote) && chars.get(start + 2) == Some(&quote);
+    let mut i = if triple { start + 3 } else { start + 1 };
+    let mut parts = Vec::new();
+    let mut literal = Vec::new();
+
+    loop {
+        let c = *chars.get(i)?;
+        if c == quote
+            && (!triple || (chars.get(i + 1) == Some(&quote) && chars.get(i + 2) == Some(&quote)))
+        {
+            if !literal.is_empty() {
+                parts.push(Part::Literal(literal));
+            }
+            return Some((parts, if triple { i + 3 } else { i + 1 }));
+        }
+        match c {
+            '\\' => {
+                literal.push((c, i));
+                literal.push((*chars.get(i + 1)?, i + 1));
+                i += 2;
+                continue;
+            }
+            '\n' if !triple => return None,
+            '{' if chars.get(i + 1) == Some(&'{') => {
+                literal.push(('{', i));
+                i += 2;
+                continue;
+            }
+            '}' if chars.get(i + 1) == Some(&'}') => {
+                literal.push(('}', i));
+                i += 2;
+                continue;
+            }
+            '}' => return None,
+            '{' => {
+                if !literal.is_empty() {
+                    parts.push(Part::Literal(std::mem::take(&mut literal)));
+                }
+                let (field, end) = parse_field(chars, i + 1, quote, triple, true)?;
+                parts.push(field);
+                i = end;
+                continue;
+            }
+            _ => literal.push((c, i)),
+        }
+        i += 1;
+    }
+}
+
+/// Parse a replacement field starting just after its `{`.
+///
+/// Returns the field and the index just past its closing `}`.
+fn parse_field(
+    chars: &[char],
+    start: usize,
+    quote: char,
+    triple: bool,
+    allow_spec: bool,
+) -> Option<(Part, usize)> {
+    let mut depth = 0;
+    let mut i = start;
+
+    let expr_end = loop {
+        let c = *chars.get(i)?;
+        match c {
+            '"' | '\'' => {
+                if c == quote && !triple {
+                    return None;
+                }
+                i = skip_string(chars, i)?;
+                continue;
+            }
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' => depth -= 1,
+            '}' if depth > 0 => depth -= 1,
+            '}' => break i,
+            '!' if depth == 0 && chars.get(i + 1) != Some(&'=') => break i,
+            ':' if depth == 0 => break i,
+            '\n' if !triple => return None,
+            '#' | '\\' => return None,
+            _ => {}
+        }
+        i += 1;
+    };
+
+    let expr: String = chars[start..expr_end].iter().collect();
+    if expr.trim().is_empty() {
+        return None;
+    }
+
+    i = expr_end;
+    let mut conversion = None;
+    if chars[i] == '!' {
+        let c = *chars.get(i + 1)?;
+        if c != 'r' && c != 's' {
+            return None;
+        }
+        conversion = Some(c);
+        i += 2;
+    }
+
+    let mut spec = None;
+    if chars.get(i) == Some(&':') {
+        if !allow_spec {
+            return None;
+        }
+        i += 1;
+        let mut spec_parts = Vec::new();
+        let mut literal = Vec::new();
+        loop {
+            let c = *chars.get(i)?;
+            match c {
+                '}' => break,
+                '{' => {
+                    if !literal.is_empty() {
+                        spec_parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    let (field, end) = parse_field(chars, i + 1, quote, triple, false)?;
+                    spec_parts.push(field);
+                    i = end;
+                    continue;
+                }
+                '\n' | '\\' => return None,
+                _ if c == quote => return None,
+                _ => literal.push((c, i)),
+            }
+            i += 1;
+        }
+        if !literal.is_empty() {
+            spec_parts.push(Part::Literal(literal));
+        }
+        spec = Some(spec_parts);
+    }
+
+    if chars.get(i) != Some(&'}') {
+        return None;
+    }
+    Some((
+        Part::Field {
+            expr: (start, expr_end),
+            conversion,
+            spec,
+        },
+        i + 1,
+    ))
+}
+
+static IDENTIFIER: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").unwrap());
+
+/// Whether any field goes beyond what native Starlark f-strings support
+fn needs_rewrite(chars: &[char], parts: &[Part]) -> bool {
+    parts.iter().any(|part| match part {
+        Part::Literal(_) => false,
+        Part::Field {
+            expr,
+            conversion,
+            spec,
+        } => {
+            let expr: String = chars[expr.0..expr.1].iter().collect();
+            conversion.is_some() || spec.is_some() || !IDENTIFIER.is_match(&expr)
+        }
+    })
+}
+
+fn emit_fstring(
+    emitter: &mut Emitter,
+    chars: &[char],
+    parts: &[Part],
+    prefix_start: usize,
+    quote_index: usize,
+    raw: bool,
+) {
+    let quote = chars[quote_index];
+    let triple =
+        chars.get(quote_index + 1) == Some(&quote) && chars.get(quote_index + 2) == Some(&quote);
+    let quotes: String = std::iter::repeat_n(quote, if triple { 3 } else { 1 }).collect();
+    let open_literal = format!("{}{}", if raw { "r" } else { "" }, quotes);
+
+    emitter.push_generated("(", prefix_start);
+    if parts.is_empty() {
+        emitter.push_generated("\"\"", prefix_start);
+    }
+    for (n, part) in parts.iter().enumerate() {
+        if n > 0 {
+            emitter.push_generated(" + ", prefix_start);
+        }
+        match part {
+            Part::Literal(text) => {
+                emitter.push_generated(&open_literal, text[0].1);
+                for (k, &(c, index)) in text.iter().enumerate() {
+                    // A quote right before the closing triple quote would end it early
+                    if triple && !raw && c == quote && k == text.len() - 1 {
+                        emitter.push_generated("\\", index);
+                    }
+                    emitter.push_original(c, index);
+                }
+                emitter.push_generated(&quotes, text[text.len() - 1].1);
+            }
+            Part::Field {
+                expr,
+                conversion,
+                spec,
+            } => {
+                // `!s` is the default. Use format_value() rather than str(),
+                // which returns strings quoted.
+                let (open, close) = match (conversion, spec.is_some()) {
+                    (Some('r'), false) => ("repr((", "))"),
+                    (Some('r'), true) => ("format_value(repr((", ")), "),
+                    (_, false) => ("format_value((", "))"),
+                    (_, true) => ("format_value((", "), "),
+                };
+                emitter.push_generated(open, expr.0);
+                for (index, &c) in chars.iter().enumerate().take(expr.1).skip(expr.0) {
+                    emitter.push_original(c, index);
+                }
+                emitter.push_generated(close, expr.1);
+                if let Some(spec) = spec {
+                    emit_spec(emitter, chars, spec, expr.1);
+                    emitter.push_generated(")", expr.1);
+                }
+            }
+        }
+    }
+    emitter.push_generated(")", prefix_start);
+}
+
+/// Emit a format spec as a Starlark string expression
+fn emit_spec(emitter: &mut Emitter, chars: &[char], spec: &[Part], anchor: usize) {
+    if spec.is_empty() {
+        emitter.push_generated("\"\"", anchor);
+        return;
+    }
+    let nested = spec.len() > 1;
+    if nested {
+        emitter.push_generated("(", anchor);
+    }
+    for (n, part) in spec.iter().enumerate() {
+        if n > 0 {
+            emitter.push_generated(" + ", anchor);
+        }
+        match part {
+            Part::Literal(text) => {
+                let escaped: String = text
+                    .iter()
+                    .map(|&(c, _)| match c {
+                        '"' => "\\\"".to_string(),
+                        '\\' => "\\\\".to_string(),
+                        c => c.to_string(),
+                    })
+                    .collect();
+                emitter.push_generated(&format!("\"{}\"", escaped), text[0].1);
+            }
+            Part::Field { expr, .. } => {
+                emitter.push_generated("format_value((", expr.0);
+                for (index, &c) in chars.iter().enumerate().take(expr.1).skip(expr.0) {
+                    emitter.push_original(c, index);
+                }
+                emitter.push_generated("))", expr.1);
+            }
+        }
+    }
+    if nested {
+        emitter.push_generated(")", anchor);
+    }
+}
+
+static LOCATION: Lazy<Regex> = Lazy::new(|| Regex::new(r"(--> [^:\s]+):(\d+):(\d+)").unwrap());
+static SNIPPET: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\s*)(\d+)( \| ?)(.*)$").unwrap());
+static FRAME: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\s*\* [^:\s]+:(\d+), in ").unwrap());
+static CARETS: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\s*\| ?)(\s*)(\^+)\s*$").unwrap());
+
+impl FStringRewrite {
+    /// Whether any f-string was rewritten
+    pub fn is_rewritten(&self) -> bool {
+        self.rewritten
+    }
+
+    /// Map the positions in a parse error back to the original source
+    pub fn map_starlark_error(&self, error: starlark::Error) -> starlark::Error {
+        if self.rewritten {
+            starlark::Error::new_other(anyhow::anyhow!(self.map_error(&error.to_string())))
+        } else {
+            error
+        }
+    }
+
+    /// Map a 1-based line/column in the rewritten source to the original column
+    pub fn map_column(&self, line: usize, col: usize) -> usize {
+        let Some(cols) = line.checked_sub(1).and_then(|l| self.column_map.get(l)) else {
+            return col;
+        };
+        match col.checked_sub(1) {
+            Some(c) if c < cols.len() => cols[c] + 1,
+            Some(c) => cols
+                .last()
+                .map_or(col, |&last| last + 1 + (c - cols.len()) + 1),
+            None => col,
+        }
+    }
+
+    /// Translate positions and code snippets in a Starlark error message
+    /// from the rewritten source back to the original one
+    pub fn map_error(&self, message: &str) -> String {
+        if !self.rewritten {
+            return message.to_string();
+        }
+
+        let mut mapped = Vec::new();
+        let mut snippet_line: Option<usize> = None;
+        let mut frame_line: Option<usize> = None;
+        for line in message.split('\n') {
+            // Traceback frames are followed by the source line, indented
+            if let Some(line_no) = frame_line.take() {
+                if let Some(original) = line_no
+                    .checked_sub(1)
+                    .and_then(|l| self.original_lines.get(l))
+                {
+                    let indent = &line[..line.len() - line.trim_start().len()];
+                    mapped.push(format!("{}{}", indent, original.trim()));
+                    continue;
+                }
+            }
+            if let Some(caps) = FRAME.captures(line) {
+                frame_line = caps[1].parse().ok();
+                mapped.push(line.to_string());
+                continue;
+            }
+            if let Some(caps) = LOCATION.captures(line) {
+                let line_no: usize = caps[2].parse().unwrap_or(0);
+                let col: usize = caps[3].parse().unwrap_or(0);
+                let replaced =
+                    format!("{}:{}:{}", &caps[1], line_no, self.map_column(line_no, col));
+                mapped.push(LOCATION.replace(line, replaced.as_str()).into_owned());
+                continue;
+            }
+            if let Some(caps) = SNIPPET.captures(line) {
+                let line_no: usize = caps[2].parse().unwrap_or(0);
+                if let Some(original) = line_no
+                    .checked_sub(1)
+                    .and_then(|l| self.original_lines.get(l))
+                {
+                    // Keep any span markers drawn before the code
+                    let code = &caps[4];
+                    let rewritten = self.source.split('\n').nth(line_no - 1).unwrap_or_default();
+                    let gutter_len = code.len().saturating_sub(rewritten.len());
+                    mapped.push(format!(
+                        "{}{}{}{}{}",
+                        &caps[1],
+                        &caps[2],
+                        &caps[3],
+                        &code[..gutter_len.min(code.len())],
+                        original
+                    ));
+                    snippet_line = Some(line_no);
+                    continue;
+                }
+            }
+            if let (Some(line_no), Some(caps)) = (snippet_line, CARETS.captures(line)) {
+                let start = caps[2].chars().count() + 1;
+                let end = start + caps[3].len() - 1;
+                let new_start = self.map_column(line_no, start);
+                let new_end = self.map_column(line_no, end).max(new_start);
+                mapped.push(format!(
+                    "{}{}{}",
+                    &caps[1],
+                    " ".repeat(new_start - 1),
+                    "^".repeat(new_end - new_start + 1)
+                ));
+                snippet_line = None;
+                continue;
+            }
+            snippet_line = None;
+            mapped.push(line.to_string());
+        }
+        mapped.join("\n")
+    }
+}
+
+/// Parsed Python-style format spec: `[[fill]align][sign][#][0][width][,|_][.precision][type]`
+#[derive(Debug, Default)]
+struct FormatSpec {
+    fill: Option<char>,
+    align: Option<char>,
+    sign: Option<char>,
+    alternate: bool,
+    zero: bool,
+    width: usize,
+    grouping: Option<char>,
+    precision: Option<usize>,
+    kind: Option<char>,
+}
+
+static SPEC: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(?:(.)?([<>^=]))?([+\- ])?(#)?(0)?(\d+)?([,_])?(?:\.(\d+))?([bcdeEfFgGnosxX%])?$")
+        .unwrap()
+});
+
+impl FormatSpec {
+    fn parse(spec: &str) -> Result<Self, String> {
+        let caps = SPEC
+            .captures(spec)
+            .ok_or_else(|| format!("Invalid format specifier '{}'", spec))?;
+        let char_at = |n: usize| caps.get(n).and_then(|m| m.as_str().chars().next());
+        Ok(FormatSpec {
+            fill: char_at(1),
+            align: char_at(2),
+            sign: char_at(3),
+            alternate: caps.get(4).is_some(),
+            zero: caps.get(5).is_some(),
+            width: caps.get(6).map_or(0, |m| m.as_str().parse().unwrap_or(0)),
+            grouping: char_at(7),
+            precision: caps.get(8).and_then(|m| m.as_str().parse().ok()),
+            kind: char_at(9),
+        })
+    }
+}
+
+/// A value to be formatted with `format_with_spec`
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormatArg {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+}
+
+/// Format a value with a Python format spec (the part after `:` in `{x:.2f}`)
+pub fn format_with_spec(value: &FormatArg, spec: &str) -> Result<String, String> {
+    let spec = FormatSpec::parse(spec)?;
+
+    let (negative, body, numeric) = match (value, spec.kind) {
+        (FormatArg::Str(s), None | Some('s')) => {
+            let text = match spec.precision {
+                Some(p) => s.chars().take(p).collect(),
+                None => s.clone(),
+            };
+            (false, text, false)
+        }
+        (FormatArg::Bool(b), None) => (false, if *b { "True" } else { "False" }.to_string(), false),
+        (FormatArg::Str(_), Some(kind)) => {
+            return Err(format!(
+                "Unknown format code '{}' for value of type 'string'",
+                kind
+            ))
+        }
+        (FormatArg::Int(_) | FormatArg::Bool(_) | FormatArg::Float(_), Some('s')) => {
+            return Err("Unknown format code 's' for a number".to_string())
+        }
+        (FormatArg::Int(_) | FormatArg::Bool(_), Some('c')) => {
+            let n = as_int(value);
+            let c = u32::try_from(n)
+                .ok()
+                .and_then(char::from_u32)
+                .ok_or_else(|| format!("%c arg not in range: {}", n))?;
+            (false, c.to_string(), false)
+        }
+        (
+            FormatArg::Int(_) | FormatArg::Bool(_),
+            None | Some('d' | 'n' | 'x' | 'X' | 'o' | 'b'),
+        ) => {
+            let n = as_int(value);
+            let magnitude = n.unsigned_abs();
+            let digits = match spec.kind {
+                Some('x') => format!("{}{:x}", if spec.alternate { "0x" } else { "" }, magnitude),
+                Some('X') => format!("{}{:X}", if spec.alternate { "0X" } else { "" }, magnitude),
+                Some('o') => format!("{}{:o}", if spec.alternate { "0o" } else { "" }, magnitude),
+                Some('b') => format!("{}{:b}", if spec.alternate { "0b" } else { "" }, magnitude),
+                _ => group_digits(&magnitude.to_string(), spec.grouping),
+            };
+            (n < 0, digits, true)
+        }
+        (FormatArg::Float(_), Some('d' | 'x' | 'X' | 'o' | 'b' | 'c')) => {
+            return Err(format!(
+                "Unknown format code '{}' for value of type 'float'",
+                spec.kind.unwrap()
+            ))
+        }
+        (_, kind) => {
+            let f = match value {
+                FormatArg::Float(f) => *f,
+                other => as_int(other) as f64,
+            };
+            (
+                f.is_sign_negative() && f != 0.0,
+                format_float(f.abs(), kind, &spec),
+                true,
+            )
+        }
+    };
+
+    let sign = match (negative, spec.sign) {
+        (true, _) => "-",
+        (false, Some('+')) if numeric => "+",
+        (false, Some(' ')) if numeric => " ",
+        _ => "",
+    };
+
+    let len = sign.chars().count() + body.chars().count();
+    if len >= spec.width {
+        return Ok(format!("{}{}", sign, body));
+    }
+    let padding = spec.width - len;
+    let (fill, align) = match (spec.fill, spec.align) {
+        (fill, Some(align)) => (fill.unwrap_or(' '), align),
+        (_, None) if spec.zero && numeric => ('0', '='),
+        (_, None) if numeric => (' ', '>'),
+        (_, None) => (' ', '<'),
+    };
+    let pad = |n: usize| std::iter::repeat_n(fill, n).collect::<String>();
+    Ok(match align {
+        '<' => format!("{}{}{}", sign, body, pad(padding)),
+        '^' => format!(
+            "{}{}{}{}",
+            pad(padding / 2),
+            sign,
+            body,
+            pad(padding - padding / 2)
+        ),
+        '=' => format!("{}{}{}", sign, pad(padding), body),
+        _ => format!("{}{}{}", pad(padding), sign, body),
+    })
+}
+
+fn as_int(value: &FormatArg) -> i64 {
+    match value {
+        FormatArg::Int(n) => *n,
+        FormatArg::Bool(b) => *b as i64,
+        FormatArg::Float(f) => *f as i64,
+        FormatArg::Str(_) => 0,
+    }
+}
+
+/// Format a non-negative float for the given presentation type
+fn format_float(f: f64, kind: Option<char>, spec: &FormatSpec) -> String {
+    if f.is_nan() {
+        return "nan".to_string();
+    }
+    if f.is_infinite() {
+        return "inf".to_string();
+    }
+    let fixed = |f: f64, precision: usize| {
+        let text = format!("{:.*}", precision, f);
+        match text.split_once('.') {
+            Some((int, frac)) => format!("{}.{}", group_digits(int, spec.grouping), frac),
+            None => group_digits(&text, spec.grouping),
+        }
+    };
+
+    match kind {
+        Some('f' | 'F') => fixed(f, spec.precision.unwrap_or(6)),
+        Some('%') => format!("{}%", fixed(f * 100.0, spec.precision.unwrap_or(6))),
+        Some('e') => exponent(f, spec.precision.unwrap_or(6)),
+        Some('E') => exponent(f, spec.precision.unwrap_or(6)).to_uppercase(),
+        Some('g' | 'n') => general(f, spec.precision.unwrap_or(6), spec, &fixed),
+        Some('G') => general(f, spec.precision.unwrap_or(6), spec, &fixed).to_uppercase(),
+        _ => match spec.precision {
+            Some(p) => general(f, p, spec, &fixed),
+            None => {
+                // Like str(): always show a fractional part
+                let text = format!("{}", f);
+                let text = if text.contains('.') {
+                    text
+                } else {
+                    format!("{}.0", text)
+                };
+                match text.split_once('.') {
+                    Some((int, frac)) => format!("{}.{}", group_digits(int, spec.grouping), frac),
+                    None => text,
+                }
+            }
+        },
+    }
+}
+
+/// `1.234560e+03` style exponent notation
+fn exponent(f: f64, precision: usize) -> String {
+    let text = format!("{:.*e}", precision, f);
+    let (mantissa, exp) = text.split_once('e').unwrap_or((&text, "0"));
+    let exp: i32 = exp.parse().unwrap_or(0);
+    format!(
+        "{}e{}{:02}",
+        mantissa,
+        if exp < 0 { '-' } else { '+' },
+        exp.abs()
+    )
+}
+
+/// The `g` presentation type: fixed or exponent depending on magnitude
+fn general(
+    f: f64,
+    precision: usize,
+    spec: &FormatSpec,
+    fixed: &dyn Fn(f64, usize) -> String,
+) -> String {
+    let precision = precision.max(1);
+    if f == 0.0 {
+        return if spec.alternate {
+            fixed(0.0, precision - 1)
+        } else {
+            "0".to_string()
+        };
+    }
+    let rounded = exponent(f, precision - 1);
+    let exp: i32 = rounded
+        .split_once('e')
+        .and_then(|(_, e)| e.parse().ok())
+        .unwrap_or(0);
+    let strip = |text: String| {
+        if spec.alternate || !text.contains('.') {
+            text
+        } else {
+            text.trim_end_matches('0').trim_end_matches('.').to_string()
+        }
+    };
+    if exp >= -4 && exp < precision as i32 {
+        strip(fixed(f, (precision as i32 - 1 - exp) as usize))
+    } else {
+        let (mantissa, exp) = rounded.split_once('e').unwrap();
+        format!("{}e{}", strip(mantissa.to_string()), exp)
+    }
+}
+
+fn group_digits(digits: &str, separator: Option<char>) -> String {
+    let Some(separator) = separator else {
+        return digits.to_string();
+    };
+    let mut grouped = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(separator);
+        }
+        grouped.push(c);
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_native_fstrings_are_untouched() {
+        let script = "x = f\"{name} is {age}\"\ny = 'a {b}'";
+        let rewrite = preprocess_fstrings(script);
+        assert!(!rewrite.is_rewritten());
+        assert_eq!(rewrite.source, script);
+    }
+
+    #[test]
+    fn test_rewrites_expressions_and_specs() {
+        let rewrite = preprocess_fstrings("f\"User: {data['user']} took {ms / 1000:.2f}s\"");
+        assert_eq!(
+            rewrite.source,
+            "(\"User: \" + format_value((data['user'])) + \" took \" + format_value((ms / 1000), \".2f\") + \"s\")"
+        );
+
+        let rewrite = preprocess_fstrings("f'{{literal}} {x!r:>{width}}'");
+        assert_eq!(
+            rewrite.source,
+            "('{literal} ' + format_value(repr((x)), (\">\" + format_value((width)))))"
+        );
+    }
+
+    #[test]
+    fn test_strings_and_comments_are_not_rewritten() {
+        let script = "# f\"{a.b}\"\ns = \"f'{x.y}'\"";
+        assert_eq!(preprocess_fstrings(script).source, script);
+    }
+
+    #[test]
+    fn test_map_column() {
+        let rewrite = preprocess_fstrings("x = f\"{a[0]}\" + 1");
+        // `+` is at column 15 in the original source
+        let rewritten_col = rewrite.source.find(" + 1").unwrap() + 2;
+        assert_eq!(rewrite.map_column(1, rewritten_col), 15);
+    }
+
+    #[test]
+    fn test_format_with_spec() {
+        let cases = [
+            (FormatArg::Float(1.23456), ".2f", "1.23"),
+            (FormatArg::Int(42), ">8", "      42"),
+            (FormatArg::Int(42), "<5", "42   "),
+            (FormatArg::Str("ab".into()), "*^6", "**ab**"),
+            (FormatArg::Int(1234567), ",", "1,234,567"),
+            (FormatArg::Int(-42), "06", "-00042"),
+            (FormatArg::Int(255), "#x", "0xff"),
+            (FormatArg::Float(0.256), ".1%", "25.6%"),
+            (FormatArg::Float(1234.5), ".2e", "1.23e+03"),
+            (FormatArg::Float(0.0001234), "g", "0.0001234"),
+            (FormatArg::Float(1234567.0), "g", "1.23457e+06"),
+            (FormatArg::Int(7), "+d", "+7"),
+            (FormatArg::Str("hello".into()), ".3", "hel"),
+        ];
+        for (value, spec, expected) in cases {
+            assert_eq!(
+                format_with_spec(&value, spec).unwrap(),
+                expected,
+                "{}",
+                spec
+            );
+        }
+        assert!(format_with_spec(&FormatArg::Str("x".into()), "d").is_err());
+        assert!(format_with_spec(&FormatArg::Float(1.5), "x").is_err());
+    }
+}
@@ -1,32 +1,453 @@
 // src/pipeline/glob_dict.rs
-use crate::variables::GlobalVariables;
-use starlark::collections::SmallMap;
-use starlark::values::{dict::Dict, Heap, Value};
+//! The `glob` value scripts see: a dict-like view over `GlobalVariables`
+//! that converts only the keys a script touches and writes only those back.
 
-/// Create a glob dictionary that syncs with GlobalVariables
-pub fn create_glob_dict<'v>(heap: &'v Heap, globals: &GlobalVariables) -> Value<'v> {
-    let mut map = SmallMap::new();
+use crate::variables::{GlobalValue, GlobalVariables};
+use allocative::Allocative;
+use starlark::collections::{Hashed, SmallMap};
+use starlark::environment::{Methods, MethodsBuilder, MethodsStatic};
+use starlark::starlark_module;
+use starlark::typing::Ty;
+use starlark::values::dict::{Dict, DictRef};
+use starlark::values::none::NoneType;
+use starlark::values::{
+    starlark_value, AllocValue, Freeze, FreezeError, FreezeResult, Freezer, Heap, NoSerialize,
+    ProvidesStaticType, StarlarkValue, Trace, Value, ValueLike,
+};
+use std::cell::{Cell, RefCell};
+use std::fmt;
 
-    // Pre-populate with existing global variables
-    for key in globals.keys() {
-        let value = globals.get(heap, &key, None);
-        let key_val = heap.alloc(key);
-        map.insert_hashed(key_val.get_hashed().unwrap(), value);
+#[derive(Trace, ProvidesStaticType, NoSerialize, Allocative)]
+pub struct GlobDict<'v> {
+    #[trace(unsafe_ignore)]
+    #[allocative(skip)]
+    globals: &'v GlobalVariables,
+    /// Keys read or written during this record; `None` marks a removal
+    #[allocative(skip)]
+    touched: RefCell<SmallMap<String, Option<Value<'v>>>>,
+    /// What sets, histograms and top-k counters looked like when read, so
+    /// that reading one does not replace it with its plain copy
+    #[trace(unsafe_ignore)]
+    #[allocative(skip)]
+    summaries: RefCell<SmallMap<String, GlobalValue>>,
+    /// `glob.clear()` was called, so untouched stored keys are gone
+    #[trace(unsafe_ignore)]
+    #[allocative(skip)]
+    cleared: Cell<bool>,
+}
+
+impl<'v> GlobDict<'v> {
+    fn new(globals: &'v GlobalVariables) -> Self {
+        GlobDict {
+            globals,
+            touched: RefCell::new(SmallMap::new()),
+            summaries: RefCell::new(SmallMap::new()),
+            cleared: Cell::new(false),
+        }
+    }
+
+    fn lookup(&self, key: &str, heap: &'v Heap) -> Option<Value<'v>> {
+        if let Some(value) = self.touched.borrow().get(key) {
+            return *value;
+        }
+        if self.cleared.get() {
+            return None;
+        }
+        // Remember the materialised value so in-place changes
+        // (glob["seen"].append(x)) are written back too
+        let stored = self.globals.get_value(key)?;
+        let value = stored.to_starlark(heap);
+        if stored.is_summarised() {
+            self.summaries
+                .borrow_mut()
+                .insert(key.to_string(), stored.summary());
+        }
+        self.touched
+            .borrow_mut()
+            .insert(key.to_string(), Some(value));
+        Some(value)
+    }
+
+    fn insert(&self, key: &str, value: Value<'v>) {
+        self.touched
+            .borrow_mut()
+            .insert(key.to_string(), Some(value));
+    }
+
+    fn remove(&self, key: &str, heap: &'v Heap) -> Option<Value<'v>> {
+        let value = self.lookup(key, heap);
+        self.touched.borrow_mut().insert(key.to_string(), None);
+        value
+    }
+
+    fn contains(&self, key: &str) -> bool {
+        match self.touched.borrow().get(key) {
+            Some(value) => value.is_some(),
+            None => !self.cleared.get() && self.globals.contains(key),
+        }
     }
 
-    let dict = Dict::new(map);
-    heap.alloc(dict)
+    /// Stored keys followed by keys added during this record
+    fn keys(&self) -> Vec<String> {
+        let touched = self.touched.borrow();
+        let mut keys: Vec<String> = if self.cleared.get() {
+            Vec::new()
+        } else {
+            self.globals
+                .keys()
+                .into_iter()
+                .filter(|k| !matches!(touched.get(k), Some(None)))
+                .collect()
+        };
+        for (key, value) in touched.iter() {
+            if value.is_some() && (self.cleared.get() || !self.globals.contains(key)) {
+                keys.push(key.clone());
+            }
+        }
+        keys
+    }
+
+    fn items(&self, heap: &'v Heap) -> Vec<(String, Value<'v>)> {
+        self.keys()
+            .into_iter()
+            .filter_map(|k| self.lookup(&k, heap).map(|v| (k, v)))
+            .collect()
+    }
+
+    /// A plain dict with the same entries
+    pub(crate) fn to_dict(&self, heap: &'v Heap) -> Dict<'v> {
+        let mut map = SmallMap::new();
+        for (key, value) in self.items(heap) {
+            let key = heap.alloc_str(&key).get_hashed();
+            map.insert_hashed(
+                Hashed::new_unchecked(key.hash(), key.key().to_value()),
+                value,
+            );
+        }
+        Dict::new(map)
+    }
+
+    /// Set `key` from each entry of a dict, a `glob` or a list of pairs
+    fn update_from(&self, pairs: Value<'v>, heap: &'v Heap) -> anyhow::Result<()> {
+        if let Some(dict) = DictRef::from_value(pairs) {
+            for (key, value) in dict.iter() {
+                self.insert(key_str(key)?, value);
+            }
+        } else if let Some(other) = pairs.downcast_ref::<GlobDict>() {
+            for (key, value) in other.items(heap) {
+                self.insert(&key, value);
+            }
+        } else {
+            for pair in pairs.iterate(heap).map_err(starlark::Error::into_anyhow)? {
+                let mut it = pair.iterate(heap).map_err(starlark::Error::into_anyhow)?;
+                let (Some(key), Some(value), None) = (it.next(), it.next(), it.next()) else {
+                    return Err(anyhow::anyhow!(
+                        "glob.update() expects a dict or a list of pairs"
+                    ));
+                };
+                self.insert(key_str(key)?, value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Write touched keys back to the store
+    fn commit(&self) {
+        if self.cleared.get() {
+            self.globals.clear();
+        }
+        let summaries = self.summaries.borrow();
+        for (key, value) in self.touched.borrow().iter() {
+            match value {
+                Some(value) => {
+                    let value = GlobalValue::from_starlark(*value);
+                    if summaries.get(key) != Some(&value) {
+                        self.globals.set_value(key.clone(), value);
+                    }
+                }
+                None => {
+                    self.globals.remove(key);
+                }
+            }
+        }
+    }
+
+    /// Commit pending changes and forget cached values, so builtins that
+    /// update the store directly and later reads of `glob` agree
+    pub(crate) fn flush(&self) -> &'v GlobalVariables {
+        self.commit();
+        self.touched.borrow_mut().clear();
+        self.summaries.borrow_mut().clear();
+        self.cleared.set(false);
+        self.globals
+    }
 }
 
-/// Update GlobalVariables from a glob dictionary after script execution
-pub fn sync_glob_dict_to_globals(glob_dict: Value<'_>, globals: &GlobalVariables) {
-    use starlark::values::dict::DictRef;
+fn key_str<'v>(key: Value<'v>) -> anyhow::Result<&'v str> {
+    key.unpack_str()
+        .ok_or_else(|| anyhow::anyhow!("glob keys must be strings, got {}", key.get_type()))
+}
 
-    if let Some(dict_ref) = DictRef::from_value(glob_dict) {
-        // Clear existing globals first
-        globals.clear();
+impl fmt::Debug for GlobDict<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GlobDict")
+            .field("keys", &self.keys())
+            .finish()
+    }
+}
+
+impl fmt::Display for GlobDict<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let touched = self.touched.borrow();
+        write!(f, "{{")?;
+        for (i, key) in self.keys().iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{:?}: ", key)?;
+            match touched.get(key) {
+                Some(Some(value)) => write!(f, "{}", value.to_repr())?,
+                _ => match self.globals.get_value(key) {
+                    Some(value) => write!(f, "{}", value.repr())?,
+                    None => write!(f, "None")?,
+                },
+            }
+        }
+        write!(f, "}}")
+    }
+}
+
+impl<'v> AllocValue<'v> for GlobDict<'v> {
+    fn alloc_value(self, heap: &'v Heap) -> Value<'v> {
+        heap.alloc_complex(self)
+    }
+}
 
-        // Copy all dict entries to GlobalVariables
+impl Freeze for GlobDict<'_> {
+    type Frozen = GlobDict<'static>;
+
+    fn freeze(self, _freezer: &Freezer) -> FreezeResult<Self::Frozen> {
+        Err(FreezeError::new(
+            "glob is per-record and cannot be frozen".to_string(),
+        ))
+    }
+}
+
+// Scripts treat `glob` as a dict, so it reports itself as one
+#[starlark_value(type = "dict")]
+impl<'v> StarlarkValue<'v> for GlobDict<'v> {
+    fn get_methods() -> Option<&'static Methods> {
+        static RES: MethodsStatic = MethodsStatic::new();
+        RES.methods(glob_methods)
+    }
+
+    fn at(&self, index: Value<'v>, heap: &'v Heap) -> starlark::Result<Value<'v>> {
+        let key = key_str(index).map_err(starlark::Error::new_other)?;
+        self.lookup(key, heap).ok_or_else(|| {
+            starlark::Error::new_other(anyhow::anyhow!("Key {:?} not found in glob", key))
+        })
+    }
+
+    fn set_at(&self, index: Value<'v>, new_value: Value<'v>) -> starlark::Result<()> {
+        self.insert(
+            key_str(index).map_err(starlark::Error::new_other)?,
+            new_value,
+        );
+        Ok(())
+    }
+
+    fn is_in(&self, other: Value<'v>) -> starlark::Result<bool> {
+        Ok(other.unpack_str().is_some_and(|key| self.contains(key)))
+    }
+
+    fn length(&self) -> starlark::Result<i32> {
+        Ok(self.keys().len() as i32)
+    }
+
+    fn to_bool(&self) -> bool {
+        !self.keys().is_empty()
+    }
+
+    fn iterate_collect(&self, heap: &'v Heap) -> starlark::Result<Vec<Value<'v>>> {
+        Ok(self.keys().into_iter().map(|k| heap.alloc(k)).collect())
+    }
+
+    fn equals(&self, other: Value<'v>) -> starlark::Result<bool> {
+        let Some(other) = DictRef::from_value(other) else {
+            return Ok(false);
+        };
+        let keys = self.keys();
+        if keys.len() != other.len() {
+            return Ok(false);
+        }
+        // Compare stored values without caching them in `touched`
+        let touched = self.touched.borrow();
+        for key in keys {
+            let Some(theirs) = other.get_str(&key) else {
+                return Ok(false);
+            };
+            let equal = match touched.get(&key) {
+                Some(Some(mine)) => mine.equals(theirs)?,
+                _ => match self.globals.get_value(&key) {
+                    Some(stored) => GlobalValue::from_starlark(theirs) == stored.summary(),
+                    None => false,
+                },
+            };
+            if !equal {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// The type `dict()` names in annotations (`def f(x: dict)`). The global
+/// `dict` is replaced so it can copy `glob`; this keeps it usable as a type.
+#[derive(Debug, ProvidesStaticType, NoSerialize, Allocative)]
+pub struct DictType;
+
+impl fmt::Display for DictType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "dict")
+    }
+}
+
+#[starlark_value(type = "dict")]
+impl<'v> StarlarkValue<'v> for DictType {
+    fn get_type_starlark_repr() -> Ty {
+        Ty::dict(Ty::any(), Ty::any())
+    }
+}
+
+#[starlark_module]
+fn glob_methods(builder: &mut MethodsBuilder) {
+    fn get<'v>(
+        this: Value<'v>,
+        key: Value<'v>,
+        default: Option<Value<'v>>,
+        heap: &'v Heap,
+    ) -> anyhow::Result<Value<'v>> {
+        let glob = this.downcast_ref::<GlobDict>().unwrap();
+        let value = key.unpack_str().and_then(|key| glob.lookup(key, heap));
+        Ok(value.or(default).unwrap_or(Value::new_none()))
+    }
+
+    fn keys<'v>(this: Value<'v>) -> anyhow::Result<Vec<String>> {
+        Ok(this.downcast_ref::<GlobDict>().unwrap().keys())
+    }
+
+    fn values<'v>(this: Value<'v>, heap: &'v Heap) -> anyhow::Result<Vec<Value<'v>>> {
+        let glob = this.downcast_ref::<GlobDict>().unwrap();
+        Ok(glob.items(heap).into_iter().map(|(_, v)| v).collect())
+    }
+
+    fn items<'v>(this: Value<'v>, heap: &'v Heap) -> anyhow::Result<Vec<(String, Value<'v>)>> {
+        Ok(this.downcast_ref::<GlobDict>().unwrap().items(heap))
+    }
+
+    fn pop<'v>(
+        this: Value<'v>,
+        key: Value<'v>,
+        default: Option<Value<'v>>,
+        heap: &'v Heap,
+    ) -> anyhow::Result<Value<'v>> {
+        let glob = this.downcast_ref::<GlobDict>().unwrap();
+        let key = key_str(key)?;
+        match glob.remove(key, heap).or(default) {
+            Some(value) => Ok(value),
+            None => Err(anyhow::anyhow!("Key {:?} not found in glob", key)),
+        }
+    }
+
+    fn setdefault<'v>(
+        this: Value<'v>,
+        key: Value<'v>,
+        default: Option<Value<'v>>,
+        heap: &'v Heap,
+    ) -> anyhow::Result<Value<'v>> {
+        let glob = this.downcast_ref::<GlobDict>().unwrap();
+        let key = key_str(key)?;
+        if let Some(value) = glob.lookup(key, heap) {
+            return Ok(value);
+        }
+        let value = default.unwrap_or(Value::new_none());
+        glob.insert(key, value);
+        Ok(value)
+    }
+
+    fn update<'v>(
+        this: Value<'v>,
+        #[starlark(require = pos)] pairs: Option<Value<'v>>,
+        #[starlark(kwargs)] kwargs: DictRef<'v>,
+        heap: &'v Heap,
+    ) -> anyhow::Result<NoneType> {
+        let glob = this.downcast_ref::<GlobDict>().unwrap();
+        if let Some(pairs) = pairs.filter(|pairs| !pairs.ptr_eq(this)) {
+            glob.update_from(pairs, heap)?;
+        }
+        for (key, value) in kwargs.iter() {
+            glob.insert(key_str(key)?, value);
+        }
+        Ok(NoneType)
+    }
+
+    fn clear<'v>(this: Value<'v>) -> anyhow::Result<NoneType> {
+        let glob = this.downcast_ref::<GlobDict>().unwrap();
+        glob.touched.borrow_mut().clear();
+        glob.cleared.set(true);
+        Ok(NoneType)
+    }
+}
+
+/// Create the `glob` value for one script run
+pub fn create_glob_dict<'v>(heap: &'v Heap, globals: &'v GlobalVariables) -> Value<'v> {
+    heap.alloc(GlobDict::new(globals))
+}
+
+/// Whether `value` is a `glob` value
+pub(crate) fn is_glob(value: Value) -> bool {
+    value.downcast_ref::<GlobDict>().is_some()
+}
+
+/// The store behind a `glob` value, with this record's pending changes
+/// committed first; `None` if the script rebound `glob` to something else
+pub(crate) fn glob_store<'v>(glob: Value<'v>) -> Option<&'v GlobalVariables> {
+    glob.downcast_ref::<GlobDict>().map(|glob| glob.flush())
+}
+
+/// The entries of a `glob` value as a JSON object, each converted with
+/// `convert`; `None` if `value` is not `glob`
+pub(crate) fn glob_to_json(
+    value: Value,
+    convert: fn(Value) -> anyhow::Result<serde_json::Value>,
+) -> Option<anyhow::Result<serde_json::Value>> {
+    let glob = value.downcast_ref::<GlobDict>()?;
+    let heap = Heap::new();
+    let touched = glob.touched.borrow();
+    let mut object = serde_json::Map::new();
+    for key in glob.keys() {
+        let converted = match touched.get(&key) {
+            Some(Some(value)) => convert(*value),
+            _ => match glob.globals.get_value(&key) {
+                Some(stored) => convert(stored.to_starlark(&heap)),
+                None => continue,
+            },
+        };
+        match converted {
+            Ok(json) => object.insert(key, json),
+            Err(e) => return Some(Err(e)),
+        };
+    }
+    Some(Ok(serde_json::Value::Object(object)))
+}
+
+/// Update GlobalVariables from `glob` after script execution
+pub fn sync_glob_dict_to_globals(glob_dict: Value<'_>, globals: &GlobalVariables) {
+    if let Some(glob) = glob_dict.downcast_ref::<GlobDict>() {
+        glob.commit();
+    } else if let Some(dict_ref) = DictRef::from_value(glob_dict) {
+        // The script rebound `glob` to a plain dict: it replaces everything
+        globals.clear();
         for (k, v) in dict_ref.iter() {
             if let Some(key_str) = k.unpack_str() {
                 globals.set(key_str.to_string(), v);
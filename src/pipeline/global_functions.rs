@@ -1,13 +1,26 @@
 // src/pipeline/global_functions.rs
+use crate::accumulators::TopK;
+use crate::pipeline::glob_dict;
 use crate::processors::window::WINDOW_CONTEXT;
-use crate::variables::GlobalVariables;
+use crate::variables::{GlobalValue, GlobalVariables};
+use indexmap::IndexMap;
+use starlark::collections::SmallMap;
+use starlark::eval::Evaluator;
+use starlark::values::dict::{Dict, DictRef};
+use starlark::values::float::StarlarkFloat;
+use starlark::values::none::NoneType;
 use chrono::{DateTime, Datelike, NaiveDateTime, TimeZone, Utc};
 use dateparser;
 use once_cell::sync::Lazy;
 use regex::Regex;
-use starlark::starlark_module;
+use allocative::Allocative;
+use starlark::environment::{Methods, MethodsBuilder, MethodsStatic};
+use starlark::values::none::NoneOr;
 use starlark::values::tuple::UnpackTuple;
-use starlark::values::{Heap, Value};
+use starlark::values::{
+    starlark_value, Heap, NoSerialize, ProvidesStaticType, StarlarkValue, Value, ValueLike,
+};
+use starlark::{starlark_module, starlark_simple_value};
 use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 
@@ -27,6 +40,13 @@ thread_local! {
 pub static BUILTIN_REGEXES: Lazy<HashMap<&'static str, (Regex, &'static str)>> = Lazy::new(|| {
     let mut patterns: HashMap<&'static str, (Regex, &'static str)> = HashMap::new();
 
+    patterns.insert(
+        "aws_key",
+        (
+            Regex::new(r"\b(?:AKIA|ASIA|AIDA|AROA|AGPA|ANPA|ANVA|AIPA)[0-9A-Z]{16}\b").unwrap(),
+            "AWS access key ID",
+        ),
+    );
     patterns.insert(
         "duration",
         (
@@ -507,6 +527,76 @@ pub(crate) fn global_functions(builder: &mut starlark::environment::GlobalsBuild
         Ok(heap.alloc(value.to_string()))
     }
 
+    /// Format a value with a Python format spec, e.g. format_value(3.14159, ".2f").
+    /// Without a spec, strings are returned as-is and other values as str().
+    /// Rewritten f-strings like f"{x:.2f}" call this.
+    fn format_value<'v>(value: Value<'v>, spec: Option<&str>) -> anyhow::Result<String> {
+        use crate::pipeline::fstring::{format_with_spec, FormatArg};
+
+        let spec = spec.unwrap_or_default();
+        if spec.is_empty() {
+            return Ok(value
+                .unpack_str()
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| value.to_string()));
+        }
+
+        let arg = match value.get_type() {
+            "bool" => FormatArg::Bool(value.unpack_bool().unwrap_or(false)),
+            "int" => match value.to_string().parse::<i64>() {
+                Ok(n) => FormatArg::Int(n),
+                Err(_) => FormatArg::Float(value.to_string().parse::<f64>()?),
+            },
+            // The float itself: its string form keeps only ~7 significant digits
+            "float" => match value.downcast_ref::<StarlarkFloat>() {
+                Some(f) => FormatArg::Float(f.0),
+                None => FormatArg::Float(value.to_string().parse::<f64>()?),
+            },
+            _ => FormatArg::Str(
+                value
+                    .unpack_str()
+                    .map(|s| s.to_string())
+                    .unwrap_or_else(|| value.to_string()),
+            ),
+        };
+        format_with_spec(&arg, spec).map_err(|e| anyhow::anyhow!("format_value(): {}", e))
+    }
+
+    /// The standard `dict()`, which also copies `glob` into a plain dict
+    #[starlark(as_type = glob_dict::DictType)]
+    fn dict<'v>(
+        #[starlark(require = pos)] pairs: Option<Value<'v>>,
+        #[starlark(kwargs)] kwargs: DictRef<'v>,
+        heap: &'v Heap,
+    ) -> starlark::Result<Dict<'v>> {
+        let Some(pos) = pairs else {
+            return Ok((*kwargs).clone());
+        };
+        let mut result = if let Some(glob) = pos.downcast_ref::<glob_dict::GlobDict>() {
+            glob.to_dict(heap)
+        } else if let Some(dict) = DictRef::from_value(pos) {
+            (*dict).clone()
+        } else {
+            let mut map = SmallMap::new();
+            for pair in pos.iterate(heap)? {
+                let mut it = pair.iterate(heap)?;
+                let (Some(key), Some(value), None) = (it.next(), it.next(), it.next()) else {
+                    return Err(anyhow::anyhow!(
+                        "dict() expects a dict or a list of pairs, got a {}",
+                        pair.get_type()
+                    )
+                    .into());
+                };
+                map.insert_hashed(key.get_hashed()?, value);
+            }
+            Dict::new(map)
+        };
+        for (key, value) in kwargs.iter_hashed() {
+            result.insert_hashed(key, value);
+        }
+        Ok(result)
+    }
+
     fn len<'v>(value: Value<'v>) -> anyhow::Result<i32> {
         use starlark::values::{dict::DictRef, list::ListRef};
 
@@ -516,6 +606,8 @@ pub(crate) fn global_functions(builder: &mut starlark::environment::GlobalsBuild
             Ok(list.len() as i32)
         } else if let Some(dict) = DictRef::from_value(value) {
             Ok(dict.len() as i32)
+        } else if let Some(glob) = value.downcast_ref::<glob_dict::GlobDict>() {
+            Ok(glob.length().map_err(starlark::Error::into_anyhow)?)
         } else {
             Err(anyhow::anyhow!(
                 "object of type '{}' has no len()",
@@ -524,6 +616,180 @@ pub(crate) fn global_functions(builder: &mut starlark::environment::GlobalsBuild
         }
     }
 
+    // Hashing and encoding
+    /// Hex SHA-256 digest of a string
+    fn sha256(text: &str) -> anyhow::Result<String> {
+        use sha2::{Digest, Sha256};
+        Ok(to_hex(&Sha256::digest(text.as_bytes())))
+    }
+
+    /// Hex MD5 digest of a string (for matching legacy ids, not for security)
+    fn md5(text: &str) -> anyhow::Result<String> {
+        use md5::{Digest, Md5};
+        Ok(to_hex(&Md5::digest(text.as_bytes())))
+    }
+
+    /// Hex HMAC-SHA256 of text with the given key
+    fn hmac(key: &str, text: &str) -> anyhow::Result<String> {
+        Ok(to_hex(&hmac_sha256(key.as_bytes(), text.as_bytes())))
+    }
+
+    /// CRC-32 checksum of a string, as an int
+    fn crc32(text: &str) -> anyhow::Result<i64> {
+        Ok(crc32fast::hash(text.as_bytes()) as i64)
+    }
+
+    fn base64_encode(
+        text: &str,
+        #[starlark(default = false)] urlsafe: bool,
+    ) -> anyhow::Result<String> {
+        use base64::Engine;
+        Ok(base64_engine(urlsafe).encode(text.as_bytes()))
+    }
+
+    fn base64_decode(
+        text: &str,
+        #[starlark(default = false)] urlsafe: bool,
+    ) -> anyhow::Result<String> {
+        use base64::Engine;
+        let bytes = base64_engine(urlsafe)
+            .decode(text.trim().trim_end_matches('='))
+            .map_err(|e| anyhow::anyhow!("base64_decode(): {}", e))?;
+        String::from_utf8(bytes)
+            .map_err(|_| anyhow::anyhow!("base64_decode(): decoded bytes are not valid UTF-8"))
+    }
+
+    /// hex(255) == "0xff" like Python; hex("hi") == "6869" (hex of the UTF-8 bytes)
+    fn hex<'v>(value: Value<'v>) -> anyhow::Result<String> {
+        if let Some(text) = value.unpack_str() {
+            return Ok(to_hex(text.as_bytes()));
+        }
+        match value.get_type() {
+            "int" => {
+                let n: i128 = value.to_string().parse()?;
+                Ok(if n < 0 {
+                    format!("-0x{:x}", -n)
+                } else {
+                    format!("0x{:x}", n)
+                })
+            }
+            other => Err(anyhow::anyhow!(
+                "hex() argument must be int or string, got: {}",
+                other
+            )),
+        }
+    }
+
+    /// Percent-encode everything except unreserved characters (A-Z a-z 0-9 - . _ ~)
+    fn url_encode(text: &str) -> anyhow::Result<String> {
+        use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+        const UNRESERVED: &AsciiSet = &NON_ALPHANUMERIC
+            .remove(b'-')
+            .remove(b'.')
+            .remove(b'_')
+            .remove(b'~');
+        Ok(utf8_percent_encode(text, UNRESERVED).to_string())
+    }
+
+    /// Decode %XX escapes; with plus=True, '+' is decoded as a space (form encoding)
+    fn url_decode(
+        text: &str,
+        #[starlark(default = false)] plus: bool,
+    ) -> anyhow::Result<String> {
+        let text = if plus {
+            text.replace('+', " ")
+        } else {
+            text.to_string()
+        };
+        percent_encoding::percent_decode_str(&text)
+            .decode_utf8()
+            .map(|s| s.into_owned())
+            .map_err(|_| anyhow::anyhow!("url_decode(): decoded bytes are not valid UTF-8"))
+    }
+
+    /// Replace a value with a deterministic pseudonym keyed by salt.
+    /// IPv4/IPv6 addresses stay addresses in the same range, emails stay
+    /// emails (keeping the top-level domain), anything else becomes a
+    /// 16-character hex token. Same value and salt, same output.
+    fn pseudonymize<'v>(value: Value<'v>, salt: &str) -> anyhow::Result<String> {
+        let text = value
+            .unpack_str()
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| value.to_string());
+        Ok(pseudonymize_text(&text, salt))
+    }
+
+    // URL and User-Agent parsing
+    /// Split a URL (absolute or relative) into a dict with scheme, host,
+    /// port, path, query (dict) and fragment; missing parts are None
+    fn parse_url<'v>(heap: &'v Heap, text: &str) -> anyhow::Result<Value<'v>> {
+        json_to_starlark_value(heap, crate::http_parse::parse_url(text))
+    }
+
+    /// Decode a query string into a dict; repeated keys give a list of values
+    fn parse_qs<'v>(heap: &'v Heap, text: &str) -> anyhow::Result<Value<'v>> {
+        json_to_starlark_value(
+            heap,
+            serde_json::Value::Object(crate::http_parse::parse_qs(text)),
+        )
+    }
+
+    /// Classify a User-Agent: dict with browser, version, os, os_version,
+    /// device (desktop/mobile/tablet/bot/other) and is_bot
+    fn parse_user_agent<'v>(heap: &'v Heap, text: &str) -> anyhow::Result<Value<'v>> {
+        json_to_starlark_value(heap, crate::http_parse::parse_user_agent(text))
+    }
+
+    // IP addresses and networks
+    /// True if ip lies in the network ("10.0.0.0/8") or any of a list of
+    /// networks; False for anything that isn't an IP address
+    fn ip_in<'v>(ip: &str, network: Value<'v>) -> anyhow::Result<bool> {
+        let Some(ip) = crate::net::parse_ip(ip) else {
+            return Ok(false);
+        };
+        if let Some(network) = network.unpack_str() {
+            let cidr = crate::net::Cidr::parse(network).map_err(|e| anyhow::anyhow!("ip_in(): {}", e))?;
+            return Ok(cidr.contains(ip));
+        }
+        Ok(cidr_set_cached(network, "ip_in")?.contains(ip))
+    }
+
+    /// 4 or 6, or None if the text isn't an IP address
+    fn ip_version(ip: &str) -> anyhow::Result<NoneOr<i32>> {
+        Ok(match crate::net::parse_ip(ip) {
+            Some(std::net::IpAddr::V4(_)) => NoneOr::Other(4),
+            Some(std::net::IpAddr::V6(_)) => NoneOr::Other(6),
+            None => NoneOr::None,
+        })
+    }
+
+    /// True for private, loopback, link-local, CGNAT and unique-local addresses
+    fn is_private(ip: &str) -> anyhow::Result<bool> {
+        Ok(crate::net::parse_ip(ip).is_some_and(crate::net::is_private))
+    }
+
+    /// The address as an integer (IPv4-mapped IPv6 counts as IPv4)
+    fn ip_to_int<'v>(heap: &'v Heap, ip: &str) -> anyhow::Result<Value<'v>> {
+        let ip = crate::net::parse_ip(ip)
+            .ok_or_else(|| anyhow::anyhow!("ip_to_int(): invalid IP address '{}'", ip))?;
+        Ok(heap.alloc(num_bigint::BigInt::from(crate::net::ip_to_int(ip))))
+    }
+
+    /// Compile a list of networks into a set for fast `s.contains(ip)` / `ip in s`
+    fn cidr_set<'v>(networks: Value<'v>) -> anyhow::Result<CidrSetValue> {
+        Ok(CidrSetValue(cidr_set_cached(networks, "cidr_set")?))
+    }
+
+    /// Location and ASN of an IP from the --geoip databases: dict with
+    /// country, country_name, city, latitude, longitude, asn and as_org
+    /// (whichever the databases provide), or None if not found
+    fn geoip<'v>(heap: &'v Heap, ip: &str) -> anyhow::Result<Value<'v>> {
+        match crate::geoip::lookup_str(ip).map_err(|e| anyhow::anyhow!("geoip(): {}", e))? {
+            Some(fields) => json_to_starlark_value(heap, serde_json::Value::Object(fields)),
+            None => Ok(Value::new_none()),
+        }
+    }
+
     // Timestamp functions
     fn parse_ts(text: String, format: Option<String>) -> anyhow::Result<i64> {
         if let Some(fmt) = format {
@@ -827,6 +1093,135 @@ pub(crate) fn global_functions(builder: &mut starlark::environment::GlobalsBuild
         })
     }
 
+    /// Add `delta` (default 1) to the counter `name` in glob; returns the new value.
+    /// `amount` is the deprecated name of `delta`.
+    fn inc<'v>(
+        name: &str,
+        delta: Option<Value<'v>>,
+        #[starlark(require = named)] amount: Option<Value<'v>>,
+        eval: &mut Evaluator<'v, '_, '_>,
+    ) -> anyhow::Result<Value<'v>> {
+        if delta.is_some() && amount.is_some() {
+            return Err(anyhow::anyhow!("inc(): give delta or amount, not both"));
+        }
+        increment_glob(eval, name, delta.or(amount))
+    }
+
+    /// Add `value` to the set `name` in glob; returns True if it was new
+    fn set_add<'v>(
+        name: &str,
+        value: Value<'v>,
+        eval: &mut Evaluator<'v, '_, '_>,
+    ) -> anyhow::Result<bool> {
+        let value = GlobalValue::from_starlark(value);
+        glob_store(eval)?.update(
+            name,
+            || GlobalValue::Set(IndexMap::new()),
+            |current| match current {
+                GlobalValue::Set(values) => match values.entry(value.set_key()) {
+                    indexmap::map::Entry::Occupied(_) => Ok(false),
+                    indexmap::map::Entry::Vacant(entry) => {
+                        entry.insert(value);
+                        Ok(true)
+                    }
+                },
+                other => Err(not_a("set_add", name, other, "set")),
+            },
+        )
+    }
+
+    /// Number of distinct values in the set `name` (0 if missing)
+    fn set_len<'v>(name: &str, eval: &mut Evaluator<'v, '_, '_>) -> anyhow::Result<i32> {
+        glob_store(eval)?.inspect(name, |current| match current {
+            None => Ok(0),
+            Some(GlobalValue::Set(values)) => Ok(values.len() as i32),
+            Some(other) => Err(not_a("set_len", name, other, "set")),
+        })
+    }
+
+    /// Add a number to the histogram `name` in glob
+    fn hist_add<'v>(
+        name: &str,
+        value: Value<'v>,
+        eval: &mut Evaluator<'v, '_, '_>,
+    ) -> anyhow::Result<NoneType> {
+        let value = number_arg("hist_add", value)?;
+        if value.is_nan() {
+            return Err(anyhow::anyhow!("hist_add(): value is NaN"));
+        }
+        glob_store(eval)?.update(
+            name,
+            || GlobalValue::Hist(Box::default()),
+            |current| match current {
+                GlobalValue::Hist(hist) => {
+                    hist.add(value);
+                    Ok(NoneType)
+                }
+                other => Err(not_a("hist_add", name, other, "histogram")),
+            },
+        )
+    }
+
+    /// Estimated value at quantile `q` (0.0-1.0) of histogram `name`; None if empty
+    fn hist_quantile<'v>(
+        name: &str,
+        q: Value<'v>,
+        eval: &mut Evaluator<'v, '_, '_>,
+    ) -> anyhow::Result<NoneOr<f64>> {
+        let q = number_arg("hist_quantile", q)?;
+        if !(0.0..=1.0).contains(&q) {
+            return Err(anyhow::anyhow!(
+                "hist_quantile(): q must be between 0 and 1, got {}",
+                q
+            ));
+        }
+        glob_store(eval)?.inspect(name, |current| match current {
+            None => Ok(NoneOr::None),
+            Some(GlobalValue::Hist(hist)) => Ok(NoneOr::from_option(hist.quantile(q))),
+            Some(other) => Err(not_a("hist_quantile", name, other, "histogram")),
+        })
+    }
+
+    /// Keep the largest value seen in glob `name`; returns it
+    fn max_of<'v>(
+        name: &str,
+        value: Value<'v>,
+        eval: &mut Evaluator<'v, '_, '_>,
+    ) -> anyhow::Result<Value<'v>> {
+        track_extreme(eval, "max_of", name, value, std::cmp::Ordering::Greater)
+    }
+
+    /// Keep the smallest value seen in glob `name`; returns it
+    fn min_of<'v>(
+        name: &str,
+        value: Value<'v>,
+        eval: &mut Evaluator<'v, '_, '_>,
+    ) -> anyhow::Result<Value<'v>> {
+        track_extreme(eval, "min_of", name, value, std::cmp::Ordering::Less)
+    }
+
+    /// Count `key` in the top-k counter `name` (k most frequent keys, default 10)
+    fn topk_add<'v>(
+        name: &str,
+        key: Value<'v>,
+        k: Option<i32>,
+        eval: &mut Evaluator<'v, '_, '_>,
+    ) -> anyhow::Result<NoneType> {
+        let key = key.unpack_str().map_or_else(|| key.to_str(), |s| s.to_string());
+        let k = k.unwrap_or(10).max(1) as usize;
+        glob_store(eval)?.update(
+            name,
+            || GlobalValue::TopK(Box::new(TopK::new(k))),
+            |current| match current {
+                GlobalValue::TopK(top) => {
+                    top.add(&key);
+                    Ok(NoneType)
+                }
+                other => Err(not_a("topk_add", name, other, "topk")),
+            },
+        )
+    }
+
     // Column extraction function
     fn cols<'v>(
         heap: &'v Heap,
@@ -1128,8 +1523,269 @@ pub(crate) fn global_functions(builder: &mut starlark::environment::GlobalsBuild
     }
 }
 
+/// The store behind `glob`, for builtins that update it in place
+fn glob_store<'v>(eval: &Evaluator<'v, '_, '_>) -> anyhow::Result<&'v GlobalVariables> {
+    if let Some(globals) = eval.module().get("glob").and_then(glob_dict::glob_store) {
+        return Ok(globals);
+    }
+    CURRENT_CONTEXT.with(|ctx_cell| match *ctx_cell.borrow() {
+        // The pipeline outlives every script run
+        Some((global_vars_ptr, _, _)) => Ok(unsafe { &*global_vars_ptr }),
+        None => Err(anyhow::anyhow!("No processing context available")),
+    })
+}
+
+fn increment_glob<'v>(
+    eval: &mut Evaluator<'v, '_, '_>,
+    name: &str,
+    delta: Option<Value<'v>>,
+) -> anyhow::Result<Value<'v>> {
+    let delta = delta.map_or(GlobalValue::Int(1), GlobalValue::from_starlark);
+    let value = glob_store(eval)?
+        .increment(name, &delta)
+        .map_err(|e| anyhow::anyhow!("inc(): {}", e))?;
+    Ok(value.to_starlark(eval.heap()))
+}
+
+fn track_extreme<'v>(
+    eval: &mut Evaluator<'v, '_, '_>,
+    function: &str,
+    name: &str,
+    value: Value<'v>,
+    keep: std::cmp::Ordering,
+) -> anyhow::Result<Value<'v>> {
+    let value = GlobalValue::from_starlark(value);
+    let result = glob_store(eval)?.update(
+        name,
+        || value.clone(),
+        |current| {
+            let ordering = match (&*current, &value) {
+                (GlobalValue::Str(a), GlobalValue::Str(b)) => Some(b.cmp(a)),
+                (a, b) => match (a.as_f64(), b.as_f64()) {
+                    (Some(a), Some(b)) => b.partial_cmp(&a),
+                    _ => None,
+                },
+            };
+            match ordering {
+                Some(ordering) => {
+                    if ordering == keep {
+                        *current = value.clone();
+                    }
+                    Ok(current.clone())
+                }
+                None => Err(anyhow::anyhow!(
+                    "{}(): cannot compare {} with glob[{:?}], a {}",
+                    function,
+                    value.type_name(),
+                    name,
+                    current.type_name()
+                )),
+            }
+        },
+    )?;
+    Ok(result.to_starlark(eval.heap()))
+}
+
+fn number_arg(function: &str, value: Value) -> anyhow::Result<f64> {
+    GlobalValue::from_starlark(value)
+        .as_f64()
+        .ok_or_else(|| anyhow::anyhow!("{}() expects a number, got {}", function, value.get_type()))
+}
+
+fn not_a(function: &str, name: &str, current: &GlobalValue, expected: &str) -> anyhow::Error {
+    anyhow::anyhow!(
+        "{}(): glob[{:?}] is a {}, not a {}",
+        function,
+        name,
+        current.type_name(),
+        expected
+    )
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub(crate) fn hmac_sha256(key: &[u8], message: &[u8]) -> Vec<u8> {
+    use hmac::{Hmac, Mac};
+    let mut mac = Hmac::<sha2::Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn base64_engine(urlsafe: bool) -> base64::engine::GeneralPurpose {
+    use base64::alphabet;
+    use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+
+    // Encode with padding, but accept input with or without it
+    let config = GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent);
+    if urlsafe {
+        GeneralPurpose::new(&alphabet::URL_SAFE, config)
+    } else {
+        GeneralPurpose::new(&alphabet::STANDARD, config)
+    }
+}
+
+/// IPv4 ranges whose addresses keep their range when pseudonymized, most
+/// specific first. Addresses outside all of them keep their class (A-E).
+const IPV4_RANGES: &[(u32, u32)] = &[
+    (0x7F00_0000, 8),  // 127.0.0.0/8 loopback
+    (0x0A00_0000, 8),  // 10.0.0.0/8 private
+    (0xAC10_0000, 12), // 172.16.0.0/12 private
+    (0xC0A8_0000, 16), // 192.168.0.0/16 private
+    (0xA9FE_0000, 16), // 169.254.0.0/16 link-local
+    (0x6440_0000, 10), // 100.64.0.0/10 shared address space
+    (0xE000_0000, 4),  // 224.0.0.0/4 multicast
+    (0xF000_0000, 4),  // 240.0.0.0/4 reserved (class E)
+    (0xC000_0000, 3),  // class C
+    (0x8000_0000, 2),  // class B
+    (0x0000_0000, 1),  // class A
+];
+
+fn ipv4_range(addr: u32) -> (u32, u32) {
+    IPV4_RANGES
+        .iter()
+        .copied()
+        .find(|&(network, prefix)| addr >> (32 - prefix) == network >> (32 - prefix))
+        .expect("class A range matches every remaining address")
+}
+
+/// Keyed digest of `value`, namespaced by `kind` and a retry counter
+fn pseudonym_digest(kind: &str, value: &str, salt: &str, attempt: u32) -> Vec<u8> {
+    hmac_sha256(
+        salt.as_bytes(),
+        format!("{}:{}:{}", kind, attempt, value).as_bytes(),
+    )
+}
+
+fn pseudonymize_ipv4(addr: std::net::Ipv4Addr, salt: &str) -> std::net::Ipv4Addr {
+    let addr = u32::from(addr);
+    let (network, prefix) = ipv4_range(addr);
+    let host_mask = u32::MAX >> prefix;
+
+    // Retry until the pseudonym falls in the same range (a class A address
+    // could otherwise land in 10/8) and isn't a network/broadcast-looking .0/.255
+    for attempt in 0.. {
+        let digest = pseudonym_digest("ipv4", &addr.to_string(), salt, attempt);
+        let host = u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]) & host_mask;
+        let candidate = network | host;
+        let last_octet = candidate & 0xFF;
+        if ipv4_range(candidate) == (network, prefix) && last_octet != 0 && last_octet != 255 {
+            return std::net::Ipv4Addr::from(candidate);
+        }
+    }
+    unreachable!()
+}
+
+fn pseudonymize_ipv6(addr: std::net::Ipv6Addr, salt: &str) -> std::net::Ipv6Addr {
+    // Keep the first 16 bits so link-local, unique-local, multicast and
+    // global unicast addresses stay in their ranges
+    let addr = u128::from(addr);
+    let digest = pseudonym_digest("ipv6", &addr.to_string(), salt, 0);
+    let mut host = [0u8; 16];
+    host.copy_from_slice(&digest[..16]);
+    let host = u128::from_be_bytes(host) & (u128::MAX >> 16);
+    std::net::Ipv6Addr::from((addr & !(u128::MAX >> 16)) | host)
+}
+
+pub(crate) fn pseudonymize_text(text: &str, salt: &str) -> String {
+    static EMAIL: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"^[^@\s]+@([^@\s]+\.)?([A-Za-z]{2,})$").unwrap());
+
+    if let Ok(addr) = text.parse::<std::net::Ipv4Addr>() {
+        return pseudonymize_ipv4(addr, salt).to_string();
+    }
+    if let Ok(addr) = text.parse::<std::net::Ipv6Addr>() {
+        return pseudonymize_ipv6(addr, salt).to_string();
+    }
+    if let Some(captures) = EMAIL.captures(text) {
+        // Emails are case-insensitive in practice, so Bob@X.com == bob@x.com
+        let digest = to_hex(&pseudonym_digest("email", &text.to_lowercase(), salt, 0));
+        return format!(
+            "{}@{}.{}",
+            &digest[..10],
+            &digest[10..18],
+            captures[2].to_lowercase()
+        );
+    }
+    to_hex(&pseudonym_digest("text", text, salt, 0))[..16].to_string()
+}
+
+/// A compiled `cidr_set([...])`
+#[derive(Debug, ProvidesStaticType, NoSerialize, Allocative)]
+pub struct CidrSetValue(#[allocative(skip)] std::sync::Arc<crate::net::CidrSet>);
+
+starlark_simple_value!(CidrSetValue);
+
+impl std::fmt::Display for CidrSetValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cidr_set(<{} ranges>)", self.0.len())
+    }
+}
+
+#[starlark_value(type = "cidr_set")]
+impl<'v> StarlarkValue<'v> for CidrSetValue {
+    fn get_methods() -> Option<&'static Methods> {
+        static RES: MethodsStatic = MethodsStatic::new();
+        RES.methods(cidr_set_methods)
+    }
+
+    fn is_in(&self, other: Value<'v>) -> starlark::Result<bool> {
+        Ok(other
+            .unpack_str()
+            .and_then(crate::net::parse_ip)
+            .is_some_and(|ip| self.0.contains(ip)))
+    }
+}
+
+#[starlark_module]
+fn cidr_set_methods(builder: &mut MethodsBuilder) {
+    /// True if ip lies in any of the set's networks
+    fn contains(this: &CidrSetValue, ip: &str) -> anyhow::Result<bool> {
+        Ok(crate::net::parse_ip(ip).is_some_and(|ip| this.0.contains(ip)))
+    }
+}
+
+thread_local! {
+    /// Scripts run once per record, so the same literal list is compiled
+    /// over and over; keep the compiled sets keyed by their networks
+    static CIDR_SETS: RefCell<HashMap<Vec<String>, std::sync::Arc<crate::net::CidrSet>>> =
+        RefCell::new(HashMap::new());
+}
+
+fn cidr_set_cached(networks: Value, function: &str) -> anyhow::Result<std::sync::Arc<crate::net::CidrSet>> {
+    use starlark::values::{list::ListRef, tuple::TupleRef};
+
+    if let Some(set) = networks.downcast_ref::<CidrSetValue>() {
+        return Ok(set.0.clone());
+    }
+    let items = ListRef::from_value(networks)
+        .map(|l| l.content().to_vec())
+        .or_else(|| TupleRef::from_value(networks).map(|t| t.content().to_vec()))
+        .ok_or_else(|| anyhow::anyhow!("{}(): expected a list of networks", function))?;
+    let networks = items
+        .iter()
+        .map(|v| {
+            v.unpack_str()
+                .map(String::from)
+                .ok_or_else(|| anyhow::anyhow!("{}(): networks must be strings, got {}", function, v.get_type()))
+        })
+        .collect::<anyhow::Result<Vec<String>>>()?;
+
+    CIDR_SETS.with(|cache| {
+        if let Some(set) = cache.borrow().get(&networks) {
+            return Ok(set.clone());
+        }
+        let set = crate::net::CidrSet::new(networks.iter().map(String::as_str))
+            .map_err(|e| anyhow::anyhow!("{}(): {}", function, e))?;
+        let set = std::sync::Arc::new(set);
+        cache.borrow_mut().insert(networks, set.clone());
+        Ok(set)
+    })
+}
+
 // Helper function for duration parsing with hybrid approach
-fn parse_duration_hybrid(duration: &str) -> anyhow::Result<f64> {
+pub(crate) fn parse_duration_hybrid(duration: &str) -> anyhow::Result<f64> {
     // Try our compact implementation first (supports fractional numbers and compact format)
     let pattern = regex::Regex::new(r"([-\d.]+)([a-z]+)").unwrap();
     let matches: Vec<_> = pattern.find_iter(duration).collect();
@@ -1228,7 +1884,7 @@ fn json_to_starlark_value(heap: &Heap, json: serde_json::Value) -> anyhow::Resul
     }
 }
 
-fn starlark_to_json_value(value: Value) -> anyhow::Result<serde_json::Value> {
+pub(crate) fn starlark_to_json_value(value: Value) -> anyhow::Result<serde_json::Value> {
     use starlark::values::{dict::DictRef, list::ListRef};
 
     if value.is_none() {
@@ -1243,6 +1899,8 @@ fn starlark_to_json_value(value: Value) -> anyhow::Result<serde_json::Value> {
         let arr: Result<Vec<serde_json::Value>, _> =
             list.iter().map(starlark_to_json_value).collect();
         Ok(serde_json::Value::Array(arr?))
+    } else if let Some(json) = glob_dict::glob_to_json(value, starlark_to_json_value) {
+        json
     } else if let Some(dict) = DictRef::from_value(value) {
         let mut obj = serde_json::Map::new();
         for (k, v) in dict.iter() {
@@ -2929,6 +3587,74 @@ mod tests {
         // Should interpolate between 3.3 and 4.4 at 75.5%
         assert!((value - 4.422).abs() < 0.001);
     }
+
+    fn eval_string(script: &str) -> String {
+        let globals = GlobalsBuilder::standard().with(global_functions).build();
+        let module = Module::new();
+        let mut eval = Evaluator::new(&module);
+        let ast = AstModule::parse("test", script.to_owned(), &Dialect::Extended).unwrap();
+        let result = eval.eval_module(ast, &globals).unwrap();
+        result
+            .unpack_str()
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| result.to_string())
+    }
+
+    #[test]
+    fn test_hash_and_encoding_functions() {
+        assert_eq!(
+            eval_string(r#"sha256("abc")"#),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(eval_string(r#"md5("abc")"#), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(
+            eval_string(r#"hmac("key", "The quick brown fox jumps over the lazy dog")"#),
+            "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+        assert_eq!(eval_string(r#"crc32("hello")"#), "907060870");
+        assert_eq!(eval_string(r#"base64_encode("hi?>")"#), "aGk/Pg==");
+        assert_eq!(eval_string(r#"base64_encode("hi?>", urlsafe=True)"#), "aGk_Pg==");
+        assert_eq!(eval_string(r#"base64_decode("aGk_Pg", urlsafe=True)"#), "hi?>");
+        assert_eq!(eval_string(r#"hex(255)"#), "0xff");
+        assert_eq!(eval_string(r#"hex(-16)"#), "-0x10");
+        assert_eq!(eval_string(r#"hex("hi")"#), "6869");
+        assert_eq!(eval_string(r#"url_encode("a b&c/é~")"#), "a%20b%26c%2F%C3%A9~");
+        assert_eq!(eval_string(r#"url_decode("a+b%26c%2F")"#), "a+b&c/");
+        assert_eq!(eval_string(r#"url_decode("a+b", plus=True)"#), "a b");
+    }
+
+    #[test]
+    fn test_pseudonymize_preserves_format() {
+        for (input, network, prefix) in [
+            ("10.1.2.3", 0x0A00_0000u32, 8),
+            ("172.20.1.9", 0xAC10_0000, 12),
+            ("192.168.1.1", 0xC0A8_0000, 16),
+            ("8.8.8.8", 0x0000_0000, 1),
+            ("130.1.2.3", 0x8000_0000, 2),
+            ("203.0.113.7", 0xC000_0000, 3),
+        ] {
+            let output: std::net::Ipv4Addr = pseudonymize_text(input, "salt").parse().unwrap();
+            assert_ne!(output.to_string(), input);
+            assert_eq!(ipv4_range(u32::from(output)), (network, prefix), "{}", input);
+        }
+
+        let email = pseudonymize_text("Bob@Example.com", "salt");
+        assert!(Regex::new(r"^[0-9a-f]{10}@[0-9a-f]{8}\.com$").unwrap().is_match(&email));
+        assert_eq!(email, pseudonymize_text("bob@example.com", "salt"));
+
+        let ipv6: std::net::Ipv6Addr = pseudonymize_text("fe80::1", "salt").parse().unwrap();
+        assert_eq!(ipv6.segments()[0], 0xfe80);
+        assert_eq!(pseudonymize_text("alice", "salt").len(), 16);
+    }
+
+    #[test]
+    fn test_pseudonymize_is_deterministic_per_salt() {
+        let a = eval_string(r#"pseudonymize("alice", "s1")"#);
+        assert_eq!(a, eval_string(r#"pseudonymize("alice", "s1")"#));
+        assert_ne!(a, eval_string(r#"pseudonymize("alice", "s2")"#));
+        assert_ne!(a, eval_string(r#"pseudonymize("bob", "s1")"#));
+        assert_eq!(eval_string(r#"pseudonymize(42, "s1")"#), pseudonymize_text("42", "s1"));
+    }
 }
 
 // Additional globals for derive mode with stelp_ prefix
@@ -2981,17 +3707,12 @@ pub(crate) fn derive_globals_with_prefix(builder: &mut starlark::environment::Gl
         Ok(starlark::values::none::NoneType)
     }
 
-    fn stelp_inc(counter_name: String) -> anyhow::Result<i32> {
-        CURRENT_CONTEXT.with(|ctx_cell| {
-            if let Some((global_vars_ptr, _, _)) = *ctx_cell.borrow() {
-                unsafe {
-                    let global_vars = &*global_vars_ptr;
-                    Ok(global_vars.increment_counter(&counter_name))
-                }
-            } else {
-                Err(anyhow::anyhow!("No processing context available"))
-            }
-        })
+    fn stelp_inc<'v>(
+        counter_name: &str,
+        delta: Option<Value<'v>>,
+        eval: &mut Evaluator<'v, '_, '_>,
+    ) -> anyhow::Result<Value<'v>> {
+        increment_glob(eval, counter_name, delta)
     }
 
     fn stelp_parse_duration(duration: String) -> anyhow::Result<f64> {
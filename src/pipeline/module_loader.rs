@@ -0,0 +1,213 @@
+// src/pipeline/module_loader.rs
+//! `load()` support for Starlark scripts.
+//!
+//! Module names are resolved as follows:
+//!
+//! - `//lib/http.star` is looked up in each search path directory in order
+//!   (`--lib-path`, then `STELP_PATH`)
+//! - absolute paths are used as-is
+//! - other paths are relative to the directory of the loading file (the
+//!   current directory for `-e` expressions), falling back to the search path
+//!
+//! Loaded modules are evaluated once, frozen and cached for the whole run, so
+//! per-record evaluation of the main script only pays for a map lookup.
+
+use crate::pipeline::fstring::preprocess_fstrings;
+use crate::pipeline::global_functions::global_functions;
+use starlark::environment::{FrozenModule, Globals, GlobalsBuilder, Module};
+use starlark::eval::{Evaluator, FileLoader};
+use starlark::syntax::{AstModule, Dialect};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Environment variable with extra module directories, separated like `PATH`
+pub const STELP_PATH_ENV: &str = "STELP_PATH";
+
+/// Resolves, evaluates and caches modules for `load()` statements
+#[derive(Default)]
+pub struct ModuleLoader {
+    search_path: Vec<PathBuf>,
+    globals: OnceLock<Globals>,
+    cache: Mutex<HashMap<PathBuf, FrozenModule>>,
+    /// Modules currently being evaluated, outermost first
+    loading: Mutex<Vec<PathBuf>>,
+}
+
+impl ModuleLoader {
+    pub fn new(search_path: Vec<PathBuf>) -> Self {
+        ModuleLoader {
+            search_path,
+            ..Default::default()
+        }
+    }
+
+    /// Search path from `--lib-path` directories followed by `STELP_PATH`
+    pub fn from_env(lib_paths: &[PathBuf]) -> Self {
+        let mut search_path = lib_paths.to_vec();
+        if let Some(paths) = std::env::var_os(STELP_PATH_ENV) {
+            search_path.extend(std::env::split_paths(&paths).filter(|p| !p.as_os_str().is_empty()));
+        }
+        Self::new(search_path)
+    }
+
+    pub fn search_path(&self) -> &[PathBuf] {
+        &self.search_path
+    }
+
+    /// Loader for a script whose relative loads start at `dir`
+    pub fn for_dir(self: &Arc<Self>, dir: impl Into<PathBuf>) -> ScriptLoader {
+        ScriptLoader {
+            loader: Arc::clone(self),
+            dir: dir.into(),
+        }
+    }
+
+    /// Find the file a `load()` module name refers to
+    pub fn resolve(&self, module: &str, from_dir: &Path) -> Result<PathBuf, String> {
+        let candidates: Vec<PathBuf> = if let Some(rooted) = module.strip_prefix("//") {
+            self.search_path
+                .iter()
+                .map(|dir| dir.join(rooted))
+                .collect()
+        } else if Path::new(module).is_absolute() {
+            vec![PathBuf::from(module)]
+        } else {
+            std::iter::once(from_dir.join(module))
+                .chain(self.search_path.iter().map(|dir| dir.join(module)))
+                .collect()
+        };
+
+        candidates
+            .iter()
+            .find(|path| path.is_file())
+            .map(|path| path.canonicalize().unwrap_or_else(|_| path.clone()))
+            .ok_or_else(|| {
+                if module.starts_with("//") && self.search_path.is_empty() {
+                    format!(
+                        "cannot load '{}': no library path set (use --lib-path or {})",
+                        module, STELP_PATH_ENV
+                    )
+                } else {
+                    let searched: Vec<String> =
+                        candidates.iter().map(|p| p.display().to_string()).collect();
+                    format!(
+                        "cannot load '{}': not found (searched {})",
+                        module,
+                        searched.join(", ")
+                    )
+                }
+            })
+    }
+
+    /// Evaluate (or fetch from the cache) the module at a resolved path
+    pub fn load_path(self: &Arc<Self>, path: &Path) -> Result<FrozenModule, starlark::Error> {
+        if let Some(module) = self.cache.lock().unwrap().get(path) {
+            return Ok(module.clone());
+        }
+
+        {
+            let mut loading = self.loading.lock().unwrap();
+            if let Some(start) = loading.iter().position(|p| p == path) {
+                let cycle: Vec<String> = loading[start..]
+                    .iter()
+                    .chain(std::iter::once(&path.to_path_buf()))
+                    .map(|p| display_path(p))
+                    .collect();
+                return Err(starlark::Error::new_other(anyhow::anyhow!(
+                    "load cycle: {}",
+                    cycle.join(" -> ")
+                )));
+            }
+            loading.push(path.to_path_buf());
+        }
+
+        let result = self.evaluate(path);
+        self.loading.lock().unwrap().retain(|p| p != path);
+
+        let module = result?;
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), module.clone());
+        Ok(module)
+    }
+
+    fn evaluate(self: &Arc<Self>, path: &Path) -> Result<FrozenModule, starlark::Error> {
+        let source = std::fs::read_to_string(path).map_err(|e| {
+            starlark::Error::new_other(anyhow::anyhow!(
+                "cannot read '{}': {}",
+                display_path(path),
+                e
+            ))
+        })?;
+
+        let dialect = Dialect {
+            enable_f_strings: true,
+            ..Dialect::Extended
+        };
+        let fstrings = preprocess_fstrings(&source);
+        let ast = AstModule::parse(&display_path(path), fstrings.source.clone(), &dialect)
+            .map_err(|e| fstrings.map_starlark_error(e))?;
+
+        let globals = self
+            .globals
+            .get_or_init(|| GlobalsBuilder::standard().with(global_functions).build());
+        let scoped = self.for_dir(path.parent().map(Path::to_path_buf).unwrap_or_default());
+
+        let module = Module::new();
+        {
+            let mut eval = Evaluator::new(&module);
+            eval.set_loader(&scoped);
+            eval.eval_module(ast, globals)
+                .map_err(|e| fstrings.map_starlark_error(e))?;
+        }
+        module.freeze().map_err(starlark::Error::from)
+    }
+}
+
+/// `FileLoader` for one script: a shared `ModuleLoader` plus the directory
+/// relative `load()` paths start from
+#[derive(Clone)]
+pub struct ScriptLoader {
+    loader: Arc<ModuleLoader>,
+    dir: PathBuf,
+}
+
+impl Default for ScriptLoader {
+    fn default() -> Self {
+        Arc::new(ModuleLoader::default()).for_dir(".")
+    }
+}
+
+impl ScriptLoader {
+    /// Resolve and evaluate every `load()` of a parsed script, so that
+    /// missing or broken modules are reported before any input is read
+    pub fn preload(&self, ast: &AstModule) -> Result<(), String> {
+        for load in ast.loads() {
+            self.load(load.module_id)
+                .map_err(|e| format!("{}\n --> {}", e, load.span))?;
+        }
+        Ok(())
+    }
+}
+
+impl FileLoader for ScriptLoader {
+    fn load(&self, module: &str) -> starlark::Result<FrozenModule> {
+        let path = self
+            .loader
+            .resolve(module, &self.dir)
+            .map_err(|e| starlark::Error::new_other(anyhow::anyhow!(e)))?;
+        self.loader.load_path(&path)
+    }
+}
+
+/// Path relative to the current directory when possible, for error messages
+fn display_path(path: &Path) -> String {
+    std::env::current_dir()
+        .ok()
+        .and_then(|cwd| path.strip_prefix(cwd).ok().map(Path::to_path_buf))
+        .unwrap_or_else(|| path.to_path_buf())
+        .display()
+        .to_string()
+}
@@ -1,10 +1,14 @@
 // src/pipeline/processors.rs - Fixed version
 use crate::context::{ProcessResult, RecordContext, RecordData};
-use crate::pipeline::glob_dict::{create_glob_dict, sync_glob_dict_to_globals};
+use crate::pipeline::fstring::{preprocess_fstrings, FStringRewrite};
+use crate::pipeline::glob_dict::{
+    create_glob_dict, glob_to_json, is_glob, sync_glob_dict_to_globals,
+};
 use crate::pipeline::global_functions::{
     derive_globals_with_prefix, global_functions, CURRENT_CONTEXT, CURRENT_MODULE, EMIT_BUFFER,
     EXIT_CODE, EXIT_FLAG, EXIT_MESSAGE, IS_DATA_MODE, SKIP_FLAG,
 };
+use crate::pipeline::module_loader::ScriptLoader;
 use crate::pipeline::stream::RecordProcessor;
 use crate::variables::GlobalVariables;
 use crate::{CompilationError, ProcessingError};
@@ -19,6 +23,8 @@ const PRELUDE_CODE: &str = include_str!("../prelude.star");
 pub struct StarlarkProcessor {
     globals: Globals,
     script_source: String,
+    fstrings: FStringRewrite,
+    loader: ScriptLoader,
     name: String,
 }
 
@@ -32,9 +38,26 @@ enum StarlarkResult {
     DataModeResult(RecordData), // NEW: For data mode, return the data variable content
 }
 
+/// Result of `StarlarkProcessor::evaluate`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evaluation {
+    /// `repr()` of the script's final value, unless it was `None`
+    pub value: Option<String>,
+    pub emitted: Vec<String>,
+}
+
 impl StarlarkProcessor {
     /// Create from script source
     pub fn from_script(name: &str, script: &str) -> Result<Self, CompilationError> {
+        Self::from_script_with_loader(name, script, ScriptLoader::default())
+    }
+
+    /// Create from script source, resolving `load()` statements with `loader`
+    pub fn from_script_with_loader(
+        name: &str,
+        script: &str,
+        loader: ScriptLoader,
+    ) -> Result<Self, CompilationError> {
         // Create globals with built-in functions
         let globals = GlobalsBuilder::standard().with(global_functions).build();
 
@@ -43,20 +66,30 @@ impl StarlarkProcessor {
             enable_f_strings: true,
             ..Dialect::Extended
         };
-        let _ast = AstModule::parse("script", script.to_string(), &dialect)?;
+        let fstrings = preprocess_fstrings(script);
+        let ast = AstModule::parse("script", fstrings.source.clone(), &dialect)
+            .map_err(|e| fstrings.map_starlark_error(e))?;
+        loader
+            .preload(&ast)
+            .map_err(|e| CompilationError::ValidationError(fstrings.map_error(&e)))?;
 
         Ok(StarlarkProcessor {
             globals,
-            script_source: script.to_string(),
+            script_source: fstrings.source.clone(),
+            fstrings,
+            loader,
             name: name.to_string(),
         })
     }
 
-    fn execute_with_context(
+    /// Run the script against a record, sync `glob` back, and hand the
+    /// script's final value to `finish` while its module is still alive
+    fn run_script<R>(
         &self,
         record: &RecordData,
         ctx: &RecordContext,
-    ) -> Result<StarlarkResult, anyhow::Error> {
+        finish: impl for<'v> FnOnce(&'v Module, starlark::values::Value<'v>) -> anyhow::Result<R>,
+    ) -> Result<R, anyhow::Error> {
         // Set up context for global functions
         CURRENT_CONTEXT.with(|ctx_cell| {
             *ctx_cell.borrow_mut() = Some((
@@ -142,110 +175,156 @@ impl StarlarkProcessor {
         prelude_eval
             .eval_module(prelude_ast, &self.globals)
             .map_err(|e| anyhow::anyhow!("Prelude execution error: {}", e))?;
-        let ast = AstModule::parse("script", self.script_source.clone(), &dialect)
-            .map_err(|e| anyhow::anyhow!("Script parse error: {}", e))?;
+        let ast =
+            AstModule::parse("script", self.script_source.clone(), &dialect).map_err(|e| {
+                anyhow::anyhow!(
+                    "Script parse error: {}",
+                    self.fstrings.map_error(&e.to_string())
+                )
+            })?;
 
         let mut eval = Evaluator::new(&module);
-        let result = eval
-            .eval_module(ast, &self.globals)
-            .map_err(|e| anyhow::anyhow!("Script execution error: {}", e))?;
-
-        // Check if user assigned to 'data' variable after script execution
-        if let Some(data_value) = module.get("data") {
-            if !data_value.is_none() {
-                // User assigned to data, switch to data mode
-                IS_DATA_MODE.with(|flag| flag.set(true));
-            } else {
-                // User set data to None, switch out of data mode
-                IS_DATA_MODE.with(|flag| flag.set(false));
-            }
-        }
+        eval.set_loader(&self.loader);
+        let result = eval.eval_module(ast, &self.globals).map_err(|e| {
+            anyhow::anyhow!(
+                "Script execution error: {}",
+                self.fstrings.map_error(&e.to_string())
+            )
+        })?;
 
         // Sync glob dictionary back to global variables after execution
         if let Some(glob_value) = module.get("glob") {
             sync_glob_dict_to_globals(glob_value, ctx.global_vars);
         }
 
-        // NEW POLICY: In data mode, return the data variable content
-        let is_data_mode = IS_DATA_MODE.with(|flag| flag.get());
-        let starlark_result = if is_data_mode {
-            // In data mode, get the final value of the data variable
+        finish(&module, result)
+    }
+
+    fn execute_with_context(
+        &self,
+        record: &RecordData,
+        ctx: &RecordContext,
+    ) -> Result<StarlarkResult, anyhow::Error> {
+        self.run_script(record, ctx, |module, result| {
+            // Check if user assigned to 'data' variable after script execution
             if let Some(data_value) = module.get("data") {
-                if data_value.is_none() {
-                    // data is None, use original record
-                    StarlarkResult::DataModeResult(record.clone())
+                if !data_value.is_none() {
+                    // User assigned to data, switch to data mode
+                    IS_DATA_MODE.with(|flag| flag.set(true));
                 } else {
-                    // Convert data variable to appropriate RecordData
-                    match starlark_to_json_value(data_value) {
-                        Ok(json_value) => {
-                            StarlarkResult::DataModeResult(RecordData::structured(json_value))
-                        }
-                        Err(_) => {
-                            // Fallback to text representation
-                            let text = if let Some(s) = data_value.unpack_str() {
-                                s.to_string()
-                            } else {
-                                data_value.to_string()
-                            };
-                            StarlarkResult::DataModeResult(RecordData::text(text))
+                    // User set data to None, switch out of data mode
+                    IS_DATA_MODE.with(|flag| flag.set(false));
+                }
+            }
+
+            // NEW POLICY: In data mode, return the data variable content
+            let is_data_mode = IS_DATA_MODE.with(|flag| flag.get());
+            let starlark_result = if is_data_mode {
+                // In data mode, get the final value of the data variable
+                if let Some(data_value) = module.get("data") {
+                    if data_value.is_none() {
+                        // data is None, use original record
+                        StarlarkResult::DataModeResult(record.clone())
+                    } else {
+                        // Convert data variable to appropriate RecordData
+                        match starlark_to_json_value(data_value) {
+                            Ok(json_value) => {
+                                StarlarkResult::DataModeResult(RecordData::structured(json_value))
+                            }
+                            Err(_) => {
+                                // Fallback to text representation
+                                let text = if let Some(s) = data_value.unpack_str() {
+                                    s.to_string()
+                                } else {
+                                    data_value.to_string()
+                                };
+                                StarlarkResult::DataModeResult(RecordData::text(text))
+                            }
                         }
                     }
+                } else {
+                    // No data variable, shouldn't happen but use original as fallback
+                    StarlarkResult::DataModeResult(record.clone())
                 }
+            } else if result.is_none() {
+                StarlarkResult::None
             } else {
-                // No data variable, shouldn't happen but use original as fallback
-                StarlarkResult::DataModeResult(record.clone())
-            }
-        } else if result.is_none() {
-            StarlarkResult::None
-        } else {
-            // NEW: Check if it's a dictionary first
-            use starlark::values::dict::DictRef;
-            if let Some(_dict) = DictRef::from_value(result) {
-                // Convert Starlark dict to JSON
-                match starlark_to_json_value(result) {
-                    Ok(json_value) => StarlarkResult::Structured(json_value),
-                    Err(_) => {
-                        // Fallback to string representation
-                        StarlarkResult::Text(result.to_string())
+                // NEW: Check if it's a dictionary first
+                use starlark::values::dict::DictRef;
+                if DictRef::from_value(result).is_some() || is_glob(result) {
+                    // Convert Starlark dict (or glob) to JSON
+                    match starlark_to_json_value(result) {
+                        Ok(json_value) => StarlarkResult::Structured(json_value),
+                        Err(_) => {
+                            // Fallback to string representation
+                            StarlarkResult::Text(result.to_string())
+                        }
                     }
-                }
-            } else if let Ok(mut iterator) = result.iterate(module.heap()) {
-                // Handle any iterable (including ranges)
-                let mut strings = Vec::new();
-                #[allow(clippy::while_let_on_iterator)]
-                while let Some(item) = iterator.next() {
-                    let item_str = if item.is_none() {
-                        String::new()
-                    } else if let Some(s) = item.unpack_str() {
+                } else if let Ok(mut iterator) = result.iterate(module.heap()) {
+                    // Handle any iterable (including ranges)
+                    let mut strings = Vec::new();
+                    #[allow(clippy::while_let_on_iterator)]
+                    while let Some(item) = iterator.next() {
+                        let item_str = if item.is_none() {
+                            String::new()
+                        } else if let Some(s) = item.unpack_str() {
+                            s.to_string()
+                        } else {
+                            let s = item.to_string();
+                            if s.starts_with('"') && s.ends_with('"') && s.len() > 1 {
+                                s[1..s.len() - 1].to_string()
+                            } else {
+                                s
+                            }
+                        };
+                        strings.push(item_str);
+                    }
+                    StarlarkResult::List(strings)
+                } else {
+                    // Single value
+                    let text = if let Some(s) = result.unpack_str() {
                         s.to_string()
                     } else {
-                        let s = item.to_string();
+                        let s = result.to_string();
                         if s.starts_with('"') && s.ends_with('"') && s.len() > 1 {
                             s[1..s.len() - 1].to_string()
                         } else {
                             s
                         }
                     };
-                    strings.push(item_str);
+                    StarlarkResult::Text(text)
                 }
-                StarlarkResult::List(strings)
-            } else {
-                // Single value
-                let text = if let Some(s) = result.unpack_str() {
-                    s.to_string()
-                } else {
-                    let s = result.to_string();
-                    if s.starts_with('"') && s.ends_with('"') && s.len() > 1 {
-                        s[1..s.len() - 1].to_string()
-                    } else {
-                        s
-                    }
-                };
-                StarlarkResult::Text(text)
-            }
-        };
+            };
+
+            Ok(starlark_result)
+        })
+    }
 
-        Ok(starlark_result)
+    /// Evaluate the script against a record for interactive use: returns the
+    /// value of its last expression and any emitted lines, updating `glob`
+    /// as a normal run would
+    pub fn evaluate(
+        &self,
+        record: &RecordData,
+        ctx: &RecordContext,
+    ) -> Result<Evaluation, anyhow::Error> {
+        EMIT_BUFFER.with(|buffer| buffer.borrow_mut().clear());
+        let value = self.run_script(record, ctx, |_, result| {
+            Ok((!result.is_none()).then(|| result.to_string()))
+        });
+
+        CURRENT_CONTEXT.with(|current_ctx| {
+            *current_ctx.borrow_mut() = None;
+        });
+        CURRENT_MODULE.with(|module_ptr| {
+            *module_ptr.borrow_mut() = None;
+        });
+        let emitted = EMIT_BUFFER.with(|buffer| std::mem::take(&mut *buffer.borrow_mut()));
+
+        Ok(Evaluation {
+            value: value?,
+            emitted,
+        })
     }
 
     pub fn process_standalone(&self, record: &RecordData, ctx: &RecordContext) -> ProcessResult {
@@ -414,11 +493,22 @@ impl RecordProcessor for StarlarkProcessor {
 pub struct FilterProcessor {
     globals: Globals,
     script_source: String,
+    fstrings: FStringRewrite,
+    loader: ScriptLoader,
     name: String,
 }
 
 impl FilterProcessor {
     pub fn from_script(name: &str, script: &str) -> Result<Self, CompilationError> {
+        Self::from_script_with_loader(name, script, ScriptLoader::default())
+    }
+
+    /// Create from script source, resolving `load()` statements with `loader`
+    pub fn from_script_with_loader(
+        name: &str,
+        script: &str,
+        loader: ScriptLoader,
+    ) -> Result<Self, CompilationError> {
         // Create globals with built-in functions
         let globals = GlobalsBuilder::standard().with(global_functions).build();
 
@@ -427,11 +517,18 @@ impl FilterProcessor {
             enable_f_strings: true,
             ..Dialect::Extended
         };
-        let _ast = AstModule::parse("filter", script.to_string(), &dialect)?;
+        let fstrings = preprocess_fstrings(script);
+        let ast = AstModule::parse("filter", fstrings.source.clone(), &dialect)
+            .map_err(|e| fstrings.map_starlark_error(e))?;
+        loader
+            .preload(&ast)
+            .map_err(|e| CompilationError::ValidationError(fstrings.map_error(&e)))?;
 
         Ok(FilterProcessor {
             globals,
-            script_source: script.to_string(),
+            script_source: fstrings.source.clone(),
+            fstrings,
+            loader,
             name: name.to_string(),
         })
     }
@@ -511,13 +608,22 @@ impl FilterProcessor {
         prelude_eval
             .eval_module(prelude_ast, &self.globals)
             .map_err(|e| anyhow::anyhow!("Prelude execution error: {}", e))?;
-        let ast = AstModule::parse("filter", self.script_source.clone(), &dialect)
-            .map_err(|e| anyhow::anyhow!("Filter parse error: {}", e))?;
+        let ast =
+            AstModule::parse("filter", self.script_source.clone(), &dialect).map_err(|e| {
+                anyhow::anyhow!(
+                    "Filter parse error: {}",
+                    self.fstrings.map_error(&e.to_string())
+                )
+            })?;
 
         let mut eval = Evaluator::new(&module);
-        let result = eval
-            .eval_module(ast, &self.globals)
-            .map_err(|e| anyhow::anyhow!("Filter execution error: {}", e))?;
+        eval.set_loader(&self.loader);
+        let result = eval.eval_module(ast, &self.globals).map_err(|e| {
+            anyhow::anyhow!(
+                "Filter execution error: {}",
+                self.fstrings.map_error(&e.to_string())
+            )
+        })?;
 
         // Check if user assigned to 'data' variable after script execution
         if let Some(data_value) = module.get("data") {
@@ -677,6 +783,8 @@ fn starlark_to_json_value(value: starlark::values::Value) -> anyhow::Result<serd
         let arr: Result<Vec<serde_json::Value>, _> =
             list.iter().map(starlark_to_json_value).collect();
         Ok(serde_json::Value::Array(arr?))
+    } else if let Some(json) = glob_to_json(value, starlark_to_json_value) {
+        json
     } else if let Some(dict) = DictRef::from_value(value) {
         let mut obj = serde_json::Map::new();
         for (k, v) in dict.iter() {
@@ -697,20 +805,38 @@ fn starlark_to_json_value(value: starlark::values::Value) -> anyhow::Result<serd
 /// Derive processor that injects data variables and prefixes Stelp functionality
 pub struct DeriveProcessor {
     script_source: String,
+    fstrings: FStringRewrite,
+    loader: ScriptLoader,
     name: String,
 }
 
 impl DeriveProcessor {
     pub fn from_script(name: &str, script: &str) -> Result<Self, CompilationError> {
+        Self::from_script_with_loader(name, script, ScriptLoader::default())
+    }
+
+    /// Create from script source, resolving `load()` statements with `loader`
+    pub fn from_script_with_loader(
+        name: &str,
+        script: &str,
+        loader: ScriptLoader,
+    ) -> Result<Self, CompilationError> {
         // Validate syntax by parsing with f-strings enabled
         let dialect = Dialect {
             enable_f_strings: true,
             ..Dialect::Extended
         };
-        let _ast = AstModule::parse("derive", script.to_string(), &dialect)?;
+        let fstrings = preprocess_fstrings(script);
+        let ast = AstModule::parse("derive", fstrings.source.clone(), &dialect)
+            .map_err(|e| fstrings.map_starlark_error(e))?;
+        loader
+            .preload(&ast)
+            .map_err(|e| CompilationError::ValidationError(fstrings.map_error(&e)))?;
 
         Ok(DeriveProcessor {
-            script_source: script.to_string(),
+            script_source: fstrings.source.clone(),
+            fstrings,
+            loader,
             name: name.to_string(),
         })
     }
@@ -930,12 +1056,22 @@ impl DeriveProcessor {
             .eval_module(prelude_ast, &derive_globals)
             .map_err(|e| anyhow::anyhow!("Prelude execution error: {}", e))?;
 
-        let ast = AstModule::parse("derive", self.script_source.clone(), &dialect)
-            .map_err(|e| anyhow::anyhow!("Derive script parse error: {}", e))?;
+        let ast =
+            AstModule::parse("derive", self.script_source.clone(), &dialect).map_err(|e| {
+                anyhow::anyhow!(
+                    "Derive script parse error: {}",
+                    self.fstrings.map_error(&e.to_string())
+                )
+            })?;
 
         let mut eval = Evaluator::new(&module);
-        eval.eval_module(ast, &derive_globals)
-            .map_err(|e| anyhow::anyhow!("Derive script execution error: {}", e))?;
+        eval.set_loader(&self.loader);
+        eval.eval_module(ast, &derive_globals).map_err(|e| {
+            anyhow::anyhow!(
+                "Derive script execution error: {}",
+                self.fstrings.map_error(&e.to_string())
+            )
+        })?;
 
         // Sync stelp_glob dictionary back to global variables
         if let Some(glob_value) = module.get("stelp_glob") {
@@ -1,13 +1,20 @@
 // src/pipeline/stream.rs
+use crate::checkpoint::{Checkpointer, InputPosition, ResumePoint};
 use crate::output_format::OutputFormatter;
+use crate::dead_letter::{error_step_and_message, DeadLetterWriter};
+use crate::output_tee::TeeSink;
+use crate::output_template::PartitionedWriter;
+use crate::profile::Profiler;
+use crate::state_file::StateFile;
 use crate::variables::GlobalVariables;
 use std::io::{BufRead, Write};
+use std::path::Path;
 use std::time::Instant;
 
 use crate::error::ProcessingError;
 use crate::pipeline::config::{ErrorStrategy, PipelineConfig};
 use crate::pipeline::context::{
-    PipelineContext, ProcessResult, ProcessingStats, RecordContext, RecordData,
+    PipelineContext, ProcessResult, ProcessingStats, ProcessorStats, RecordContext, RecordData,
 };
 
 /// Main trait for record processing steps
@@ -15,17 +22,47 @@ pub trait RecordProcessor: Send + Sync {
     fn process(&mut self, record: &RecordData, ctx: &RecordContext) -> ProcessResult;
     fn name(&self) -> &str;
     fn reset(&mut self) {} // Called between files/streams
+    /// Named event counts reported by --stats (e.g. redactions per pattern)
+    fn counters(&self) -> Vec<(String, usize)> {
+        Vec::new()
+    }
+    /// Held-back records that became ready while processing the last
+    /// record; they continue through the steps after this one
+    fn take_ready(&mut self) -> Vec<RecordData> {
+        Vec::new()
+    }
+    /// Records held back until the last input ends (e.g. a reservoir
+    /// sample); they continue through the steps after this one
+    fn finish(&mut self) -> Vec<RecordData> {
+        Vec::new()
+    }
 }
 
 /// Main pipeline orchestrator
 pub struct StreamPipeline {
     processors: Vec<Box<dyn RecordProcessor>>,
+    processor_stats: Vec<ProcessorStats>,
     begin_processor: Option<Box<dyn RecordProcessor>>,
     end_processor: Option<Box<dyn RecordProcessor>>,
     context: PipelineContext,
     config: PipelineConfig,
     stats: ProcessingStats,
     output_formatter: OutputFormatter,
+    partitioned_output: Option<PartitionedWriter>,
+    tee_sinks: Vec<TeeSink>,
+    dead_letter: Option<DeadLetterWriter>,
+    profiler: Option<Profiler>,
+    state_file: Option<StateFile>,
+    checkpointer: Option<Checkpointer>,
+    /// Where the next input starts (set by `start_input`, used once)
+    input_start: ResumePoint,
+    /// Position of the next unread record in the current input
+    input_position: InputPosition,
+    /// The current input continues from a checkpoint, so BEGIN already ran
+    resumed: bool,
+    /// No input follows the current one, so held records are released
+    /// when it ends
+    last_input: bool,
     exit_code: i32,
 }
 
@@ -40,10 +77,21 @@ impl StreamPipeline {
         );
         StreamPipeline {
             processors: Vec::new(),
+            processor_stats: Vec::new(),
             begin_processor: None,
             end_processor: None,
-            context: PipelineContext::new(),
+            context: PipelineContext::with_glob_limit(config.glob_limit),
             output_formatter,
+            partitioned_output: None,
+            tee_sinks: Vec::new(),
+            dead_letter: None,
+            profiler: None,
+            state_file: None,
+            checkpointer: None,
+            input_start: ResumePoint::default(),
+            input_position: InputPosition::default(),
+            resumed: false,
+            last_input: true,
             config,
             stats: ProcessingStats::default(),
             exit_code: 0,
@@ -51,6 +99,7 @@ impl StreamPipeline {
     }
 
     pub fn add_processor(&mut self, processor: Box<dyn RecordProcessor>) {
+        self.processor_stats.push(ProcessorStats::new(processor.name()));
         self.processors.push(processor);
     }
 
@@ -74,6 +123,241 @@ impl StreamPipeline {
         &self.output_formatter
     }
 
+    /// Route output records to files computed from an output template instead
+    /// of the output stream
+    pub fn set_partitioned_output(&mut self, writer: PartitionedWriter) {
+        self.partitioned_output = Some(writer);
+    }
+
+    /// Collect per-step timings (see `--profile`)
+    pub fn enable_profiling(&mut self, top_n: usize) {
+        let format_name = format!("{:?}", self.config.output_format).to_lowercase();
+        self.profiler = Some(Profiler::new(top_n, &format_name));
+    }
+
+    pub fn get_profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    /// Whether input parsers should time themselves
+    pub fn is_profiling(&self) -> bool {
+        self.profiler.is_some()
+    }
+
+    /// Record the time an input format took to parse one line
+    pub fn record_parse_time(&mut self, format_name: &str, elapsed: std::time::Duration, ok: bool) {
+        if let Some(profiler) = &mut self.profiler {
+            profiler.record_parse(format_name, elapsed, ok);
+        }
+    }
+
+    /// Add an extra output sink that receives every output record
+    pub fn add_tee_sink(&mut self, sink: TeeSink) {
+        self.tee_sinks.push(sink);
+    }
+
+    /// Load `glob` from `state` now and save it back with `save_state()`
+    pub fn set_state_file(&mut self, state: StateFile) -> Result<usize, String> {
+        let loaded = state.load(&self.context.global_vars)?;
+        self.state_file = Some(state);
+        Ok(loaded)
+    }
+
+    pub fn save_state(&mut self) -> Result<(), String> {
+        match &mut self.state_file {
+            Some(state) => state.save(&self.context.global_vars),
+            None => Ok(()),
+        }
+    }
+
+    /// Save checkpoints while reading input files (see `--checkpoint`)
+    pub fn set_checkpointer(&mut self, checkpointer: Checkpointer) {
+        self.checkpointer = Some(checkpointer);
+    }
+
+    /// Announce the next input. `start` is where the reader handed to the
+    /// next `process_*` call is positioned (default: the beginning); a
+    /// non-zero offset continues a checkpointed run, so BEGIN is skipped.
+    pub fn start_input(&mut self, file: Option<&Path>, start: ResumePoint) -> Result<(), String> {
+        if let (Some(checkpointer), Some(file)) = (&mut self.checkpointer, file) {
+            checkpointer.set_file(file)?;
+        }
+        self.input_start = start;
+        Ok(())
+    }
+
+    /// Tell the pipeline whether more inputs follow the next one; records
+    /// held back by steps like `--reservoir` come out only after the last
+    pub fn set_last_input(&mut self, last: bool) {
+        self.last_input = last;
+    }
+
+    fn begin_input(&mut self) {
+        let start = std::mem::take(&mut self.input_start);
+        self.context.line_number = start.line_number;
+        self.context.record_count = start.record_count;
+        self.input_position = start.position;
+        self.resumed = start.position.offset > 0;
+    }
+
+    pub fn input_position(&self) -> InputPosition {
+        self.input_position
+    }
+
+    /// Record that `bytes` spanning `lines` lines of input were consumed
+    pub fn advance_input(&mut self, bytes: u64, lines: usize) {
+        self.input_position.offset += bytes;
+        self.input_position.lines += lines;
+    }
+
+    /// Save a checkpoint at the current input position if one is due.
+    /// Input loops call this before each record, after the previous
+    /// record's output is complete, so a checkpoint never skips a record.
+    pub fn checkpoint_if_due<W: Write>(&mut self, output: &mut W) {
+        if !self.checkpointer.as_ref().is_some_and(|c| c.is_due()) {
+            return;
+        }
+        let flushed = output.flush().and_then(|_| match &mut self.dead_letter {
+            Some(dead_letter) => dead_letter.flush(),
+            None => Ok(()),
+        });
+        let saved = match flushed {
+            Ok(()) => self.save_checkpoint(),
+            Err(e) => Err(format!("not saving checkpoint, flushing output failed: {}", e)),
+        };
+        if let Err(e) = saved {
+            eprintln!("stelp: {}", e);
+        }
+    }
+
+    /// Save a checkpoint at the current input position now
+    pub fn save_checkpoint(&mut self) -> Result<(), String> {
+        let point = ResumePoint {
+            position: self.input_position,
+            line_number: self.context.line_number,
+            record_count: self.context.record_count,
+        };
+        match &mut self.checkpointer {
+            Some(checkpointer) => checkpointer.save(point, &self.context.global_vars),
+            None => Ok(()),
+        }
+    }
+
+    /// Write rejected input lines (parse errors and failing steps) to a dead-letter file
+    pub fn set_dead_letter(&mut self, writer: DeadLetterWriter) {
+        self.dead_letter = Some(writer);
+    }
+
+    pub fn has_dead_letter(&self) -> bool {
+        self.dead_letter.is_some()
+    }
+
+    /// Number of entries written to the dead-letter output
+    pub fn dead_letter_count(&self) -> usize {
+        self.dead_letter.as_ref().map_or(0, |d| d.count())
+    }
+
+    /// Remember the raw input line for the next record, so that it can be
+    /// written to the dead-letter output if processing fails. Only used by
+    /// input formats that parse lines into structured records.
+    pub fn set_current_input(&mut self, line_number: usize, line: &str) {
+        if let Some(dead_letter) = &mut self.dead_letter {
+            dead_letter.set_current_input(line_number, line);
+        }
+    }
+
+    /// Write a rejected input line to the dead-letter output, if configured
+    pub fn write_dead_letter(
+        &mut self,
+        line_number: usize,
+        step: &str,
+        error: &str,
+        line: Option<&str>,
+    ) {
+        if let Some(dead_letter) = &mut self.dead_letter {
+            let file = self.context.file_name.as_deref();
+            if let Err(e) = dead_letter.write_entry(file, line_number, step, error, line) {
+                eprintln!("stelp: failed to write dead-letter output: {}", e);
+            }
+        }
+    }
+
+    /// Write a record whose processing failed to the dead-letter output
+    fn write_rejected_record(&mut self, err: &ProcessingError, record: &RecordData) {
+        let Some(dead_letter) = &mut self.dead_letter else {
+            return;
+        };
+        let (step, message) = error_step_and_message(err);
+        let (line_number, line) = match dead_letter.take_current_input() {
+            Some(input) => input,
+            None => {
+                let line = match record {
+                    RecordData::Text(text) => text.clone(),
+                    RecordData::Structured(data) => data.to_string(),
+                };
+                (self.context.line_number, line)
+            }
+        };
+        self.write_dead_letter(line_number, &step, &message, Some(&line));
+    }
+
+    /// Flush and close the dead-letter, partitioned and tee outputs, and
+    /// collect the final processor counters
+    pub fn finish_output(&mut self) -> Result<(), ProcessingError> {
+        for (processor, stats) in self.processors.iter().zip(&mut self.processor_stats) {
+            stats.counters = processor.counters().into_iter().collect();
+        }
+        if let Some(dead_letter) = &mut self.dead_letter {
+            dead_letter.flush()?;
+        }
+        if let Some(partitioned) = &mut self.partitioned_output {
+            partitioned.finish()?;
+        }
+        for sink in &mut self.tee_sinks {
+            sink.finish()?;
+        }
+        Ok(())
+    }
+
+    /// Write a record to the configured output destination
+    fn write_output<W: Write>(
+        &mut self,
+        output: &mut W,
+        record: &RecordData,
+    ) -> Result<(), ProcessingError> {
+        let started = self.profiler.is_some().then(Instant::now);
+        match &mut self.partitioned_output {
+            Some(partitioned) => partitioned.write_record(record)?,
+            None => self.output_formatter.write_record(output, record)?,
+        }
+        if let (Some(profiler), Some(started)) = (&mut self.profiler, started) {
+            profiler.record_format(started.elapsed());
+        }
+
+        if !self.tee_sinks.is_empty() {
+            let ctx = RecordContext {
+                line_number: self.context.line_number,
+                record_count: self.context.record_count,
+                file_name: self.context.file_name.as_deref(),
+                global_vars: &self.context.global_vars,
+                debug: self.config.debug,
+            };
+            for sink in &mut self.tee_sinks {
+                match sink.write_record(record, &ctx) {
+                    Ok(()) => {}
+                    // A failing tee filter only drops the record from that sink
+                    Err(e @ ProcessingError::ScriptError { .. })
+                        if matches!(self.config.error_strategy, ErrorStrategy::Skip) =>
+                    {
+                        eprintln!("stelp: tee '{}': {}", sink.path().display(), e);
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Reset processor state between files (but keep global variables)
     pub fn reset_processors(&mut self) {
         for processor in &mut self.processors {
@@ -126,7 +410,7 @@ impl StreamPipeline {
 
             match begin_processor.process(&empty_record, &begin_ctx) {
                 ProcessResult::Transform(output_record) => {
-                    if let Err(e) = self.output_formatter.write_record(output, &output_record) {
+                    if let Err(e) = self.write_output(output, &output_record) {
                         if !e.to_string().contains("Broken pipe") {
                             return Err(e.into());
                         }
@@ -136,7 +420,7 @@ impl StreamPipeline {
                 }
                 ProcessResult::FanOut(output_records) => {
                     for output_record in output_records {
-                        if let Err(e) = self.output_formatter.write_record(output, &output_record) {
+                        if let Err(e) = self.write_output(output, &output_record) {
                             if e.to_string().contains("Broken pipe") {
                                 break;
                             }
@@ -147,7 +431,7 @@ impl StreamPipeline {
                 }
                 ProcessResult::TransformWithEmissions { primary, emissions } => {
                     if let Some(primary_record) = primary {
-                        if let Err(e) = self.output_formatter.write_record(output, &primary_record)
+                        if let Err(e) = self.write_output(output, &primary_record)
                         {
                             if !e.to_string().contains("Broken pipe") {
                                 return Err(e.into());
@@ -157,7 +441,7 @@ impl StreamPipeline {
                         }
                     }
                     for emission in emissions {
-                        if let Err(e) = self.output_formatter.write_record(output, &emission) {
+                        if let Err(e) = self.write_output(output, &emission) {
                             if e.to_string().contains("Broken pipe") {
                                 break;
                             }
@@ -172,7 +456,7 @@ impl StreamPipeline {
                 } => {
                     self.exit_code = code;
                     if let Some(output_record) = final_output {
-                        if let Err(e) = self.output_formatter.write_record(output, &output_record) {
+                        if let Err(e) = self.write_output(output, &output_record) {
                             if !e.to_string().contains("Broken pipe") {
                                 return Err(e.into());
                             }
@@ -202,12 +486,19 @@ impl StreamPipeline {
             file_stats.records_processed += 1;
 
             // Process the record through the pipeline
-            match self
+            let result = self
                 .process_record(&record)
-                .map_err(|e| Box::new(e) as Box<dyn std::error::Error>)?
-            {
+                .map_err(|e| Box::new(e) as Box<dyn std::error::Error>)?;
+            // Records released by held-back steps (--dedupe-count) go first
+            if let Err(e) = self.flush_held_records(output, &mut file_stats, false) {
+                if e.to_string().contains("Broken pipe") {
+                    break;
+                }
+                return Err(e.into());
+            }
+            match result {
                 ProcessResult::Transform(output_record) => {
-                    if let Err(e) = self.output_formatter.write_record(output, &output_record) {
+                    if let Err(e) = self.write_output(output, &output_record) {
                         // Handle broken pipe gracefully
                         if e.to_string().contains("Broken pipe") {
                             break;
@@ -218,7 +509,7 @@ impl StreamPipeline {
                 }
                 ProcessResult::FanOut(output_records) => {
                     for output_record in output_records {
-                        if let Err(e) = self.output_formatter.write_record(output, &output_record) {
+                        if let Err(e) = self.write_output(output, &output_record) {
                             if e.to_string().contains("Broken pipe") {
                                 break;
                             }
@@ -229,7 +520,7 @@ impl StreamPipeline {
                 }
                 ProcessResult::TransformWithEmissions { primary, emissions } => {
                     if let Some(output_record) = primary {
-                        if let Err(e) = self.output_formatter.write_record(output, &output_record) {
+                        if let Err(e) = self.write_output(output, &output_record) {
                             if e.to_string().contains("Broken pipe") {
                                 break;
                             }
@@ -238,7 +529,7 @@ impl StreamPipeline {
                         file_stats.records_output += 1;
                     }
                     for emission in emissions {
-                        if let Err(e) = self.output_formatter.write_record(output, &emission) {
+                        if let Err(e) = self.write_output(output, &emission) {
                             if e.to_string().contains("Broken pipe") {
                                 break;
                             }
@@ -256,7 +547,7 @@ impl StreamPipeline {
                 } => {
                     self.exit_code = code;
                     if let Some(output_record) = final_output {
-                        if let Err(e) = self.output_formatter.write_record(output, &output_record) {
+                        if let Err(e) = self.write_output(output, &output_record) {
                             if !e.to_string().contains("Broken pipe") {
                                 return Err(e.into());
                             }
@@ -271,6 +562,7 @@ impl StreamPipeline {
                     ErrorStrategy::Skip => {
                         file_stats.errors += 1;
                         eprintln!("stelp: line {}: {}", self.context.line_number, err);
+                        self.write_rejected_record(&err, &record);
                         continue;
                     }
                 },
@@ -279,6 +571,13 @@ impl StreamPipeline {
             self.context.total_processed += 1;
         }
 
+        // Records held back by sampling steps come out after the last input, before END
+        if let Err(e) = self.flush_held_records(output, &mut file_stats, self.last_input) {
+            if !e.to_string().contains("Broken pipe") {
+                return Err(e.into());
+            }
+        }
+
         // Execute END processor if present
         if let Some(end_processor) = &mut self.end_processor {
             let end_ctx = RecordContext {
@@ -294,7 +593,7 @@ impl StreamPipeline {
 
             match end_processor.process(&empty_record, &end_ctx) {
                 ProcessResult::Transform(output_record) => {
-                    if let Err(e) = self.output_formatter.write_record(output, &output_record) {
+                    if let Err(e) = self.write_output(output, &output_record) {
                         if !e.to_string().contains("Broken pipe") {
                             return Err(e.into());
                         }
@@ -304,7 +603,7 @@ impl StreamPipeline {
                 }
                 ProcessResult::FanOut(output_records) => {
                     for output_record in output_records {
-                        if let Err(e) = self.output_formatter.write_record(output, &output_record) {
+                        if let Err(e) = self.write_output(output, &output_record) {
                             if e.to_string().contains("Broken pipe") {
                                 break;
                             }
@@ -315,7 +614,7 @@ impl StreamPipeline {
                 }
                 ProcessResult::TransformWithEmissions { primary, emissions } => {
                     if let Some(primary_record) = primary {
-                        if let Err(e) = self.output_formatter.write_record(output, &primary_record)
+                        if let Err(e) = self.write_output(output, &primary_record)
                         {
                             if !e.to_string().contains("Broken pipe") {
                                 return Err(e.into());
@@ -325,7 +624,7 @@ impl StreamPipeline {
                         }
                     }
                     for emission in emissions {
-                        if let Err(e) = self.output_formatter.write_record(output, &emission) {
+                        if let Err(e) = self.write_output(output, &emission) {
                             if e.to_string().contains("Broken pipe") {
                                 break;
                             }
@@ -340,7 +639,7 @@ impl StreamPipeline {
                 } => {
                     self.exit_code = code;
                     if let Some(output_record) = final_output {
-                        if let Err(e) = self.output_formatter.write_record(output, &output_record) {
+                        if let Err(e) = self.write_output(output, &output_record) {
                             if !e.to_string().contains("Broken pipe") {
                                 return Err(e.into());
                             }
@@ -377,7 +676,7 @@ impl StreamPipeline {
 
     pub fn process_stream<R: BufRead, W: Write>(
         &mut self,
-        input: R,
+        mut input: R,
         output: &mut W,
         filename: Option<&str>,
     ) -> Result<ProcessingStats, ProcessingError> {
@@ -385,14 +684,13 @@ impl StreamPipeline {
 
         // Update context for new file
         self.context.file_name = filename.map(|s| s.to_string());
-        self.context.line_number = 0;
-        self.context.record_count = 0;
+        self.begin_input();
 
         // Reset local stats for this file
         let mut file_stats = ProcessingStats::default();
 
         // Execute BEGIN processor if present
-        if let Some(begin_processor) = &mut self.begin_processor {
+        if let Some(begin_processor) = self.begin_processor.as_mut().filter(|_| !self.resumed) {
             let begin_ctx = RecordContext {
                 line_number: 0,
                 record_count: 0,
@@ -406,7 +704,7 @@ impl StreamPipeline {
 
             match begin_processor.process(&empty_record, &begin_ctx) {
                 ProcessResult::Transform(output_record) => {
-                    if let Err(e) = self.output_formatter.write_record(output, &output_record) {
+                    if let Err(e) = self.write_output(output, &output_record) {
                         if !e.to_string().contains("Broken pipe") {
                             return Err(e);
                         }
@@ -416,7 +714,7 @@ impl StreamPipeline {
                 }
                 ProcessResult::FanOut(output_records) => {
                     for output_record in output_records {
-                        if let Err(e) = self.output_formatter.write_record(output, &output_record) {
+                        if let Err(e) = self.write_output(output, &output_record) {
                             if e.to_string().contains("Broken pipe") {
                                 break;
                             }
@@ -427,7 +725,7 @@ impl StreamPipeline {
                 }
                 ProcessResult::TransformWithEmissions { primary, emissions } => {
                     if let Some(primary_record) = primary {
-                        if let Err(e) = self.output_formatter.write_record(output, &primary_record)
+                        if let Err(e) = self.write_output(output, &primary_record)
                         {
                             if !e.to_string().contains("Broken pipe") {
                                 return Err(e);
@@ -437,7 +735,7 @@ impl StreamPipeline {
                         }
                     }
                     for emission in emissions {
-                        if let Err(e) = self.output_formatter.write_record(output, &emission) {
+                        if let Err(e) = self.write_output(output, &emission) {
                             if e.to_string().contains("Broken pipe") {
                                 break;
                             }
@@ -452,7 +750,7 @@ impl StreamPipeline {
                 } => {
                     self.exit_code = code;
                     if let Some(output_record) = final_output {
-                        if let Err(e) = self.output_formatter.write_record(output, &output_record) {
+                        if let Err(e) = self.write_output(output, &output_record) {
                             if !e.to_string().contains("Broken pipe") {
                                 return Err(e);
                             }
@@ -476,8 +774,18 @@ impl StreamPipeline {
             }
         }
 
-        for line_result in input.lines() {
-            let line = line_result.map_err(ProcessingError::IoError)?;
+        // read_line rather than lines() so the byte offset is known
+        let mut line = String::new();
+        loop {
+            self.checkpoint_if_due(output);
+            line.clear();
+            let bytes = input.read_line(&mut line).map_err(ProcessingError::IoError)?;
+            if bytes == 0 {
+                break;
+            }
+            self.advance_input(bytes as u64, 1);
+            trim_line_ending(&mut line);
+            let line = line.clone();
 
             self.context.line_number += 1;
             self.context.record_count += 1;
@@ -494,9 +802,17 @@ impl StreamPipeline {
             let record = RecordData::text(line);
 
             // Process the record through the pipeline
-            match self.process_record(&record)? {
+            let result = self.process_record(&record)?;
+            // Records released by held-back steps (--dedupe-count) go first
+            if let Err(e) = self.flush_held_records(output, &mut file_stats, false) {
+                if e.to_string().contains("Broken pipe") {
+                    break;
+                }
+                return Err(e);
+            }
+            match result {
                 ProcessResult::Transform(output_record) => {
-                    if let Err(e) = self.output_formatter.write_record(output, &output_record) {
+                    if let Err(e) = self.write_output(output, &output_record) {
                         // Handle broken pipe gracefully
                         if e.to_string().contains("Broken pipe") {
                             break;
@@ -507,7 +823,7 @@ impl StreamPipeline {
                 }
                 ProcessResult::FanOut(output_records) => {
                     for output_record in output_records {
-                        if let Err(e) = self.output_formatter.write_record(output, &output_record) {
+                        if let Err(e) = self.write_output(output, &output_record) {
                             if e.to_string().contains("Broken pipe") {
                                 break;
                             }
@@ -518,7 +834,7 @@ impl StreamPipeline {
                 }
                 ProcessResult::TransformWithEmissions { primary, emissions } => {
                     if let Some(output_record) = primary {
-                        if let Err(e) = self.output_formatter.write_record(output, &output_record) {
+                        if let Err(e) = self.write_output(output, &output_record) {
                             if e.to_string().contains("Broken pipe") {
                                 break;
                             }
@@ -527,7 +843,7 @@ impl StreamPipeline {
                         file_stats.records_output += 1;
                     }
                     for emission in emissions {
-                        if let Err(e) = self.output_formatter.write_record(output, &emission) {
+                        if let Err(e) = self.write_output(output, &emission) {
                             if e.to_string().contains("Broken pipe") {
                                 break;
                             }
@@ -545,7 +861,7 @@ impl StreamPipeline {
                 } => {
                     self.exit_code = code;
                     if let Some(output_record) = final_output {
-                        if let Err(e) = self.output_formatter.write_record(output, &output_record) {
+                        if let Err(e) = self.write_output(output, &output_record) {
                             if !e.to_string().contains("Broken pipe") {
                                 return Err(e);
                             }
@@ -560,6 +876,7 @@ impl StreamPipeline {
                     ErrorStrategy::Skip => {
                         file_stats.errors += 1;
                         eprintln!("stelp: line {}: {}", self.context.line_number, err);
+                        self.write_rejected_record(&err, &record);
                         continue;
                     }
                 },
@@ -568,6 +885,13 @@ impl StreamPipeline {
             self.context.total_processed += 1;
         }
 
+        // Records held back by sampling steps come out after the last input, before END
+        if let Err(e) = self.flush_held_records(output, &mut file_stats, self.last_input) {
+            if !e.to_string().contains("Broken pipe") {
+                return Err(e);
+            }
+        }
+
         // Execute END processor if present
         if let Some(end_processor) = &mut self.end_processor {
             let end_ctx = RecordContext {
@@ -583,7 +907,7 @@ impl StreamPipeline {
 
             match end_processor.process(&empty_record, &end_ctx) {
                 ProcessResult::Transform(output_record) => {
-                    if let Err(e) = self.output_formatter.write_record(output, &output_record) {
+                    if let Err(e) = self.write_output(output, &output_record) {
                         if !e.to_string().contains("Broken pipe") {
                             return Err(e);
                         }
@@ -593,7 +917,7 @@ impl StreamPipeline {
                 }
                 ProcessResult::FanOut(output_records) => {
                     for output_record in output_records {
-                        if let Err(e) = self.output_formatter.write_record(output, &output_record) {
+                        if let Err(e) = self.write_output(output, &output_record) {
                             if e.to_string().contains("Broken pipe") {
                                 break;
                             }
@@ -604,7 +928,7 @@ impl StreamPipeline {
                 }
                 ProcessResult::TransformWithEmissions { primary, emissions } => {
                     if let Some(primary_record) = primary {
-                        if let Err(e) = self.output_formatter.write_record(output, &primary_record)
+                        if let Err(e) = self.write_output(output, &primary_record)
                         {
                             if !e.to_string().contains("Broken pipe") {
                                 return Err(e);
@@ -614,7 +938,7 @@ impl StreamPipeline {
                         }
                     }
                     for emission in emissions {
-                        if let Err(e) = self.output_formatter.write_record(output, &emission) {
+                        if let Err(e) = self.write_output(output, &emission) {
                             if e.to_string().contains("Broken pipe") {
                                 break;
                             }
@@ -629,7 +953,7 @@ impl StreamPipeline {
                 } => {
                     self.exit_code = code;
                     if let Some(output_record) = final_output {
-                        if let Err(e) = self.output_formatter.write_record(output, &output_record) {
+                        if let Err(e) = self.write_output(output, &output_record) {
                             if !e.to_string().contains("Broken pipe") {
                                 return Err(e);
                             }
@@ -664,9 +988,73 @@ impl StreamPipeline {
         Ok(file_stats)
     }
 
-    fn process_record(&mut self, record: &RecordData) -> Result<ProcessResult, ProcessingError> {
-        let mut current_record = record.clone();
+    /// Run records that processors held back through the rest of the chain
+    /// and write the results: those ready now after each record
+    /// (`RecordProcessor::take_ready`), and all of them when the last input
+    /// ends, before END (`RecordProcessor::finish`)
+    fn flush_held_records<W: Write>(
+        &mut self,
+        output: &mut W,
+        file_stats: &mut ProcessingStats,
+        end: bool,
+    ) -> Result<(), ProcessingError> {
+        for index in 0..self.processors.len() {
+            let held = if end {
+                self.processors[index].finish()
+            } else {
+                self.processors[index].take_ready()
+            };
+            for record in held {
+                let ctx = RecordContext {
+                    line_number: self.context.line_number,
+                    record_count: self.context.record_count,
+                    file_name: self.context.file_name.as_deref(),
+                    global_vars: &self.context.global_vars,
+                    debug: self.config.debug,
+                };
+                let result = Self::run_processors(
+                    &mut self.processors,
+                    &mut self.processor_stats,
+                    &mut self.profiler,
+                    index + 1,
+                    record.clone(),
+                    &ctx,
+                );
+                let records = match result {
+                    ProcessResult::Transform(record) => vec![record],
+                    ProcessResult::FanOut(records) => records,
+                    ProcessResult::TransformWithEmissions { primary, emissions } => {
+                        primary.into_iter().chain(emissions).collect()
+                    }
+                    ProcessResult::Skip => Vec::new(),
+                    ProcessResult::Exit { data, code } => {
+                        self.exit_code = code;
+                        if let Some(record) = data {
+                            self.write_output(output, &record)?;
+                            file_stats.records_output += 1;
+                        }
+                        return Ok(());
+                    }
+                    ProcessResult::Error(err) => match self.config.error_strategy {
+                        ErrorStrategy::FailFast => return Err(err),
+                        ErrorStrategy::Skip => {
+                            file_stats.errors += 1;
+                            eprintln!("stelp: {}", err);
+                            self.write_rejected_record(&err, &record);
+                            Vec::new()
+                        }
+                    },
+                };
+                for record in records {
+                    self.write_output(output, &record)?;
+                    file_stats.records_output += 1;
+                }
+            }
+        }
+        Ok(())
+    }
 
+    fn process_record(&mut self, record: &RecordData) -> Result<ProcessResult, ProcessingError> {
         let ctx = RecordContext {
             line_number: self.context.line_number,
             record_count: self.context.record_count,
@@ -675,29 +1063,70 @@ impl StreamPipeline {
             debug: self.config.debug,
         };
 
-        // Process through all processors in sequence
-        for processor in &mut self.processors {
-            match processor.process(&current_record, &ctx) {
+        let chain_started = Instant::now();
+        let result = Self::run_processors(
+            &mut self.processors,
+            &mut self.processor_stats,
+            &mut self.profiler,
+            0,
+            record.clone(),
+            &ctx,
+        );
+        if let Some(profiler) = &mut self.profiler {
+            profiler.record_chain(chain_started.elapsed(), ctx.line_number, ctx.file_name);
+        }
+        if let Some(state) = &mut self.state_file {
+            // A failed checkpoint is retried on the next record and at exit
+            if let Err(e) = state.save_if_due(&self.context.global_vars) {
+                eprintln!("stelp: {}", e);
+            }
+        }
+        Ok(result)
+    }
+
+    fn run_processors(
+        processors: &mut [Box<dyn RecordProcessor>],
+        processor_stats: &mut [ProcessorStats],
+        profiler: &mut Option<Profiler>,
+        first: usize,
+        mut current_record: RecordData,
+        ctx: &RecordContext,
+    ) -> ProcessResult {
+        // Process through the processors in sequence, starting at `first`
+        for (index, (processor, stats)) in processors
+            .iter_mut()
+            .zip(processor_stats)
+            .enumerate()
+            .skip(first)
+        {
+            let started = Instant::now();
+            let result = processor.process(&current_record, ctx);
+            let elapsed = started.elapsed();
+            stats.record_result(&result, elapsed);
+            if let Some(profiler) = profiler {
+                profiler.record_processor(index, processor.name(), &result, elapsed);
+            }
+            match result {
                 ProcessResult::Transform(new_record) => {
                     current_record = new_record;
                     // Continue to next processor
                 }
                 ProcessResult::Skip => {
                     // If any processor skips, the whole record is skipped
-                    return Ok(ProcessResult::Skip);
+                    return ProcessResult::Skip;
                 }
                 ProcessResult::Error(err) => {
                     // If any processor errors, handle according to error strategy
-                    return Ok(ProcessResult::Error(err));
+                    return ProcessResult::Error(err);
                 }
                 other_result => {
                     // For terminate, fan-out, etc., stop processing and return
-                    return Ok(other_result);
+                    return other_result;
                 }
             }
         }
 
-        Ok(ProcessResult::Transform(current_record))
+        ProcessResult::Transform(current_record)
     }
 
     /// Get current accumulated stats
@@ -705,6 +1134,11 @@ impl StreamPipeline {
         &self.stats
     }
 
+    /// Per-processor counters, in pipeline order
+    pub fn get_processor_stats(&self) -> &[ProcessorStats] {
+        &self.processor_stats
+    }
+
     /// Process a single record with streaming (used by input format parsers)
     pub fn process_single_record_streaming<W: Write>(
         &mut self,
@@ -721,12 +1155,23 @@ impl StreamPipeline {
         }
 
         // Process the record through the pipeline
-        match self
+        let result = self
             .process_record(&record)
-            .map_err(|e| Box::new(e) as Box<dyn std::error::Error>)?
-        {
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error>)?;
+        // Records released by held-back steps (--dedupe-count) go first
+        let mut released = ProcessingStats::default();
+        let flushed = self.flush_held_records(output, &mut released, false);
+        self.stats.records_output += released.records_output;
+        self.stats.errors += released.errors;
+        if let Err(e) = flushed {
+            if e.to_string().contains("Broken pipe") {
+                return Ok(false); // Signal to stop processing
+            }
+            return Err(e.into());
+        }
+        match result {
             ProcessResult::Transform(output_record) => {
-                if let Err(e) = self.output_formatter.write_record(output, &output_record) {
+                if let Err(e) = self.write_output(output, &output_record) {
                     if e.to_string().contains("Broken pipe") {
                         return Ok(false); // Signal to stop processing
                     }
@@ -736,7 +1181,7 @@ impl StreamPipeline {
             }
             ProcessResult::FanOut(output_records) => {
                 for output_record in output_records {
-                    if let Err(e) = self.output_formatter.write_record(output, &output_record) {
+                    if let Err(e) = self.write_output(output, &output_record) {
                         if e.to_string().contains("Broken pipe") {
                             return Ok(false); // Signal to stop processing
                         }
@@ -747,7 +1192,7 @@ impl StreamPipeline {
             }
             ProcessResult::TransformWithEmissions { primary, emissions } => {
                 if let Some(output_record) = primary {
-                    if let Err(e) = self.output_formatter.write_record(output, &output_record) {
+                    if let Err(e) = self.write_output(output, &output_record) {
                         if e.to_string().contains("Broken pipe") {
                             return Ok(false); // Signal to stop processing
                         }
@@ -756,7 +1201,7 @@ impl StreamPipeline {
                     self.stats.records_output += 1;
                 }
                 for emission in emissions {
-                    if let Err(e) = self.output_formatter.write_record(output, &emission) {
+                    if let Err(e) = self.write_output(output, &emission) {
                         if e.to_string().contains("Broken pipe") {
                             return Ok(false); // Signal to stop processing
                         }
@@ -774,7 +1219,7 @@ impl StreamPipeline {
             } => {
                 self.exit_code = code;
                 if let Some(output_record) = final_output {
-                    if let Err(e) = self.output_formatter.write_record(output, &output_record) {
+                    if let Err(e) = self.write_output(output, &output_record) {
                         if !e.to_string().contains("Broken pipe") {
                             return Err(e.into());
                         }
@@ -788,6 +1233,7 @@ impl StreamPipeline {
                 ErrorStrategy::Skip => {
                     self.stats.errors += 1;
                     eprintln!("stelp: line {}: {}", self.context.line_number, err);
+                    self.write_rejected_record(&err, &record);
                 }
             },
         }
@@ -799,8 +1245,7 @@ impl StreamPipeline {
     /// Initialize streaming context (used by input format parsers)
     pub fn init_streaming_context(&mut self, filename: Option<&str>) {
         self.context.file_name = filename.map(|s| s.to_string());
-        self.context.line_number = 0;
-        self.context.record_count = 0;
+        self.begin_input();
     }
 
     /// Execute BEGIN processor for streaming (used by input format parsers)
@@ -810,7 +1255,7 @@ impl StreamPipeline {
     ) -> Result<usize, Box<dyn std::error::Error>> {
         let mut records_output = 0;
         
-        if let Some(begin_processor) = &mut self.begin_processor {
+        if let Some(begin_processor) = self.begin_processor.as_mut().filter(|_| !self.resumed) {
             let begin_ctx = RecordContext {
                 line_number: 0,
                 record_count: 0,
@@ -823,7 +1268,7 @@ impl StreamPipeline {
 
             match begin_processor.process(&empty_record, &begin_ctx) {
                 ProcessResult::Transform(output_record) => {
-                    if let Err(e) = self.output_formatter.write_record(output, &output_record) {
+                    if let Err(e) = self.write_output(output, &output_record) {
                         if !e.to_string().contains("Broken pipe") {
                             return Err(e.into());
                         }
@@ -833,7 +1278,7 @@ impl StreamPipeline {
                 }
                 ProcessResult::FanOut(output_records) => {
                     for output_record in output_records {
-                        if let Err(e) = self.output_formatter.write_record(output, &output_record) {
+                        if let Err(e) = self.write_output(output, &output_record) {
                             if e.to_string().contains("Broken pipe") {
                                 break;
                             }
@@ -844,7 +1289,7 @@ impl StreamPipeline {
                 }
                 ProcessResult::TransformWithEmissions { primary, emissions } => {
                     if let Some(primary_record) = primary {
-                        if let Err(e) = self.output_formatter.write_record(output, &primary_record) {
+                        if let Err(e) = self.write_output(output, &primary_record) {
                             if !e.to_string().contains("Broken pipe") {
                                 return Err(e.into());
                             }
@@ -853,7 +1298,7 @@ impl StreamPipeline {
                         }
                     }
                     for emission in emissions {
-                        if let Err(e) = self.output_formatter.write_record(output, &emission) {
+                        if let Err(e) = self.write_output(output, &emission) {
                             if e.to_string().contains("Broken pipe") {
                                 break;
                             }
@@ -865,7 +1310,7 @@ impl StreamPipeline {
                 ProcessResult::Exit { data: final_output, code } => {
                     self.exit_code = code;
                     if let Some(output_record) = final_output {
-                        if let Err(e) = self.output_formatter.write_record(output, &output_record) {
+                        if let Err(e) = self.write_output(output, &output_record) {
                             if !e.to_string().contains("Broken pipe") {
                                 return Err(e.into());
                             }
@@ -897,6 +1342,17 @@ impl StreamPipeline {
         output: &mut W,
     ) -> Result<usize, Box<dyn std::error::Error>> {
         let mut records_output = 0;
+
+        // Records held back by sampling steps come out after the last input, before END
+        let mut held_stats = ProcessingStats::default();
+        let flushed = self.flush_held_records(output, &mut held_stats, self.last_input);
+        records_output += held_stats.records_output;
+        self.stats.errors += held_stats.errors;
+        if let Err(e) = flushed {
+            if !e.to_string().contains("Broken pipe") {
+                return Err(e.into());
+            }
+        }
         
         if let Some(end_processor) = &mut self.end_processor {
             let end_ctx = RecordContext {
@@ -911,7 +1367,7 @@ impl StreamPipeline {
 
             match end_processor.process(&empty_record, &end_ctx) {
                 ProcessResult::Transform(output_record) => {
-                    if let Err(e) = self.output_formatter.write_record(output, &output_record) {
+                    if let Err(e) = self.write_output(output, &output_record) {
                         if !e.to_string().contains("Broken pipe") {
                             return Err(e.into());
                         }
@@ -921,7 +1377,7 @@ impl StreamPipeline {
                 }
                 ProcessResult::FanOut(output_records) => {
                     for output_record in output_records {
-                        if let Err(e) = self.output_formatter.write_record(output, &output_record) {
+                        if let Err(e) = self.write_output(output, &output_record) {
                             if e.to_string().contains("Broken pipe") {
                                 break;
                             }
@@ -932,7 +1388,7 @@ impl StreamPipeline {
                 }
                 ProcessResult::TransformWithEmissions { primary, emissions } => {
                     if let Some(primary_record) = primary {
-                        if let Err(e) = self.output_formatter.write_record(output, &primary_record) {
+                        if let Err(e) = self.write_output(output, &primary_record) {
                             if !e.to_string().contains("Broken pipe") {
                                 return Err(e.into());
                             }
@@ -941,7 +1397,7 @@ impl StreamPipeline {
                         }
                     }
                     for emission in emissions {
-                        if let Err(e) = self.output_formatter.write_record(output, &emission) {
+                        if let Err(e) = self.write_output(output, &emission) {
                             if e.to_string().contains("Broken pipe") {
                                 break;
                             }
@@ -953,7 +1409,7 @@ impl StreamPipeline {
                 ProcessResult::Exit { data: final_output, code } => {
                     self.exit_code = code;
                     if let Some(output_record) = final_output {
-                        if let Err(e) = self.output_formatter.write_record(output, &output_record) {
+                        if let Err(e) = self.write_output(output, &output_record) {
                             if !e.to_string().contains("Broken pipe") {
                                 return Err(e.into());
                             }
@@ -1002,7 +1458,21 @@ impl StreamPipeline {
             processor.reset();
         }
 
+        for stats in &mut self.processor_stats {
+            *stats = ProcessorStats::new(&stats.name);
+        }
+
         self.output_formatter.reset();
         self.stats = ProcessingStats::default();
     }
 }
+
+/// Strip a trailing `\n` or `\r\n`, as `BufRead::lines()` does
+pub(crate) fn trim_line_ending(line: &mut String) {
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+}
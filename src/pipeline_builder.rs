@@ -0,0 +1,378 @@
+// src/pipeline_builder.rs
+//! Assembles the processors of a pipeline from its options.
+//!
+//! The command line and the pipeline files run by `stelp test` describe a
+//! pipeline with the same options; both fill in a `PipelineBuilder`, which
+//! adds the processors to a `StreamPipeline` in the order they run:
+//!
+//! 1. type coercion and schema validation, so everything sees typed values
+//! 2. level filter and levelmap
+//! 3. template ids, so that steps can use them
+//! 4. the steps, wrapped in windows unless they select records
+//! 5. template and field profile reports, covering the records steps kept
+//! 6. redaction, so scripts still see the original values
+//! 7. BEGIN and END
+
+use crate::chunking::{parse_chunk_strategy, ChunkConfig};
+use crate::input_format::InputFormat;
+use crate::output_format::OutputFormat;
+use crate::pipeline::module_loader::ModuleLoader;
+use crate::pipeline::stream::{RecordProcessor, StreamPipeline};
+use crate::pipeline_file::{PipelineFile, PipelineStep};
+use crate::processors::coerce::parse_type_spec;
+use crate::processors::{
+    DedupeKey, DedupeOptions, DedupeProcessor, JsonSchema, PatternsMode, PatternsProcessor,
+    RateLimitProcessor, RedactMode, RedactPattern, ReservoirProcessor, SampleByProcessor,
+    SampleProcessor, SchemaFormat, SchemaProcessor, ValidateMode, ValidateProcessor, WindowConfig,
+};
+use crate::{
+    DeriveProcessor, ExtractProcessor, FilterProcessor, LevelFilterProcessor, LevelMapProcessor,
+    RedactProcessor, StarlarkProcessor, TypeCoercionProcessor, WindowProcessor,
+};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Options describing a pipeline's processors
+#[derive(Debug, Clone, Default)]
+pub struct PipelineBuilder {
+    pub input_format: Option<InputFormat>,
+    pub steps: Vec<PipelineStep>,
+    /// Files pasted in front of every expression, as for `-I/--include`
+    pub includes: Vec<PathBuf>,
+    /// Extra `load()` directories, searched before `STELP_PATH`
+    pub lib_paths: Vec<PathBuf>,
+    /// Directory relative `load()`s in expressions start from
+    pub base_dir: PathBuf,
+    pub begin: Option<String>,
+    pub end: Option<String>,
+    /// Run BEGIN/END over the records even without steps
+    pub pass_through: bool,
+    pub types: Option<String>,
+    pub infer_types: bool,
+    pub validate: Option<PathBuf>,
+    pub validate_mode: ValidateMode,
+    pub levels: Option<String>,
+    pub exclude_levels: Option<String>,
+    /// Show a levelmap, with or without colors
+    pub levelmap: Option<bool>,
+    /// Report this many templates (0 for all), as for `--patterns`
+    pub patterns_top: Option<usize>,
+    pub pattern_field: Option<String>,
+    pub schema: Option<SchemaFormat>,
+    pub window_size: Option<usize>,
+    pub window_time: Option<String>,
+    pub window_by: Option<String>,
+    pub window_keys: Option<usize>,
+    pub dedupe_window: Option<String>,
+    pub dedupe_keys: Option<usize>,
+    pub dedupe_count: bool,
+    pub seed: Option<u64>,
+    pub redact_patterns: Vec<RedactPattern>,
+    pub redact_mode: RedactMode,
+    pub redact_salt: String,
+    pub chunk_lines: Option<usize>,
+    pub chunk_start: Option<String>,
+    pub chunk_delim: Option<String>,
+}
+
+impl PipelineBuilder {
+    /// Options of a pipeline file; relative loads start at its directory
+    pub fn from_pipeline_file(file: &PipelineFile, path: &Path) -> Result<Self, String> {
+        use clap::ValueEnum;
+
+        let input_format = file
+            .input_format
+            .as_ref()
+            .map(|name| {
+                InputFormat::from_str(name, true)
+                    .map_err(|_| format!("unknown input format '{}'", name))
+            })
+            .transpose()?;
+        Ok(PipelineBuilder {
+            input_format,
+            steps: file.steps.clone(),
+            lib_paths: file.lib_path.clone(),
+            base_dir: parent_dir(path).to_path_buf(),
+            begin: file.begin.clone(),
+            end: file.end.clone(),
+            pass_through: true,
+            types: file.types.as_ref().map(|t| t.to_csv()),
+            infer_types: file.infer_types,
+            levels: file.levels.as_ref().map(|l| l.to_csv()),
+            exclude_levels: file.exclude_levels.as_ref().map(|l| l.to_csv()),
+            window_size: file.window,
+            window_time: file.window_time.clone(),
+            window_by: file.window_by.clone(),
+            window_keys: file.window_keys,
+            dedupe_window: file.dedupe_window.clone(),
+            dedupe_keys: file.dedupe_keys,
+            dedupe_count: file.dedupe_count,
+            seed: file.seed,
+            chunk_lines: file.chunk_lines,
+            chunk_start: file.chunk_start.clone(),
+            chunk_delim: file.chunk_delim.clone(),
+            ..PipelineBuilder::default()
+        })
+    }
+
+    /// Output format used when none is given
+    pub fn default_output_format(&self) -> OutputFormat {
+        match self.input_format {
+            // A JSON Schema is a JSON document
+            _ if self.schema == Some(SchemaFormat::JsonSchema) => OutputFormat::Jsonl,
+            // Templates, template ids and field profiles are fields, even for text input
+            Some(InputFormat::Line)
+                if self.patterns_top.is_some()
+                    || self.pattern_field.is_some()
+                    || self.schema.is_some() =>
+            {
+                OutputFormat::Logfmt
+            }
+            Some(InputFormat::Line) => OutputFormat::Line, // Text input defaults to text output
+            _ => OutputFormat::Logfmt, // All structured formats default to logfmt (plain mode affects rendering, not format choice)
+        }
+    }
+
+    /// The chunking strategy, if any
+    pub fn chunk_config(&self) -> Result<Option<ChunkConfig>, String> {
+        let strategy = if let Some(lines) = self.chunk_lines {
+            format!("lines:{}", lines)
+        } else if let Some(pattern) = &self.chunk_start {
+            format!("start-pattern:{}", pattern)
+        } else if let Some(delimiter) = &self.chunk_delim {
+            format!("delimiter:{}", delimiter)
+        } else {
+            return Ok(None);
+        };
+        Ok(Some(ChunkConfig {
+            strategy: parse_chunk_strategy(&strategy)?,
+        }))
+    }
+
+    /// Add the processors, BEGIN and END to `pipeline`
+    pub fn add_processors(&self, pipeline: &mut StreamPipeline) -> Result<(), String> {
+        if self.types.is_some() || self.infer_types {
+            let types = parse_type_spec(self.types.as_deref().unwrap_or(""))
+                .map_err(|e| format!("--types: {}", e))?;
+            pipeline.add_processor(Box::new(TypeCoercionProcessor::new(
+                "types",
+                types,
+                self.infer_types,
+            )));
+        }
+
+        if let Some(path) = &self.validate {
+            let schema = JsonSchema::load(path).map_err(|e| format!("--validate: {}", e))?;
+            pipeline.add_processor(Box::new(ValidateProcessor::new(
+                "validate",
+                schema,
+                self.validate_mode,
+            )));
+        }
+
+        if self.levels.is_some() || self.exclude_levels.is_some() {
+            pipeline.add_processor(Box::new(LevelFilterProcessor::new(
+                "level_filter",
+                self.levels.as_deref(),
+                self.exclude_levels.as_deref(),
+            )));
+        }
+
+        if let Some(use_color) = self.levelmap {
+            pipeline.add_processor(Box::new(LevelMapProcessor::new("levelmap", use_color)));
+        }
+
+        if let Some(field) = &self.pattern_field {
+            pipeline.add_processor(Box::new(PatternsProcessor::new(
+                "patterns",
+                PatternsMode::Annotate {
+                    field: field.clone(),
+                },
+            )));
+        }
+
+        // Modules loaded with load() are shared by all scripts
+        let module_loader = Arc::new(ModuleLoader::from_env(&self.lib_paths));
+
+        let window = WindowConfig::from_options(
+            self.window_size,
+            self.window_time.as_deref(),
+            self.window_by.as_deref(),
+            self.window_keys,
+        )?;
+        let dedupe = DedupeOptions::from_options(
+            self.dedupe_window.as_deref(),
+            self.dedupe_keys,
+            self.dedupe_count,
+        )?;
+        for (i, step) in self.steps.iter().enumerate() {
+            let processor = self.step_processor(i, step, &module_loader, &dedupe)?;
+            pipeline.add_processor(match &window {
+                Some(window) if !step.selects_records() => {
+                    Box::new(WindowProcessor::with_config(window.clone(), processor))
+                }
+                _ => processor,
+            });
+        }
+
+        if let Some(top) = self.patterns_top {
+            pipeline.add_processor(Box::new(PatternsProcessor::new(
+                "patterns",
+                PatternsMode::Report { top },
+            )));
+        }
+        if let Some(format) = self.schema {
+            pipeline.add_processor(Box::new(SchemaProcessor::new("schema", format)));
+        }
+
+        // Without steps, pass records through so that BEGIN/END see them
+        if self.steps.is_empty()
+            && self.pass_through
+            && (self.begin.is_some() || self.end.is_some())
+        {
+            let identity = match self.input_format.as_ref().unwrap_or(&InputFormat::Line) {
+                InputFormat::Line => "line",
+                _ => "data",
+            };
+            let processor = StarlarkProcessor::from_script("identity", identity)
+                .map_err(|e| format!("failed to compile default identity processor: {}", e))?;
+            pipeline.add_processor(Box::new(processor));
+        }
+
+        if !self.redact_patterns.is_empty() {
+            pipeline.add_processor(Box::new(RedactProcessor::new(
+                "redact",
+                self.redact_patterns.clone(),
+                self.redact_mode,
+                &self.redact_salt,
+            )));
+        }
+
+        if let Some(code) = &self.begin {
+            let processor = StarlarkProcessor::from_script_with_loader(
+                "BEGIN",
+                &self.script(code)?,
+                module_loader.for_dir(&self.base_dir),
+            )
+            .map_err(|e| format!("failed to compile BEGIN expression: {}", e))?;
+            pipeline.set_begin_processor(Box::new(processor));
+        }
+        if let Some(code) = &self.end {
+            let processor = StarlarkProcessor::from_script_with_loader(
+                "END",
+                &self.script(code)?,
+                module_loader.for_dir(&self.base_dir),
+            )
+            .map_err(|e| format!("failed to compile END expression: {}", e))?;
+            pipeline.set_end_processor(Box::new(processor));
+        }
+        Ok(())
+    }
+
+    fn step_processor(
+        &self,
+        i: usize,
+        step: &PipelineStep,
+        module_loader: &Arc<ModuleLoader>,
+        dedupe: &DedupeOptions,
+    ) -> Result<Box<dyn RecordProcessor>, String> {
+        Ok(match step {
+            PipelineStep::Extract(pattern) => Box::new(
+                ExtractProcessor::new(&format!("extract_{}", i + 1), pattern)
+                    .map_err(|e| format!("failed to compile extract-vars pattern: {}", e))?,
+            ),
+            PipelineStep::Eval(code) => Box::new(
+                StarlarkProcessor::from_script_with_loader(
+                    &format!("eval_{}", i + 1),
+                    &self.script(code)?,
+                    module_loader.for_dir(&self.base_dir),
+                )
+                .map_err(|e| format!("failed to compile eval expression {}: {}", i + 1, e))?,
+            ),
+            PipelineStep::Filter(code) => Box::new(
+                FilterProcessor::from_script_with_loader(
+                    &format!("filter_{}", i + 1),
+                    &self.script(code)?,
+                    module_loader.for_dir(&self.base_dir),
+                )
+                .map_err(|e| format!("failed to compile filter expression {}: {}", i + 1, e))?,
+            ),
+            PipelineStep::Derive(code) => Box::new(
+                DeriveProcessor::from_script_with_loader(
+                    &format!("derive_{}", i + 1),
+                    &self.script(code)?,
+                    module_loader.for_dir(&self.base_dir),
+                )
+                .map_err(|e| format!("failed to compile derive expression {}: {}", i + 1, e))?,
+            ),
+            PipelineStep::ScriptFile(script_path) => {
+                let script = std::fs::read_to_string(script_path).map_err(|e| {
+                    format!(
+                        "failed to read script file '{}': {}",
+                        script_path.display(),
+                        e
+                    )
+                })?;
+                // Relative loads in a script file start at the script's directory
+                Box::new(
+                    StarlarkProcessor::from_script_with_loader(
+                        &format!("script:{}", script_path.display()),
+                        &self.script(&script)?,
+                        module_loader.for_dir(parent_dir(script_path)),
+                    )
+                    .map_err(|e| format!("failed to compile script file: {}", e))?,
+                )
+            }
+            PipelineStep::Sample(rate) => Box::new(SampleProcessor::new(
+                &format!("sample_{}", i + 1),
+                *rate,
+                self.seed,
+            )?),
+            PipelineStep::SampleBy(spec) => Box::new(SampleByProcessor::parse(
+                &format!("sample_by_{}", i + 1),
+                spec,
+                self.seed,
+            )?),
+            PipelineStep::Reservoir(size) => Box::new(ReservoirProcessor::new(
+                &format!("reservoir_{}", i + 1),
+                *size,
+                self.seed,
+            )),
+            PipelineStep::RateLimit(spec) => Box::new(RateLimitProcessor::parse(
+                &format!("rate_limit_{}", i + 1),
+                spec,
+            )?),
+            PipelineStep::DedupeBy(fields) => Box::new(DedupeProcessor::new(
+                &format!("dedupe_{}", i + 1),
+                DedupeKey::fields(&fields.to_csv())?,
+                dedupe.clone(),
+            )),
+            PipelineStep::DedupeLine => Box::new(DedupeProcessor::new(
+                &format!("dedupe_{}", i + 1),
+                DedupeKey::Line,
+                dedupe.clone(),
+            )),
+        })
+    }
+
+    /// The script of an expression, after the `includes`
+    fn script(&self, code: &str) -> Result<String, String> {
+        let mut script = String::new();
+        for include_path in &self.includes {
+            let include_content = std::fs::read_to_string(include_path).map_err(|e| {
+                format!("Include file '{}' not found: {}", include_path.display(), e)
+            })?;
+            script.push_str(&include_content);
+            script.push_str("\n\n");
+        }
+        script.push_str(code);
+        Ok(script)
+    }
+}
+
+/// Directory of a file, `.` for bare file names
+fn parent_dir(path: &Path) -> &Path {
+    path.parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+}
@@ -0,0 +1,553 @@
+// src/pipeline_file.rs
+//! Declarative pipeline files (`-P/--pipeline`).
+//!
+//! A pipeline file declares the same things as the command line: formats,
+//! ordered processing steps, window size, chunking, level filters and keys.
+//! It can be written in YAML:
+//!
+//! ```yaml
+//! input-format: combined
+//! steps:
+//!   - filter: 'data["status"] >= 500'
+//!   - derive: 'ms = duration * 1000'
+//! keys: [ts, status, path, ms]
+//! output-format: jsonl
+//! ```
+//!
+//! or as a Starlark file (`.star`) calling `settings()` and `stage()`:
+//!
+//! ```python
+//! settings(input_format = "combined", keys = ["ts", "status", "path", "ms"])
+//! stage("filter", 'data["status"] >= 500')
+//! stage("derive", "ms = duration * 1000")
+//! ```
+//!
+//! Command-line flags override file settings; command-line steps run after
+//! the file's steps.
+
+use serde::Deserialize;
+use starlark::environment::{GlobalsBuilder, Module};
+use starlark::eval::Evaluator;
+use starlark::starlark_module;
+use starlark::syntax::{AstModule, Dialect};
+use starlark::values::none::NoneType;
+use starlark::values::Value;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+
+/// A processing step, in the order it runs
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PipelineStep {
+    #[serde(alias = "extract-vars")]
+    Extract(String),
+    Eval(String),
+    Filter(String),
+    Derive(String),
+    #[serde(rename = "script")]
+    ScriptFile(PathBuf),
+    /// Keep each record with this probability, as for `--sample`
+    Sample(f64),
+    /// `FIELD:RATE`, as for `--sample-by`
+    SampleBy(String),
+    /// Sample size, as for `--reservoir`
+    Reservoir(usize),
+    /// `N/PERIOD[:FIELD]`, as for `--rate-limit`
+    RateLimit(String),
+    /// Fields identifying duplicates, as for `--dedupe-by`
+    DedupeBy(NameList),
+    /// Drop repeated lines, as for `--dedupe-line`
+    DedupeLine,
+}
+
+impl PipelineStep {
+    /// Sampling and dedupe steps select records rather than run code
+    pub fn selects_records(&self) -> bool {
+        matches!(
+            self,
+            PipelineStep::Sample(_)
+                | PipelineStep::SampleBy(_)
+                | PipelineStep::Reservoir(_)
+                | PipelineStep::RateLimit(_)
+                | PipelineStep::DedupeBy(_)
+                | PipelineStep::DedupeLine
+        )
+    }
+}
+
+/// Comma-separated string or list, like `-k a,b` / `keys: [a, b]`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum NameList {
+    Csv(String),
+    List(Vec<String>),
+}
+
+impl NameList {
+    /// Comma-separated form, as the CLI option would take it
+    pub fn to_csv(&self) -> String {
+        match self {
+            NameList::Csv(s) => s.clone(),
+            NameList::List(items) => items.join(","),
+        }
+    }
+}
+
+/// Settings and steps read from a pipeline file
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PipelineFile {
+    pub input_format: Option<String>,
+    pub output_format: Option<String>,
+    #[serde(default)]
+    pub steps: Vec<PipelineStep>,
+    pub begin: Option<String>,
+    pub end: Option<String>,
+    pub window: Option<usize>,
+    /// Time span of window functions, as for `--window-time`
+    pub window_time: Option<String>,
+    /// Field to keep separate windows for, as for `--window-by`
+    pub window_by: Option<String>,
+    /// Maximum number of `window-by` windows, as for `--window-keys`
+    pub window_keys: Option<usize>,
+    pub chunk_lines: Option<usize>,
+    pub chunk_start: Option<String>,
+    pub chunk_delim: Option<String>,
+    pub levels: Option<NameList>,
+    pub exclude_levels: Option<NameList>,
+    pub keys: Option<NameList>,
+    pub remove_keys: Option<NameList>,
+    /// Field types, `status:int` entries as for `--types`
+    pub types: Option<NameList>,
+    #[serde(default)]
+    pub infer_types: bool,
+    #[serde(default)]
+    pub lib_path: Vec<PathBuf>,
+    #[serde(default)]
+    pub plain: bool,
+    #[serde(default)]
+    pub fail_fast: bool,
+    /// Maximum number of `glob` keys, as for `--glob-limit`
+    pub glob_limit: Option<usize>,
+    /// Persist `glob` between runs, as for `--state-file`
+    pub state_file: Option<PathBuf>,
+    /// Key prefixes to persist, as for `--state-keys`
+    pub state_keys: Option<NameList>,
+    /// Random seed for sampling steps, as for `--seed`
+    pub seed: Option<u64>,
+    /// How long dedupe steps remember a key, as for `--dedupe-window`
+    pub dedupe_window: Option<String>,
+    /// Maximum number of keys dedupe steps remember, as for `--dedupe-keys`
+    pub dedupe_keys: Option<usize>,
+    /// Emit first records with a repeat count, as for `--dedupe-count`
+    #[serde(default)]
+    pub dedupe_count: bool,
+}
+
+impl PipelineFile {
+    /// Read a pipeline file; `.star` files are Starlark, anything else YAML.
+    ///
+    /// Relative script steps and library paths are resolved against the
+    /// directory of the pipeline file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read pipeline file '{}': {}", path.display(), e))?;
+        let is_starlark = path.extension().and_then(|e| e.to_str()) == Some("star");
+        let mut file = if is_starlark {
+            Self::from_starlark(&path.display().to_string(), &source)
+        } else {
+            Self::from_yaml(&source)
+        }
+        .map_err(|e| format!("invalid pipeline file '{}': {}", path.display(), e))?;
+
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            file.resolve_paths(dir);
+        }
+        Ok(file)
+    }
+
+    pub fn from_yaml(source: &str) -> Result<Self, String> {
+        // Go through JSON so steps can be written as `- filter: expr` maps
+        // (serde_yaml alone expects `!filter expr` tags for enums)
+        let document: serde_json::Value =
+            serde_yaml::from_str(source).map_err(|e| e.to_string())?;
+        serde_json::from_value(document).map_err(|e| e.to_string())
+    }
+
+    pub fn from_starlark(name: &str, source: &str) -> Result<Self, String> {
+        let dialect = Dialect {
+            enable_f_strings: true,
+            ..Dialect::Extended
+        };
+        let ast =
+            AstModule::parse(name, source.to_string(), &dialect).map_err(|e| e.to_string())?;
+        let globals = GlobalsBuilder::standard()
+            .with(pipeline_file_functions)
+            .build();
+        let module = Module::new();
+
+        DECLARED.with(|d| *d.borrow_mut() = Declared::default());
+        {
+            let mut eval = Evaluator::new(&module);
+            eval.eval_module(ast, &globals).map_err(|e| e.to_string())?;
+        }
+        let declared = DECLARED.with(|d| std::mem::take(&mut *d.borrow_mut()));
+
+        let mut document = declared.settings;
+        document.insert(
+            "steps".to_string(),
+            serde_json::Value::Array(declared.stages),
+        );
+        serde_json::from_value(serde_json::Value::Object(document)).map_err(|e| e.to_string())
+    }
+
+    /// Read a file of command-line options, like `-f jsonl --filter 'data["status"] >= 500'`.
+    ///
+    /// Only options that a pipeline file can set are accepted; a leading
+    /// `stelp` is ignored. Relative paths are resolved as for `load`.
+    pub fn load_command_line(path: &Path) -> Result<Self, String> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read command file '{}': {}", path.display(), e))?;
+        let mut file = split_command_line(&source)
+            .and_then(|args| Self::from_args(&args))
+            .map_err(|e| format!("invalid command file '{}': {}", path.display(), e))?;
+
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            file.resolve_paths(dir);
+        }
+        Ok(file)
+    }
+
+    /// Settings and steps of command-line options, steps in the order given
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut args = args.iter().peekable();
+        if args.peek().is_some_and(|arg| *arg == "stelp") {
+            args.next();
+        }
+
+        let mut document = serde_json::Map::new();
+        let mut steps = Vec::new();
+        while let Some(arg) = args.next() {
+            let (name, inline_value) = match arg.strip_prefix("--") {
+                Some(long) => match long.split_once('=') {
+                    Some((name, value)) => (name.to_string(), Some(value.to_string())),
+                    None => (long.to_string(), None),
+                },
+                None => match arg.strip_prefix('-').and_then(short_option) {
+                    Some(name) => (name.to_string(), None),
+                    None => return Err(format!("unsupported argument '{}'", arg)),
+                },
+            };
+            let name = match name.as_str() {
+                "extract-vars" => "extract".to_string(),
+                "window" | "window-size" => "window".to_string(),
+                _ => name,
+            };
+
+            if FLAG_OPTIONS.contains(&name.as_str()) {
+                document.insert(name, serde_json::Value::Bool(true));
+                continue;
+            }
+            if name == "dedupe-line" {
+                steps.push(serde_json::json!("dedupe-line"));
+                continue;
+            }
+            let value = match inline_value {
+                Some(value) => value,
+                None => args
+                    .next()
+                    .cloned()
+                    .ok_or_else(|| format!("option '--{}' needs a value", name))?,
+            };
+
+            let value = match name.as_str() {
+                "sample" => serde_json::json!(crate::processors::sample::parse_rate(&value)?),
+                _ if NUMBER_OPTIONS.contains(&name.as_str()) => value
+                    .parse::<u64>()
+                    .map(serde_json::Value::from)
+                    .map_err(|_| format!("option '--{}' needs a number, got '{}'", name, value))?,
+                _ => serde_json::Value::String(value),
+            };
+            if STEP_OPTIONS.contains(&name.as_str()) {
+                steps.push(serde_json::json!({ name: value }));
+            } else if name == "lib-path" {
+                document
+                    .entry("lib-path")
+                    .or_insert_with(|| serde_json::json!([]))
+                    .as_array_mut()
+                    .expect("lib-path is a list")
+                    .push(value);
+            } else {
+                document.insert(name, value);
+            }
+        }
+
+        document.insert("steps".to_string(), serde_json::Value::Array(steps));
+        serde_json::from_value(serde_json::Value::Object(document)).map_err(|e| e.to_string())
+    }
+
+    fn resolve_paths(&mut self, dir: &Path) {
+        for step in &mut self.steps {
+            if let PipelineStep::ScriptFile(path) = step {
+                if path.is_relative() {
+                    *path = dir.join(&*path);
+                }
+            }
+        }
+        for path in &mut self.lib_path {
+            if path.is_relative() {
+                *path = dir.join(&*path);
+            }
+        }
+    }
+}
+
+/// Command-line options that add a step
+const STEP_OPTIONS: &[&str] = &[
+    "extract",
+    "eval",
+    "filter",
+    "derive",
+    "script",
+    "sample",
+    "sample-by",
+    "reservoir",
+    "rate-limit",
+    "dedupe-by",
+];
+
+/// Command-line options without a value
+const FLAG_OPTIONS: &[&str] = &["plain", "fail-fast", "infer-types", "dedupe-count"];
+
+/// Command-line options taking a whole number
+const NUMBER_OPTIONS: &[&str] = &[
+    "window",
+    "window-keys",
+    "chunk-lines",
+    "glob-limit",
+    "seed",
+    "dedupe-keys",
+    "reservoir",
+];
+
+/// Long name of a short option
+fn short_option(short: &str) -> Option<&'static str> {
+    Some(match short {
+        "e" => "eval",
+        "d" => "derive",
+        "s" => "script",
+        "f" => "input-format",
+        "F" => "output-format",
+        "k" => "keys",
+        "K" => "remove-keys",
+        "p" => "plain",
+        "l" => "levels",
+        "L" => "exclude-levels",
+        _ => return None,
+    })
+}
+
+/// Split a command line into arguments, honouring single and double quotes
+/// and backslash escapes like a POSIX shell
+fn split_command_line(source: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut current: Option<String> = None;
+    let mut chars = source.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('\n') | None => {}
+                Some(escaped) => current.get_or_insert_with(String::new).push(escaped),
+            },
+            '\'' => {
+                let arg = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => arg.push(c),
+                        None => return Err("unclosed single quote".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                let arg = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => arg.push(c),
+                            Some('\n') => {}
+                            Some(c) => {
+                                arg.push('\\');
+                                arg.push(c);
+                            }
+                            None => return Err("unclosed double quote".to_string()),
+                        },
+                        Some(c) => arg.push(c),
+                        None => return Err("unclosed double quote".to_string()),
+                    }
+                }
+            }
+            c if c.is_whitespace() => args.extend(current.take()),
+            c => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(current);
+    Ok(args)
+}
+
+/// Settings and stages collected while evaluating a Starlark pipeline file
+#[derive(Default)]
+struct Declared {
+    settings: serde_json::Map<String, serde_json::Value>,
+    stages: Vec<serde_json::Value>,
+}
+
+thread_local! {
+    static DECLARED: RefCell<Declared> = RefCell::new(Declared::default());
+}
+
+#[starlark_module]
+fn pipeline_file_functions(builder: &mut GlobalsBuilder) {
+    /// Add a step: stage("filter", 'data["status"] >= 500'), stage("sample", 0.1),
+    /// stage("dedupe-line")
+    fn stage<'v>(kind: &str, code: Option<Value<'v>>) -> anyhow::Result<NoneType> {
+        let step = match code {
+            Some(code) => {
+                serde_json::json!({ kind: crate::variables::GlobalValue::from_starlark(code).to_json() })
+            }
+            None => serde_json::json!(kind),
+        };
+        serde_json::from_value::<PipelineStep>(step.clone()).map_err(|_| {
+            anyhow::anyhow!(
+                "unknown stage kind '{}' or invalid argument (expected extract, eval, filter, \
+                 derive or script with code, sample, sample-by, reservoir, rate-limit or \
+                 dedupe-by with a setting, or dedupe-line)",
+                kind
+            )
+        })?;
+        DECLARED.with(|d| d.borrow_mut().stages.push(step));
+        Ok(NoneType)
+    }
+
+    /// Set pipeline options: settings(input_format = "jsonl", window = 10)
+    fn settings<'v>(
+        #[starlark(kwargs)] kwargs: starlark::collections::SmallMap<String, Value<'v>>,
+    ) -> anyhow::Result<NoneType> {
+        DECLARED.with(|d| -> anyhow::Result<()> {
+            let mut declared = d.borrow_mut();
+            for (name, value) in kwargs.iter() {
+                declared.settings.insert(
+                    name.replace('_', "-"),
+                    crate::pipeline::global_functions::starlark_to_json_value(*value)?,
+                );
+            }
+            Ok(())
+        })?;
+        Ok(NoneType)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_yaml_and_starlark_files_are_equivalent() {
+        let yaml = PipelineFile::from_yaml(
+            r#"
+input-format: combined
+steps:
+  - filter: 'data["status"] >= 500'
+  - derive: "ms = duration * 1000"
+  - script: enrich.star
+  - rate-limit: 10/s:client
+  - dedupe-line
+keys: [ts, status]
+window: 5
+"#,
+        )
+        .unwrap();
+        let star = PipelineFile::from_starlark(
+            "pipeline.star",
+            r#"
+settings(input_format = "combined", keys = ["ts", "status"], window = 5)
+stage("filter", 'data["status"] >= 500')
+stage("derive", "ms = duration * 1000")
+stage("script", "enrich.star")
+stage("rate-limit", "10/s:client")
+stage("dedupe-line")
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(yaml, star);
+        assert_eq!(
+            yaml.steps[0],
+            PipelineStep::Filter("data[\"status\"] >= 500".to_string())
+        );
+        assert_eq!(
+            yaml.steps[2],
+            PipelineStep::ScriptFile(PathBuf::from("enrich.star"))
+        );
+        assert_eq!(
+            yaml.steps[3],
+            PipelineStep::RateLimit("10/s:client".to_string())
+        );
+        assert_eq!(yaml.keys.unwrap().to_csv(), "ts,status");
+
+        let sampled = PipelineFile::from_starlark(
+            "p.star",
+            "settings(seed = 1)\nstage(\"sample\", 0.1)\nstage(\"reservoir\", 50)\nstage(\"dedupe-line\")",
+        )
+        .unwrap();
+        assert_eq!(
+            sampled.steps,
+            vec![
+                PipelineStep::Sample(0.1),
+                PipelineStep::Reservoir(50),
+                PipelineStep::DedupeLine
+            ]
+        );
+        assert_eq!(sampled.seed, Some(1));
+    }
+
+    #[test]
+    fn test_command_line() {
+        let args = split_command_line(
+            "stelp -f combined --filter 'data[\"status\"] >= 500' \\\n  -d \"ms = duration * 1000\" --sample=10% --dedupe-line -k ts,status --window 5 -p",
+        )
+        .unwrap();
+        assert_eq!(args[4], "data[\"status\"] >= 500");
+        assert_eq!(args[6], "ms = duration * 1000");
+
+        let file = PipelineFile::from_args(&args).unwrap();
+        assert_eq!(file.input_format.as_deref(), Some("combined"));
+        assert_eq!(
+            file.steps,
+            vec![
+                PipelineStep::Filter("data[\"status\"] >= 500".to_string()),
+                PipelineStep::Derive("ms = duration * 1000".to_string()),
+                PipelineStep::Sample(0.1),
+                PipelineStep::DedupeLine,
+            ]
+        );
+        assert_eq!(file.keys.unwrap().to_csv(), "ts,status");
+        assert_eq!(file.window, Some(5));
+        assert!(file.plain);
+
+        let invalid = |line: &str| PipelineFile::from_args(&split_command_line(line).unwrap());
+        assert!(invalid("--filter").is_err());
+        assert!(invalid("-f jsonl input.jsonl").is_err());
+        assert!(invalid("--window five").is_err());
+        assert!(invalid("--output out.jsonl").is_err());
+        assert!(split_command_line("--filter 'line").is_err());
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        assert!(PipelineFile::from_yaml("input-fromat: jsonl").is_err());
+        assert!(PipelineFile::from_yaml("steps:\n  - transform: line").is_err());
+        assert!(PipelineFile::from_starlark("p.star", "stage(\"map\", \"line\")").is_err());
+    }
+}
@@ -0,0 +1,276 @@
+// src/processors/coerce.rs
+//! `--types` / `--infer-types`: turn the strings produced by text-based
+//! input formats (CSV, logfmt, fields, combined) into typed values.
+
+use crate::error::ProcessingError;
+use crate::pipeline::context::{ProcessResult, RecordContext, RecordData};
+use crate::pipeline::global_functions::parse_duration_hybrid;
+use crate::pipeline::stream::RecordProcessor;
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::Value;
+
+/// Target type of a `--types` entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    Int,
+    Float,
+    Bool,
+    Str,
+    /// Seconds, from "1.5s", "2h30m", ...
+    Duration,
+    /// Unix epoch seconds, from RFC 3339, common log format and other dates
+    Timestamp,
+}
+
+impl FieldType {
+    fn parse(name: &str) -> Result<Self, String> {
+        match name.trim().to_lowercase().as_str() {
+            "int" | "integer" => Ok(FieldType::Int),
+            "float" | "number" => Ok(FieldType::Float),
+            "bool" | "boolean" => Ok(FieldType::Bool),
+            "str" | "string" => Ok(FieldType::Str),
+            "duration" => Ok(FieldType::Duration),
+            "timestamp" | "ts" => Ok(FieldType::Timestamp),
+            other => Err(format!(
+                "unknown type '{}' (expected int, float, bool, str, duration or timestamp)",
+                other
+            )),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FieldType::Int => "int",
+            FieldType::Float => "float",
+            FieldType::Bool => "bool",
+            FieldType::Str => "str",
+            FieldType::Duration => "duration",
+            FieldType::Timestamp => "timestamp",
+        }
+    }
+}
+
+/// Parse `status:int,bytes:int,latency:duration`
+pub fn parse_type_spec(spec: &str) -> Result<Vec<(String, FieldType)>, String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (field, kind) = entry.split_once(':').ok_or_else(|| {
+                format!("invalid type declaration '{}' (expected field:type)", entry)
+            })?;
+            Ok((field.trim().to_string(), FieldType::parse(kind)?))
+        })
+        .collect()
+}
+
+/// Convert one value; empty strings become null
+pub fn coerce(value: &Value, kind: FieldType) -> Result<Value, String> {
+    let text = match value {
+        Value::String(s) if s.trim().is_empty() && kind != FieldType::Str => {
+            return Ok(Value::Null)
+        }
+        Value::String(s) => s.trim().to_string(),
+        Value::Null => return Ok(Value::Null),
+        other => other.to_string(),
+    };
+    let fail = || format!("cannot convert '{}' to {}", text, kind.name());
+
+    Ok(match kind {
+        FieldType::Str => match value {
+            Value::String(s) => Value::String(s.clone()),
+            _ => Value::String(text),
+        },
+        FieldType::Int => match text.parse::<i64>() {
+            Ok(i) => Value::from(i),
+            // Accept whole floats such as "3.0" or 1e3
+            Err(_) => match text.parse::<f64>() {
+                Ok(f) if f.fract() == 0.0 && f.abs() < i64::MAX as f64 => Value::from(f as i64),
+                _ => return Err(fail()),
+            },
+        },
+        FieldType::Float => text
+            .parse::<f64>()
+            .ok()
+            .filter(|f| f.is_finite())
+            .map(Value::from)
+            .ok_or_else(fail)?,
+        FieldType::Bool => match text.to_lowercase().as_str() {
+            "true" | "yes" | "on" | "1" | "t" | "y" => Value::Bool(true),
+            "false" | "no" | "off" | "0" | "f" | "n" => Value::Bool(false),
+            _ => return Err(fail()),
+        },
+        FieldType::Duration => match text.parse::<f64>() {
+            Ok(seconds) => Value::from(seconds),
+            Err(_) => Value::from(parse_duration_hybrid(&text).map_err(|_| fail())?),
+        },
+        FieldType::Timestamp => Value::from(parse_timestamp(&text).ok_or_else(fail)?),
+    })
+}
+
+/// Epoch seconds from a number (seconds or milliseconds) or a date string
+pub(crate) fn parse_timestamp(text: &str) -> Option<i64> {
+    parse_datetime(text).map(|dt| dt.timestamp())
+}
+
+/// Time of a number (epoch seconds or milliseconds) or a date string
+pub(crate) fn parse_datetime(text: &str) -> Option<DateTime<Utc>> {
+    if let Ok(number) = text.parse::<f64>() {
+        // Anything past the year 5138 in seconds is really milliseconds
+        let millis = if number.abs() >= 1e11 {
+            number
+        } else {
+            number * 1000.0
+        };
+        return Utc.timestamp_millis_opt(millis as i64).single();
+    }
+    if let Ok(dt) = DateTime::parse_from_rfc3339(text) {
+        return Some(dt.with_timezone(&Utc));
+    }
+    // Common/combined log format: 10/Oct/2000:13:55:36 -0700
+    if let Ok(dt) = DateTime::parse_from_str(text, "%d/%b/%Y:%H:%M:%S %z") {
+        return Some(dt.with_timezone(&Utc));
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"] {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(text, format) {
+            return Some(dt.and_utc());
+        }
+    }
+    dateparser::parse(text).ok()
+}
+
+/// A string that is unambiguously a number. Leading zeros ("007", zip codes,
+/// ids) and leading '+' keep values as strings.
+pub(crate) fn infer(text: &str) -> Option<Value> {
+    static NUMBER: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"^-?(?:0|[1-9]\d*)(\.\d+)?(?:[eE][-+]?\d+)?$").unwrap());
+
+    let captures = NUMBER.captures(text)?;
+    let is_float = captures.get(1).is_some() || text.contains(['e', 'E']);
+    if is_float {
+        text.parse::<f64>()
+            .ok()
+            .filter(|f| f.is_finite())
+            .map(Value::from)
+    } else {
+        // Too big for an i64: leave it alone rather than lose precision
+        text.parse::<i64>().ok().map(Value::from)
+    }
+}
+
+/// Applies declared field types (and optionally number inference) to the
+/// top-level fields of structured records. Text records pass through.
+pub struct TypeCoercionProcessor {
+    name: String,
+    types: Vec<(String, FieldType)>,
+    infer: bool,
+}
+
+impl TypeCoercionProcessor {
+    pub fn new(name: &str, types: Vec<(String, FieldType)>, infer: bool) -> Self {
+        TypeCoercionProcessor {
+            name: name.to_string(),
+            types,
+            infer,
+        }
+    }
+
+    fn apply(&self, data: &Value) -> Result<Value, String> {
+        let Value::Object(fields) = data else {
+            return Ok(data.clone());
+        };
+        let mut fields = fields.clone();
+        if self.infer {
+            for value in fields.values_mut() {
+                if let Some(inferred) = value.as_str().and_then(infer) {
+                    *value = inferred;
+                }
+            }
+        }
+        for (field, kind) in &self.types {
+            if let Some(value) = fields.get_mut(field) {
+                *value = coerce(value, *kind).map_err(|e| format!("field '{}': {}", field, e))?;
+            }
+        }
+        Ok(Value::Object(fields))
+    }
+}
+
+impl RecordProcessor for TypeCoercionProcessor {
+    fn process(&mut self, record: &RecordData, ctx: &RecordContext) -> ProcessResult {
+        match record {
+            RecordData::Text(_) => ProcessResult::Transform(record.clone()),
+            RecordData::Structured(data) => match self.apply(data) {
+                Ok(data) => ProcessResult::Transform(RecordData::Structured(data)),
+                Err(message) => ProcessResult::Error(ProcessingError::ScriptError {
+                    step: self.name.clone(),
+                    line: ctx.line_number,
+                    source: anyhow::anyhow!(message),
+                }),
+            },
+        }
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_type_spec() {
+        assert_eq!(
+            parse_type_spec("status:int, ok:bool,latency:duration").unwrap(),
+            vec![
+                ("status".to_string(), FieldType::Int),
+                ("ok".to_string(), FieldType::Bool),
+                ("latency".to_string(), FieldType::Duration),
+            ]
+        );
+        assert!(parse_type_spec("status").is_err());
+        assert!(parse_type_spec("status:decimal").is_err());
+    }
+
+    #[test]
+    fn test_coerce() {
+        assert_eq!(coerce(&json!("42"), FieldType::Int).unwrap(), json!(42));
+        assert_eq!(coerce(&json!("3.0"), FieldType::Int).unwrap(), json!(3));
+        assert!(coerce(&json!("3.5"), FieldType::Int).is_err());
+        assert_eq!(coerce(&json!(""), FieldType::Int).unwrap(), Value::Null);
+        assert_eq!(coerce(&json!("Yes"), FieldType::Bool).unwrap(), json!(true));
+        assert_eq!(
+            coerce(&json!("1m30s"), FieldType::Duration).unwrap(),
+            json!(90.0)
+        );
+        assert_eq!(coerce(&json!(200), FieldType::Str).unwrap(), json!("200"));
+        assert_eq!(
+            coerce(&json!("10/Oct/2000:13:55:36 -0700"), FieldType::Timestamp).unwrap(),
+            json!(971211336)
+        );
+        assert_eq!(
+            coerce(&json!("2024-01-15T10:30:00Z"), FieldType::Timestamp).unwrap(),
+            json!(1705314600)
+        );
+        assert_eq!(
+            coerce(&json!(1705314600123_i64), FieldType::Timestamp).unwrap(),
+            json!(1705314600)
+        );
+    }
+
+    #[test]
+    fn test_infer() {
+        assert_eq!(infer("12"), Some(json!(12)));
+        assert_eq!(infer("-0.5"), Some(json!(-0.5)));
+        assert_eq!(infer("1e3"), Some(json!(1000.0)));
+        assert_eq!(infer("007"), None);
+        assert_eq!(infer("+5"), None);
+        assert_eq!(infer("12a"), None);
+        assert_eq!(infer("99999999999999999999"), None);
+    }
+}
@@ -0,0 +1,382 @@
+// src/processors/dedupe.rs
+//! `--dedupe-by FIELDS` / `--dedupe-line`: drop records whose key was
+//! already seen.
+//!
+//! Keys are remembered for `--dedupe-window` (measured with record
+//! timestamps, as for `--window-time`) and at most `--dedupe-keys` of them
+//! are kept, forgetting the least recently seen. With `--dedupe-count` the
+//! first record of each key is held back instead and emitted with a
+//! `repeat_count` once its window closes, like syslog's "last message
+//! repeated N times".
+
+use super::window::record_timestamp;
+use crate::pipeline::context::{ProcessResult, RecordContext, RecordData};
+use crate::pipeline::global_functions::parse_duration_hybrid;
+use crate::pipeline::stream::RecordProcessor;
+use std::collections::{BTreeMap, HashMap};
+
+/// Default bound on the number of remembered keys
+pub const DEFAULT_DEDUPE_KEYS: usize = 100_000;
+
+/// Field added to records emitted by `--dedupe-count`
+pub const REPEAT_COUNT_FIELD: &str = "repeat_count";
+
+/// What makes two records duplicates
+#[derive(Debug, Clone, PartialEq)]
+pub enum DedupeKey {
+    /// The same values for all these fields
+    Fields(Vec<String>),
+    /// The same line (or the same record, for structured input)
+    Line,
+}
+
+impl DedupeKey {
+    /// Comma-separated field names, as for `--dedupe-by`
+    pub fn fields(spec: &str) -> Result<Self, String> {
+        let fields: Vec<String> = spec
+            .split(',')
+            .map(|f| f.trim().to_string())
+            .filter(|f| !f.is_empty())
+            .collect();
+        if fields.is_empty() {
+            return Err("--dedupe-by: no field names given".to_string());
+        }
+        Ok(DedupeKey::Fields(fields))
+    }
+}
+
+/// Settings shared by all dedupe steps
+#[derive(Debug, Clone, PartialEq)]
+pub struct DedupeOptions {
+    /// Seconds a key is remembered after its first record
+    pub window: Option<f64>,
+    pub max_keys: usize,
+    /// Emit the first record with its repeat count instead of dropping repeats
+    pub count: bool,
+}
+
+impl Default for DedupeOptions {
+    fn default() -> Self {
+        DedupeOptions {
+            window: None,
+            max_keys: DEFAULT_DEDUPE_KEYS,
+            count: false,
+        }
+    }
+}
+
+impl DedupeOptions {
+    /// Build from the command line / pipeline file options
+    pub fn from_options(
+        window: Option<&str>,
+        max_keys: Option<usize>,
+        count: bool,
+    ) -> Result<Self, String> {
+        let window = window
+            .map(|w| {
+                parse_duration_hybrid(w)
+                    .map_err(|_| format!("--dedupe-window: invalid duration '{}'", w))
+            })
+            .transpose()?;
+        Ok(DedupeOptions {
+            window,
+            max_keys: max_keys.unwrap_or(DEFAULT_DEDUPE_KEYS).max(1),
+            count,
+        })
+    }
+}
+
+struct Seen {
+    /// Timestamp of the first record, which starts the window
+    first_seen: i64,
+    first_order: u64,
+    last_order: u64,
+    repeats: u64,
+    /// The first record, held back in count mode
+    held: Option<RecordData>,
+}
+
+/// Processor that drops (or counts) repeated records
+pub struct DedupeProcessor {
+    name: String,
+    key: DedupeKey,
+    options: DedupeOptions,
+    seen: HashMap<String, Seen>,
+    /// First-seen order -> key, for closing windows
+    by_first: BTreeMap<u64, String>,
+    /// Last-seen order -> key, for LRU eviction
+    by_last: BTreeMap<u64, String>,
+    order: u64,
+    /// Count-mode records whose window closed during the last record
+    ready: Vec<RecordData>,
+    dropped: usize,
+}
+
+impl DedupeProcessor {
+    pub fn new(name: &str, key: DedupeKey, options: DedupeOptions) -> Self {
+        DedupeProcessor {
+            name: name.to_string(),
+            key,
+            options,
+            seen: HashMap::new(),
+            by_first: BTreeMap::new(),
+            by_last: BTreeMap::new(),
+            order: 0,
+            ready: Vec::new(),
+            dropped: 0,
+        }
+    }
+
+    /// The dedupe key of a record; `None` if it has none of the fields
+    fn record_key(&self, record: &RecordData) -> Option<String> {
+        match (&self.key, record) {
+            (DedupeKey::Line, RecordData::Text(line)) => Some(line.clone()),
+            (DedupeKey::Line, RecordData::Structured(data)) => Some(data.to_string()),
+            (DedupeKey::Fields(_), RecordData::Text(_)) => None,
+            (DedupeKey::Fields(fields), RecordData::Structured(data)) => {
+                let values: Vec<&serde_json::Value> = fields
+                    .iter()
+                    .map(|field| data.get(field).unwrap_or(&serde_json::Value::Null))
+                    .collect();
+                if values.iter().all(|v| v.is_null()) {
+                    return None;
+                }
+                // JSON keeps "1" and 1 apart and can't be confused by separators
+                Some(
+                    serde_json::Value::from(values.into_iter().cloned().collect::<Vec<_>>())
+                        .to_string(),
+                )
+            }
+        }
+    }
+
+    /// Forget a key, releasing its held record
+    fn forget(&mut self, key: &str) {
+        if let Some(seen) = self.seen.remove(key) {
+            self.by_first.remove(&seen.first_order);
+            self.by_last.remove(&seen.last_order);
+            if let Some(held) = seen.held {
+                self.ready.push(annotate(held, seen.repeats));
+            }
+        }
+    }
+
+    /// Close the windows that ended before `now`
+    fn expire(&mut self, now: i64) {
+        let Some(window) = self.options.window else {
+            return;
+        };
+        // Records are assumed to be roughly in time order, so the oldest
+        // first-seen keys expire first
+        while let Some((_, key)) = self.by_first.first_key_value() {
+            let first_seen = self.seen[key].first_seen;
+            if (now - first_seen) as f64 <= window {
+                break;
+            }
+            let key = key.clone();
+            self.forget(&key);
+        }
+    }
+}
+
+/// Add the repeat count to a record emitted by `--dedupe-count`
+fn annotate(record: RecordData, repeats: u64) -> RecordData {
+    match record {
+        RecordData::Structured(serde_json::Value::Object(mut object)) => {
+            object.insert(REPEAT_COUNT_FIELD.to_string(), repeats.into());
+            RecordData::Structured(serde_json::Value::Object(object))
+        }
+        RecordData::Text(line) if repeats > 0 => RecordData::Text(format!(
+            "{} (repeated {} time{})",
+            line,
+            repeats,
+            if repeats == 1 { "" } else { "s" }
+        )),
+        other => other,
+    }
+}
+
+impl RecordProcessor for DedupeProcessor {
+    fn process(&mut self, record: &RecordData, _ctx: &RecordContext) -> ProcessResult {
+        let now = record_timestamp(record).unwrap_or_else(|| chrono::Utc::now().timestamp());
+        self.expire(now);
+
+        let Some(key) = self.record_key(record) else {
+            return ProcessResult::Transform(record.clone());
+        };
+
+        self.order += 1;
+        let order = self.order;
+        if let Some(seen) = self.seen.get_mut(&key) {
+            self.by_last.remove(&seen.last_order);
+            seen.last_order = order;
+            seen.repeats += 1;
+            self.by_last.insert(order, key);
+            self.dropped += 1;
+            return ProcessResult::Skip;
+        }
+
+        while self.seen.len() >= self.options.max_keys {
+            let Some((_, oldest)) = self.by_last.first_key_value() else {
+                break;
+            };
+            let oldest = oldest.clone();
+            self.forget(&oldest);
+        }
+        self.seen.insert(
+            key.clone(),
+            Seen {
+                first_seen: now,
+                first_order: order,
+                last_order: order,
+                repeats: 0,
+                held: self.options.count.then(|| record.clone()),
+            },
+        );
+        self.by_first.insert(order, key.clone());
+        self.by_last.insert(order, key);
+
+        if self.options.count {
+            ProcessResult::Skip
+        } else {
+            ProcessResult::Transform(record.clone())
+        }
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn counters(&self) -> Vec<(String, usize)> {
+        vec![("duplicates".to_string(), self.dropped)]
+    }
+
+    fn take_ready(&mut self) -> Vec<RecordData> {
+        std::mem::take(&mut self.ready)
+    }
+
+    fn finish(&mut self) -> Vec<RecordData> {
+        // The last input has ended: release the held records with their
+        // repeat counts
+        if self.options.count {
+            let keys: Vec<String> = self.by_first.values().cloned().collect();
+            for key in keys {
+                self.forget(&key);
+            }
+        }
+        std::mem::take(&mut self.ready)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::variables::GlobalVariables;
+    use serde_json::json;
+
+    fn run(processor: &mut DedupeProcessor, records: &[RecordData]) -> Vec<RecordData> {
+        let globals = GlobalVariables::new();
+        let ctx = RecordContext {
+            line_number: 0,
+            record_count: 0,
+            file_name: None,
+            global_vars: &globals,
+            debug: false,
+        };
+        let mut output = Vec::new();
+        for record in records {
+            if let ProcessResult::Transform(record) = processor.process(record, &ctx) {
+                output.push(record);
+            }
+            output.extend(processor.take_ready());
+        }
+        output.extend(processor.finish());
+        output
+    }
+
+    fn event(id: &str, ts: i64) -> RecordData {
+        RecordData::structured(json!({"id": id, "ts": ts}))
+    }
+
+    #[test]
+    fn test_dedupe_by_fields_within_window() {
+        let options = DedupeOptions::from_options(Some("10s"), None, false).unwrap();
+        let mut dedupe = DedupeProcessor::new("d", DedupeKey::Fields(vec!["id".into()]), options);
+        let output = run(
+            &mut dedupe,
+            &[
+                event("a", 100),
+                event("b", 101),
+                event("a", 105),
+                // Window of the first "a" has passed
+                event("a", 111),
+                RecordData::structured(json!({"other": 1})),
+                RecordData::structured(json!({"other": 1})),
+            ],
+        );
+        let ids: Vec<String> = output
+            .iter()
+            .map(|r| r.as_structured().unwrap()["id"].to_string())
+            .collect();
+        assert_eq!(ids, vec!["\"a\"", "\"b\"", "\"a\"", "null", "null"]);
+        assert_eq!(dedupe.counters(), vec![("duplicates".to_string(), 1)]);
+    }
+
+    #[test]
+    fn test_dedupe_key_limit_forgets_least_recent() {
+        let options = DedupeOptions::from_options(None, Some(2), false).unwrap();
+        let mut dedupe = DedupeProcessor::new("d", DedupeKey::Line, options);
+        let lines: Vec<RecordData> = ["a", "b", "a", "c", "b", "a"]
+            .iter()
+            .map(|l| RecordData::text(l.to_string()))
+            .collect();
+        let output: Vec<String> = run(&mut dedupe, &lines)
+            .iter()
+            .map(|r| r.as_text().unwrap().to_string())
+            .collect();
+        // "c" pushes out "b" (seen less recently than the repeated "a"),
+        // and the returning "b" then pushes out "a"
+        assert_eq!(output, vec!["a", "b", "c", "b", "a"]);
+    }
+
+    #[test]
+    fn test_dedupe_count_emits_when_window_closes() {
+        let options = DedupeOptions::from_options(Some("1m"), None, true).unwrap();
+        let mut dedupe = DedupeProcessor::new("d", DedupeKey::Fields(vec!["id".into()]), options);
+        let globals = GlobalVariables::new();
+        let ctx = RecordContext {
+            line_number: 0,
+            record_count: 0,
+            file_name: None,
+            global_vars: &globals,
+            debug: false,
+        };
+        for record in [
+            event("a", 0),
+            event("a", 10),
+            event("b", 20),
+            event("a", 30),
+        ] {
+            assert!(matches!(dedupe.process(&record, &ctx), ProcessResult::Skip));
+            assert!(dedupe.take_ready().is_empty());
+        }
+        dedupe.process(&event("c", 65), &ctx);
+        let ready = dedupe.take_ready();
+        assert_eq!(ready.len(), 1);
+        assert_eq!(
+            ready[0].as_structured().unwrap(),
+            &json!({"id": "a", "ts": 0, "repeat_count": 2})
+        );
+        let rest: Vec<serde_json::Value> = dedupe
+            .finish()
+            .into_iter()
+            .map(|r| r.as_structured().unwrap()["repeat_count"].clone())
+            .collect();
+        assert_eq!(rest, vec![json!(0), json!(0)]);
+
+        assert_eq!(
+            annotate(RecordData::text("x".into()), 3).as_text(),
+            Some("x (repeated 3 times)")
+        );
+    }
+}
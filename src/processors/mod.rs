@@ -1,3 +1,17 @@
 // src/processors/mod.rs
+pub mod coerce;
+pub mod dedupe;
+pub mod patterns;
+pub mod redact;
+pub mod sample;
+pub mod schema;
+pub mod validate;
 pub mod window;
-pub use window::{WindowProcessor, WindowRecord, WINDOW_CONTEXT};
+pub use coerce::{FieldType, TypeCoercionProcessor};
+pub use dedupe::{DedupeKey, DedupeOptions, DedupeProcessor};
+pub use patterns::{PatternsMode, PatternsProcessor};
+pub use redact::{RedactMode, RedactPattern, RedactProcessor};
+pub use sample::{RateLimitProcessor, ReservoirProcessor, SampleByProcessor, SampleProcessor};
+pub use schema::{SchemaFormat, SchemaProcessor};
+pub use validate::{JsonSchema, ValidateMode, ValidateProcessor};
+pub use window::{WindowConfig, WindowProcessor, WindowRecord, WINDOW_CONTEXT};
@@ -0,0 +1,346 @@
+// src/processors/patterns.rs
+//! `--patterns` / `--pattern-field`: mine message templates from logs.
+//!
+//! Messages (the line, or the first `MESSAGE_KEYS` field of a structured
+//! record) have their variable parts masked with the builtin regexes, so
+//! `Connection to 10.0.0.1 timed out after 30s` becomes
+//! `Connection to <IP> timed out after <NUM>s`, and are then clustered with a
+//! Drain-style prefix tree: messages with the same number of tokens and the
+//! same first token are compared, and joined to a cluster when enough of
+//! their tokens agree, turning the tokens that differ into `<*>`.
+
+use crate::pipeline::config::MESSAGE_KEYS;
+use crate::pipeline::context::{ProcessResult, RecordContext, RecordData};
+use crate::pipeline::global_functions::BUILTIN_REGEXES;
+use crate::pipeline::stream::RecordProcessor;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// Templates reported by `--patterns` unless `--patterns-top` says otherwise
+pub const DEFAULT_PATTERNS_TOP: usize = 50;
+
+/// Builtin regexes masked in messages, in order, with their placeholders
+const MASKS: &[(&str, &str)] = &[
+    ("uuid", "<UUID>"),
+    ("ipv4", "<IP>"),
+    ("hexnum", "<HEX>"),
+    ("num", "<NUM>"),
+];
+
+/// Token standing for any value in a template
+const WILDCARD: &str = "<*>";
+
+/// Leading tokens used to find a message's clusters
+const PREFIX_TOKENS: usize = 1;
+
+/// Children of a tree node before further tokens share a `<*>` child
+const MAX_CHILDREN: usize = 100;
+
+/// Fraction of tokens that must agree for a message to join a cluster
+const SIMILARITY: f64 = 0.4;
+
+/// Sample lines kept per template
+const MAX_SAMPLES: usize = 3;
+
+/// Replace variable parts of a message with placeholders
+pub fn mask_message(message: &str) -> String {
+    MASKS
+        .iter()
+        .fold(message.to_string(), |text, (name, placeholder)| {
+            let (regex, _) = &BUILTIN_REGEXES[name];
+            regex.replace_all(&text, *placeholder).into_owned()
+        })
+}
+
+fn is_variable(token: &str) -> bool {
+    token == WILDCARD
+        || MASKS
+            .iter()
+            .any(|(_, placeholder)| token.contains(placeholder))
+}
+
+/// A message template and the messages that matched it
+#[derive(Debug, Clone)]
+pub struct Cluster {
+    /// Short hash of the first message's masked tokens; it does not change
+    /// as the template gains wildcards
+    pub id: String,
+    pub tokens: Vec<String>,
+    pub count: u64,
+    pub samples: Vec<String>,
+}
+
+impl Cluster {
+    pub fn template(&self) -> String {
+        self.tokens.join(" ")
+    }
+
+    /// Fraction of non-wildcard tokens equal to `tokens`, and the number of
+    /// wildcards (more wildcards break ties, being a more general template)
+    fn similarity(&self, tokens: &[&str]) -> (f64, usize) {
+        if tokens.is_empty() {
+            return (1.0, 0);
+        }
+        let mut same = 0;
+        let mut wildcards = 0;
+        for (template, token) in self.tokens.iter().zip(tokens) {
+            if template == WILDCARD {
+                wildcards += 1;
+            } else if template == token {
+                same += 1;
+            }
+        }
+        (same as f64 / tokens.len() as f64, wildcards)
+    }
+}
+
+#[derive(Default)]
+struct Node {
+    children: HashMap<String, Node>,
+    /// Indexes into `Drain::clusters`, at the leaves
+    clusters: Vec<usize>,
+}
+
+/// Drain-style template miner
+#[derive(Default)]
+pub struct Drain {
+    /// Token count -> prefix tree
+    roots: HashMap<usize, Node>,
+    clusters: Vec<Cluster>,
+}
+
+impl Drain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a message and return the cluster it joined
+    pub fn add(&mut self, message: &str) -> &Cluster {
+        let masked = mask_message(message);
+        let tokens: Vec<&str> = masked.split_whitespace().collect();
+
+        let mut node = self.roots.entry(tokens.len()).or_default();
+        for token in tokens.iter().take(PREFIX_TOKENS) {
+            let mut key = if is_variable(token) { WILDCARD } else { token };
+            if !node.children.contains_key(key) && node.children.len() >= MAX_CHILDREN {
+                key = WILDCARD;
+            }
+            node = node.children.entry(key.to_string()).or_default();
+        }
+
+        let best = node
+            .clusters
+            .iter()
+            .map(|&index| (index, self.clusters[index].similarity(&tokens)))
+            .filter(|(_, (similarity, _))| *similarity >= SIMILARITY)
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|(index, _)| index);
+
+        let index = match best {
+            Some(index) => {
+                let cluster = &mut self.clusters[index];
+                for (template, token) in cluster.tokens.iter_mut().zip(&tokens) {
+                    if template != token {
+                        *template = WILDCARD.to_string();
+                    }
+                }
+                index
+            }
+            None => {
+                let index = self.clusters.len();
+                self.clusters.push(Cluster {
+                    id: template_id(&tokens),
+                    tokens: tokens.iter().map(|t| t.to_string()).collect(),
+                    count: 0,
+                    samples: Vec::new(),
+                });
+                node.clusters.push(index);
+                index
+            }
+        };
+
+        let cluster = &mut self.clusters[index];
+        cluster.count += 1;
+        if cluster.samples.len() < MAX_SAMPLES && !cluster.samples.iter().any(|s| s == message) {
+            cluster.samples.push(message.to_string());
+        }
+        cluster
+    }
+
+    /// Clusters, most frequent first (then in order of first appearance)
+    pub fn clusters(&self) -> Vec<&Cluster> {
+        let mut clusters: Vec<&Cluster> = self.clusters.iter().collect();
+        clusters.sort_by_key(|cluster| std::cmp::Reverse(cluster.count));
+        clusters
+    }
+}
+
+fn template_id(tokens: &[&str]) -> String {
+    let digest = Sha256::digest(tokens.join(" ").as_bytes());
+    digest[..4].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The message of a record: the line itself, or the first string
+/// `MESSAGE_KEYS` field
+fn record_message(record: &RecordData) -> Option<&str> {
+    match record {
+        RecordData::Text(line) => Some(line),
+        RecordData::Structured(data) => MESSAGE_KEYS
+            .iter()
+            .find_map(|key| data.get(*key).and_then(|v| v.as_str())),
+    }
+}
+
+/// What a patterns processor outputs
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatternsMode {
+    /// Consume the records and report the `top` templates at the end of the
+    /// input (all of them when 0)
+    Report { top: usize },
+    /// Pass records on with their template id in this field
+    Annotate { field: String },
+}
+
+/// Processor that clusters messages into templates
+pub struct PatternsProcessor {
+    name: String,
+    mode: PatternsMode,
+    drain: Drain,
+    /// Templates found in inputs already reported
+    reported: usize,
+}
+
+impl PatternsProcessor {
+    pub fn new(name: &str, mode: PatternsMode) -> Self {
+        PatternsProcessor {
+            name: name.to_string(),
+            mode,
+            drain: Drain::new(),
+            reported: 0,
+        }
+    }
+}
+
+impl RecordProcessor for PatternsProcessor {
+    fn process(&mut self, record: &RecordData, _ctx: &RecordContext) -> ProcessResult {
+        let Some(message) = record_message(record) else {
+            return match self.mode {
+                PatternsMode::Report { .. } => ProcessResult::Skip,
+                PatternsMode::Annotate { .. } => ProcessResult::Transform(record.clone()),
+            };
+        };
+        let id = self.drain.add(message).id.clone();
+        match &self.mode {
+            PatternsMode::Report { .. } => ProcessResult::Skip,
+            PatternsMode::Annotate { field } => {
+                let mut object = match record {
+                    RecordData::Structured(serde_json::Value::Object(object)) => object.clone(),
+                    // Text lines become records, as a line has no fields
+                    _ => {
+                        let mut object = serde_json::Map::new();
+                        object.insert("line".to_string(), message.into());
+                        object
+                    }
+                };
+                object.insert(field.clone(), id.into());
+                ProcessResult::Transform(RecordData::Structured(serde_json::Value::Object(object)))
+            }
+        }
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn counters(&self) -> Vec<(String, usize)> {
+        vec![(
+            "templates".to_string(),
+            self.reported + self.drain.clusters.len(),
+        )]
+    }
+
+    fn finish(&mut self) -> Vec<RecordData> {
+        let PatternsMode::Report { top } = self.mode else {
+            return Vec::new();
+        };
+        let clusters = self.drain.clusters();
+        let top = if top == 0 { clusters.len() } else { top };
+        let report = clusters
+            .into_iter()
+            .take(top)
+            .map(|cluster| {
+                RecordData::structured(serde_json::json!({
+                    "template_id": cluster.id,
+                    "count": cluster.count,
+                    "template": cluster.template(),
+                    "samples": cluster.samples,
+                }))
+            })
+            .collect();
+        // Like END, the report covers one input
+        self.reported += self.drain.clusters.len();
+        self.drain = Drain::new();
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mask_message() {
+        assert_eq!(
+            mask_message("Connection to 10.0.0.1 timed out after 30s"),
+            "Connection to <IP> timed out after <NUM>s"
+        );
+        assert_eq!(
+            mask_message("job 123e4567-e89b-12d3-a456-426614174000 at 0x7ffd"),
+            "job <UUID> at <HEX>"
+        );
+    }
+
+    #[test]
+    fn test_drain_clusters_messages() {
+        let mut drain = Drain::new();
+        let first = drain
+            .add("Connection to 10.0.0.1 timed out after 30s")
+            .id
+            .clone();
+        drain.add("user alice logged in");
+        drain.add("Connection to 10.0.0.2 timed out after 5s");
+        drain.add("user bob logged in");
+        let again = drain
+            .add("Connection to 192.168.1.1 timed out after 12s")
+            .id
+            .clone();
+        drain.add("disk full");
+        assert_eq!(first, again);
+
+        let clusters = drain.clusters();
+        let summary: Vec<(String, u64)> =
+            clusters.iter().map(|c| (c.template(), c.count)).collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("Connection to <IP> timed out after <NUM>s".to_string(), 3),
+                ("user <*> logged in".to_string(), 2),
+                ("disk full".to_string(), 1),
+            ]
+        );
+        assert_eq!(
+            clusters[1].samples,
+            vec!["user alice logged in", "user bob logged in"]
+        );
+    }
+
+    #[test]
+    fn test_dissimilar_messages_stay_apart() {
+        let mut drain = Drain::new();
+        drain.add("GET /index.html 200");
+        drain.add("GET /about.html 404");
+        drain.add("POST /login 302");
+        let templates: Vec<String> = drain.clusters().iter().map(|c| c.template()).collect();
+        assert_eq!(templates, vec!["GET <*> <NUM>", "POST /login <NUM>"]);
+    }
+}
@@ -0,0 +1,300 @@
+// src/processors/redact.rs
+//! `--redact`: rewrite PII found by the built-in pattern catalogue.
+
+use crate::pipeline::context::{ProcessResult, RecordContext, RecordData};
+use crate::pipeline::global_functions::{hmac_sha256, pseudonymize_text, to_hex, BUILTIN_REGEXES};
+use crate::pipeline::stream::RecordProcessor;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+/// Patterns only `--redact` uses, looked up before the shared catalogue.
+/// `jwt` is stricter than the shared one, which matches any `a.b.c`, as
+/// masking every dotted name would be worse than missing odd tokens.
+static REDACT_REGEXES: Lazy<HashMap<&'static str, (Regex, &'static str)>> = Lazy::new(|| {
+    HashMap::from([
+        (
+            "credit_card",
+            (
+                Regex::new(r"\b\d(?:[ -]?\d){12,18}\b").unwrap(),
+                "credit card number (13-19 digits, optionally space/dash separated, Luhn-checked)",
+            ),
+        ),
+        (
+            "jwt",
+            (
+                Regex::new(r"\beyJ[A-Za-z0-9_-]+\.eyJ[A-Za-z0-9_-]+\.[A-Za-z0-9_-]+").unwrap(),
+                "JSON Web Token (JWT)",
+            ),
+        ),
+    ])
+});
+
+/// Patterns only available to `--redact`, for `--list-patterns`
+pub fn redact_only_patterns() -> Vec<(&'static str, &'static str)> {
+    let mut patterns: Vec<_> = REDACT_REGEXES
+        .iter()
+        .filter(|(name, _)| !BUILTIN_REGEXES.contains_key(*name))
+        .map(|(name, (_, desc))| (*name, *desc))
+        .collect();
+    patterns.sort_by_key(|(name, _)| *name);
+    patterns
+}
+
+/// How a matched substring is rewritten
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum, Default)]
+pub enum RedactMode {
+    #[default]
+    #[value(name = "mask", help = "Replace with [REDACTED:<pattern>]")]
+    Mask,
+    #[value(name = "hash", help = "Replace with a keyed 16-hex-digit hash")]
+    Hash,
+    #[value(
+        name = "pseudonymize",
+        help = "Replace with a stable fake of the same shape (see pseudonymize())"
+    )]
+    Pseudonymize,
+}
+
+/// A named pattern to redact
+#[derive(Debug, Clone)]
+pub struct RedactPattern {
+    pub name: String,
+    pub regex: Regex,
+}
+
+impl RedactPattern {
+    /// Look a pattern up in the built-in catalogue (see --list-patterns)
+    pub fn builtin(name: &str) -> Option<Self> {
+        REDACT_REGEXES
+            .get(name)
+            .or_else(|| BUILTIN_REGEXES.get(name))
+            .map(|(regex, _)| RedactPattern {
+                name: name.to_string(),
+                regex: regex.clone(),
+            })
+    }
+
+    /// Parse a pattern file: one `name = regex` per line, `#` starts a comment
+    pub fn parse_file(content: &str) -> Result<Vec<Self>, String> {
+        let mut patterns = Vec::new();
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, regex) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected 'name = regex'", index + 1))?;
+            let name = name.trim();
+            if name.is_empty() {
+                return Err(format!("line {}: missing pattern name", index + 1));
+            }
+            let regex = Regex::new(regex.trim())
+                .map_err(|e| format!("line {}: invalid regex for '{}': {}", index + 1, name, e))?;
+            patterns.push(RedactPattern {
+                name: name.to_string(),
+                regex,
+            });
+        }
+        Ok(patterns)
+    }
+
+    pub fn load_file(path: &Path) -> Result<Vec<Self>, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read '{}': {}", path.display(), e))?;
+        Self::parse_file(&content).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Extra check for patterns whose regex alone is too loose
+    fn accepts(&self, matched: &str) -> bool {
+        match self.name.as_str() {
+            "credit_card" => luhn_valid(matched),
+            _ => true,
+        }
+    }
+}
+
+/// Luhn checksum over the digits of `text`, ignoring separators
+fn luhn_valid(text: &str) -> bool {
+    let digits: Vec<u32> = text.chars().filter_map(|c| c.to_digit(10)).collect();
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| match (i % 2 == 1, d * 2) {
+            (true, doubled) if doubled > 9 => doubled - 9,
+            (true, doubled) => doubled,
+            (false, _) => d,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+/// Rewrites every match of the configured patterns in text lines and in all
+/// string values (at any depth) of structured records
+pub struct RedactProcessor {
+    name: String,
+    patterns: Vec<RedactPattern>,
+    mode: RedactMode,
+    salt: String,
+    counts: BTreeMap<String, usize>,
+}
+
+impl RedactProcessor {
+    pub fn new(name: &str, patterns: Vec<RedactPattern>, mode: RedactMode, salt: &str) -> Self {
+        let counts = patterns.iter().map(|p| (p.name.clone(), 0)).collect();
+        RedactProcessor {
+            name: name.to_string(),
+            patterns,
+            mode,
+            salt: salt.to_string(),
+            counts,
+        }
+    }
+
+    /// Redact one string. Overlapping matches are resolved leftmost-longest,
+    /// with earlier patterns winning ties.
+    pub fn redact_str(&mut self, text: &str) -> String {
+        let mut matches: Vec<(usize, usize, usize)> = Vec::new();
+        for (index, pattern) in self.patterns.iter().enumerate() {
+            for m in pattern.regex.find_iter(text) {
+                if !m.is_empty() && pattern.accepts(m.as_str()) {
+                    matches.push((m.start(), m.end(), index));
+                }
+            }
+        }
+        if matches.is_empty() {
+            return text.to_string();
+        }
+        matches.sort_by_key(|&(start, end, index)| (start, std::cmp::Reverse(end), index));
+
+        let mut result = String::with_capacity(text.len());
+        let mut position = 0;
+        for (start, end, index) in matches {
+            if start < position {
+                continue;
+            }
+            result.push_str(&text[position..start]);
+            let name = &self.patterns[index].name;
+            result.push_str(&self.replacement(name, &text[start..end]));
+            *self.counts.entry(name.clone()).or_default() += 1;
+            position = end;
+        }
+        result.push_str(&text[position..]);
+        result
+    }
+
+    fn replacement(&self, name: &str, matched: &str) -> String {
+        match self.mode {
+            RedactMode::Mask => format!("[REDACTED:{}]", name),
+            RedactMode::Hash => {
+                to_hex(&hmac_sha256(self.salt.as_bytes(), matched.as_bytes()))[..16].to_string()
+            }
+            RedactMode::Pseudonymize => pseudonymize_text(matched, &self.salt),
+        }
+    }
+
+    fn redact_value(&mut self, value: &Value) -> Value {
+        match value {
+            Value::String(s) => Value::String(self.redact_str(s)),
+            Value::Array(items) => {
+                Value::Array(items.iter().map(|v| self.redact_value(v)).collect())
+            }
+            Value::Object(map) => Value::Object(
+                map.iter()
+                    .map(|(k, v)| (k.clone(), self.redact_value(v)))
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    }
+}
+
+impl RecordProcessor for RedactProcessor {
+    fn process(&mut self, record: &RecordData, _ctx: &RecordContext) -> ProcessResult {
+        ProcessResult::Transform(match record {
+            RecordData::Text(line) => RecordData::Text(self.redact_str(line)),
+            RecordData::Structured(value) => RecordData::Structured(self.redact_value(value)),
+        })
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn counters(&self) -> Vec<(String, usize)> {
+        self.counts
+            .iter()
+            .map(|(name, count)| (name.clone(), *count))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn processor(names: &[&str], mode: RedactMode) -> RedactProcessor {
+        let patterns = names
+            .iter()
+            .map(|name| RedactPattern::builtin(name).unwrap())
+            .collect();
+        RedactProcessor::new("redact", patterns, mode, "salt")
+    }
+
+    #[test]
+    fn test_luhn() {
+        assert!(luhn_valid("4111 1111 1111 1111"));
+        assert!(luhn_valid("5500-0000-0000-0004"));
+        assert!(!luhn_valid("4111 1111 1111 1112"));
+    }
+
+    #[test]
+    fn test_mask_counts_and_overlaps() {
+        let mut redact = processor(&["email", "fqdn", "credit_card"], RedactMode::Mask);
+        let out = redact.redact_str("bob@example.com paid 4111111111111111 ref 1234567890123");
+        assert_eq!(
+            out,
+            "[REDACTED:email] paid [REDACTED:credit_card] ref 1234567890123"
+        );
+        assert_eq!(
+            redact.counters(),
+            vec![
+                ("credit_card".to_string(), 1),
+                ("email".to_string(), 1),
+                ("fqdn".to_string(), 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_jwt_needs_json_segments() {
+        let mut redact = processor(&["jwt"], RedactMode::Mask);
+        assert_eq!(
+            redact.redact_str("auth eyJhbGciOiJIUzI1NiJ9.eyJzdWIiOiIxIn0.c2ln for api.example.com"),
+            "auth [REDACTED:jwt] for api.example.com"
+        );
+    }
+
+    #[test]
+    fn test_hash_is_stable() {
+        let mut redact = processor(&["ipv4"], RedactMode::Hash);
+        let first = redact.redact_str("from 10.1.2.3");
+        assert_eq!(first, redact.redact_str("from 10.1.2.3"));
+        assert_eq!(first.len(), "from ".len() + 16);
+    }
+
+    #[test]
+    fn test_parse_file() {
+        let patterns =
+            RedactPattern::parse_file("# corp ids\nemployee = EMP-\\d{6}\n\nticket=T#\\d+\n")
+                .unwrap();
+        let names: Vec<_> = patterns.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["employee", "ticket"]);
+        assert!(patterns[1].regex.is_match("T#42"));
+        assert!(RedactPattern::parse_file("no equals sign").is_err());
+    }
+}
@@ -0,0 +1,398 @@
+// src/processors/sample.rs
+//! Sampling steps: `--sample`, `--sample-by`, `--reservoir` and
+//! `--rate-limit`.
+//!
+//! Each is an ordinary processor, so it runs at its position among the
+//! `--filter`/`-e` steps: a sample placed after a filter samples the records
+//! that passed it.
+
+use crate::pipeline::context::{ProcessResult, RecordContext, RecordData};
+use crate::pipeline::global_functions::parse_duration_hybrid;
+use crate::pipeline::stream::RecordProcessor;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// Rate-limit buckets kept before idle ones are dropped
+const MAX_BUCKETS: usize = 10_000;
+
+/// Parse a sampling rate: a fraction in (0, 1] such as `0.01`, or a
+/// percentage such as `1%`
+pub fn parse_rate(text: &str) -> Result<f64, String> {
+    let text = text.trim();
+    let rate = match text.strip_suffix('%') {
+        Some(percent) => percent.trim().parse::<f64>().map(|p| p / 100.0),
+        None => text.parse::<f64>(),
+    }
+    .map_err(|_| format!("invalid sampling rate '{}'", text))?;
+    check_rate(rate)
+}
+
+fn check_rate(rate: f64) -> Result<f64, String> {
+    if rate > 0.0 && rate <= 1.0 {
+        Ok(rate)
+    } else {
+        Err(format!(
+            "sampling rate {} must be greater than 0 and at most 1",
+            rate
+        ))
+    }
+}
+
+/// Value of `field` as a string key; `None` for text records and records
+/// without the field
+fn field_key(record: &RecordData, field: &str) -> Option<String> {
+    match record.as_structured()?.get(field)? {
+        serde_json::Value::String(s) => Some(s.clone()),
+        serde_json::Value::Null => None,
+        other => Some(other.to_string()),
+    }
+}
+
+fn rng(seed: Option<u64>) -> fastrand::Rng {
+    seed.map_or_else(fastrand::Rng::new, fastrand::Rng::with_seed)
+}
+
+/// `--sample RATE`: keep each record with probability RATE
+pub struct SampleProcessor {
+    name: String,
+    rate: f64,
+    rng: fastrand::Rng,
+}
+
+impl SampleProcessor {
+    pub fn new(name: &str, rate: f64, seed: Option<u64>) -> Result<Self, String> {
+        Ok(SampleProcessor {
+            name: name.to_string(),
+            rate: check_rate(rate).map_err(|e| format!("--sample: {}", e))?,
+            rng: rng(seed),
+        })
+    }
+}
+
+impl RecordProcessor for SampleProcessor {
+    fn process(&mut self, record: &RecordData, _ctx: &RecordContext) -> ProcessResult {
+        if self.rng.f64() < self.rate {
+            ProcessResult::Transform(record.clone())
+        } else {
+            ProcessResult::Skip
+        }
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// `--sample-by FIELD:RATE`: keep all or none of the records with a given
+/// field value, chosen by hashing the value, so the same keys are kept on
+/// every run (records without the field are sampled as one group)
+pub struct SampleByProcessor {
+    name: String,
+    field: String,
+    /// Hashes below this are kept
+    threshold: u64,
+    seed: Option<u64>,
+}
+
+impl SampleByProcessor {
+    pub fn new(name: &str, field: &str, rate: f64, seed: Option<u64>) -> Self {
+        SampleByProcessor {
+            name: name.to_string(),
+            field: field.to_string(),
+            threshold: if rate >= 1.0 {
+                u64::MAX
+            } else {
+                (rate * u64::MAX as f64) as u64
+            },
+            seed,
+        }
+    }
+
+    /// Parse `FIELD:RATE`
+    pub fn parse(name: &str, spec: &str, seed: Option<u64>) -> Result<Self, String> {
+        let (field, rate) = spec
+            .rsplit_once(':')
+            .filter(|(field, _)| !field.is_empty())
+            .ok_or_else(|| format!("--sample-by: expected FIELD:RATE, got '{}'", spec))?;
+        let rate = parse_rate(rate).map_err(|e| format!("--sample-by: {}", e))?;
+        Ok(Self::new(name, field, rate, seed))
+    }
+
+    fn keeps(&self, key: &str) -> bool {
+        let mut hasher = Sha256::new();
+        if let Some(seed) = self.seed {
+            hasher.update(seed.to_le_bytes());
+        }
+        hasher.update(key.as_bytes());
+        let digest = hasher.finalize();
+        let hash = u64::from_be_bytes(digest[..8].try_into().unwrap());
+        hash <= self.threshold
+    }
+}
+
+impl RecordProcessor for SampleByProcessor {
+    fn process(&mut self, record: &RecordData, _ctx: &RecordContext) -> ProcessResult {
+        let key = field_key(record, &self.field).unwrap_or_default();
+        if self.keeps(&key) {
+            ProcessResult::Transform(record.clone())
+        } else {
+            ProcessResult::Skip
+        }
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// `--reservoir N`: a uniform sample of N records, emitted in input order
+/// when the last input ends
+pub struct ReservoirProcessor {
+    name: String,
+    size: usize,
+    seen: u64,
+    /// (input position, record)
+    sample: Vec<(u64, RecordData)>,
+    rng: fastrand::Rng,
+}
+
+impl ReservoirProcessor {
+    pub fn new(name: &str, size: usize, seed: Option<u64>) -> Self {
+        ReservoirProcessor {
+            name: name.to_string(),
+            size,
+            seen: 0,
+            sample: Vec::with_capacity(size.min(1024)),
+            rng: rng(seed),
+        }
+    }
+}
+
+impl RecordProcessor for ReservoirProcessor {
+    fn process(&mut self, record: &RecordData, _ctx: &RecordContext) -> ProcessResult {
+        // Algorithm R: the n-th record replaces a random slot with
+        // probability size/n
+        let position = self.seen;
+        self.seen += 1;
+        if self.sample.len() < self.size {
+            self.sample.push((position, record.clone()));
+        } else {
+            let slot = self.rng.u64(0..self.seen) as usize;
+            if slot < self.size {
+                self.sample[slot] = (position, record.clone());
+            }
+        }
+        ProcessResult::Skip
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn finish(&mut self) -> Vec<RecordData> {
+        let mut sample = std::mem::take(&mut self.sample);
+        sample.sort_by_key(|(position, _)| *position);
+        self.seen = 0;
+        sample.into_iter().map(|(_, record)| record).collect()
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// `--rate-limit N/PERIOD[:FIELD]`: a token bucket per field value that
+/// lets through bursts of up to N records and N per PERIOD after that
+pub struct RateLimitProcessor {
+    name: String,
+    capacity: f64,
+    /// Tokens added per second
+    refill: f64,
+    field: Option<String>,
+    buckets: HashMap<Option<String>, Bucket>,
+    limited: usize,
+}
+
+impl RateLimitProcessor {
+    pub fn new(name: &str, count: u32, period: f64, field: Option<&str>) -> Self {
+        RateLimitProcessor {
+            name: name.to_string(),
+            capacity: count as f64,
+            refill: count as f64 / period,
+            field: field.map(|f| f.to_string()),
+            buckets: HashMap::new(),
+            limited: 0,
+        }
+    }
+
+    /// Parse `N/PERIOD[:FIELD]`, e.g. `100/s`, `10/5m:user`
+    pub fn parse(name: &str, spec: &str) -> Result<Self, String> {
+        let invalid = || {
+            format!(
+                "--rate-limit: expected N/PERIOD[:FIELD] such as 100/s or 10/m:user, got '{}'",
+                spec
+            )
+        };
+        let (count, rest) = spec.split_once('/').ok_or_else(invalid)?;
+        let (period, field) = match rest.split_once(':') {
+            Some((period, field)) if !field.is_empty() => (period, Some(field)),
+            Some(_) => return Err(invalid()),
+            None => (rest, None),
+        };
+        let count: u32 = count.trim().parse().map_err(|_| invalid())?;
+        // A bare unit means one of it: "s" is "1s"
+        let period = period.trim();
+        let period = if period.starts_with(|c: char| c.is_ascii_digit()) {
+            parse_duration_hybrid(period)
+        } else {
+            parse_duration_hybrid(&format!("1{}", period))
+        }
+        .map_err(|_| invalid())?;
+        if count == 0 || period <= 0.0 {
+            return Err(invalid());
+        }
+        Ok(Self::new(name, count, period, field))
+    }
+
+    fn allow(&mut self, key: Option<String>, now: Instant) -> bool {
+        if !self.buckets.contains_key(&key) && self.buckets.len() >= MAX_BUCKETS {
+            self.evict(now);
+        }
+        let capacity = self.capacity;
+        let bucket = self.buckets.entry(key).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.refill).min(capacity);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Drop buckets that have refilled (forgetting them changes nothing),
+    /// or failing that the least recently used one
+    fn evict(&mut self, now: Instant) {
+        let (capacity, refill) = (self.capacity, self.refill);
+        self.buckets.retain(|_, bucket| {
+            let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+            bucket.tokens + elapsed * refill < capacity
+        });
+        if self.buckets.len() >= MAX_BUCKETS {
+            let oldest = self
+                .buckets
+                .iter()
+                .min_by_key(|(_, bucket)| bucket.updated)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.buckets.remove(&oldest);
+            }
+        }
+    }
+}
+
+impl RecordProcessor for RateLimitProcessor {
+    fn process(&mut self, record: &RecordData, _ctx: &RecordContext) -> ProcessResult {
+        let key = self
+            .field
+            .as_ref()
+            .and_then(|field| field_key(record, field));
+        if self.allow(key, Instant::now()) {
+            ProcessResult::Transform(record.clone())
+        } else {
+            self.limited += 1;
+            ProcessResult::Skip
+        }
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn counters(&self) -> Vec<(String, usize)> {
+        vec![("rate_limited".to_string(), self.limited)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!(parse_rate("0.25"), Ok(0.25));
+        assert_eq!(parse_rate("5%"), Ok(0.05));
+        assert!(parse_rate("0").is_err());
+        assert!(parse_rate("1.5").is_err());
+        assert!(parse_rate("lots").is_err());
+    }
+
+    #[test]
+    fn test_sample_by_is_deterministic() {
+        let sampler = SampleByProcessor::parse("s", "user:0.5", None).unwrap();
+        let kept: Vec<bool> = (0..200)
+            .map(|i| sampler.keeps(&format!("user{}", i)))
+            .collect();
+        let again = SampleByProcessor::parse("s", "user:0.5", None).unwrap();
+        assert!((0..200).all(|i| again.keeps(&format!("user{}", i)) == kept[i]));
+        let count = kept.iter().filter(|k| **k).count();
+        assert!((60..140).contains(&count), "{}", count);
+
+        assert!(SampleByProcessor::parse("s", "user:1", None)
+            .unwrap()
+            .keeps("anyone"));
+        assert!(SampleByProcessor::parse("s", "0.5", None).is_err());
+    }
+
+    #[test]
+    fn test_reservoir_keeps_input_order() {
+        let mut reservoir = ReservoirProcessor::new("r", 3, Some(7));
+        let globals = crate::variables::GlobalVariables::new();
+        let ctx = RecordContext {
+            line_number: 0,
+            record_count: 0,
+            file_name: None,
+            global_vars: &globals,
+            debug: false,
+        };
+        for i in 0..100 {
+            let result = reservoir.process(&RecordData::text(i.to_string()), &ctx);
+            assert!(matches!(result, ProcessResult::Skip));
+        }
+        let sample: Vec<usize> = reservoir
+            .finish()
+            .iter()
+            .map(|r| r.as_text().unwrap().parse().unwrap())
+            .collect();
+        assert_eq!(sample.len(), 3);
+        assert!(sample.windows(2).all(|w| w[0] < w[1]));
+        assert!(reservoir.finish().is_empty());
+    }
+
+    #[test]
+    fn test_rate_limit_token_bucket() {
+        let mut limiter = RateLimitProcessor::parse("r", "2/s:user").unwrap();
+        let start = Instant::now();
+        let alice = || Some("alice".to_string());
+        assert!(limiter.allow(alice(), start));
+        assert!(limiter.allow(alice(), start));
+        assert!(!limiter.allow(alice(), start));
+        // Other keys have their own bucket
+        assert!(limiter.allow(Some("bob".to_string()), start));
+        // Half a second refills one token
+        assert!(limiter.allow(alice(), start + Duration::from_millis(500)));
+        assert!(!limiter.allow(alice(), start + Duration::from_millis(500)));
+
+        assert!(RateLimitProcessor::parse("r", "10/5m").is_ok());
+        assert!(RateLimitProcessor::parse("r", "10").is_err());
+        assert!(RateLimitProcessor::parse("r", "0/s").is_err());
+        assert!(RateLimitProcessor::parse("r", "10/s:").is_err());
+    }
+}
@@ -0,0 +1,554 @@
+// src/processors/schema.rs
+//! `--schema`: profile the fields of structured records.
+//!
+//! Every (nested) field path gets its presence, the types of its values,
+//! an estimate of its distinct values, the range of its numbers and
+//! timestamps, its most frequent values and a few examples. At the end of
+//! the input the profile is output as one record per field, as a JSON Schema
+//! document, or as a `--types` declaration.
+
+use super::coerce::{infer, parse_timestamp, FieldType};
+use crate::accumulators::{HyperLogLog, TopK};
+use crate::pipeline::context::{ProcessResult, RecordContext, RecordData};
+use crate::pipeline::global_functions::parse_duration_hybrid;
+use crate::pipeline::stream::RecordProcessor;
+use indexmap::IndexMap;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+/// Field paths profiled; further fields are only counted
+const MAX_FIELDS: usize = 1_000;
+
+/// Most frequent values reported per field
+const TOP_VALUES: usize = 5;
+
+/// Example values reported per field
+const MAX_EXAMPLES: usize = 3;
+
+/// What `--schema` outputs
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum, Default)]
+pub enum SchemaFormat {
+    #[default]
+    #[value(name = "report", help = "One record per field path")]
+    Report,
+    #[value(name = "json-schema", help = "JSON Schema (draft 2020-12) document")]
+    JsonSchema,
+    #[value(name = "types", help = "--types declaration for the top-level fields")]
+    Types,
+}
+
+/// What a value looks like: its JSON type, with strings that hold numbers,
+/// booleans, timestamps or durations told apart (as they are in CSV or
+/// logfmt input)
+fn value_kind(value: &Value) -> &'static str {
+    static TIMESTAMP: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r"^(?:\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}|\d{2}/[A-Za-z]{3}/\d{4}:\d{2}:\d{2})")
+            .unwrap()
+    });
+    static DURATION: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"^(?:\d+(?:\.\d+)?(?:ns|us|µs|ms|s|m|h|d))+$").unwrap());
+
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Number(n) if n.is_f64() => "float",
+        Value::Number(_) => "int",
+        Value::Object(_) => "object",
+        Value::Array(_) => "array",
+        Value::String(s) => {
+            let text = s.trim();
+            match infer(text) {
+                Some(Value::Number(n)) if n.is_f64() => return "float",
+                Some(_) => return "int",
+                None => {}
+            }
+            if text.eq_ignore_ascii_case("true") || text.eq_ignore_ascii_case("false") {
+                "bool"
+            } else if TIMESTAMP.is_match(text) && parse_timestamp(text).is_some() {
+                "timestamp"
+            } else if DURATION.is_match(text) && parse_duration_hybrid(text).is_ok() {
+                "duration"
+            } else {
+                "str"
+            }
+        }
+    }
+}
+
+/// JSON Schema name of a value's type
+fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Everything observed about one field path
+struct FieldProfile {
+    /// Records the field appears in
+    records: u64,
+    last_record: u64,
+    /// Values seen; more than `records` for array items
+    occurrences: u64,
+    json_types: BTreeMap<&'static str, u64>,
+    kinds: BTreeMap<&'static str, u64>,
+    numbers: Option<(f64, f64)>,
+    timestamps: Option<(i64, i64)>,
+    distinct: HyperLogLog,
+    top: TopK,
+    examples: Vec<Value>,
+    children: IndexMap<String, FieldProfile>,
+    items: Option<Box<FieldProfile>>,
+}
+
+impl FieldProfile {
+    fn new() -> Self {
+        FieldProfile {
+            records: 0,
+            last_record: 0,
+            occurrences: 0,
+            json_types: BTreeMap::new(),
+            kinds: BTreeMap::new(),
+            numbers: None,
+            timestamps: None,
+            distinct: HyperLogLog::new(),
+            top: TopK::new(TOP_VALUES),
+            examples: Vec::new(),
+            children: IndexMap::new(),
+            items: None,
+        }
+    }
+
+    /// Add a value of the field in record number `record`; `fields` counts
+    /// the paths profiled so far
+    fn observe(&mut self, value: &Value, record: u64, fields: &mut usize) {
+        if self.last_record != record {
+            self.last_record = record;
+            self.records += 1;
+        }
+        self.occurrences += 1;
+        *self.json_types.entry(json_type(value)).or_insert(0) += 1;
+        let kind = value_kind(value);
+        *self.kinds.entry(kind).or_insert(0) += 1;
+
+        match value {
+            Value::Object(object) => {
+                for (key, child) in object {
+                    if !self.children.contains_key(key) {
+                        if *fields >= MAX_FIELDS {
+                            continue;
+                        }
+                        *fields += 1;
+                        self.children.insert(key.clone(), FieldProfile::new());
+                    }
+                    self.children[key].observe(child, record, fields);
+                }
+            }
+            Value::Array(items) => {
+                for item in items {
+                    if self.items.is_none() {
+                        if *fields >= MAX_FIELDS {
+                            break;
+                        }
+                        *fields += 1;
+                        self.items = Some(Box::new(FieldProfile::new()));
+                    }
+                    if let Some(profile) = self.items.as_mut() {
+                        profile.observe(item, record, fields);
+                    }
+                }
+            }
+            Value::Null => {}
+            scalar => self.observe_scalar(scalar, kind),
+        }
+    }
+
+    fn observe_scalar(&mut self, value: &Value, kind: &str) {
+        let text = match value {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        self.distinct.add(&text);
+        self.top.add(&text);
+        if self.examples.len() < MAX_EXAMPLES && !self.examples.contains(value) {
+            self.examples.push(value.clone());
+        }
+
+        let number = match value {
+            Value::Number(n) => n.as_f64(),
+            Value::String(s) if kind == "int" || kind == "float" => s.trim().parse().ok(),
+            _ => None,
+        };
+        if let Some(number) = number {
+            let (min, max) = self.numbers.get_or_insert((number, number));
+            *min = min.min(number);
+            *max = max.max(number);
+        }
+        if kind == "timestamp" {
+            if let Some(ts) = parse_timestamp(text.trim()) {
+                let (min, max) = self.timestamps.get_or_insert((ts, ts));
+                *min = (*min).min(ts);
+                *max = (*max).max(ts);
+            }
+        }
+    }
+
+    /// The `--types` type fitting all non-null values, if they are scalars
+    fn suggested_type(&self) -> Option<FieldType> {
+        let kinds: Vec<&str> = self
+            .kinds
+            .keys()
+            .copied()
+            .filter(|kind| *kind != "null")
+            .collect();
+        let all = |allowed: &[&str]| kinds.iter().all(|kind| allowed.contains(kind));
+        if kinds.is_empty() || kinds.contains(&"object") || kinds.contains(&"array") {
+            None
+        } else if all(&["int"]) {
+            Some(FieldType::Int)
+        } else if all(&["int", "float"]) {
+            Some(FieldType::Float)
+        } else if all(&["bool"]) {
+            Some(FieldType::Bool)
+        } else if all(&["timestamp"]) {
+            Some(FieldType::Timestamp)
+        } else if all(&["duration", "int", "float"]) {
+            Some(FieldType::Duration)
+        } else {
+            Some(FieldType::Str)
+        }
+    }
+
+    /// Report records for this field and the fields nested in it
+    fn report(&self, path: &str, total: u64, output: &mut Vec<RecordData>) {
+        let mut record = Map::new();
+        record.insert("field".to_string(), json!(path));
+        let present = if total == 0 {
+            0.0
+        } else {
+            (self.records as f64 * 1000.0 / total as f64).round() / 10.0
+        };
+        record.insert("present".to_string(), json!(present));
+        let kinds: Vec<&str> = self.kinds.keys().copied().collect();
+        record.insert("types".to_string(), json!(kinds.join(",")));
+        if let Some(kind) = self.suggested_type() {
+            record.insert("type".to_string(), json!(kind.name()));
+        }
+        if !self.examples.is_empty() {
+            record.insert("cardinality".to_string(), json!(self.distinct.estimate()));
+        }
+        if let Some((min, max)) = self.numbers {
+            record.insert("min".to_string(), number(min));
+            record.insert("max".to_string(), number(max));
+        } else if let Some((min, max)) = self.timestamps {
+            record.insert("min".to_string(), json!(rfc3339(min)));
+            record.insert("max".to_string(), json!(rfc3339(max)));
+        }
+        if !self.examples.is_empty() {
+            let top: Map<String, Value> = self
+                .top
+                .top()
+                .into_iter()
+                .map(|(value, count)| (value, json!(count)))
+                .collect();
+            record.insert("top".to_string(), Value::Object(top));
+            record.insert("examples".to_string(), Value::Array(self.examples.clone()));
+        }
+        output.push(RecordData::Structured(Value::Object(record)));
+
+        for (key, child) in &self.children {
+            child.report(&format!("{}.{}", path, key), total, output);
+        }
+        if let Some(items) = &self.items {
+            items.report(&format!("{}[]", path), total, output);
+        }
+    }
+
+    /// JSON Schema for the values of this field
+    fn json_schema(&self) -> Value {
+        let mut types: Vec<&str> = self.json_types.keys().copied().collect();
+        // Every integer is also a number
+        if types.contains(&"number") {
+            types.retain(|t| *t != "integer");
+        }
+        let mut schema = Map::new();
+        match types.as_slice() {
+            [] => {}
+            [single] => {
+                schema.insert("type".to_string(), json!(single));
+            }
+            several => {
+                schema.insert("type".to_string(), json!(several));
+            }
+        }
+        if let Some(&objects) = self.json_types.get("object") {
+            object_schema(&self.children, objects, &mut schema);
+        }
+        if let Some(items) = &self.items {
+            schema.insert("items".to_string(), items.json_schema());
+        }
+        Value::Object(schema)
+    }
+}
+
+/// Add `properties` and `required` (the fields found in all `objects`
+/// objects) to a schema
+fn object_schema(
+    children: &IndexMap<String, FieldProfile>,
+    objects: u64,
+    schema: &mut Map<String, Value>,
+) {
+    if children.is_empty() {
+        return;
+    }
+    let properties: Map<String, Value> = children
+        .iter()
+        .map(|(key, child)| (key.clone(), child.json_schema()))
+        .collect();
+    let required: Vec<&String> = children
+        .iter()
+        .filter(|(_, child)| child.occurrences == objects)
+        .map(|(key, _)| key)
+        .collect();
+    schema.insert("properties".to_string(), Value::Object(properties));
+    if !required.is_empty() {
+        schema.insert("required".to_string(), json!(required));
+    }
+}
+
+/// Whole numbers as integers
+fn number(value: f64) -> Value {
+    if value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+        json!(value as i64)
+    } else {
+        json!(value)
+    }
+}
+
+fn rfc3339(ts: i64) -> String {
+    chrono::DateTime::from_timestamp(ts, 0)
+        .map(|dt| dt.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+        .unwrap_or_else(|| ts.to_string())
+}
+
+/// Processor that profiles structured records and outputs the profile at
+/// the end of the last input
+pub struct SchemaProcessor {
+    name: String,
+    format: SchemaFormat,
+    /// Profiles of the top-level fields
+    fields: IndexMap<String, FieldProfile>,
+    records: u64,
+    field_count: usize,
+}
+
+impl SchemaProcessor {
+    pub fn new(name: &str, format: SchemaFormat) -> Self {
+        SchemaProcessor {
+            name: name.to_string(),
+            format,
+            fields: IndexMap::new(),
+            records: 0,
+            field_count: 0,
+        }
+    }
+
+    fn json_schema(&self) -> Value {
+        let mut schema = Map::new();
+        schema.insert(
+            "$schema".to_string(),
+            json!("https://json-schema.org/draft/2020-12/schema"),
+        );
+        schema.insert("type".to_string(), json!("object"));
+        object_schema(&self.fields, self.records, &mut schema);
+        Value::Object(schema)
+    }
+
+    fn types_declaration(&self) -> String {
+        self.fields
+            .iter()
+            .filter_map(|(key, profile)| match profile.suggested_type()? {
+                FieldType::Str => None,
+                kind => Some(format!("{}:{}", key, kind.name())),
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+impl RecordProcessor for SchemaProcessor {
+    fn process(&mut self, record: &RecordData, _ctx: &RecordContext) -> ProcessResult {
+        if let Some(Value::Object(object)) = record.as_structured() {
+            self.records += 1;
+            for (key, value) in object {
+                if !self.fields.contains_key(key) {
+                    if self.field_count >= MAX_FIELDS {
+                        continue;
+                    }
+                    self.field_count += 1;
+                    self.fields.insert(key.clone(), FieldProfile::new());
+                }
+                self.fields[key].observe(value, self.records, &mut self.field_count);
+            }
+        }
+        ProcessResult::Skip
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn counters(&self) -> Vec<(String, usize)> {
+        vec![("fields".to_string(), self.field_count)]
+    }
+
+    fn finish(&mut self) -> Vec<RecordData> {
+        let output = match self.format {
+            SchemaFormat::Report => {
+                let mut output = Vec::new();
+                for (key, profile) in &self.fields {
+                    profile.report(key, self.records, &mut output);
+                }
+                output
+            }
+            SchemaFormat::JsonSchema if self.records > 0 => {
+                vec![RecordData::Structured(self.json_schema())]
+            }
+            SchemaFormat::Types if self.records > 0 => {
+                vec![RecordData::Text(self.types_declaration())]
+            }
+            _ => Vec::new(),
+        };
+        // Like END, the profile covers one input
+        self.fields.clear();
+        self.records = 0;
+        self.field_count = 0;
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::variables::GlobalVariables;
+
+    fn profile(format: SchemaFormat, records: &[Value]) -> Vec<RecordData> {
+        let globals = GlobalVariables::new();
+        let ctx = RecordContext {
+            line_number: 0,
+            record_count: 0,
+            file_name: None,
+            global_vars: &globals,
+            debug: false,
+        };
+        let mut schema = SchemaProcessor::new("schema", format);
+        for record in records {
+            schema.process(&RecordData::structured(record.clone()), &ctx);
+        }
+        schema.finish()
+    }
+
+    fn records() -> Vec<Value> {
+        vec![
+            json!({"ts": "2024-01-01T00:00:00Z", "status": "200", "user": {"id": 7, "tags": ["a"]}}),
+            json!({"ts": "2024-01-01T00:05:00Z", "status": "404", "user": {"id": 9}, "took": "1.5s"}),
+            json!({"ts": "2024-01-01T00:01:00Z", "status": "200", "user": {"id": 7, "tags": []}, "took": null}),
+            json!({"ts": "2024-01-01T00:02:00Z", "status": "500", "user": {"id": 8.5}}),
+        ]
+    }
+
+    #[test]
+    fn test_value_kinds() {
+        assert_eq!(value_kind(&json!("42")), "int");
+        assert_eq!(value_kind(&json!("007")), "str");
+        assert_eq!(value_kind(&json!("1.5")), "float");
+        assert_eq!(value_kind(&json!("TRUE")), "bool");
+        assert_eq!(value_kind(&json!("2024-01-01 10:00:00")), "timestamp");
+        assert_eq!(value_kind(&json!("2h30m")), "duration");
+        assert_eq!(value_kind(&json!("5 minutes")), "str");
+        assert_eq!(value_kind(&json!(1.0)), "float");
+    }
+
+    #[test]
+    fn test_field_report() {
+        let report = profile(SchemaFormat::Report, &records());
+        let fields: Vec<&Value> = report.iter().map(|r| r.as_structured().unwrap()).collect();
+        let paths: Vec<&str> = fields
+            .iter()
+            .map(|f| f["field"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                "ts",
+                "status",
+                "user",
+                "user.id",
+                "user.tags",
+                "user.tags[]",
+                "took"
+            ]
+        );
+
+        assert_eq!(fields[0]["type"], "timestamp");
+        assert_eq!(fields[0]["min"], "2024-01-01T00:00:00Z");
+        assert_eq!(fields[0]["max"], "2024-01-01T00:05:00Z");
+
+        assert_eq!(fields[1]["present"], 100.0);
+        assert_eq!(fields[1]["type"], "int");
+        assert_eq!(fields[1]["cardinality"], 3);
+        assert_eq!(fields[1]["min"], 200);
+        assert_eq!(fields[1]["max"], 500);
+        assert_eq!(fields[1]["top"], json!({"200": 2, "404": 1, "500": 1}));
+
+        assert_eq!(fields[2]["types"], "object");
+        assert!(fields[2].get("top").is_none());
+        assert_eq!(fields[3]["types"], "float,int");
+        assert_eq!(fields[3]["type"], "float");
+        assert_eq!(fields[3]["max"], 9);
+        assert_eq!(fields[4]["present"], 50.0);
+        assert_eq!(fields[5]["present"], 25.0);
+        assert_eq!(fields[6]["types"], "duration,null");
+        assert_eq!(fields[6]["type"], "duration");
+        assert_eq!(fields[6]["examples"], json!(["1.5s"]));
+    }
+
+    #[test]
+    fn test_json_schema_and_types() {
+        let schema = profile(SchemaFormat::JsonSchema, &records());
+        assert_eq!(
+            schema[0].as_structured().unwrap(),
+            &json!({
+                "$schema": "https://json-schema.org/draft/2020-12/schema",
+                "type": "object",
+                "properties": {
+                    "ts": {"type": "string"},
+                    "status": {"type": "string"},
+                    "user": {
+                        "type": "object",
+                        "properties": {
+                            "id": {"type": "number"},
+                            "tags": {"type": "array", "items": {"type": "string"}}
+                        },
+                        "required": ["id"]
+                    },
+                    "took": {"type": ["null", "string"]}
+                },
+                "required": ["ts", "status", "user"]
+            })
+        );
+
+        let types = profile(SchemaFormat::Types, &records());
+        assert_eq!(
+            types[0].as_text(),
+            Some("ts:timestamp,status:int,took:duration")
+        );
+    }
+}
@@ -0,0 +1,641 @@
+// src/processors/validate.rs
+//! `--validate schema.json`: check structured records against a JSON Schema.
+//!
+//! A subset of draft 2020-12 is supported: `type`, `enum`, `const`,
+//! `required`, `properties`, `additionalProperties`, `items`, `minItems`,
+//! `maxItems`, `pattern`, `minLength`, `maxLength`, `minimum`, `maximum`,
+//! `exclusiveMinimum` and `exclusiveMaximum`. A schema using another
+//! keyword that changes validation (`$ref`, `oneOf`, ...) is rejected rather
+//! than half-checked; annotations (`format`, `description`, ...) are
+//! ignored. Text records pass through unchecked.
+
+use crate::error::ProcessingError;
+use crate::pipeline::context::{ProcessResult, RecordContext, RecordData};
+use crate::pipeline::stream::RecordProcessor;
+use indexmap::IndexMap;
+use regex::Regex;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Field listing the violations of a record in `annotate` mode
+pub const ERRORS_FIELD: &str = "_errors";
+
+/// What happens to records that violate the schema
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum, Default)]
+pub enum ValidateMode {
+    /// Treat them as errors: skipped (and written to --dead-letter) or fatal with --fail-fast
+    #[default]
+    Reject,
+    /// Pass them on with the violations in an `_errors` field
+    Annotate,
+    /// Pass them on unchanged; --stats counts the violations
+    Count,
+}
+
+/// Draft 2020-12 keywords (and their draft 7 names) that constrain values
+/// but are not implemented
+const UNSUPPORTED_KEYWORDS: &[&str] = &[
+    "$ref",
+    "$dynamicRef",
+    "$recursiveRef",
+    "$defs",
+    "definitions",
+    "allOf",
+    "anyOf",
+    "oneOf",
+    "not",
+    "if",
+    "then",
+    "else",
+    "dependentRequired",
+    "dependentSchemas",
+    "dependencies",
+    "patternProperties",
+    "propertyNames",
+    "unevaluatedProperties",
+    "minProperties",
+    "maxProperties",
+    "prefixItems",
+    "additionalItems",
+    "unevaluatedItems",
+    "contains",
+    "minContains",
+    "maxContains",
+    "uniqueItems",
+    "multipleOf",
+];
+
+/// JSON Schema type names
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum JsonType {
+    Null,
+    Boolean,
+    Integer,
+    Number,
+    String,
+    Array,
+    Object,
+}
+
+impl JsonType {
+    fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "null" => Ok(JsonType::Null),
+            "boolean" => Ok(JsonType::Boolean),
+            "integer" => Ok(JsonType::Integer),
+            "number" => Ok(JsonType::Number),
+            "string" => Ok(JsonType::String),
+            "array" => Ok(JsonType::Array),
+            "object" => Ok(JsonType::Object),
+            other => Err(format!("unknown type '{}'", other)),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            JsonType::Null => "null",
+            JsonType::Boolean => "boolean",
+            JsonType::Integer => "integer",
+            JsonType::Number => "number",
+            JsonType::String => "string",
+            JsonType::Array => "array",
+            JsonType::Object => "object",
+        }
+    }
+
+    fn matches(self, value: &Value) -> bool {
+        match (self, value) {
+            (JsonType::Null, Value::Null)
+            | (JsonType::Boolean, Value::Bool(_))
+            | (JsonType::Number, Value::Number(_))
+            | (JsonType::String, Value::String(_))
+            | (JsonType::Array, Value::Array(_))
+            | (JsonType::Object, Value::Object(_)) => true,
+            // 1.0 is an integer too
+            (JsonType::Integer, Value::Number(n)) => n.as_f64().is_some_and(|f| f.fract() == 0.0),
+            _ => false,
+        }
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+enum Additional {
+    Allowed,
+    Forbidden,
+    Schema(Box<JsonSchema>),
+}
+
+/// A compiled schema
+pub struct JsonSchema {
+    /// The `false` schema, which nothing matches
+    never: bool,
+    types: Option<Vec<JsonType>>,
+    allowed: Option<Vec<Value>>,
+    constant: Option<Value>,
+    required: Vec<String>,
+    properties: IndexMap<String, JsonSchema>,
+    additional: Additional,
+    items: Option<Box<JsonSchema>>,
+    min_items: Option<usize>,
+    max_items: Option<usize>,
+    pattern: Option<Regex>,
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+    minimum: Option<f64>,
+    maximum: Option<f64>,
+    exclusive_minimum: Option<f64>,
+    exclusive_maximum: Option<f64>,
+}
+
+/// One way in which a value breaks a schema
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    /// Where: `user.roles[2]`
+    pub path: String,
+    /// The keyword broken, e.g. `required`
+    pub keyword: &'static str,
+    pub message: String,
+}
+
+impl Violation {
+    /// The rule broken, for counting: the path without array indexes and
+    /// the keyword, e.g. `user.roles[]/enum`
+    pub fn rule(&self) -> String {
+        let mut path = String::with_capacity(self.path.len());
+        let mut in_index = false;
+        for c in self.path.chars() {
+            match c {
+                '[' => {
+                    in_index = true;
+                    path.push_str("[]");
+                }
+                ']' => in_index = false,
+                _ if in_index => {}
+                c => path.push(c),
+            }
+        }
+        format!(
+            "{}/{}",
+            if path.is_empty() { "$" } else { &path },
+            self.keyword
+        )
+    }
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+fn child_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+fn number(schema: &Value, keyword: &str) -> Result<Option<f64>, String> {
+    match schema.get(keyword) {
+        None => Ok(None),
+        Some(value) => value
+            .as_f64()
+            .map(Some)
+            .ok_or_else(|| format!("'{}' must be a number", keyword)),
+    }
+}
+
+fn count(schema: &Value, keyword: &str) -> Result<Option<usize>, String> {
+    match schema.get(keyword) {
+        None => Ok(None),
+        Some(value) => value
+            .as_u64()
+            .map(|n| Some(n as usize))
+            .ok_or_else(|| format!("'{}' must be a non-negative integer", keyword)),
+    }
+}
+
+impl JsonSchema {
+    /// Read and compile a schema file
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read schema '{}': {}", path.display(), e))?;
+        let schema: Value = serde_json::from_str(&text)
+            .map_err(|e| format!("invalid JSON in schema '{}': {}", path.display(), e))?;
+        Self::from_json(&schema).map_err(|e| format!("schema '{}': {}", path.display(), e))
+    }
+
+    pub fn from_json(schema: &Value) -> Result<Self, String> {
+        let mut compiled = JsonSchema {
+            never: false,
+            types: None,
+            allowed: None,
+            constant: None,
+            required: Vec::new(),
+            properties: IndexMap::new(),
+            additional: Additional::Allowed,
+            items: None,
+            min_items: None,
+            max_items: None,
+            pattern: None,
+            min_length: None,
+            max_length: None,
+            minimum: None,
+            maximum: None,
+            exclusive_minimum: None,
+            exclusive_maximum: None,
+        };
+        let object = match schema {
+            Value::Bool(accept) => {
+                compiled.never = !accept;
+                return Ok(compiled);
+            }
+            Value::Object(object) => object,
+            _ => return Err("a schema must be an object or a boolean".to_string()),
+        };
+        if let Some(keyword) = UNSUPPORTED_KEYWORDS
+            .iter()
+            .find(|keyword| object.contains_key(**keyword))
+        {
+            return Err(format!("unsupported keyword '{}'", keyword));
+        }
+
+        compiled.types = match object.get("type") {
+            None => None,
+            Some(Value::String(name)) => Some(vec![JsonType::parse(name)?]),
+            Some(Value::Array(names)) => Some(
+                names
+                    .iter()
+                    .map(|name| {
+                        name.as_str()
+                            .ok_or_else(|| "'type' entries must be strings".to_string())
+                            .and_then(JsonType::parse)
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            Some(_) => return Err("'type' must be a string or an array".to_string()),
+        };
+        compiled.allowed = match object.get("enum") {
+            None => None,
+            Some(Value::Array(values)) => Some(values.clone()),
+            Some(_) => return Err("'enum' must be an array".to_string()),
+        };
+        compiled.constant = object.get("const").cloned();
+        if let Some(required) = object.get("required") {
+            compiled.required = required
+                .as_array()
+                .and_then(|names| names.iter().map(|n| n.as_str().map(String::from)).collect())
+                .ok_or_else(|| "'required' must be an array of strings".to_string())?;
+        }
+        if let Some(properties) = object.get("properties") {
+            let properties = properties
+                .as_object()
+                .ok_or_else(|| "'properties' must be an object".to_string())?;
+            for (key, schema) in properties {
+                let schema = Self::from_json(schema).map_err(|e| format!("{}: {}", key, e))?;
+                compiled.properties.insert(key.clone(), schema);
+            }
+        }
+        compiled.additional = match object.get("additionalProperties") {
+            None | Some(Value::Bool(true)) => Additional::Allowed,
+            Some(Value::Bool(false)) => Additional::Forbidden,
+            Some(schema) => Additional::Schema(Box::new(Self::from_json(schema)?)),
+        };
+        if let Some(items) = object.get("items") {
+            compiled.items = Some(Box::new(
+                Self::from_json(items).map_err(|e| format!("items: {}", e))?,
+            ));
+        }
+        compiled.min_items = count(schema, "minItems")?;
+        compiled.max_items = count(schema, "maxItems")?;
+        if let Some(pattern) = object.get("pattern") {
+            let pattern = pattern
+                .as_str()
+                .ok_or_else(|| "'pattern' must be a string".to_string())?;
+            compiled.pattern = Some(
+                Regex::new(pattern).map_err(|e| format!("invalid pattern '{}': {}", pattern, e))?,
+            );
+        }
+        compiled.min_length = count(schema, "minLength")?;
+        compiled.max_length = count(schema, "maxLength")?;
+        compiled.minimum = number(schema, "minimum")?;
+        compiled.maximum = number(schema, "maximum")?;
+        compiled.exclusive_minimum = number(schema, "exclusiveMinimum")?;
+        compiled.exclusive_maximum = number(schema, "exclusiveMaximum")?;
+        Ok(compiled)
+    }
+
+    /// All violations of `value`, found at `path`
+    pub fn validate(&self, value: &Value, path: &str, violations: &mut Vec<Violation>) {
+        let mut fail = |keyword: &'static str, message: String| {
+            violations.push(Violation {
+                path: path.to_string(),
+                keyword,
+                message,
+            })
+        };
+
+        if self.never {
+            fail("false", "not allowed".to_string());
+            return;
+        }
+        if let Some(types) = &self.types {
+            if !types.iter().any(|t| t.matches(value)) {
+                let expected: Vec<&str> = types.iter().map(|t| t.name()).collect();
+                fail(
+                    "type",
+                    format!(
+                        "expected {}, got {}",
+                        expected.join(" or "),
+                        type_name(value)
+                    ),
+                );
+                // Other keywords would only repeat the mismatch
+                return;
+            }
+        }
+        if let Some(allowed) = &self.allowed {
+            if !allowed.contains(value) {
+                fail(
+                    "enum",
+                    format!("{} is not one of the allowed values", value),
+                );
+            }
+        }
+        if let Some(constant) = &self.constant {
+            if constant != value {
+                fail("const", format!("expected {}, got {}", constant, value));
+            }
+        }
+
+        match value {
+            Value::String(text) => {
+                let length = text.chars().count();
+                if let Some(min) = self.min_length.filter(|min| length < *min) {
+                    fail("minLength", format!("shorter than {} characters", min));
+                }
+                if let Some(max) = self.max_length.filter(|max| length > *max) {
+                    fail("maxLength", format!("longer than {} characters", max));
+                }
+                if let Some(pattern) = &self.pattern {
+                    if !pattern.is_match(text) {
+                        fail(
+                            "pattern",
+                            format!("\"{}\" does not match pattern '{}'", text, pattern),
+                        );
+                    }
+                }
+            }
+            Value::Number(n) => {
+                let n = n.as_f64().unwrap_or(f64::NAN);
+                if let Some(min) = self.minimum.filter(|min| n < *min) {
+                    fail("minimum", format!("{} is below the minimum {}", n, min));
+                }
+                if let Some(max) = self.maximum.filter(|max| n > *max) {
+                    fail("maximum", format!("{} is above the maximum {}", n, max));
+                }
+                if let Some(min) = self.exclusive_minimum.filter(|min| n <= *min) {
+                    fail("exclusiveMinimum", format!("{} is not above {}", n, min));
+                }
+                if let Some(max) = self.exclusive_maximum.filter(|max| n >= *max) {
+                    fail("exclusiveMaximum", format!("{} is not below {}", n, max));
+                }
+            }
+            Value::Array(items) => {
+                if let Some(min) = self.min_items.filter(|min| items.len() < *min) {
+                    fail("minItems", format!("fewer than {} items", min));
+                }
+                if let Some(max) = self.max_items.filter(|max| items.len() > *max) {
+                    fail("maxItems", format!("more than {} items", max));
+                }
+                if let Some(schema) = &self.items {
+                    for (index, item) in items.iter().enumerate() {
+                        schema.validate(item, &format!("{}[{}]", path, index), violations);
+                    }
+                }
+            }
+            Value::Object(object) => {
+                for name in &self.required {
+                    if !object.contains_key(name) {
+                        violations.push(Violation {
+                            path: child_path(path, name),
+                            keyword: "required",
+                            message: "required field is missing".to_string(),
+                        });
+                    }
+                }
+                for (key, child) in object {
+                    let child_path = child_path(path, key);
+                    match (self.properties.get(key), &self.additional) {
+                        (Some(schema), _) => schema.validate(child, &child_path, violations),
+                        (None, Additional::Allowed) => {}
+                        (None, Additional::Forbidden) => violations.push(Violation {
+                            path: child_path,
+                            keyword: "additionalProperties",
+                            message: "field is not allowed".to_string(),
+                        }),
+                        (None, Additional::Schema(schema)) => {
+                            schema.validate(child, &child_path, violations)
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Processor that checks structured records against a schema
+pub struct ValidateProcessor {
+    name: String,
+    schema: JsonSchema,
+    mode: ValidateMode,
+    invalid: usize,
+    /// Violations per rule, see `Violation::rule`
+    rules: BTreeMap<String, usize>,
+}
+
+impl ValidateProcessor {
+    pub fn new(name: &str, schema: JsonSchema, mode: ValidateMode) -> Self {
+        ValidateProcessor {
+            name: name.to_string(),
+            schema,
+            mode,
+            invalid: 0,
+            rules: BTreeMap::new(),
+        }
+    }
+}
+
+impl RecordProcessor for ValidateProcessor {
+    fn process(&mut self, record: &RecordData, ctx: &RecordContext) -> ProcessResult {
+        let Some(data) = record.as_structured() else {
+            return ProcessResult::Transform(record.clone());
+        };
+        let mut violations = Vec::new();
+        self.schema.validate(data, "", &mut violations);
+        if violations.is_empty() {
+            return ProcessResult::Transform(record.clone());
+        }
+
+        self.invalid += 1;
+        for violation in &violations {
+            *self.rules.entry(violation.rule()).or_insert(0) += 1;
+        }
+        let messages: Vec<String> = violations.iter().map(|v| v.to_string()).collect();
+        match self.mode {
+            ValidateMode::Reject => ProcessResult::Error(ProcessingError::ScriptError {
+                step: self.name.clone(),
+                line: ctx.line_number,
+                source: anyhow::anyhow!("schema violation: {}", messages.join("; ")),
+            }),
+            ValidateMode::Annotate => {
+                let mut data = data.clone();
+                if let Value::Object(object) = &mut data {
+                    object.insert(ERRORS_FIELD.to_string(), messages.into());
+                }
+                ProcessResult::Transform(RecordData::Structured(data))
+            }
+            ValidateMode::Count => ProcessResult::Transform(record.clone()),
+        }
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn counters(&self) -> Vec<(String, usize)> {
+        let mut counters = vec![("invalid".to_string(), self.invalid)];
+        counters.extend(
+            self.rules
+                .iter()
+                .map(|(rule, count)| (rule.clone(), *count)),
+        );
+        counters
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn violations(schema: Value, value: Value) -> Vec<String> {
+        let schema = JsonSchema::from_json(&schema).unwrap();
+        let mut violations = Vec::new();
+        schema.validate(&value, "", &mut violations);
+        violations
+            .iter()
+            .map(|v| format!("{} ({})", v, v.rule()))
+            .collect()
+    }
+
+    #[test]
+    fn test_types_required_and_nested() {
+        let schema = json!({
+            "type": "object",
+            "required": ["status", "user"],
+            "properties": {
+                "status": {"type": "integer", "minimum": 100, "maximum": 599},
+                "user": {
+                    "type": "object",
+                    "required": ["id"],
+                    "properties": {"roles": {"type": "array", "items": {"enum": ["admin", "dev"]}}}
+                },
+                "took": {"type": ["number", "null"], "exclusiveMinimum": 0}
+            }
+        });
+        assert!(violations(
+            schema.clone(),
+            json!({"status": 200.0, "user": {"id": 1}, "took": null})
+        )
+        .is_empty());
+        assert_eq!(
+            violations(
+                schema.clone(),
+                json!({"status": "200", "user": {"roles": ["dev", "root"]}, "took": 0})
+            ),
+            vec![
+                "status: expected integer, got string (status/type)",
+                "user.id: required field is missing (user.id/required)",
+                "user.roles[1]: \"root\" is not one of the allowed values (user.roles[]/enum)",
+                "took: 0 is not above 0 (took/exclusiveMinimum)",
+            ]
+        );
+        assert_eq!(
+            violations(schema, json!({"status": 700, "user": {"id": 1}})),
+            vec!["status: 700 is above the maximum 599 (status/maximum)"]
+        );
+    }
+
+    #[test]
+    fn test_strings_arrays_and_additional_properties() {
+        let schema = json!({
+            "properties": {
+                "level": {"type": "string", "pattern": "^(info|warn|error)$"},
+                "code": {"type": "string", "minLength": 3, "maxLength": 3},
+                "tags": {"type": "array", "maxItems": 1},
+                "kind": {"const": "event"}
+            },
+            "additionalProperties": false
+        });
+        assert_eq!(
+            violations(
+                schema,
+                json!({"level": "debug", "code": "E1", "tags": [1, 2], "kind": "event", "x": 1})
+            ),
+            vec![
+                "level: \"debug\" does not match pattern '^(info|warn|error)$' (level/pattern)",
+                "code: shorter than 3 characters (code/minLength)",
+                "tags: more than 1 items (tags/maxItems)",
+                "x: field is not allowed (x/additionalProperties)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_schemas() {
+        assert!(JsonSchema::from_json(&json!({"type": "text"})).is_err());
+        assert!(JsonSchema::from_json(&json!({"pattern": "("})).is_err());
+        assert!(JsonSchema::from_json(&json!({"properties": {"a": 1}})).is_err());
+        assert!(JsonSchema::from_json(&json!(false)).is_ok());
+    }
+
+    #[test]
+    fn test_unsupported_keywords_are_rejected() {
+        let err = |schema: Value| JsonSchema::from_json(&schema).err().unwrap();
+        assert_eq!(
+            err(json!({"oneOf": [{"type": "string"}]})),
+            "unsupported keyword 'oneOf'"
+        );
+        assert_eq!(
+            err(json!({"properties": {"user": {"$ref": "#/$defs/user"}}})),
+            "user: unsupported keyword '$ref'"
+        );
+        // Annotations don't change what is valid
+        assert!(JsonSchema::from_json(&json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "title": "event",
+            "description": "an event",
+            "properties": {"ts": {"type": "string", "format": "date-time", "examples": ["2024-01-01T00:00:00Z"]}}
+        }))
+        .is_ok());
+    }
+}
@@ -1,20 +1,29 @@
 // src/processors/window.rs
+use super::coerce::parse_timestamp;
+use crate::pipeline::config::TIMESTAMP_KEYS;
 use crate::pipeline::context::{ProcessResult, RecordContext, RecordData};
+use crate::pipeline::global_functions::parse_duration_hybrid;
 use crate::pipeline::stream::RecordProcessor;
 use serde_json;
 use std::cell::RefCell;
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+/// Default bound on the number of `--window-by` partitions
+pub const DEFAULT_WINDOW_KEYS: usize = 10_000;
 
 /// Record stored in window buffer
 #[derive(Debug, Clone)]
 pub struct WindowRecord {
     /// Text content (if text record)
     pub line: Option<String>,
-    /// Structured data (if structured record)  
+    /// Structured data (if structured record)
     pub data: Option<serde_json::Value>,
     /// Metadata for debugging
     pub line_number: usize,
     pub record_count: usize,
+    /// Epoch seconds used by time windows: the record's timestamp field,
+    /// or the time it was processed if it has none
+    pub timestamp: i64,
 }
 
 impl WindowRecord {
@@ -24,46 +33,183 @@ impl WindowRecord {
             data: record.as_structured().cloned(),
             line_number: ctx.line_number,
             record_count: ctx.record_count,
+            timestamp: record_timestamp(record).unwrap_or_else(|| chrono::Utc::now().timestamp()),
+        }
+    }
+}
+
+/// First timestamp field (see `TIMESTAMP_KEYS`) of a structured record
+pub(crate) fn record_timestamp(record: &RecordData) -> Option<i64> {
+    let object = record.as_structured()?.as_object()?;
+    let value = TIMESTAMP_KEYS.iter().find_map(|key| object.get(*key))?;
+    match value {
+        serde_json::Value::String(s) => parse_timestamp(s),
+        serde_json::Value::Number(n) => parse_timestamp(&n.to_string()),
+        _ => None,
+    }
+}
+
+/// Which records a window keeps
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WindowConfig {
+    /// Keep at most this many records (`--window N`)
+    pub size: Option<usize>,
+    /// Keep records at most this many seconds older than the current one
+    /// (`--window-time 5m`)
+    pub duration: Option<f64>,
+    /// Keep a separate window per value of this field (`--window-by user`)
+    pub key: Option<String>,
+    /// Partitions kept before the least recently used is dropped
+    pub max_keys: usize,
+}
+
+impl WindowConfig {
+    /// The classic window of the last `size` records
+    pub fn last(size: usize) -> Self {
+        WindowConfig {
+            size: Some(size),
+            max_keys: DEFAULT_WINDOW_KEYS,
+            ..Default::default()
+        }
+    }
+
+    /// Build from the command line / pipeline file options; `None` when no
+    /// window is configured
+    pub fn from_options(
+        size: Option<usize>,
+        time: Option<&str>,
+        key: Option<&str>,
+        max_keys: Option<usize>,
+    ) -> Result<Option<Self>, String> {
+        if size.is_none() && time.is_none() {
+            if key.is_some() {
+                return Err("--window-by needs --window N or --window-time DURATION".to_string());
+            }
+            return Ok(None);
         }
+        let duration = time
+            .map(|t| {
+                parse_duration_hybrid(t)
+                    .map_err(|_| format!("--window-time: invalid duration '{}'", t))
+            })
+            .transpose()?;
+        Ok(Some(WindowConfig {
+            size,
+            duration,
+            key: key.map(|k| k.to_string()),
+            max_keys: max_keys.unwrap_or(DEFAULT_WINDOW_KEYS).max(1),
+        }))
     }
 }
 
 /// Processor that maintains a sliding window of recent records
 pub struct WindowProcessor {
-    window_size: usize,
-    buffer: VecDeque<WindowRecord>,
+    config: WindowConfig,
+    /// One buffer per `--window-by` value (a single `None` entry otherwise)
+    partitions: HashMap<Option<String>, Partition>,
+    /// Last use tick -> partition, for LRU eviction
+    recency: BTreeMap<u64, Option<String>>,
+    tick: u64,
     inner_processor: Box<dyn RecordProcessor>,
 }
 
+struct Partition {
+    buffer: VecDeque<WindowRecord>,
+    tick: u64,
+}
+
 impl WindowProcessor {
     pub fn new(window_size: usize, inner_processor: Box<dyn RecordProcessor>) -> Self {
+        Self::with_config(WindowConfig::last(window_size), inner_processor)
+    }
+
+    pub fn with_config(config: WindowConfig, inner_processor: Box<dyn RecordProcessor>) -> Self {
         WindowProcessor {
-            window_size,
-            buffer: VecDeque::with_capacity(window_size),
+            config,
+            partitions: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
             inner_processor,
         }
     }
 
-    fn add_to_buffer(&mut self, record: &RecordData, ctx: &RecordContext) {
+    /// The `--window-by` value of a record; records without the field share
+    /// one partition
+    fn partition_key(&self, record: &RecordData) -> Option<String> {
+        let field = self.config.key.as_ref()?;
+        match record.as_structured()?.get(field)? {
+            serde_json::Value::String(s) => Some(s.clone()),
+            serde_json::Value::Null => None,
+            other => Some(other.to_string()),
+        }
+    }
+
+    /// Add the record to its partition and return that partition's records
+    fn add_to_buffer(
+        &mut self,
+        record: &RecordData,
+        ctx: &RecordContext,
+    ) -> &VecDeque<WindowRecord> {
         let window_record = WindowRecord::from_record_data(record, ctx);
+        let key = self.partition_key(record);
 
-        self.buffer.push_back(window_record);
+        self.tick += 1;
+        let tick = self.tick;
+        if let Some(partition) = self.partitions.get_mut(&key) {
+            self.recency.remove(&partition.tick);
+            partition.tick = tick;
+        } else {
+            // Make room by dropping the least recently used partition
+            while self.partitions.len() >= self.config.max_keys {
+                let Some((_, oldest)) = self.recency.pop_first() else {
+                    break;
+                };
+                self.partitions.remove(&oldest);
+            }
+            self.partitions.insert(
+                key.clone(),
+                Partition {
+                    buffer: VecDeque::with_capacity(self.config.size.unwrap_or(0).min(1024)),
+                    tick,
+                },
+            );
+        }
+        self.recency.insert(tick, key.clone());
+
+        let partition = self.partitions.get_mut(&key).unwrap();
+        let buffer = &mut partition.buffer;
+        let now = window_record.timestamp;
+        buffer.push_back(window_record);
 
         // Keep buffer at target size
-        while self.buffer.len() > self.window_size {
-            self.buffer.pop_front();
+        if let Some(size) = self.config.size {
+            while buffer.len() > size {
+                buffer.pop_front();
+            }
+        }
+        // Drop records that fell out of the time range (input is assumed to
+        // be roughly in time order)
+        if let Some(duration) = self.config.duration {
+            let cutoff = now as f64 - duration;
+            while buffer
+                .front()
+                .is_some_and(|r| (r.timestamp as f64) < cutoff)
+            {
+                buffer.pop_front();
+            }
         }
+        buffer
     }
 }
 
 impl RecordProcessor for WindowProcessor {
     fn process(&mut self, record: &RecordData, ctx: &RecordContext) -> ProcessResult {
-        // Add current record to window buffer
-        self.add_to_buffer(record, ctx);
+        // Add current record to its window buffer
+        let buffer = self.add_to_buffer(record, ctx).clone();
 
         // Set up window context for functions to access
         WINDOW_CONTEXT.with(|window_ctx| {
-            *window_ctx.borrow_mut() = Some(self.buffer.clone());
+            *window_ctx.borrow_mut() = Some(buffer);
         });
 
         // Process with inner processor (which will have access to window variables)
@@ -0,0 +1,284 @@
+// src/profile.rs
+//! `--profile`: timers and counters for every pipeline step.
+//!
+//! Covers the input parser, each `RecordProcessor` and the output formatter.
+//! Per-call latencies go into a log2 histogram, so memory stays constant and
+//! p99 is approximate (within a factor of two).
+
+use crate::pipeline::context::ProcessResult;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::io::{self, Write};
+use std::time::Duration;
+
+const BUCKETS: usize = 64;
+
+/// Latency histogram with power-of-two nanosecond buckets
+#[derive(Debug, Clone)]
+pub struct LatencyHistogram {
+    buckets: [u64; BUCKETS],
+    count: u64,
+    total: Duration,
+    max: Duration,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        LatencyHistogram {
+            buckets: [0; BUCKETS],
+            count: 0,
+            total: Duration::ZERO,
+            max: Duration::ZERO,
+        }
+    }
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, elapsed: Duration) {
+        let nanos = elapsed.as_nanos().min(u64::MAX as u128) as u64;
+        let bucket = (64 - nanos.leading_zeros() as usize).min(BUCKETS - 1);
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.total += elapsed;
+        self.max = self.max.max(elapsed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    pub fn mean(&self) -> Duration {
+        if self.count == 0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(self.total.as_secs_f64() / self.count as f64)
+        }
+    }
+
+    /// Upper bound of the bucket containing the given percentile (0-100)
+    pub fn percentile(&self, pct: f64) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+        let rank = ((pct / 100.0) * self.count as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bucket, &n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                let upper = if bucket == 0 {
+                    0
+                } else {
+                    1u64 << bucket.min(63)
+                };
+                return Duration::from_nanos(upper).min(self.max);
+            }
+        }
+        self.max
+    }
+}
+
+/// Timing and selectivity of one pipeline step
+#[derive(Debug, Clone, Default)]
+pub struct StepProfile {
+    pub name: String,
+    pub latency: LatencyHistogram,
+    pub records_out: u64,
+}
+
+impl StepProfile {
+    fn new(name: &str) -> Self {
+        StepProfile {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Output records per input record
+    pub fn selectivity(&self) -> f64 {
+        if self.latency.count() == 0 {
+            0.0
+        } else {
+            self.records_out as f64 / self.latency.count() as f64
+        }
+    }
+}
+
+/// A record and the time the processor chain spent on it
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SlowRecord {
+    pub elapsed: Duration,
+    pub line_number: usize,
+    pub file_name: Option<String>,
+}
+
+pub struct Profiler {
+    parse_steps: Vec<StepProfile>,
+    processor_steps: Vec<StepProfile>,
+    format_step: StepProfile,
+    slowest: BinaryHeap<Reverse<SlowRecord>>,
+    top_n: usize,
+}
+
+impl Profiler {
+    pub fn new(top_n: usize, output_format_name: &str) -> Self {
+        Profiler {
+            parse_steps: Vec::new(),
+            processor_steps: Vec::new(),
+            format_step: StepProfile::new(&format!("format:{}", output_format_name)),
+            slowest: BinaryHeap::new(),
+            top_n,
+        }
+    }
+
+    pub fn record_parse(&mut self, format_name: &str, elapsed: Duration, ok: bool) {
+        let name = format!("parse:{}", format_name.to_lowercase());
+        let step = match self.parse_steps.iter().position(|s| s.name == name) {
+            Some(i) => &mut self.parse_steps[i],
+            None => {
+                self.parse_steps.push(StepProfile::new(&name));
+                self.parse_steps.last_mut().unwrap()
+            }
+        };
+        step.latency.record(elapsed);
+        step.records_out += ok as u64;
+    }
+
+    /// Record one call of the processor at `index` in the chain
+    pub fn record_processor(
+        &mut self,
+        index: usize,
+        name: &str,
+        result: &ProcessResult,
+        elapsed: Duration,
+    ) {
+        while self.processor_steps.len() <= index {
+            self.processor_steps.push(StepProfile::new(name));
+        }
+        let step = &mut self.processor_steps[index];
+        step.latency.record(elapsed);
+        step.records_out += match result {
+            ProcessResult::Transform(_) => 1,
+            ProcessResult::FanOut(records) => records.len() as u64,
+            ProcessResult::TransformWithEmissions { primary, emissions } => {
+                primary.is_some() as u64 + emissions.len() as u64
+            }
+            ProcessResult::Exit { data, .. } => data.is_some() as u64,
+            ProcessResult::Skip | ProcessResult::Error(_) => 0,
+        };
+    }
+
+    pub fn record_format(&mut self, elapsed: Duration) {
+        self.format_step.latency.record(elapsed);
+        self.format_step.records_out += 1;
+    }
+
+    /// Track the time the whole processor chain took for one record
+    pub fn record_chain(&mut self, elapsed: Duration, line_number: usize, file_name: Option<&str>) {
+        if self.top_n == 0 {
+            return;
+        }
+        if self.slowest.len() == self.top_n {
+            match self.slowest.peek() {
+                Some(Reverse(fastest)) if fastest.elapsed < elapsed => {
+                    self.slowest.pop();
+                }
+                _ => return,
+            }
+        }
+        self.slowest.push(Reverse(SlowRecord {
+            elapsed,
+            line_number,
+            file_name: file_name.map(|s| s.to_string()),
+        }));
+    }
+
+    /// All steps in pipeline order: parse, processors, format
+    pub fn steps(&self) -> Vec<&StepProfile> {
+        let mut steps: Vec<&StepProfile> = self.parse_steps.iter().collect();
+        steps.extend(self.processor_steps.iter());
+        if self.format_step.latency.count() > 0 {
+            steps.push(&self.format_step);
+        }
+        steps
+    }
+
+    /// Slowest records, slowest first
+    pub fn slowest_records(&self) -> Vec<SlowRecord> {
+        let mut records: Vec<SlowRecord> = self.slowest.iter().map(|r| r.0.clone()).collect();
+        records.sort_by(|a, b| b.cmp(a));
+        records
+    }
+
+    pub fn write_report<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        writeln!(
+            output,
+            "{:<24} {:>10} {:>12} {:>10} {:>10} {:>12}",
+            "step", "calls", "total_ms", "mean_us", "p99_us", "selectivity"
+        )?;
+        for step in self.steps() {
+            let latency = &step.latency;
+            writeln!(
+                output,
+                "{:<24} {:>10} {:>12.3} {:>10.1} {:>10.1} {:>11.1}%",
+                step.name,
+                latency.count(),
+                latency.total().as_secs_f64() * 1000.0,
+                latency.mean().as_secs_f64() * 1_000_000.0,
+                latency.percentile(99.0).as_secs_f64() * 1_000_000.0,
+                step.selectivity() * 100.0
+            )?;
+        }
+
+        let slowest = self.slowest_records();
+        if !slowest.is_empty() {
+            writeln!(output, "Slowest records:")?;
+            for record in slowest {
+                writeln!(
+                    output,
+                    "  {:>10.3}ms  {}:{}",
+                    record.elapsed.as_secs_f64() * 1000.0,
+                    record.file_name.as_deref().unwrap_or("<stdin>"),
+                    record.line_number
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_percentiles() {
+        let mut histogram = LatencyHistogram::default();
+        for _ in 0..99 {
+            histogram.record(Duration::from_nanos(100));
+        }
+        histogram.record(Duration::from_millis(5));
+
+        assert_eq!(histogram.count(), 100);
+        assert!(histogram.percentile(50.0) <= Duration::from_nanos(128));
+        assert!(histogram.percentile(50.0) >= Duration::from_nanos(100));
+        assert!(histogram.percentile(100.0) == Duration::from_millis(5));
+    }
+
+    #[test]
+    fn test_keeps_top_n_slowest_records() {
+        let mut profiler = Profiler::new(2, "jsonl");
+        for (line, ms) in [(1, 5), (2, 1), (3, 9), (4, 3)] {
+            profiler.record_chain(Duration::from_millis(ms), line, Some("a.log"));
+        }
+        let lines: Vec<usize> = profiler
+            .slowest_records()
+            .iter()
+            .map(|r| r.line_number)
+            .collect();
+        assert_eq!(lines, vec![3, 1]);
+    }
+}
@@ -0,0 +1,705 @@
+// src/repl.rs
+//! `stelp repl`: explore records interactively while building a pipeline.
+//!
+//! The first N records of a file are loaded up front. Expressions are
+//! evaluated against the current record with the usual `line`, `data`,
+//! `glob` and built-in functions; `:add` turns a tested expression into a
+//! pipeline step, and the equivalent command line is printed on exit.
+
+use crate::input_format::{InputFormat, InputFormatWrapper};
+use crate::pipeline::config::PipelineConfig;
+use crate::pipeline::context::{ProcessResult, RecordContext, RecordData};
+use crate::pipeline::module_loader::ModuleLoader;
+use crate::pipeline::stream::{RecordProcessor, StreamPipeline};
+use crate::pipeline_file::PipelineStep;
+use crate::variables::GlobalVariables;
+use crate::{DeriveProcessor, FilterProcessor, StarlarkProcessor};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use std::io::{self, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Records loaded when `--records` is not given
+pub const DEFAULT_RECORD_LIMIT: usize = 1000;
+
+const COMMANDS: &[&str] = &[
+    ":add", ":cmd", ":goto", ":help", ":keys", ":next", ":prev", ":quit", ":show", ":steps",
+    ":undo",
+];
+
+const HELP: &str = "\
+Expressions are evaluated against the current record (line, data, glob, LINENUM, ...).
+  :next [N], :n      move forward N records (default 1)
+  :prev [N], :p      move back N records
+  :goto N            jump to record N
+  :show              print the current record after the added steps
+  :keys              list the field names seen in the loaded records
+  :add [KIND] [EXPR] add EXPR (default: the last expression) as an eval,
+                     filter or derive step (default: eval)
+  :steps             list the added steps
+  :undo              remove the last step
+  :cmd               print the equivalent stelp command line
+  :quit, :q          exit and print the command line
+A line ending in ':' starts a block; finish it with an empty line.";
+
+/// A record loaded for exploration
+#[derive(Debug, Clone)]
+struct LoadedRecord {
+    line_number: usize,
+    data: RecordData,
+}
+
+/// REPL state: the loaded records, the current position and the steps
+/// added so far
+pub struct Repl {
+    file: PathBuf,
+    input_format: Option<InputFormat>,
+    records: Vec<LoadedRecord>,
+    keys: Vec<String>,
+    position: usize,
+    globals: GlobalVariables,
+    lib_paths: Vec<PathBuf>,
+    loader: Arc<ModuleLoader>,
+    steps: Vec<(PipelineStep, Box<dyn RecordProcessor>)>,
+    last_expression: Option<String>,
+    /// `def` and `load()` blocks entered so far, with the names they bind
+    definitions: Vec<(Vec<String>, String)>,
+}
+
+impl Repl {
+    /// Load up to `limit` records from `file`
+    pub fn load(
+        file: &Path,
+        input_format: Option<InputFormat>,
+        limit: usize,
+        lib_paths: &[PathBuf],
+    ) -> Result<Self, String> {
+        let detected = input_format
+            .clone()
+            .or_else(|| InputFormat::from_extension(file))
+            .unwrap_or(InputFormat::Line);
+
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let mut pipeline = StreamPipeline::new(PipelineConfig {
+            input_format: Some(detected.clone()),
+            ..PipelineConfig::default()
+        });
+        pipeline.add_processor(Box::new(CaptureProcessor {
+            records: Arc::clone(&captured),
+            limit,
+        }));
+
+        let input = std::fs::File::open(file)
+            .map_err(|e| format!("failed to open input file '{}': {}", file.display(), e))?;
+        InputFormatWrapper::new(Some(&detected))
+            .process_with_pipeline(
+                BufReader::new(input),
+                &mut pipeline,
+                &mut io::sink(),
+                Some(&file.to_string_lossy()),
+            )
+            .map_err(|e| format!("reading '{}' failed: {}", file.display(), e))?;
+
+        let records = std::mem::take(&mut *captured.lock().unwrap());
+        if records.is_empty() {
+            return Err(format!("no records in '{}'", file.display()));
+        }
+        let mut keys: Vec<String> = records
+            .iter()
+            .filter_map(|r| r.data.as_structured()?.as_object())
+            .flat_map(|object| object.keys().cloned())
+            .collect();
+        keys.sort();
+        keys.dedup();
+
+        Ok(Repl {
+            file: file.to_path_buf(),
+            input_format,
+            records,
+            keys,
+            position: 0,
+            globals: GlobalVariables::new(),
+            lib_paths: lib_paths.to_vec(),
+            loader: Arc::new(ModuleLoader::from_env(lib_paths)),
+            steps: Vec::new(),
+            last_expression: None,
+            definitions: Vec::new(),
+        })
+    }
+
+    pub fn record_count(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Prompt showing the current position, e.g. `[3/1000]> `
+    pub fn prompt(&self) -> String {
+        format!("[{}/{}]> ", self.position + 1, self.records.len())
+    }
+
+    /// Field names and variables offered for tab-completion
+    pub fn completion_words(&self) -> Vec<String> {
+        let mut words = self.keys.clone();
+        words.extend(["data", "line", "glob"].map(String::from));
+        words
+    }
+
+    /// Handle one command or expression, writing its output to `out`.
+    /// Returns false when the user asked to quit.
+    pub fn handle(&mut self, input: &str, out: &mut dyn Write) -> io::Result<bool> {
+        let input = input.trim_end();
+        if input.trim().is_empty() {
+            return Ok(true);
+        }
+        if !input.starts_with(':') {
+            self.evaluate(input, out)?;
+            return Ok(true);
+        }
+
+        let (command, rest) = input
+            .split_once(char::is_whitespace)
+            .map(|(c, r)| (c, r.trim()))
+            .unwrap_or((input, ""));
+        match command {
+            ":next" | ":n" => match parse_count(rest) {
+                Ok(n) => self.move_to(self.position.saturating_add(n), out)?,
+                Err(e) => writeln!(out, "error: {}", e)?,
+            },
+            ":prev" | ":p" => match parse_count(rest) {
+                Ok(n) => self.move_to(self.position.saturating_sub(n), out)?,
+                Err(e) => writeln!(out, "error: {}", e)?,
+            },
+            ":goto" | ":g" => match rest.parse::<usize>() {
+                Ok(n) if n >= 1 => self.move_to(n - 1, out)?,
+                _ => writeln!(out, "error: usage: :goto N (1-{})", self.records.len())?,
+            },
+            ":show" => self.show(out)?,
+            ":keys" => {
+                if self.keys.is_empty() {
+                    writeln!(out, "(no structured fields)")?;
+                } else {
+                    writeln!(out, "{}", self.keys.join(" "))?;
+                }
+            }
+            ":add" => self.add_step(rest, out)?,
+            ":steps" => {
+                if self.steps.is_empty() {
+                    writeln!(out, "(no steps)")?;
+                }
+                for (i, (step, _)) in self.steps.iter().enumerate() {
+                    writeln!(out, "{}. {}", i + 1, step_label(step))?;
+                }
+            }
+            ":undo" => match self.steps.pop() {
+                Some((step, _)) => writeln!(out, "removed {}", step_label(&step))?,
+                None => writeln!(out, "(no steps)")?,
+            },
+            ":cmd" => writeln!(out, "{}", self.command_line())?,
+            ":help" | ":h" => writeln!(out, "{}", HELP)?,
+            ":quit" | ":q" => return Ok(false),
+            _ => writeln!(out, "error: unknown command '{}' (try :help)", command)?,
+        }
+        Ok(true)
+    }
+
+    /// The stelp invocation running the added steps over the whole file
+    pub fn command_line(&self) -> String {
+        use clap::ValueEnum;
+
+        let mut args = vec!["stelp".to_string()];
+        if let Some(format) = self
+            .input_format
+            .as_ref()
+            .and_then(|f| f.to_possible_value())
+        {
+            args.push("-f".to_string());
+            args.push(format.get_name().to_string());
+        }
+        for path in &self.lib_paths {
+            args.push("--lib-path".to_string());
+            args.push(shell_quote(&path.to_string_lossy()));
+        }
+        for (step, _) in &self.steps {
+            let (flag, code) = match step {
+                PipelineStep::Eval(code) => ("-e", code),
+                PipelineStep::Filter(code) => ("--filter", code),
+                PipelineStep::Derive(code) => ("--derive", code),
+                _ => continue,
+            };
+            args.push(flag.to_string());
+            args.push(shell_quote(code));
+        }
+        args.push(shell_quote(&self.file.to_string_lossy()));
+        args.join(" ")
+    }
+
+    fn context(&self) -> RecordContext<'_> {
+        RecordContext {
+            line_number: self.records[self.position].line_number,
+            record_count: self.position + 1,
+            file_name: self.file.to_str(),
+            global_vars: &self.globals,
+            debug: false,
+        }
+    }
+
+    /// The current record after the added steps, or the step that dropped it
+    fn current_record(&mut self) -> Result<RecordData, String> {
+        // Built from the fields directly so the steps can be borrowed mutably
+        let ctx = RecordContext {
+            line_number: self.records[self.position].line_number,
+            record_count: self.position + 1,
+            file_name: self.file.to_str(),
+            global_vars: &self.globals,
+            debug: false,
+        };
+        let mut record = self.records[self.position].data.clone();
+        for (i, (step, processor)) in self.steps.iter_mut().enumerate() {
+            let result = processor.process(&record, &ctx);
+            record = match result {
+                ProcessResult::Transform(next) => next,
+                ProcessResult::TransformWithEmissions {
+                    primary: Some(next),
+                    ..
+                } => next,
+                ProcessResult::FanOut(mut records) if !records.is_empty() => records.remove(0),
+                ProcessResult::Exit {
+                    data: Some(next), ..
+                } => next,
+                ProcessResult::Error(e) => {
+                    return Err(format!(
+                        "step {} ({}) failed: {}",
+                        i + 1,
+                        step_label(step),
+                        e
+                    ))
+                }
+                _ => {
+                    return Err(format!(
+                        "record dropped by step {} ({})",
+                        i + 1,
+                        step_label(step)
+                    ))
+                }
+            };
+        }
+        Ok(record)
+    }
+
+    fn move_to(&mut self, position: usize, out: &mut dyn Write) -> io::Result<()> {
+        let last = self.records.len() - 1;
+        if position > last {
+            writeln!(out, "(only {} records loaded)", self.records.len())?;
+        }
+        self.position = position.min(last);
+        self.show(out)
+    }
+
+    fn show(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        let line_number = self.records[self.position].line_number;
+        match self.current_record() {
+            Ok(record) => writeln!(
+                out,
+                "record {} (line {}): {}",
+                self.position + 1,
+                line_number,
+                format_record(&record)
+            ),
+            Err(e) => writeln!(
+                out,
+                "record {} (line {}): {}",
+                self.position + 1,
+                line_number,
+                e
+            ),
+        }
+    }
+
+    fn evaluate(&mut self, expression: &str, out: &mut dyn Write) -> io::Result<()> {
+        let record = match self.current_record() {
+            Ok(record) => record,
+            Err(e) => return writeln!(out, "error: {}", e),
+        };
+        let names = defined_names(expression);
+        let script = self.with_definitions(expression, true);
+        let processor = match StarlarkProcessor::from_script_with_loader(
+            "repl",
+            &script,
+            self.loader.for_dir("."),
+        ) {
+            Ok(processor) => processor,
+            Err(e) => return writeln!(out, "error: {}", e),
+        };
+
+        match processor.evaluate(&record, &self.context()) {
+            Ok(evaluation) => {
+                for line in &evaluation.emitted {
+                    writeln!(out, "emit: {}", line)?;
+                }
+                if let Some(value) = evaluation.value {
+                    writeln!(out, "{}", value)?;
+                }
+                if names.is_empty() {
+                    self.last_expression = Some(expression.to_string());
+                } else {
+                    writeln!(out, "defined {}", names.join(", "))?;
+                    self.definitions.push((names, expression.to_string()));
+                }
+                Ok(())
+            }
+            Err(e) => writeln!(out, "error: {}", e),
+        }
+    }
+
+    /// Prefix `code` with the session's definitions: all of them, or only
+    /// if `code` uses one of their names (so steps stay self-contained
+    /// without dragging in unrelated helpers)
+    fn with_definitions(&self, code: &str, always: bool) -> String {
+        let used = always
+            || self
+                .definitions
+                .iter()
+                .flat_map(|(names, _)| names)
+                .any(|name| mentions(code, name));
+        if !used || self.definitions.is_empty() {
+            return code.to_string();
+        }
+        let mut script: Vec<&str> = self.definitions.iter().map(|(_, d)| d.as_str()).collect();
+        script.push(code);
+        script.join("\n")
+    }
+
+    fn add_step(&mut self, args: &str, out: &mut dyn Write) -> io::Result<()> {
+        let (kind, code) = match args.split_once(char::is_whitespace) {
+            Some((kind, code)) if matches!(kind, "eval" | "filter" | "derive") => {
+                (kind, code.trim().to_string())
+            }
+            _ if matches!(args, "eval" | "filter" | "derive") => (args, String::new()),
+            _ => ("eval", args.to_string()),
+        };
+        let code = if code.is_empty() {
+            match &self.last_expression {
+                Some(expression) => expression.clone(),
+                None => return writeln!(out, "error: no expression to add yet"),
+            }
+        } else {
+            code
+        };
+
+        let code = self.with_definitions(&code, false);
+        let name = format!("{}_{}", kind, self.steps.len() + 1);
+        let loader = self.loader.for_dir(".");
+        let compiled: Result<(PipelineStep, Box<dyn RecordProcessor>), _> = match kind {
+            "filter" => FilterProcessor::from_script_with_loader(&name, &code, loader).map(|p| {
+                (
+                    PipelineStep::Filter(code),
+                    Box::new(p) as Box<dyn RecordProcessor>,
+                )
+            }),
+            "derive" => DeriveProcessor::from_script_with_loader(&name, &code, loader).map(|p| {
+                (
+                    PipelineStep::Derive(code),
+                    Box::new(p) as Box<dyn RecordProcessor>,
+                )
+            }),
+            _ => StarlarkProcessor::from_script_with_loader(&name, &code, loader).map(|p| {
+                (
+                    PipelineStep::Eval(code),
+                    Box::new(p) as Box<dyn RecordProcessor>,
+                )
+            }),
+        };
+        match compiled {
+            Ok((step, processor)) => {
+                writeln!(
+                    out,
+                    "added step {}: {}",
+                    self.steps.len() + 1,
+                    step_label(&step)
+                )?;
+                self.steps.push((step, processor));
+                self.show(out)
+            }
+            Err(e) => writeln!(out, "error: {}", e),
+        }
+    }
+}
+
+/// Collects the first `limit` records, then stops the run
+struct CaptureProcessor {
+    records: Arc<Mutex<Vec<LoadedRecord>>>,
+    limit: usize,
+}
+
+impl RecordProcessor for CaptureProcessor {
+    fn process(&mut self, record: &RecordData, ctx: &RecordContext) -> ProcessResult {
+        let mut records = self.records.lock().unwrap();
+        if records.len() >= self.limit {
+            return ProcessResult::Exit {
+                data: None,
+                code: 0,
+            };
+        }
+        records.push(LoadedRecord {
+            line_number: ctx.line_number,
+            data: record.clone(),
+        });
+        ProcessResult::Skip
+    }
+
+    fn name(&self) -> &str {
+        "repl_capture"
+    }
+}
+
+/// Names bound by a `def` or `load()` block; empty for anything else
+fn defined_names(code: &str) -> Vec<String> {
+    static DEF: Lazy<Regex> = Lazy::new(|| Regex::new(r"^def\s+([A-Za-z_]\w*)\s*\(").unwrap());
+    static LOAD_SYMBOL: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r#",\s*(?:([A-Za-z_]\w*)\s*=\s*)?"([A-Za-z_]\w*)""#).unwrap());
+
+    let code = code.trim_start();
+    if let Some(captures) = DEF.captures(code) {
+        vec![captures[1].to_string()]
+    } else if code.starts_with("load(") {
+        LOAD_SYMBOL
+            .captures_iter(code)
+            .map(|c| {
+                c.get(1)
+                    .unwrap_or_else(|| c.get(2).unwrap())
+                    .as_str()
+                    .to_string()
+            })
+            .collect()
+    } else {
+        Vec::new()
+    }
+}
+
+/// Whether `name` appears in `code` as a whole identifier
+fn mentions(code: &str, name: &str) -> bool {
+    code.match_indices(name).any(|(i, _)| {
+        let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+        !code[..i].ends_with(is_ident) && !code[i + name.len()..].starts_with(is_ident)
+    })
+}
+
+fn parse_count(arg: &str) -> Result<usize, String> {
+    if arg.is_empty() {
+        Ok(1)
+    } else {
+        arg.parse()
+            .map_err(|_| format!("expected a number of records, got '{}'", arg))
+    }
+}
+
+/// One-line description of a step; code with definitions prepended is
+/// shown by its last line
+fn step_label(step: &PipelineStep) -> String {
+    let last_line = |code: &str| code.lines().last().unwrap_or("").trim().to_string();
+    match step {
+        PipelineStep::Extract(pattern) => format!("extract {}", pattern),
+        PipelineStep::Eval(code) => format!("eval {}", last_line(code)),
+        PipelineStep::Filter(code) => format!("filter {}", last_line(code)),
+        PipelineStep::Derive(code) => format!("derive {}", last_line(code)),
+        PipelineStep::ScriptFile(path) => format!("script {}", path.display()),
+        PipelineStep::Sample(rate) => format!("sample {}", rate),
+        PipelineStep::SampleBy(spec) => format!("sample-by {}", spec),
+        PipelineStep::Reservoir(size) => format!("reservoir {}", size),
+        PipelineStep::RateLimit(spec) => format!("rate-limit {}", spec),
+        PipelineStep::DedupeBy(fields) => format!("dedupe-by {}", fields.to_csv()),
+        PipelineStep::DedupeLine => "dedupe-line".to_string(),
+    }
+}
+
+fn format_record(record: &RecordData) -> String {
+    match record {
+        RecordData::Text(text) => text.clone(),
+        RecordData::Structured(data) => data.to_string(),
+    }
+}
+
+/// Quote an argument for a POSIX shell if it needs it
+fn shell_quote(arg: &str) -> String {
+    let safe = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,@%+".contains(c));
+    if safe {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// Complete the word before `pos` from `words`, or a `:command`
+pub fn complete_word(words: &[String], line: &str, pos: usize) -> (usize, Vec<String>) {
+    let before = &line[..pos];
+    if before.starts_with(':') && !before.contains(char::is_whitespace) {
+        let matches = COMMANDS
+            .iter()
+            .filter(|c| c.starts_with(before))
+            .map(|c| c.to_string())
+            .collect();
+        return (0, matches);
+    }
+
+    let start = before
+        .rfind(|c: char| !(c.is_alphanumeric() || "_.-@".contains(c)))
+        .map(|i| i + before[i..].chars().next().map_or(1, char::len_utf8))
+        .unwrap_or(0);
+    let prefix = &before[start..];
+    let mut matches: Vec<String> = words
+        .iter()
+        .filter(|w| w.starts_with(prefix))
+        .cloned()
+        .collect();
+    matches.sort();
+    matches.dedup();
+    (start, matches)
+}
+
+/// rustyline helper completing field names and commands
+struct ReplHelper {
+    words: Vec<String>,
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(complete_word(&self.words, line, pos))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+/// Read commands from the terminal until `:quit` or end of input, then
+/// print the command line for the added steps
+pub fn run_interactive(repl: &mut Repl) -> Result<(), String> {
+    let mut editor: Editor<ReplHelper, DefaultHistory> =
+        Editor::new().map_err(|e| format!("cannot start line editor: {}", e))?;
+    editor.set_helper(Some(ReplHelper {
+        words: repl.completion_words(),
+    }));
+
+    let mut stdout = io::stdout();
+    let write_error = |e: io::Error| format!("failed to write output: {}", e);
+    writeln!(
+        stdout,
+        "loaded {} records from {} (:help for commands)",
+        repl.record_count(),
+        repl.file.display()
+    )
+    .map_err(write_error)?;
+    repl.show(&mut stdout).map_err(write_error)?;
+
+    loop {
+        let mut input = match editor.readline(&repl.prompt()) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(format!("failed to read input: {}", e)),
+        };
+        // A trailing ':' opens a block (def, if, for), ended by an empty line
+        if input.trim_end().ends_with(':') && !input.starts_with(':') {
+            loop {
+                match editor.readline("... ") {
+                    Ok(line) if line.trim().is_empty() => break,
+                    Ok(line) => {
+                        input.push('\n');
+                        input.push_str(&line);
+                    }
+                    Err(ReadlineError::Interrupted) => {
+                        input.clear();
+                        break;
+                    }
+                    Err(_) => break,
+                }
+            }
+        }
+        if input.trim().is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(input.as_str());
+
+        if !repl.handle(&input, &mut stdout).map_err(write_error)? {
+            break;
+        }
+        if let Some(helper) = editor.helper_mut() {
+            helper.words = repl.completion_words();
+        }
+    }
+
+    if !repl.steps.is_empty() {
+        writeln!(stdout, "{}", repl.command_line()).map_err(write_error)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_complete_word() {
+        let words: Vec<String> = ["status", "size", "path"].map(String::from).to_vec();
+        assert_eq!(
+            complete_word(&words, "data[\"st", 8),
+            (6, vec!["status".to_string()])
+        );
+        assert_eq!(
+            complete_word(&words, "s", 1),
+            (0, vec!["size".to_string(), "status".to_string()])
+        );
+        assert_eq!(
+            complete_word(&words, ":ne", 3),
+            (0, vec![":next".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_defined_names() {
+        assert_eq!(
+            defined_names("def slow(ms):\n    return ms > 100"),
+            vec!["slow"]
+        );
+        assert_eq!(
+            defined_names(r#"load("//http.star", "classify", kind = "status_kind")"#),
+            vec!["classify", "kind"]
+        );
+        assert!(defined_names("data[\"ms\"] > 100").is_empty());
+        assert!(mentions("slow(data[\"ms\"])", "slow"));
+        assert!(!mentions("slower(1)", "slow"));
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("app.log"), "app.log");
+        assert_eq!(
+            shell_quote("data[\"status\"] >= 500"),
+            "'data[\"status\"] >= 500'"
+        );
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+    }
+}
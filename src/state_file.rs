@@ -0,0 +1,183 @@
+// src/state_file.rs
+//! Persistent `glob` state (`--state-file path.json`).
+//!
+//! The file is loaded before BEGIN runs and saved after END, so counters
+//! and "last seen" markers carry over between runs (e.g. cron jobs over
+//! hourly log slices):
+//!
+//! `{"version":1,"saved_at":"2024-01-15T10:00:00Z","glob":{"errors":42}}`
+//!
+//! Saves go to a temporary file in the same directory that is then renamed
+//! over the old one, so a crash never leaves a half-written state file.
+
+use crate::variables::GlobalVariables;
+use serde_json::{json, Value};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Format version written to new files
+pub const STATE_VERSION: u64 = 1;
+
+pub struct StateFile {
+    path: PathBuf,
+    /// Only keys starting with one of these persist; empty keeps everything
+    prefixes: Vec<String>,
+    /// Also save every `interval` while records are flowing
+    interval: Option<Duration>,
+    last_saved: Instant,
+}
+
+impl StateFile {
+    pub fn new(path: &Path, prefixes: Vec<String>) -> Self {
+        StateFile {
+            path: path.to_path_buf(),
+            prefixes,
+            interval: None,
+            last_saved: Instant::now(),
+        }
+    }
+
+    pub fn with_interval(mut self, interval: Option<Duration>) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn persists(&self, key: &str) -> bool {
+        self.prefixes.is_empty() || self.prefixes.iter().any(|p| key.starts_with(p.as_str()))
+    }
+
+    /// Load saved keys into `globals`; a missing file is a first run.
+    /// Returns the number of keys loaded.
+    pub fn load(&self, globals: &GlobalVariables) -> Result<usize, String> {
+        let text = match fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(self.error("read", e)),
+        };
+        let document: Value = serde_json::from_str(&text).map_err(|e| self.error("parse", e))?;
+        let version = document.get("version").and_then(Value::as_u64);
+        match version {
+            Some(STATE_VERSION) => {}
+            Some(v) if v > STATE_VERSION => {
+                return Err(format!(
+                    "state file '{}' has version {}, newer than this stelp supports ({})",
+                    self.path.display(),
+                    v,
+                    STATE_VERSION
+                ))
+            }
+            _ => {
+                return Err(format!(
+                    "state file '{}' is not a stelp state file (missing or invalid version)",
+                    self.path.display()
+                ))
+            }
+        }
+
+        match document.get("glob") {
+            Some(Value::Object(glob)) => Ok(globals.load_json(glob, |key| self.persists(key))),
+            _ => Ok(0),
+        }
+    }
+
+    /// Write the persisted keys of `globals` atomically
+    pub fn save(&mut self, globals: &GlobalVariables) -> Result<(), String> {
+        let glob = globals.to_json(|key| self.persists(key));
+        let document = json!({
+            "version": STATE_VERSION,
+            "saved_at": chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            "glob": glob,
+        });
+
+        write_json_atomically(&self.path, &document).map_err(|e| self.error("write", e))?;
+        self.last_saved = Instant::now();
+        Ok(())
+    }
+
+    /// `save` if the `--state-interval` has elapsed since the last save
+    pub fn save_if_due(&mut self, globals: &GlobalVariables) -> Result<(), String> {
+        match self.interval {
+            Some(interval) if self.last_saved.elapsed() >= interval => self.save(globals),
+            _ => Ok(()),
+        }
+    }
+
+    fn error(&self, action: &str, e: impl std::fmt::Display) -> String {
+        format!(
+            "failed to {} state file '{}': {}",
+            action,
+            self.path.display(),
+            e
+        )
+    }
+}
+
+/// Write `document` to a temporary file next to `path` and rename it into place
+pub(crate) fn write_json_atomically(path: &Path, document: &Value) -> std::io::Result<()> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(format!(".tmp{}", std::process::id()));
+    let temp_path = path.with_file_name(temp_name);
+    let result = fs::File::create(&temp_path)
+        .and_then(|mut file| {
+            serde_json::to_writer_pretty(&mut file, document)?;
+            file.write_all(b"\n")?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::variables::GlobalValue;
+
+    #[test]
+    fn test_save_and_load_with_prefixes() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("state.json");
+
+        let globals = GlobalVariables::new();
+        globals.set_value("keep_count".to_string(), GlobalValue::Int(3));
+        globals.set_value("tmp_scratch".to_string(), GlobalValue::Str("x".into()));
+        let mut state = StateFile::new(&path, vec!["keep_".to_string()]);
+        state.save(&globals).unwrap();
+
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved["version"], STATE_VERSION);
+        assert_eq!(saved["glob"], json!({"keep_count": 3}));
+
+        let restored = GlobalVariables::new();
+        assert_eq!(state.load(&restored).unwrap(), 1);
+        assert_eq!(restored.get_value("keep_count"), Some(GlobalValue::Int(3)));
+    }
+
+    #[test]
+    fn test_load_missing_and_invalid_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("state.json");
+        let state = StateFile::new(&path, Vec::new());
+        assert_eq!(state.load(&GlobalVariables::new()).unwrap(), 0);
+
+        fs::write(&path, r#"{"version": 99, "glob": {}}"#).unwrap();
+        assert!(state
+            .load(&GlobalVariables::new())
+            .unwrap_err()
+            .contains("newer than this stelp supports"));
+
+        fs::write(&path, r#"{"errors": 1}"#).unwrap();
+        assert!(state
+            .load(&GlobalVariables::new())
+            .unwrap_err()
+            .contains("not a stelp state file"));
+    }
+}
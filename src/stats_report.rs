@@ -0,0 +1,374 @@
+// src/stats_report.rs
+//! `--stats` report: human-readable text, JSON or logfmt.
+
+use crate::output_format::{OutputFormat, OutputFormatter};
+use crate::pipeline::context::{ProcessingStats, ProcessorStats, RecordData};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+/// Maximum number of parse error samples reported per input format
+const PARSE_ERROR_SAMPLES: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum, Default)]
+pub enum StatsFormat {
+    #[default]
+    #[value(name = "text", help = "Human-readable summary")]
+    Text,
+    #[value(name = "json", help = "Single JSON document")]
+    Json,
+    #[value(
+        name = "logfmt",
+        help = "One logfmt line per total, file, step and parse error format"
+    )]
+    Logfmt,
+}
+
+impl StatsFormat {
+    /// Infer the report format from a file extension (JSON unless it says otherwise)
+    pub fn from_extension(path: &std::path::Path) -> StatsFormat {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("txt") => StatsFormat::Text,
+            Some("logfmt") | Some("log") => StatsFormat::Logfmt,
+            _ => StatsFormat::Json,
+        }
+    }
+}
+
+/// Statistics of a single input file
+#[derive(Debug, Clone)]
+pub struct FileStats {
+    pub file: String,
+    pub stats: ProcessingStats,
+}
+
+/// Everything reported by `--stats`
+pub struct StatsReport<'a> {
+    pub total: &'a ProcessingStats,
+    pub files: &'a [FileStats],
+    pub processors: &'a [ProcessorStats],
+}
+
+impl StatsReport<'_> {
+    pub fn write<W: Write>(&self, output: &mut W, format: StatsFormat) -> io::Result<()> {
+        match format {
+            StatsFormat::Text => self.write_text(output),
+            StatsFormat::Json => {
+                serde_json::to_writer_pretty(&mut *output, &self.to_json())?;
+                writeln!(output)
+            }
+            StatsFormat::Logfmt => self.write_logfmt(output),
+        }
+    }
+
+    /// The full report as a JSON document
+    pub fn to_json(&self) -> Value {
+        let mut report = stats_to_json(self.total);
+        report.insert(
+            "files".to_string(),
+            Value::Array(
+                self.files
+                    .iter()
+                    .map(|f| {
+                        let mut entry = Map::new();
+                        entry.insert("file".to_string(), json!(f.file));
+                        entry.extend(stats_to_json(&f.stats));
+                        Value::Object(entry)
+                    })
+                    .collect(),
+            ),
+        );
+        report.insert(
+            "processors".to_string(),
+            Value::Array(self.processors.iter().map(processor_to_json).collect()),
+        );
+        report.insert(
+            "parse_error_samples".to_string(),
+            Value::Object(self.parse_error_samples()),
+        );
+        Value::Object(report)
+    }
+
+    /// Parse errors grouped by input format, with the first few samples of each
+    fn parse_error_samples(&self) -> Map<String, Value> {
+        let mut by_format: BTreeMap<&str, (usize, Vec<Value>)> = BTreeMap::new();
+        for file in self.files {
+            for error in &file.stats.parse_errors {
+                let entry = by_format.entry(&error.format_name).or_default();
+                entry.0 += 1;
+                if entry.1.len() < PARSE_ERROR_SAMPLES {
+                    entry.1.push(json!({
+                        "file": file.file,
+                        "line_number": error.line_number,
+                        "error": error.error,
+                    }));
+                }
+            }
+        }
+        by_format
+            .into_iter()
+            .map(|(format, (count, samples))| {
+                (
+                    format.to_lowercase(),
+                    json!({"count": count, "samples": samples}),
+                )
+            })
+            .collect()
+    }
+
+    fn write_logfmt<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        let mut formatter =
+            OutputFormatter::new_with_colors(OutputFormat::Logfmt, None, None, Some(false));
+        let mut write_line = |fields: Map<String, Value>| {
+            let fields = fields
+                .into_iter()
+                .map(|(key, value)| (key, logfmt_value(value)))
+                .collect();
+            formatter
+                .write_record(output, &RecordData::Structured(Value::Object(fields)))
+                .map_err(|e| io::Error::other(e.to_string()))
+        };
+
+        let mut total = Map::new();
+        total.insert("scope".to_string(), json!("total"));
+        total.extend(stats_to_json(self.total));
+        write_line(total)?;
+
+        for file in self.files {
+            let mut entry = Map::new();
+            entry.insert("scope".to_string(), json!("file"));
+            entry.insert("file".to_string(), json!(file.file));
+            entry.extend(stats_to_json(&file.stats));
+            write_line(entry)?;
+        }
+
+        for processor in self.processors {
+            let mut entry = Map::new();
+            entry.insert("scope".to_string(), json!("processor"));
+            if let Value::Object(fields) = processor_to_json(processor) {
+                entry.extend(fields);
+            }
+            write_line(entry)?;
+        }
+
+        for (format, summary) in self.parse_error_samples() {
+            let mut entry = Map::new();
+            entry.insert("scope".to_string(), json!("parse_errors"));
+            entry.insert("format".to_string(), json!(format));
+            entry.insert("count".to_string(), summary["count"].clone());
+            if let Some(first) = summary["samples"].get(0) {
+                entry.insert("first_file".to_string(), first["file"].clone());
+                entry.insert("first_line".to_string(), first["line_number"].clone());
+                entry.insert("first_error".to_string(), first["error"].clone());
+            }
+            write_line(entry)?;
+        }
+        Ok(())
+    }
+
+    fn write_text<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        let total_stats = self.total;
+        let processing_ms = total_stats.processing_time.as_secs_f64() * 1000.0;
+        let records_per_sec = if processing_ms > 0.0 {
+            (total_stats.records_processed as f64) / (processing_ms / 1000.0)
+        } else {
+            0.0
+        };
+
+        // Basic stats line
+        if total_stats.lines_seen > 0 {
+            let percentage =
+                (total_stats.records_processed as f64 / total_stats.lines_seen as f64) * 100.0;
+            writeln!(
+                output,
+                "Records shown: {} ({:.0}% of {} lines seen)",
+                total_stats.records_processed, percentage, total_stats.lines_seen
+            )?;
+        } else {
+            writeln!(output, "Records shown: {}", total_stats.records_processed)?;
+        }
+
+        // Time span information if we have timestamps
+        if let (Some(earliest), Some(latest)) =
+            (total_stats.earliest_timestamp, total_stats.latest_timestamp)
+        {
+            use chrono::DateTime;
+            if let (Some(earliest_dt), Some(latest_dt)) = (
+                DateTime::from_timestamp(earliest, 0),
+                DateTime::from_timestamp(latest, 0),
+            ) {
+                let duration_seconds = latest - earliest;
+                let records_per_sec = if duration_seconds > 0 {
+                    total_stats.records_processed as f64 / duration_seconds as f64
+                } else {
+                    0.0
+                };
+
+                writeln!(
+                    output,
+                    "Time span shown: {} to {}  ({}, {:.1} records/s)",
+                    earliest_dt.to_rfc3339(),
+                    latest_dt.to_rfc3339(),
+                    format_duration(duration_seconds),
+                    records_per_sec
+                )?;
+            }
+        }
+
+        // Keys seen (if any structured data was processed)
+        if !total_stats.keys_seen.is_empty() {
+            let mut keys: Vec<_> = total_stats.keys_seen.iter().cloned().collect();
+            keys.sort();
+            writeln!(output, "Keys seen: {}", keys.join(","))?;
+        }
+
+        // Log levels seen (if any were detected)
+        if !total_stats.levels_seen.is_empty() {
+            let mut levels: Vec<_> = total_stats.levels_seen.iter().collect();
+            levels.sort_by_key(|(level, _)| level.as_str());
+            let level_summary: Vec<String> = levels
+                .iter()
+                .map(|(level, key)| format!("{} (keys: {})", level, key))
+                .collect();
+            writeln!(output, "Log levels seen: {}", level_summary.join(", "))?;
+        }
+
+        // Performance details
+        writeln!(
+            output,
+            "Performance: {} records processed, {} output, {} skipped, {} errors in {:.2}ms ({:.0} records/s)",
+            total_stats.records_processed,
+            total_stats.records_output,
+            total_stats.records_skipped,
+            total_stats.errors,
+            processing_ms,
+            records_per_sec
+        )?;
+
+        // Processor-specific counts, e.g. "redact: email=3, ipv4=1"
+        for processor in self.processors {
+            if processor.counters.is_empty() {
+                continue;
+            }
+            let counts: Vec<String> = processor
+                .counters
+                .iter()
+                .map(|(name, count)| format!("{}={}", name, count))
+                .collect();
+            writeln!(output, "{}: {}", processor.name, counts.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+fn stats_to_json(stats: &ProcessingStats) -> Map<String, Value> {
+    let processing_ms = stats.processing_time.as_secs_f64() * 1000.0;
+    let records_per_sec = if processing_ms > 0.0 {
+        stats.records_processed as f64 / (processing_ms / 1000.0)
+    } else {
+        0.0
+    };
+    let to_rfc3339 = |ts: Option<i64>| {
+        ts.and_then(|t| chrono::DateTime::from_timestamp(t, 0))
+            .map(|dt| dt.to_rfc3339())
+    };
+
+    let mut keys: Vec<&String> = stats.keys_seen.iter().collect();
+    keys.sort();
+    let levels: BTreeMap<&String, &String> = stats.levels_seen.iter().collect();
+
+    let mut map = Map::new();
+    map.insert("lines_seen".to_string(), json!(stats.lines_seen));
+    map.insert(
+        "records_processed".to_string(),
+        json!(stats.records_processed),
+    );
+    map.insert("records_output".to_string(), json!(stats.records_output));
+    map.insert("records_skipped".to_string(), json!(stats.records_skipped));
+    map.insert("errors".to_string(), json!(stats.errors));
+    map.insert("parse_errors".to_string(), json!(stats.parse_errors.len()));
+    map.insert(
+        "processing_time_ms".to_string(),
+        json!(round3(processing_ms)),
+    );
+    map.insert(
+        "records_per_sec".to_string(),
+        json!(records_per_sec.round()),
+    );
+    map.insert(
+        "earliest_timestamp".to_string(),
+        json!(to_rfc3339(stats.earliest_timestamp)),
+    );
+    map.insert(
+        "latest_timestamp".to_string(),
+        json!(to_rfc3339(stats.latest_timestamp)),
+    );
+    map.insert("keys_seen".to_string(), json!(keys));
+    map.insert("levels_seen".to_string(), json!(levels));
+    map
+}
+
+fn processor_to_json(stats: &ProcessorStats) -> Value {
+    let mut entry = json!({
+        "name": stats.name,
+        "records_in": stats.records_in,
+        "records_out": stats.records_out,
+        "records_skipped": stats.records_skipped,
+        "errors": stats.errors,
+        "time_ms": round3(stats.time.as_secs_f64() * 1000.0),
+    });
+    if !stats.counters.is_empty() {
+        entry["counters"] = json!(stats.counters);
+    }
+    entry
+}
+
+/// Collapse lists and maps into comma-separated strings for logfmt output
+fn logfmt_value(value: Value) -> Value {
+    match value {
+        Value::Array(items) => Value::String(
+            items
+                .iter()
+                .map(|v| {
+                    v.as_str()
+                        .map(String::from)
+                        .unwrap_or_else(|| v.to_string())
+                })
+                .collect::<Vec<_>>()
+                .join(","),
+        ),
+        Value::Object(map) => Value::String(
+            map.iter()
+                .map(|(k, v)| {
+                    let v = v
+                        .as_str()
+                        .map(String::from)
+                        .unwrap_or_else(|| v.to_string());
+                    format!("{}:{}", k, v)
+                })
+                .collect::<Vec<_>>()
+                .join(","),
+        ),
+        other => other,
+    }
+}
+
+fn round3(value: f64) -> f64 {
+    (value * 1000.0).round() / 1000.0
+}
+
+/// Format a duration in seconds as `H:MM:SS`
+pub fn format_duration(seconds: i64) -> String {
+    if seconds < 60 {
+        format!("0:00:{:02}", seconds)
+    } else if seconds < 3600 {
+        let minutes = seconds / 60;
+        let secs = seconds % 60;
+        format!("0:{:02}:{:02}", minutes, secs)
+    } else {
+        let hours = seconds / 3600;
+        let minutes = (seconds % 3600) / 60;
+        let secs = seconds % 60;
+        format!("{}:{:02}:{:02}", hours, minutes, secs)
+    }
+}
@@ -0,0 +1,621 @@
+// src/test_runner.rs
+//! `stelp test`: regression tests for pipelines.
+//!
+//! Two kinds of tests are discovered under the given paths:
+//!
+//! - **Golden cases**: a directory containing a pipeline file
+//!   (`pipeline.yaml`, `pipeline.yml` or `pipeline.star`) or a `cmd` file of
+//!   command-line options, an input file (`input` or `input.*`) and the
+//!   expected output (`expected` or `expected.*`). The pipeline runs
+//!   in-process over the input and its output is compared with the expected
+//!   file.
+//! - **Starlark tests**: `*_test.star` files. Every top-level `test_*`
+//!   function is called, with `assert_eq()`, `assert_ne()`, `assert_true()`
+//!   and `assert_false()` available alongside the usual builtins.
+//!
+//! With `update` set, golden files are rewritten from the actual output
+//! instead of being compared.
+
+use crate::input_format::{InputFormat, InputFormatWrapper};
+use crate::output_format::OutputFormat;
+use crate::pipeline::config::{ErrorStrategy, PipelineConfig};
+use crate::pipeline::fstring::preprocess_fstrings;
+use crate::pipeline::global_functions::global_functions;
+use crate::pipeline::module_loader::ModuleLoader;
+use crate::pipeline::stream::StreamPipeline;
+use crate::pipeline_builder::PipelineBuilder;
+use crate::pipeline_file::PipelineFile;
+use starlark::environment::{GlobalsBuilder, Module};
+use starlark::eval::Evaluator;
+use starlark::starlark_module;
+use starlark::syntax::{AstModule, Dialect};
+use starlark::values::none::NoneType;
+use starlark::values::Value;
+use std::io::{self, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+const PIPELINE_FILES: &[&str] = &["pipeline.yaml", "pipeline.yml", "pipeline.star", CMD_FILE];
+/// Golden case pipeline given as command-line options
+const CMD_FILE: &str = "cmd";
+const STARLARK_TEST_SUFFIX: &str = "_test.star";
+/// Expected output file created by `--update` when a case has none yet
+const DEFAULT_EXPECTED_FILE: &str = "expected.out";
+
+/// Options for a test run
+#[derive(Debug, Clone, Default)]
+pub struct TestOptions {
+    /// Rewrite golden files instead of comparing against them
+    pub update: bool,
+    /// Extra `load()` directories, searched before `STELP_PATH`
+    pub lib_paths: Vec<PathBuf>,
+}
+
+/// A discovered test
+#[derive(Debug, Clone, PartialEq)]
+pub enum TestCase {
+    Golden {
+        dir: PathBuf,
+        pipeline: PathBuf,
+        input: PathBuf,
+        expected: Option<PathBuf>,
+    },
+    Starlark(PathBuf),
+}
+
+/// Outcome of one Starlark test function
+pub type TestResult = Result<(), String>;
+
+/// Counts from a test run
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TestSummary {
+    pub passed: usize,
+    pub failed: usize,
+    pub updated: usize,
+}
+
+impl TestSummary {
+    pub fn success(&self) -> bool {
+        self.failed == 0
+    }
+}
+
+/// Find golden cases and Starlark test files under `paths`, in path order
+pub fn discover(paths: &[PathBuf]) -> Result<Vec<TestCase>, String> {
+    let mut cases = Vec::new();
+    for path in paths {
+        if path.is_file() {
+            if is_starlark_test(path) {
+                cases.push(TestCase::Starlark(path.clone()));
+            } else if let Some(case) = path.parent().and_then(golden_case) {
+                // A pipeline file given directly selects its case
+                cases.push(case);
+            } else {
+                return Err(format!(
+                    "'{}' is not a test directory or *{} file",
+                    path.display(),
+                    STARLARK_TEST_SUFFIX
+                ));
+            }
+        } else if path.is_dir() {
+            discover_dir(path, &mut cases)
+                .map_err(|e| format!("cannot read '{}': {}", path.display(), e))?;
+        } else {
+            return Err(format!("'{}' does not exist", path.display()));
+        }
+    }
+    Ok(cases)
+}
+
+fn discover_dir(dir: &Path, cases: &mut Vec<TestCase>) -> io::Result<()> {
+    if let Some(case) = golden_case(dir) {
+        cases.push(case);
+    }
+
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .collect();
+    entries.sort();
+
+    for path in entries {
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        if path.is_dir() {
+            if !name.starts_with('.') && name != "target" {
+                discover_dir(&path, cases)?;
+            }
+        } else if is_starlark_test(&path) {
+            cases.push(TestCase::Starlark(path));
+        }
+    }
+    Ok(())
+}
+
+fn is_starlark_test(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n.ends_with(STARLARK_TEST_SUFFIX))
+}
+
+/// The golden case in `dir`, if it has a pipeline file and an input file
+fn golden_case(dir: &Path) -> Option<TestCase> {
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let pipeline = PIPELINE_FILES
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file())?;
+    let input = file_with_stem(dir, "input")?;
+    Some(TestCase::Golden {
+        dir: dir.to_path_buf(),
+        pipeline,
+        input,
+        expected: file_with_stem(dir, "expected"),
+    })
+}
+
+/// `dir/<stem>` or the first `dir/<stem>.*`
+fn file_with_stem(dir: &Path, stem: &str) -> Option<PathBuf> {
+    let mut matches: Vec<PathBuf> = std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file() && path.file_stem().and_then(|s| s.to_str()) == Some(stem))
+        .collect();
+    matches.sort();
+    matches.into_iter().next()
+}
+
+/// Run every discovered test, reporting results to `out`
+pub fn run_tests(
+    paths: &[PathBuf],
+    options: &TestOptions,
+    out: &mut dyn Write,
+) -> Result<TestSummary, String> {
+    let cases = discover(paths)?;
+    if cases.is_empty() {
+        let searched: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+        return Err(format!("no tests found in {}", searched.join(", ")));
+    }
+
+    let mut summary = TestSummary::default();
+    for case in &cases {
+        match case {
+            TestCase::Golden {
+                dir,
+                pipeline,
+                input,
+                expected,
+            } => run_golden_case(
+                dir,
+                pipeline,
+                input,
+                expected.as_deref(),
+                options,
+                out,
+                &mut summary,
+            ),
+            TestCase::Starlark(path) => run_starlark_file(path, options, out, &mut summary),
+        }
+        .map_err(|e| format!("failed to write test report: {}", e))?;
+    }
+
+    writeln!(
+        out,
+        "\n{} passed, {} failed{}",
+        summary.passed,
+        summary.failed,
+        if summary.updated > 0 {
+            format!(", {} updated", summary.updated)
+        } else {
+            String::new()
+        }
+    )
+    .map_err(|e| format!("failed to write test report: {}", e))?;
+    Ok(summary)
+}
+
+fn run_golden_case(
+    dir: &Path,
+    pipeline: &Path,
+    input: &Path,
+    expected: Option<&Path>,
+    options: &TestOptions,
+    out: &mut dyn Write,
+    summary: &mut TestSummary,
+) -> io::Result<()> {
+    let name = dir.display();
+    let actual = match run_pipeline_file(pipeline, input, &options.lib_paths) {
+        Ok(actual) => actual,
+        Err(e) => {
+            summary.failed += 1;
+            return writeln!(out, "FAIL {}: {}", name, e);
+        }
+    };
+
+    if options.update {
+        let path = expected
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| dir.join(DEFAULT_EXPECTED_FILE));
+        let unchanged = std::fs::read_to_string(&path).is_ok_and(|old| old == actual);
+        if unchanged {
+            summary.passed += 1;
+            return writeln!(out, "PASS {}", name);
+        }
+        std::fs::write(&path, &actual)?;
+        summary.updated += 1;
+        return writeln!(out, "UPDATED {} ({})", name, path.display());
+    }
+
+    let Some(expected_path) = expected else {
+        summary.failed += 1;
+        return writeln!(
+            out,
+            "FAIL {}: no expected output file (run with --update to create it)",
+            name
+        );
+    };
+    let expected_output = std::fs::read_to_string(expected_path)?;
+    if expected_output == actual {
+        summary.passed += 1;
+        writeln!(out, "PASS {}", name)
+    } else {
+        summary.failed += 1;
+        writeln!(
+            out,
+            "FAIL {}: output differs from {}",
+            name,
+            expected_path.display()
+        )?;
+        writeln!(out, "--- expected")?;
+        writeln!(out, "+++ actual")?;
+        write!(out, "{}", line_diff(&expected_output, &actual))
+    }
+}
+
+/// Run a pipeline file (or `cmd` file) over an input file and return the output
+pub fn run_pipeline_file(
+    pipeline_path: &Path,
+    input_path: &Path,
+    lib_paths: &[PathBuf],
+) -> Result<String, String> {
+    use clap::ValueEnum;
+
+    let file = if pipeline_path
+        .file_name()
+        .is_some_and(|name| name == CMD_FILE)
+    {
+        PipelineFile::load_command_line(pipeline_path)?
+    } else {
+        PipelineFile::load(pipeline_path)?
+    };
+    let mut builder = PipelineBuilder::from_pipeline_file(&file, pipeline_path)?;
+    builder.input_format = builder
+        .input_format
+        .or_else(|| InputFormat::from_extension(input_path))
+        .or(Some(InputFormat::Line));
+    builder.lib_paths.splice(0..0, lib_paths.iter().cloned());
+
+    let output_format = match &file.output_format {
+        Some(name) => OutputFormat::from_str(name, true)
+            .map_err(|_| format!("unknown output format '{}'", name))?,
+        None => builder.default_output_format(),
+    };
+    let split = |list: &crate::pipeline_file::NameList| -> Vec<String> {
+        list.to_csv()
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect()
+    };
+
+    let config = PipelineConfig {
+        error_strategy: if file.fail_fast {
+            ErrorStrategy::FailFast
+        } else {
+            ErrorStrategy::Skip
+        },
+        input_format: builder.input_format.clone(),
+        output_format,
+        keys: file.keys.as_ref().map(split),
+        remove_keys: file.remove_keys.as_ref().map(split),
+        // Goldens must not depend on whether the runner has a terminal
+        color_preference: Some(false),
+        plain: file.plain,
+        glob_limit: file.glob_limit,
+        ..PipelineConfig::default()
+    };
+    let mut pipeline = StreamPipeline::new(config);
+    builder.add_processors(&mut pipeline)?;
+
+    let mut format_wrapper = InputFormatWrapper::new(builder.input_format.as_ref());
+    if let Some(chunk_config) = builder.chunk_config()? {
+        format_wrapper = format_wrapper.with_chunking(chunk_config);
+    }
+
+    let input = std::fs::File::open(input_path).map_err(|e| {
+        format!(
+            "failed to open input file '{}': {}",
+            input_path.display(),
+            e
+        )
+    })?;
+    let mut output = Vec::new();
+    format_wrapper
+        .process_with_pipeline(
+            BufReader::new(input),
+            &mut pipeline,
+            &mut output,
+            Some(&input_path.to_string_lossy()),
+        )
+        .map_err(|e| format!("processing '{}' failed: {}", input_path.display(), e))?;
+    pipeline
+        .finish_output()
+        .map_err(|e| format!("failed to write output files: {}", e))?;
+
+    String::from_utf8(output).map_err(|e| format!("output is not valid UTF-8: {}", e))
+}
+
+fn run_starlark_file(
+    path: &Path,
+    options: &TestOptions,
+    out: &mut dyn Write,
+    summary: &mut TestSummary,
+) -> io::Result<()> {
+    match starlark_tests(path, &options.lib_paths) {
+        Ok(results) if results.is_empty() => {
+            summary.failed += 1;
+            writeln!(out, "FAIL {}: no test_* functions defined", path.display())
+        }
+        Ok(results) => {
+            for (name, result) in results {
+                match result {
+                    Ok(()) => {
+                        summary.passed += 1;
+                        writeln!(out, "PASS {}::{}", path.display(), name)?;
+                    }
+                    Err(e) => {
+                        summary.failed += 1;
+                        writeln!(out, "FAIL {}::{}: {}", path.display(), name, e)?;
+                    }
+                }
+            }
+            Ok(())
+        }
+        Err(e) => {
+            summary.failed += 1;
+            writeln!(out, "FAIL {}: {}", path.display(), e)
+        }
+    }
+}
+
+/// Evaluate a Starlark test file and call each `test_*` function, in name order
+pub fn starlark_tests(
+    path: &Path,
+    lib_paths: &[PathBuf],
+) -> Result<Vec<(String, TestResult)>, String> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|e| format!("failed to read '{}': {}", path.display(), e))?;
+    let fstrings = preprocess_fstrings(&source);
+    let dialect = Dialect {
+        enable_f_strings: true,
+        ..Dialect::Extended
+    };
+    let ast = AstModule::parse(
+        &path.display().to_string(),
+        fstrings.source.clone(),
+        &dialect,
+    )
+    .map_err(|e| fstrings.map_error(&e.to_string()))?;
+
+    let globals = GlobalsBuilder::standard()
+        .with(global_functions)
+        .with(assert_functions)
+        .build();
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let loader = Arc::new(ModuleLoader::from_env(lib_paths)).for_dir(dir);
+
+    let module = Module::new();
+    {
+        let mut eval = Evaluator::new(&module);
+        eval.set_loader(&loader);
+        eval.eval_module(ast, &globals)
+            .map_err(|e| fstrings.map_error(&e.to_string()))?;
+    }
+
+    let mut names: Vec<String> = module
+        .names()
+        .map(|name| name.as_str().to_string())
+        .filter(|name| name.starts_with("test_"))
+        .collect();
+    names.sort();
+
+    let mut results = Vec::new();
+    for name in names {
+        let Some(function) = module.get(&name) else {
+            continue;
+        };
+        if function.get_type() != "function" {
+            continue;
+        }
+        let mut eval = Evaluator::new(&module);
+        eval.set_loader(&loader);
+        let result = eval
+            .eval_function(function, &[], &[])
+            .map(|_| ())
+            .map_err(|e| fstrings.map_error(&e.to_string()));
+        results.push((name, result));
+    }
+    Ok(results)
+}
+
+#[starlark_module]
+fn assert_functions(builder: &mut GlobalsBuilder) {
+    /// Fail the test unless actual == expected
+    fn assert_eq<'v>(
+        actual: Value<'v>,
+        expected: Value<'v>,
+        #[starlark(default = "")] msg: &str,
+    ) -> anyhow::Result<NoneType> {
+        if actual
+            .equals(expected)
+            .map_err(starlark::Error::into_anyhow)?
+        {
+            Ok(NoneType)
+        } else {
+            Err(assertion_error(
+                msg,
+                format!("assert_eq failed: {} != {}", actual, expected),
+            ))
+        }
+    }
+
+    /// Fail the test if actual == unexpected
+    fn assert_ne<'v>(
+        actual: Value<'v>,
+        unexpected: Value<'v>,
+        #[starlark(default = "")] msg: &str,
+    ) -> anyhow::Result<NoneType> {
+        if actual
+            .equals(unexpected)
+            .map_err(starlark::Error::into_anyhow)?
+        {
+            Err(assertion_error(
+                msg,
+                format!("assert_ne failed: both are {}", actual),
+            ))
+        } else {
+            Ok(NoneType)
+        }
+    }
+
+    /// Fail the test unless the condition is truthy
+    fn assert_true<'v>(
+        condition: Value<'v>,
+        #[starlark(default = "")] msg: &str,
+    ) -> anyhow::Result<NoneType> {
+        if condition.to_bool() {
+            Ok(NoneType)
+        } else {
+            Err(assertion_error(
+                msg,
+                format!("assert_true failed: {} is falsy", condition),
+            ))
+        }
+    }
+
+    /// Fail the test if the condition is truthy
+    fn assert_false<'v>(
+        condition: Value<'v>,
+        #[starlark(default = "")] msg: &str,
+    ) -> anyhow::Result<NoneType> {
+        if condition.to_bool() {
+            Err(assertion_error(
+                msg,
+                format!("assert_false failed: {} is truthy", condition),
+            ))
+        } else {
+            Ok(NoneType)
+        }
+    }
+}
+
+fn assertion_error(msg: &str, default: String) -> anyhow::Error {
+    if msg.is_empty() {
+        anyhow::anyhow!(default)
+    } else {
+        anyhow::anyhow!("{}: {}", msg, default)
+    }
+}
+
+/// Number of unchanged lines shown around each change
+const DIFF_CONTEXT: usize = 3;
+
+/// Line diff of two outputs: ` ` unchanged, `-` expected only, `+` actual only
+pub fn line_diff(expected: &str, actual: &str) -> String {
+    let old: Vec<&str> = expected.lines().collect();
+    let new: Vec<&str> = actual.lines().collect();
+
+    // Skip the common prefix and suffix so the LCS table stays small
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    // lcs[i][j] = length of the LCS of old_mid[i..] and new_mid[j..]
+    let mut lcs = vec![vec![0usize; new_mid.len() + 1]; old_mid.len() + 1];
+    for i in (0..old_mid.len()).rev() {
+        for j in (0..new_mid.len()).rev() {
+            lcs[i][j] = if old_mid[i] == new_mid[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    // No context when only the trailing newline differs
+    let context = if old_mid.is_empty() && new_mid.is_empty() {
+        0
+    } else {
+        DIFF_CONTEXT
+    };
+    let mut diff = String::new();
+    for line in &old[prefix.saturating_sub(context)..prefix] {
+        diff.push_str(&format!(" {}\n", line));
+    }
+    let (mut i, mut j) = (0, 0);
+    while i < old_mid.len() || j < new_mid.len() {
+        if i < old_mid.len() && j < new_mid.len() && old_mid[i] == new_mid[j] {
+            diff.push_str(&format!(" {}\n", old_mid[i]));
+            i += 1;
+            j += 1;
+        } else if i < old_mid.len() && (j == new_mid.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            diff.push_str(&format!("-{}\n", old_mid[i]));
+            i += 1;
+        } else {
+            diff.push_str(&format!("+{}\n", new_mid[j]));
+            j += 1;
+        }
+    }
+    let tail = old.len() - suffix;
+    for line in &old[tail..(tail + context).min(old.len())] {
+        diff.push_str(&format!(" {}\n", line));
+    }
+    if expected.ends_with('\n') != actual.ends_with('\n') {
+        diff.push_str("\\ trailing newline differs\n");
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_diff() {
+        let diff = line_diff("a\nb\nc\nd\n", "a\nB\nc\nd\ne\n");
+        assert_eq!(diff, " a\n-b\n+B\n c\n d\n+e\n");
+        assert_eq!(line_diff("x\n", "x\n"), "");
+        assert_eq!(line_diff("x\n", "x"), "\\ trailing newline differs\n");
+    }
+
+    #[test]
+    fn test_line_diff_limits_context() {
+        let expected: String = (1..=10).map(|n| format!("{}\n", n)).collect();
+        let actual = expected.replace("5\n", "five\n");
+        assert_eq!(
+            line_diff(&expected, &actual),
+            " 2\n 3\n 4\n-5\n+five\n 6\n 7\n 8\n"
+        );
+    }
+}
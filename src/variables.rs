@@ -1,51 +1,380 @@
 // src/variables.rs
-use starlark::values::{Heap, Value};
-use std::cell::RefCell;
-use std::collections::HashMap;
-
-/// Global variables that persist across lines
-/// TODO: Memory optimization - HashMap grows linearly with unique variable names
-/// For constant memory usage in streaming scenarios, consider:
-/// - Bounded storage with LRU eviction
-/// - Periodic clearing of unused variables  
-/// - Reference counting for variable lifecycle management
+use crate::accumulators::{Histogram, TopK};
+use indexmap::IndexMap;
+use starlark::collections::SmallMap;
+use starlark::values::dict::{Dict, DictRef};
+use starlark::values::float::StarlarkFloat;
+use starlark::values::list::ListRef;
+use starlark::values::tuple::TupleRef;
+use starlark::values::{Heap, UnpackValue, Value, ValueLike};
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+
+/// A value kept in `glob` between records. Starlark values live on a
+/// per-record heap, so anything that must outlive the record is copied
+/// into this owned form.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GlobalValue {
+    None,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    List(Vec<GlobalValue>),
+    Tuple(Vec<GlobalValue>),
+    /// Entries in insertion order; keys may be any hashable value
+    Dict(Vec<(GlobalValue, GlobalValue)>),
+    /// Distinct values added with `set_add`, keyed by their JSON text.
+    /// Scripts see a list.
+    Set(IndexMap<String, GlobalValue>),
+    /// Quantile sketch fed by `hist_add`; scripts see a summary dict
+    Hist(Box<Histogram>),
+    /// Frequent keys counted by `topk_add`; scripts see a key -> count dict
+    TopK(Box<TopK>),
+}
+
+impl GlobalValue {
+    pub fn from_starlark(value: Value) -> GlobalValue {
+        if value.is_none() {
+            GlobalValue::None
+        } else if let Some(b) = value.unpack_bool() {
+            GlobalValue::Bool(b)
+        } else if let Ok(Some(i)) = i64::unpack_value(value) {
+            GlobalValue::Int(i)
+        } else if let Some(f) = value.downcast_ref::<StarlarkFloat>() {
+            GlobalValue::Float(f.0)
+        } else if let Some(s) = value.unpack_str() {
+            GlobalValue::Str(s.to_string())
+        } else if let Some(list) = ListRef::from_value(value) {
+            GlobalValue::List(list.iter().map(GlobalValue::from_starlark).collect())
+        } else if let Some(tuple) = TupleRef::from_value(value) {
+            GlobalValue::Tuple(tuple.iter().map(GlobalValue::from_starlark).collect())
+        } else if let Some(dict) = DictRef::from_value(value) {
+            GlobalValue::Dict(
+                dict.iter()
+                    .map(|(k, v)| (GlobalValue::from_starlark(k), GlobalValue::from_starlark(v)))
+                    .collect(),
+            )
+        } else {
+            // Integers beyond i64, functions, structs, ...: keep what they print as
+            GlobalValue::Str(value.to_string())
+        }
+    }
+
+    pub fn to_starlark<'v>(&self, heap: &'v Heap) -> Value<'v> {
+        match self {
+            GlobalValue::None => Value::new_none(),
+            GlobalValue::Bool(b) => Value::new_bool(*b),
+            GlobalValue::Int(i) => heap.alloc(*i),
+            GlobalValue::Float(f) => heap.alloc(*f),
+            GlobalValue::Str(s) => heap.alloc(s.as_str()),
+            GlobalValue::List(items) => heap.alloc(
+                items
+                    .iter()
+                    .map(|v| v.to_starlark(heap))
+                    .collect::<Vec<_>>(),
+            ),
+            GlobalValue::Tuple(items) => heap.alloc(starlark::values::tuple::AllocTuple(
+                items.iter().map(|v| v.to_starlark(heap)),
+            )),
+            GlobalValue::Dict(entries) => {
+                let mut map = SmallMap::with_capacity(entries.len());
+                for (k, v) in entries {
+                    // Keys were hashable when stored, so this only skips on a bug
+                    if let Ok(key) = k.to_starlark(heap).get_hashed() {
+                        map.insert_hashed(key, v.to_starlark(heap));
+                    }
+                }
+                heap.alloc(Dict::new(map))
+            }
+            GlobalValue::Set(_) | GlobalValue::Hist(_) | GlobalValue::TopK(_) => {
+                self.summary().to_starlark(heap)
+            }
+        }
+    }
+
+    /// Ints and floats as f64
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            GlobalValue::Int(i) => Some(*i as f64),
+            GlobalValue::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    /// Type name as scripts know it
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            GlobalValue::None => "NoneType",
+            GlobalValue::Bool(_) => "bool",
+            GlobalValue::Int(_) => "int",
+            GlobalValue::Float(_) => "float",
+            GlobalValue::Str(_) => "string",
+            GlobalValue::List(_) => "list",
+            GlobalValue::Tuple(_) => "tuple",
+            GlobalValue::Dict(_) => "dict",
+            GlobalValue::Set(_) => "set",
+            GlobalValue::Hist(_) => "histogram",
+            GlobalValue::TopK(_) => "topk",
+        }
+    }
+
+    /// Whether scripts see a plain copy (`summary()`) rather than the value itself
+    pub fn is_summarised(&self) -> bool {
+        matches!(
+            self,
+            GlobalValue::Set(_) | GlobalValue::Hist(_) | GlobalValue::TopK(_)
+        )
+    }
+
+    /// The plain value scripts see for sets, histograms and top-k counters
+    pub fn summary(&self) -> GlobalValue {
+        let entry = |key: &str, value: GlobalValue| (GlobalValue::Str(key.to_string()), value);
+        let float = |value: Option<f64>| value.map_or(GlobalValue::None, GlobalValue::Float);
+        match self {
+            GlobalValue::Set(values) => GlobalValue::List(values.values().cloned().collect()),
+            GlobalValue::Hist(hist) => GlobalValue::Dict(vec![
+                entry("count", GlobalValue::Int(hist.count as i64)),
+                entry("sum", GlobalValue::Float(hist.sum)),
+                entry("min", GlobalValue::Float(hist.min)),
+                entry("max", GlobalValue::Float(hist.max)),
+                entry("mean", float(hist.mean())),
+                entry("p50", float(hist.quantile(0.5))),
+                entry("p90", float(hist.quantile(0.9))),
+                entry("p99", float(hist.quantile(0.99))),
+            ]),
+            GlobalValue::TopK(top) => GlobalValue::Dict(
+                top.top()
+                    .into_iter()
+                    .map(|(key, count)| entry(&key, GlobalValue::Int(count as i64)))
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    }
+
+    /// Key identifying this value in a set
+    pub(crate) fn set_key(&self) -> String {
+        self.to_json().to_string()
+    }
+
+    /// Starlark representation, e.g. `{"a": [1, 2.5]}`
+    pub fn repr(&self) -> String {
+        let heap = Heap::new();
+        self.to_starlark(&heap).to_repr()
+    }
+
+    /// JSON for the state file. Tuples and dicts with non-string keys have
+    /// no JSON equivalent and are written as `{"$tuple": [...]}` and
+    /// `{"$dict": [[key, value], ...]}` so they load back unchanged.
+    pub fn to_json(&self) -> serde_json::Value {
+        use serde_json::{json, Value as Json};
+        match self {
+            GlobalValue::None => Json::Null,
+            GlobalValue::Bool(b) => Json::Bool(*b),
+            GlobalValue::Int(i) => Json::from(*i),
+            GlobalValue::Float(f) => Json::from(*f),
+            GlobalValue::Str(s) => Json::String(s.clone()),
+            GlobalValue::List(items) => Json::Array(items.iter().map(Self::to_json).collect()),
+            GlobalValue::Tuple(items) => {
+                json!({ "$tuple": items.iter().map(Self::to_json).collect::<Vec<_>>() })
+            }
+            GlobalValue::Dict(entries) => {
+                if entries
+                    .iter()
+                    .all(|(k, _)| matches!(k, GlobalValue::Str(_)))
+                {
+                    Json::Object(
+                        entries
+                            .iter()
+                            .map(|(k, v)| match k {
+                                GlobalValue::Str(k) => (k.clone(), v.to_json()),
+                                _ => unreachable!(),
+                            })
+                            .collect(),
+                    )
+                } else {
+                    let pairs: Vec<Json> = entries
+                        .iter()
+                        .map(|(k, v)| json!([k.to_json(), v.to_json()]))
+                        .collect();
+                    json!({ "$dict": pairs })
+                }
+            }
+            GlobalValue::Set(values) => {
+                json!({ "$set": values.values().map(Self::to_json).collect::<Vec<_>>() })
+            }
+            GlobalValue::Hist(hist) => json!({ "$hist": hist }),
+            GlobalValue::TopK(top) => json!({ "$topk": top }),
+        }
+    }
+
+    pub fn from_json(json: &serde_json::Value) -> GlobalValue {
+        use serde_json::Value as Json;
+        match json {
+            Json::Null => GlobalValue::None,
+            Json::Bool(b) => GlobalValue::Bool(*b),
+            Json::Number(n) => match n.as_i64() {
+                Some(i) => GlobalValue::Int(i),
+                None => GlobalValue::Float(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            Json::String(s) => GlobalValue::Str(s.clone()),
+            Json::Array(items) => GlobalValue::List(items.iter().map(Self::from_json).collect()),
+            Json::Object(obj) => {
+                if let (1, Some(Json::Array(items))) = (obj.len(), obj.get("$tuple")) {
+                    return GlobalValue::Tuple(items.iter().map(Self::from_json).collect());
+                }
+                if let (1, Some(Json::Array(items))) = (obj.len(), obj.get("$set")) {
+                    return GlobalValue::Set(
+                        items
+                            .iter()
+                            .map(|item| {
+                                let value = Self::from_json(item);
+                                (value.set_key(), value)
+                            })
+                            .collect(),
+                    );
+                }
+                if obj.len() == 1 {
+                    if let Some(Ok(hist)) = obj.get("$hist").map(|h| serde_json::from_value(h.clone())) {
+                        return GlobalValue::Hist(Box::new(hist));
+                    }
+                    if let Some(Ok(top)) = obj.get("$topk").map(|t| serde_json::from_value(t.clone())) {
+                        return GlobalValue::TopK(Box::new(top));
+                    }
+                }
+                if let (1, Some(Json::Array(pairs))) = (obj.len(), obj.get("$dict")) {
+                    return GlobalValue::Dict(
+                        pairs
+                            .iter()
+                            .filter_map(|pair| match pair.as_array()?.as_slice() {
+                                [k, v] => Some((Self::from_json(k), Self::from_json(v))),
+                                _ => None,
+                            })
+                            .collect(),
+                    );
+                }
+                GlobalValue::Dict(
+                    obj.iter()
+                        .map(|(k, v)| (GlobalValue::Str(k.clone()), Self::from_json(v)))
+                        .collect(),
+                )
+            }
+        }
+    }
+}
+
+struct Entry {
+    value: GlobalValue,
+    /// Last access, for LRU eviction when the store is bounded
+    tick: u64,
+}
+
+/// Global variables (`glob`) that persist across records.
+///
+/// By default every key is kept. With `with_limit(n)` the store holds at
+/// most `n` keys and evicts the least recently used one to make room, so
+/// scripts that key on unbounded values (IPs, session ids, ...) run in
+/// constant memory.
 pub struct GlobalVariables {
-    store: RefCell<HashMap<String, String>>, // Store as JSON strings to avoid lifetime issues
+    store: RefCell<IndexMap<String, Entry>>,
+    limit: Option<usize>,
+    /// tick -> key, oldest first; only maintained when bounded
+    recency: RefCell<BTreeMap<u64, String>>,
+    tick: Cell<u64>,
+    evicted: Cell<usize>,
 }
 
 impl GlobalVariables {
     pub fn new() -> Self {
         GlobalVariables {
-            store: RefCell::new(HashMap::new()),
+            store: RefCell::new(IndexMap::new()),
+            limit: None,
+            recency: RefCell::new(BTreeMap::new()),
+            tick: Cell::new(0),
+            evicted: Cell::new(0),
+        }
+    }
+
+    /// Keep at most `limit` keys, evicting the least recently used
+    pub fn with_limit(limit: usize) -> Self {
+        GlobalVariables {
+            limit: Some(limit.max(1)),
+            ..Self::new()
+        }
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    /// Number of keys dropped to stay within the limit
+    pub fn evicted(&self) -> usize {
+        self.evicted.get()
+    }
+
+    fn next_tick(&self) -> u64 {
+        let tick = self.tick.get() + 1;
+        self.tick.set(tick);
+        tick
+    }
+
+    /// Mark `entry` as just used
+    fn touch(&self, name: &str, entry: &mut Entry) {
+        if self.limit.is_some() {
+            let mut recency = self.recency.borrow_mut();
+            recency.remove(&entry.tick);
+            entry.tick = self.next_tick();
+            recency.insert(entry.tick, name.to_string());
         }
     }
 
     pub fn get<'v>(&self, heap: &'v Heap, name: &str, default: Option<Value<'v>>) -> Value<'v> {
-        if let Some(json_str) = self.store.borrow().get(name) {
-            // Try to deserialize from JSON
-            if let Ok(json_value) = serde_json::from_str::<serde_json::Value>(json_str) {
-                if let Ok(starlark_value) = json_to_starlark_value(heap, json_value) {
-                    return starlark_value;
-                }
-            }
+        match self.get_value(name) {
+            Some(value) => value.to_starlark(heap),
+            None => default.unwrap_or(Value::new_none()),
         }
-        default.unwrap_or(Value::new_none())
+    }
+
+    pub fn get_value(&self, name: &str) -> Option<GlobalValue> {
+        let mut store = self.store.borrow_mut();
+        let entry = store.get_mut(name)?;
+        self.touch(name, entry);
+        Some(entry.value.clone())
     }
 
     pub fn set(&self, name: String, value: Value<'_>) {
-        // Convert to JSON for storage
-        if let Ok(json_value) = starlark_to_json_value(value) {
-            if let Ok(json_str) = serde_json::to_string(&json_value) {
-                self.store.borrow_mut().insert(name, json_str);
+        self.set_value(name, GlobalValue::from_starlark(value));
+    }
+
+    pub fn set_value(&self, name: String, value: GlobalValue) {
+        let mut store = self.store.borrow_mut();
+        if let Some(entry) = store.get_mut(&name) {
+            entry.value = value;
+            self.touch(&name, entry);
+            return;
+        }
+
+        if let Some(limit) = self.limit {
+            let mut recency = self.recency.borrow_mut();
+            while store.len() >= limit {
+                let Some((_, oldest)) = recency.pop_first() else {
+                    break;
+                };
+                store.shift_remove(&oldest);
+                self.evicted.set(self.evicted.get() + 1);
             }
         }
+        let mut entry = Entry { value, tick: 0 };
+        self.touch(&name, &mut entry);
+        store.insert(name, entry);
     }
 
     pub fn clear(&self) {
         self.store.borrow_mut().clear();
+        self.recency.borrow_mut().clear();
     }
 
-    // Additional methods needed for glob dictionary
     pub fn contains(&self, name: &str) -> bool {
         self.store.borrow().contains_key(name)
     }
@@ -58,32 +387,114 @@ impl GlobalVariables {
         self.store.borrow().is_empty()
     }
 
+    /// Keys in insertion order
     pub fn keys(&self) -> Vec<String> {
         self.store.borrow().keys().cloned().collect()
     }
 
     pub fn remove(&self, name: &str) -> bool {
-        self.store.borrow_mut().remove(name).is_some()
+        match self.store.borrow_mut().shift_remove(name) {
+            Some(entry) => {
+                self.recency.borrow_mut().remove(&entry.tick);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Keys accepted by `keep` as a JSON object (see `GlobalValue::to_json`)
+    pub fn to_json(
+        &self,
+        keep: impl Fn(&str) -> bool,
+    ) -> serde_json::Map<String, serde_json::Value> {
+        self.store
+            .borrow()
+            .iter()
+            .filter(|(key, _)| keep(key))
+            .map(|(key, entry)| (key.clone(), entry.value.to_json()))
+            .collect()
+    }
+
+    /// Set every key accepted by `keep` from a `to_json` object; returns how many were set
+    pub fn load_json(
+        &self,
+        object: &serde_json::Map<String, serde_json::Value>,
+        keep: impl Fn(&str) -> bool,
+    ) -> usize {
+        let mut loaded = 0;
+        for (key, value) in object {
+            if keep(key) {
+                self.set_value(key.clone(), GlobalValue::from_json(value));
+                loaded += 1;
+            }
+        }
+        loaded
+    }
+
+    /// Run `f` on the value of `name` (if any) without copying it
+    pub fn inspect<R>(&self, name: &str, f: impl FnOnce(Option<&GlobalValue>) -> R) -> R {
+        let mut store = self.store.borrow_mut();
+        match store.get_mut(name) {
+            Some(entry) => {
+                self.touch(name, entry);
+                f(Some(&entry.value))
+            }
+            None => f(None),
+        }
     }
 
-    pub fn get_raw(&self, name: &str) -> Option<String> {
-        self.store.borrow().get(name).cloned()
+    /// Run `f` on the value of `name` in place, storing `init()` first if
+    /// the key is missing. Avoids copying large values such as sets.
+    pub fn update<R>(
+        &self,
+        name: &str,
+        init: impl FnOnce() -> GlobalValue,
+        f: impl FnOnce(&mut GlobalValue) -> R,
+    ) -> R {
+        if !self.contains(name) {
+            self.set_value(name.to_string(), init());
+        }
+        let mut store = self.store.borrow_mut();
+        let entry = store.get_mut(name).expect("key was just set");
+        self.touch(name, entry);
+        f(&mut entry.value)
     }
 
-    pub fn increment_counter(&self, name: &str) -> i32 {
-        let current = self
-            .get_raw(name)
-            .and_then(|s| serde_json::from_str::<serde_json::Value>(&s).ok())
-            .and_then(|v| v.as_i64())
-            .unwrap_or(0) as i32;
+    /// Add `delta` (an int or float) to the counter `name` and return the
+    /// new value. Missing and non-numeric counters start from 0; ints stay
+    /// ints until a float is added.
+    pub fn increment(&self, name: &str, delta: &GlobalValue) -> Result<GlobalValue, String> {
+        if !matches!(delta, GlobalValue::Int(_) | GlobalValue::Float(_)) {
+            return Err(format!(
+                "increment must be an int or float, got {}",
+                delta.type_name()
+            ));
+        }
+        self.update(
+            name,
+            || GlobalValue::Int(0),
+            |current| {
+                let new_value = match (&*current, delta) {
+                    (GlobalValue::Int(a), GlobalValue::Int(b)) => GlobalValue::Int(
+                        a.checked_add(*b)
+                            .ok_or_else(|| format!("counter '{}' overflowed", name))?,
+                    ),
+                    (GlobalValue::Float(a), GlobalValue::Int(b)) => GlobalValue::Float(a + *b as f64),
+                    (GlobalValue::Int(a), GlobalValue::Float(b)) => GlobalValue::Float(*a as f64 + b),
+                    (GlobalValue::Float(a), GlobalValue::Float(b)) => GlobalValue::Float(a + b),
+                    _ => delta.clone(),
+                };
+                *current = new_value.clone();
+                Ok(new_value)
+            },
+        )
+    }
 
-        let new_value = current + 1;
-        let json_str = serde_json::to_string(&serde_json::Value::Number(serde_json::Number::from(
-            new_value,
-        )))
-        .unwrap_or_else(|_| "1".to_string());
-        self.store.borrow_mut().insert(name.to_string(), json_str);
-        new_value
+    pub fn increment_counter(&self, name: &str) -> i64 {
+        match self.increment(name, &GlobalValue::Int(1)) {
+            Ok(GlobalValue::Int(i)) => i,
+            _ => 0,
+        }
     }
 }
 
@@ -93,68 +504,118 @@ impl Default for GlobalVariables {
     }
 }
 
-// Helper functions for JSON conversion
-fn json_to_starlark_value(heap: &Heap, json: serde_json::Value) -> anyhow::Result<Value<'_>> {
-    match json {
-        serde_json::Value::Null => Ok(Value::new_none()),
-        serde_json::Value::Bool(b) => Ok(Value::new_bool(b)),
-        serde_json::Value::Number(n) => {
-            if let Some(i) = n.as_i64() {
-                Ok(heap.alloc(i as i32))
-            } else if let Some(f) = n.as_f64() {
-                Ok(heap.alloc(f))
-            } else {
-                Ok(heap.alloc(n.to_string()))
-            }
-        }
-        serde_json::Value::String(s) => Ok(heap.alloc(s)),
-        serde_json::Value::Array(arr) => {
-            let values: Result<Vec<Value>, _> = arr
-                .into_iter()
-                .map(|v| json_to_starlark_value(heap, v))
-                .collect();
-            Ok(heap.alloc(values?))
-        }
-        serde_json::Value::Object(obj) => {
-            // For now, just create a simple dict representation as a string
-            // This avoids the complex Starlark dict API issues
-            let mut items = Vec::new();
-            for (k, v) in obj {
-                let value_str = match json_to_starlark_value(heap, v) {
-                    Ok(val) => val.to_string(),
-                    Err(_) => "None".to_string(),
-                };
-                items.push(format!("{}: {}", k, value_str));
-            }
-            let dict_str = format!("{{{}}}", items.join(", "));
-            Ok(heap.alloc(dict_str))
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_values_round_trip_natively() {
+        let heap = Heap::new();
+        let globals = GlobalVariables::new();
+        let nested = GlobalValue::Dict(vec![
+            (GlobalValue::Str("hits".into()), GlobalValue::Int(3)),
+            (
+                GlobalValue::Tuple(vec![GlobalValue::Int(404), GlobalValue::Str("/".into())]),
+                GlobalValue::List(vec![GlobalValue::Float(0.5), GlobalValue::None]),
+            ),
+        ]);
+        globals.set("stats".to_string(), nested.to_starlark(&heap));
+        assert_eq!(globals.get_value("stats"), Some(nested));
+        assert_eq!(
+            globals.get(&heap, "stats", None).to_repr(),
+            r#"{"hits": 3, (404, "/"): [0.5, None]}"#
+        );
+
+        globals.set("big".to_string(), heap.alloc(1_i64 << 40));
+        assert_eq!(globals.get_value("big"), Some(GlobalValue::Int(1 << 40)));
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let value = GlobalValue::Dict(vec![
+            (GlobalValue::Str("n".into()), GlobalValue::Int(7)),
+            (
+                GlobalValue::Str("by_status".into()),
+                GlobalValue::Dict(vec![(GlobalValue::Int(404), GlobalValue::Float(1.5))]),
+            ),
+            (
+                GlobalValue::Str("pair".into()),
+                GlobalValue::Tuple(vec![GlobalValue::Str("a".into()), GlobalValue::None]),
+            ),
+        ]);
+        let json = value.to_json();
+        assert_eq!(json["n"], 7);
+        assert_eq!(json["by_status"]["$dict"][0][0], 404);
+        assert_eq!(GlobalValue::from_json(&json), value);
+    }
+
+    #[test]
+    fn test_limit_evicts_least_recently_used() {
+        let globals = GlobalVariables::with_limit(2);
+        globals.set_value("a".to_string(), GlobalValue::Int(1));
+        globals.set_value("b".to_string(), GlobalValue::Int(2));
+        // Reading "a" makes "b" the oldest
+        assert!(globals.get_value("a").is_some());
+        globals.set_value("c".to_string(), GlobalValue::Int(3));
+
+        assert_eq!(globals.keys(), vec!["a", "c"]);
+        assert_eq!(globals.evicted(), 1);
+
+        // Updating an existing key never evicts
+        globals.set_value("c".to_string(), GlobalValue::Int(4));
+        assert_eq!(globals.len(), 2);
+        assert_eq!(globals.evicted(), 1);
     }
-}
 
-fn starlark_to_json_value(value: Value) -> anyhow::Result<serde_json::Value> {
-    use starlark::values::{dict::DictRef, list::ListRef};
-
-    if value.is_none() {
-        Ok(serde_json::Value::Null)
-    } else if let Some(b) = value.unpack_bool() {
-        Ok(serde_json::Value::Bool(b))
-    } else if let Some(i) = value.unpack_i32() {
-        Ok(serde_json::Value::Number(serde_json::Number::from(i)))
-    } else if let Some(s) = value.unpack_str() {
-        Ok(serde_json::Value::String(s.to_string()))
-    } else if let Some(list) = ListRef::from_value(value) {
-        let arr: Result<Vec<serde_json::Value>, _> =
-            list.iter().map(starlark_to_json_value).collect();
-        Ok(serde_json::Value::Array(arr?))
-    } else if let Some(dict) = DictRef::from_value(value) {
-        let mut obj = serde_json::Map::new();
-        for (k, v) in dict.iter() {
-            let key = k.to_string();
-            obj.insert(key, starlark_to_json_value(v)?);
-        }
-        Ok(serde_json::Value::Object(obj))
-    } else {
-        Ok(serde_json::Value::String(value.to_string()))
+    #[test]
+    fn test_increment_counter() {
+        let globals = GlobalVariables::new();
+        assert_eq!(globals.increment_counter("n"), 1);
+        assert_eq!(globals.increment_counter("n"), 2);
+        globals.set_value("s".to_string(), GlobalValue::Str("x".into()));
+        assert_eq!(globals.increment_counter("s"), 1);
+    }
+
+    #[test]
+    fn test_increment_deltas() {
+        let globals = GlobalVariables::new();
+        assert_eq!(
+            globals.increment("n", &GlobalValue::Int(1 << 40)),
+            Ok(GlobalValue::Int(1 << 40))
+        );
+        assert_eq!(
+            globals.increment("n", &GlobalValue::Float(0.5)),
+            Ok(GlobalValue::Float((1_i64 << 40) as f64 + 0.5))
+        );
+        globals.set_value("max".to_string(), GlobalValue::Int(i64::MAX));
+        assert!(globals
+            .increment("max", &GlobalValue::Int(1))
+            .unwrap_err()
+            .contains("overflowed"));
+        assert!(globals
+            .increment("n", &GlobalValue::Str("1".into()))
+            .is_err());
+    }
+
+    #[test]
+    fn test_summaries_round_trip_through_json() {
+        let mut hist = Histogram::new();
+        hist.add(3.0);
+        let mut top = TopK::new(2);
+        top.add("a");
+        let set = GlobalValue::Set(
+            [GlobalValue::Str("a".into()), GlobalValue::Int(1)]
+                .into_iter()
+                .map(|v| (v.set_key(), v))
+                .collect(),
+        );
+        for value in [
+            set,
+            GlobalValue::Hist(Box::new(hist)),
+            GlobalValue::TopK(Box::new(top)),
+        ] {
+            assert_eq!(GlobalValue::from_json(&value.to_json()), value);
+            assert!(value.is_summarised());
+        }
     }
 }
@@ -0,0 +1,135 @@
+// tests/accumulator_tests.rs
+use assert_cmd::Command;
+use predicates::prelude::*;
+use tempfile::TempDir;
+
+const LATENCIES: &str = "alice 120\nbob 80\nalice 95.5\ncarol 300\nalice 40\n";
+
+fn stelp() -> Command {
+    Command::cargo_bin("stelp").unwrap()
+}
+
+#[test]
+fn test_inc_with_deltas() {
+    stelp()
+        .args([
+            "-e",
+            r#"user, ms = line.split(" "); inc("requests"); inc("ms", float(ms)); None"#,
+        ])
+        .args(["--end", r#"str([glob["requests"], glob["ms"]])"#])
+        .write_stdin(LATENCIES)
+        .assert()
+        .success()
+        .stdout("[5, 635.5]\n");
+
+    // Counters are 64-bit
+    stelp()
+        .args([
+            "-e",
+            r#"str(inc("big", 3000000000) + inc("big", 3000000000))"#,
+        ])
+        .write_stdin("x\n")
+        .assert()
+        .success()
+        .stdout("9000000000\n");
+
+    // `amount` is still accepted from the old prelude inc(key, amount=1)
+    stelp()
+        .args(["-e", r#"str(inc("n", amount=5) + inc("n", delta=2))"#])
+        .write_stdin("x\n")
+        .assert()
+        .success()
+        .stdout("12\n");
+}
+
+#[test]
+fn test_distinct_and_extremes() {
+    stelp()
+        .args(["-e"])
+        .arg(
+            r#"user, ms = line.split(" ")
+new = set_add("users", user)
+max_of("slowest", float(ms))
+min_of("first_user", user)
+"%s %s" % (user, new)"#,
+        )
+        .args(["--end"])
+        .arg(r#"str([set_len("users"), glob["users"], glob["slowest"], glob["first_user"]])"#)
+        .write_stdin(LATENCIES)
+        .assert()
+        .success()
+        .stdout(
+            "alice True\nbob True\nalice False\ncarol True\nalice False\n\
+             [3, [\"alice\", \"bob\", \"carol\"], 300.0, \"alice\"]\n",
+        );
+}
+
+#[test]
+fn test_histogram_and_topk_in_end() {
+    stelp()
+        .args(["-e"])
+        .arg(
+            r#"user, ms = line.split(" ")
+hist_add("latency", float(ms))
+topk_add("users", user, 2)
+# Reading the summary does not replace the histogram
+summary = glob["latency"]
+None"#,
+        )
+        .args(["--end"])
+        .arg(
+            r#"h = glob["latency"]
+str([h["count"], h["min"], h["max"], hist_quantile("latency", 1.0), glob["users"]])"#,
+        )
+        .write_stdin(LATENCIES)
+        .assert()
+        .success()
+        .stdout("[5, 40.0, 300.0, 300.0, {\"alice\": 3, \"bob\": 1}]\n");
+}
+
+#[test]
+fn test_topk_counts_only_what_it_saw() {
+    // More distinct keys than the counter tracks, then one repeated key
+    let mut input: String = (0..30).map(|i| format!("u{}\n", i)).collect();
+    input.push_str(&"hot\n".repeat(5));
+
+    stelp()
+        .args(["-e", r#"topk_add("users", line, 2)"#])
+        .args(["--end", r#"str(glob["users"])"#])
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout("{\"hot\": 5}\n");
+}
+
+#[test]
+fn test_type_mismatch_is_an_error() {
+    stelp()
+        .args(["--fail-fast", "-e", r#"glob["x"] = 1; set_add("x", line)"#])
+        .write_stdin("a\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            r#"set_add(): glob["x"] is a int, not a set"#,
+        ));
+}
+
+#[test]
+fn test_summaries_persist_in_state_file() {
+    let dir = TempDir::new().unwrap();
+    let state = dir.path().join("state.json");
+    let script =
+        r#"user, ms = line.split(" "); set_add("users", user); hist_add("ms", float(ms)); None"#;
+    let report = r#"str([set_len("users"), glob["ms"]["count"]])"#;
+
+    for expected in ["[3, 5]\n", "[3, 10]\n"] {
+        stelp()
+            .arg("--state-file")
+            .arg(&state)
+            .args(["-e", script, "--end", report])
+            .write_stdin(LATENCIES)
+            .assert()
+            .success()
+            .stdout(expected);
+    }
+}
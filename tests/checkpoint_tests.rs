@@ -0,0 +1,122 @@
+// tests/checkpoint_tests.rs
+use assert_cmd::Command;
+use predicates::prelude::*;
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+const NUMBERED: &str = r#"n = inc("n")
+"%d %s %d" % (LINENUM, line, n)"#;
+
+fn stelp_with_checkpoint(checkpoint: &Path) -> Command {
+    let mut cmd = Command::cargo_bin("stelp").unwrap();
+    cmd.arg("--checkpoint").arg(checkpoint);
+    cmd
+}
+
+#[test]
+fn test_resume_continues_line_numbers_and_glob() {
+    let dir = TempDir::new().unwrap();
+    let input = dir.path().join("app.log");
+    let checkpoint = dir.path().join("app.ckpt");
+    fs::write(&input, "a\nb\nc\nd\ne\n").unwrap();
+
+    // Stop after the third line
+    stelp_with_checkpoint(&checkpoint)
+        .args(["--begin", r#""BEGIN""#, "-e"])
+        .arg(format!("exit(0) if LINENUM == 3 else None\n{}", NUMBERED))
+        .arg(&input)
+        .assert()
+        .success()
+        .stdout("BEGIN\n1 a 1\n2 b 2\n");
+
+    // BEGIN already ran for this file, so it is not repeated
+    stelp_with_checkpoint(&checkpoint)
+        .args(["--resume", "--begin", r#""BEGIN""#, "-e", NUMBERED])
+        .arg(&input)
+        .assert()
+        .success()
+        .stdout("4 d 4\n5 e 5\n");
+
+    // A grown file only yields the new lines
+    fs::write(&input, "a\nb\nc\nd\ne\nf\n").unwrap();
+    stelp_with_checkpoint(&checkpoint)
+        .args(["--resume", "-e", NUMBERED])
+        .arg(&input)
+        .assert()
+        .success()
+        .stdout("6 f 6\n");
+}
+
+#[test]
+fn test_resume_after_failure_reprocesses_unfinished_record() {
+    let dir = TempDir::new().unwrap();
+    let input = dir.path().join("users.csv");
+    let checkpoint = dir.path().join("users.ckpt");
+    fs::write(&input, "id,name\n1,a\n2,b\n3,c\n4,d\n").unwrap();
+
+    stelp_with_checkpoint(&checkpoint)
+        .args([
+            "--checkpoint-interval",
+            "0",
+            "--fail-fast",
+            "-F",
+            "jsonl",
+            "-e",
+        ])
+        .arg(r#"fail("boom") if data["id"] == "3" else data"#)
+        .arg(&input)
+        .assert()
+        .failure()
+        .stdout("{\"id\":\"1\",\"name\":\"a\"}\n{\"id\":\"2\",\"name\":\"b\"}\n");
+
+    // The failing row had not completed, so it is read again
+    stelp_with_checkpoint(&checkpoint)
+        .args(["--resume", "-F", "jsonl", "-e"])
+        .arg(r#"data["line"] = LINENUM; data"#)
+        .arg(&input)
+        .assert()
+        .success()
+        .stdout(
+            "{\"id\":\"3\",\"name\":\"c\",\"line\":3}\n{\"id\":\"4\",\"name\":\"d\",\"line\":4}\n",
+        );
+}
+
+#[test]
+fn test_resume_refuses_other_files() {
+    let dir = TempDir::new().unwrap();
+    let input = dir.path().join("app.log");
+    let checkpoint = dir.path().join("app.ckpt");
+    fs::write(&input, "first\nsecond\n").unwrap();
+
+    stelp_with_checkpoint(&checkpoint)
+        .args(["-e", "line"])
+        .arg(&input)
+        .assert()
+        .success();
+
+    // Same size, different content: not the checkpointed file any more
+    fs::write(&input, "FIRST\nSECOND\n").unwrap();
+    stelp_with_checkpoint(&checkpoint)
+        .args(["--resume", "-e", "line"])
+        .arg(&input)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "remove the checkpoint to start over",
+        ));
+
+    stelp_with_checkpoint(&checkpoint)
+        .args(["--resume", "-e", "line"])
+        .arg(dir.path().join("missing.log"))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("which is not an input file"));
+
+    stelp_with_checkpoint(&checkpoint)
+        .args(["-e", "line"])
+        .write_stdin("a\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("stdin cannot be resumed"));
+}
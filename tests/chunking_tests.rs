@@ -1,3 +1,5 @@
+#![allow(clippy::needless_update)]
+
 use regex::Regex;
 use std::io::Cursor;
 use stelp::chunking::{chunk_lines, parse_chunk_strategy, ChunkConfig, ChunkStrategy};
@@ -0,0 +1,14 @@
+// tests/common/mod.rs
+use assert_cmd::Command;
+
+/// Run stelp with `args` on `input`, assert that it succeeds and return stdout
+pub fn stelp(args: &[&str], input: &str) -> String {
+    let output = Command::cargo_bin("stelp")
+        .unwrap()
+        .args(args)
+        .write_stdin(input)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8(output.stdout).unwrap()
+}
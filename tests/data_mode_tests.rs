@@ -185,6 +185,7 @@ fn test_filter_still_works_in_data_mode() {
 }
 
 #[test]
+#[allow(clippy::field_reassign_with_default)]
 fn test_data_mode_pipeline() {
     println!("=== Testing data mode in full pipeline ===");
 
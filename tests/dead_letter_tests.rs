@@ -0,0 +1,83 @@
+// tests/dead_letter_tests.rs
+use assert_cmd::Command;
+use serde_json::Value;
+use std::fs;
+use tempfile::TempDir;
+
+fn read_entries(path: &std::path::Path) -> Vec<Value> {
+    fs::read_to_string(path)
+        .unwrap()
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_dead_letter_collects_parse_and_script_errors() {
+    let dir = TempDir::new().unwrap();
+    let input = dir.path().join("in.jsonl");
+    let dead_letter = dir.path().join("rejected.jsonl");
+    fs::write(&input, "{\"count\":2}\nnot json\n{\"count\":0}\n").unwrap();
+
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-f", "jsonl", "-F", "jsonl"])
+        .args(["-e", "data[\"n\"] = 100 // data[\"count\"]"])
+        .arg("--dead-letter")
+        .arg(&dead_letter)
+        .arg(&input)
+        .assert()
+        .code(1)
+        .stdout("{\"count\":2,\"n\":50}\n");
+
+    let entries = read_entries(&dead_letter);
+    assert_eq!(entries.len(), 2);
+
+    assert_eq!(entries[0]["line_number"], 2);
+    assert_eq!(entries[0]["step"], "parse:jsonl");
+    assert_eq!(entries[0]["line"], "not json");
+    assert_eq!(entries[0]["file"], input.to_string_lossy().as_ref());
+
+    assert_eq!(entries[1]["line_number"], 3);
+    assert_eq!(entries[1]["step"], "eval_1");
+    assert_eq!(entries[1]["line"], "{\"count\":0}");
+    assert!(entries[1]["error"]
+        .as_str()
+        .unwrap()
+        .contains("division by zero"));
+}
+
+#[test]
+fn test_dead_letter_text_mode_and_csv_rows() {
+    let dir = TempDir::new().unwrap();
+    let dead_letter = dir.path().join("rejected.jsonl");
+
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-e", "fail(\"bad\") if line == \"b\" else line"])
+        .arg("--dead-letter")
+        .arg(&dead_letter)
+        .write_stdin("a\nb\nc\n")
+        .assert()
+        .stdout("a\nc\n");
+
+    let entries = read_entries(&dead_letter);
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["file"], "<stdin>");
+    assert_eq!(entries[0]["line_number"], 2);
+    assert_eq!(entries[0]["line"], "b");
+
+    let csv_dead_letter = dir.path().join("rejected_csv.jsonl");
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-f", "csv", "--filter", "int(data[\"n\"]) > 0"])
+        .arg("--dead-letter")
+        .arg(&csv_dead_letter)
+        .write_stdin("name,n\nx,1\n\"y, z\",oops\n")
+        .assert();
+
+    let entries = read_entries(&csv_dead_letter);
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["line_number"], 3);
+    assert_eq!(entries[0]["line"], "\"y, z\",oops");
+}
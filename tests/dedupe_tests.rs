@@ -0,0 +1,129 @@
+// tests/dedupe_tests.rs
+mod common;
+
+use common::stelp;
+use std::fs;
+use tempfile::TempDir;
+
+const EVENTS: &str = r#"{"ts":"2024-01-01T00:00:00Z","request_id":"r1","msg":"a"}
+{"ts":"2024-01-01T00:00:05Z","request_id":"r1","msg":"a retry"}
+{"ts":"2024-01-01T00:01:00Z","request_id":"r2","msg":"b"}
+{"ts":"2024-01-01T00:12:00Z","request_id":"r1","msg":"a again"}
+{"ts":"2024-01-01T00:12:01Z","request_id":"r2","msg":"b again"}
+"#;
+
+#[test]
+fn test_dedupe_by_field() {
+    assert_eq!(
+        stelp(
+            &["-f", "jsonl", "--dedupe-by", "request_id", "-k", "msg"],
+            EVENTS
+        ),
+        "msg=a\nmsg=b\n"
+    );
+    // Keys are forgotten ten minutes after their first record
+    assert_eq!(
+        stelp(
+            &[
+                "-f",
+                "jsonl",
+                "--dedupe-by",
+                "request_id",
+                "--dedupe-window",
+                "10m",
+                "-k",
+                "msg"
+            ],
+            EVENTS
+        ),
+        "msg=a\nmsg=b\nmsg=\"a again\"\nmsg=\"b again\"\n"
+    );
+    // Records are duplicates only if all fields match
+    assert_eq!(
+        stelp(
+            &["-f", "jsonl", "--dedupe-by", "request_id,msg", "-k", "msg"],
+            "{\"request_id\":1,\"msg\":\"x\"}\n{\"request_id\":1,\"msg\":\"y\"}\n{\"request_id\":1,\"msg\":\"x\"}\n"
+        ),
+        "msg=x\nmsg=y\n"
+    );
+}
+
+#[test]
+fn test_dedupe_line_and_key_limit() {
+    assert_eq!(stelp(&["--dedupe-line"], "a\nb\na\nc\nb\n"), "a\nb\nc\n");
+    assert_eq!(
+        stelp(&["--dedupe-line", "--dedupe-keys", "1"], "a\na\nb\na\n"),
+        "a\nb\na\n"
+    );
+}
+
+#[test]
+fn test_dedupe_count() {
+    assert_eq!(
+        stelp(
+            &[
+                "-f",
+                "jsonl",
+                "--dedupe-by",
+                "request_id",
+                "--dedupe-window",
+                "10m",
+                "--dedupe-count",
+                "-k",
+                "msg,repeat_count",
+                "--end",
+                r#""done""#,
+            ],
+            EVENTS
+        ),
+        "msg=a repeat_count=1\nmsg=b repeat_count=0\nmsg=\"a again\" repeat_count=0\n\
+         msg=\"b again\" repeat_count=0\ndone\n"
+    );
+    assert_eq!(
+        stelp(
+            &["--dedupe-line", "--dedupe-count"],
+            "disk full\nok\ndisk full\ndisk full\n"
+        ),
+        "disk full (repeated 2 times)\nok\n"
+    );
+}
+
+#[test]
+fn test_dedupe_runs_at_its_position() {
+    // The filter sees every record; the eval only the first of each id
+    assert_eq!(
+        stelp(
+            &[
+                "-f",
+                "jsonl",
+                "--filter",
+                r#"inc("seen") > 0"#,
+                "--dedupe-by",
+                "request_id",
+                "-e",
+                r#"inc("kept"); data"#,
+                "-k",
+                "msg",
+                "--end",
+                r#""%d/%d" % (glob["kept"], glob["seen"])"#,
+            ],
+            EVENTS
+        ),
+        "msg=a\nmsg=b\n2/5\n"
+    );
+}
+
+#[test]
+fn test_dedupe_in_pipeline_file() {
+    let dir = TempDir::new().unwrap();
+    let pipeline = dir.path().join("dedupe.yaml");
+    fs::write(
+        &pipeline,
+        "input-format: jsonl\ndedupe-window: 10m\nkeys: [msg]\nsteps:\n  - dedupe-by: [request_id]\n",
+    )
+    .unwrap();
+    assert_eq!(
+        stelp(&["-P", pipeline.to_str().unwrap()], EVENTS),
+        "msg=a\nmsg=b\nmsg=\"a again\"\nmsg=\"b again\"\n"
+    );
+}
@@ -1,3 +1,5 @@
+#![allow(clippy::field_reassign_with_default)]
+
 use std::io::Cursor;
 use stelp::config::PipelineConfig;
 use stelp::input_format::{InputFormat, InputFormatWrapper};
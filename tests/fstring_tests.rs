@@ -0,0 +1,69 @@
+// tests/fstring_tests.rs
+use assert_cmd::Command;
+use predicates::prelude::*;
+
+#[test]
+fn test_fstring_expressions_and_format_specs() {
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-f", "jsonl", "-F", "logfmt", "-k", "msg"])
+        .args([
+            "-e",
+            r#"data["msg"] = f"{data['user'].upper()} took {data['ms'] / 1000:.2f}s [{data['code']:>5}] {', '.join(data['tags'])}""#,
+        ])
+        .write_stdin(r#"{"user":"bob","ms":1534,"code":404,"tags":["a","b"]}"#)
+        .assert()
+        .success()
+        .stdout("msg=\"BOB took 1.53s [  404] a, b\"\n");
+}
+
+#[test]
+fn test_fstring_float_specs_keep_full_precision() {
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-e", "x = 1234567.891\nf\"{x:.3f} {x * 10:,.1f}\""])
+        .write_stdin("a\n")
+        .assert()
+        .success()
+        .stdout("1234567.891 12,345,678.9\n");
+}
+
+#[test]
+fn test_fstring_in_filter_and_derive() {
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-f", "jsonl", "-F", "jsonl"])
+        .args(["--filter", r#"f"{data['level'][:3]}" == "err""#])
+        .args(["--derive", r#"label = f"{level!r}:{count:03d}""#])
+        .write_stdin("{\"level\":\"error\",\"count\":7}\n{\"level\":\"info\",\"count\":1}\n")
+        .assert()
+        .success()
+        .stdout("{\"level\":\"error\",\"count\":7,\"label\":\"\\\"error\\\":007\"}\n");
+}
+
+#[test]
+fn test_fstring_triple_quoted_and_nested_spec() {
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args([
+            "-e",
+            "width = 6\nf\"\"\"[{line:>{width}}]\n{{{len(line)}}}\"\"\"",
+        ])
+        .write_stdin("abc\n")
+        .assert()
+        .success()
+        .stdout("[   abc]\n{3}\n");
+}
+
+#[test]
+fn test_fstring_errors_point_at_original_source() {
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-e", "x = [1]\ny = f\"{x[0]:.1f}\" + 1\ny"])
+        .write_stdin("a\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--> script:2:5"))
+        .stderr(predicate::str::contains("y = f\"{x[0]:.1f}\" + 1"))
+        .stderr(predicate::str::contains("format_value").not());
+}
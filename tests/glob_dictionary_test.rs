@@ -1,7 +1,7 @@
 use std::io::Cursor;
 use stelp::config::PipelineConfig;
 use stelp::context::{RecordContext, RecordData};
-use stelp::variables::GlobalVariables;
+use stelp::variables::{GlobalValue, GlobalVariables};
 use stelp::StarlarkProcessor;
 use stelp::StreamPipeline;
 
@@ -236,3 +236,135 @@ str(glob)
         }
     }
 }
+
+#[test]
+fn test_glob_keeps_native_types_across_records() {
+    let config = PipelineConfig::default();
+    let mut pipeline = StreamPipeline::new(config);
+    let processor = StarlarkProcessor::from_script(
+        "test",
+        r#"
+glob.setdefault("seen", []).append(line)
+by_len = glob.get("by_len", {})
+by_len[len(line)] = by_len.get(len(line), 0) + 1
+glob["by_len"] = by_len
+glob["total"] = glob.get("total", 0.0) + 0.5
+f"{glob['seen']} {glob['by_len']} {glob['total']} {type(glob['total'])}"
+        "#,
+    )
+    .unwrap();
+    pipeline.add_processor(Box::new(processor));
+
+    let mut output = Vec::new();
+    pipeline
+        .process_stream(Cursor::new("ab\ncd\nefg\n"), &mut output, None)
+        .unwrap();
+
+    let last = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .last()
+        .unwrap()
+        .to_string();
+    assert_eq!(last, r#"["ab", "cd", "efg"] {2: 2, 3: 1} 1.5 float"#);
+}
+
+#[test]
+fn test_glob_limit_evicts_least_recently_used() {
+    let config = PipelineConfig {
+        glob_limit: Some(2),
+        ..Default::default()
+    };
+    let mut pipeline = StreamPipeline::new(config);
+    let processor = StarlarkProcessor::from_script(
+        "test",
+        r#"
+glob[line] = glob.get(line, 0) + 1
+        "#,
+    )
+    .unwrap();
+    pipeline.add_processor(Box::new(processor));
+
+    let mut output = Vec::new();
+    pipeline
+        .process_stream(Cursor::new("a\nb\na\nc\n"), &mut output, None)
+        .unwrap();
+
+    // "b" was the least recently used key when "c" arrived
+    let globals = pipeline.get_global_vars();
+    assert_eq!(globals.keys(), vec!["a", "c"]);
+    assert_eq!(globals.evicted(), 1);
+}
+
+/// Run `script` on one record with `n` = 1 already stored in glob
+fn run_with_stored_glob(script: &str, record: RecordData) -> RecordData {
+    let globals = GlobalVariables::new();
+    globals.set_value("n".to_string(), GlobalValue::Int(1));
+    let ctx = RecordContext {
+        line_number: 1,
+        record_count: 1,
+        file_name: None,
+        global_vars: &globals,
+        debug: false,
+    };
+    let processor = StarlarkProcessor::from_script("test", script).unwrap();
+    match processor.process_standalone(&record, &ctx) {
+        stelp::context::ProcessResult::Transform(output) => output,
+        other => panic!("Expected Transform result, got: {:?}", other),
+    }
+}
+
+fn glob_text(script: &str) -> String {
+    let output = run_with_stored_glob(script, RecordData::text("test".to_string()));
+    output.as_text().unwrap().to_string()
+}
+
+#[test]
+fn test_glob_len_and_type_like_a_dict() {
+    assert_eq!(glob_text(r#"str(len(glob)) + " " + type(glob)"#), "1 dict");
+}
+
+#[test]
+fn test_glob_update() {
+    assert_eq!(
+        glob_text("glob.update({\"m\": 2}, k=3)\nstr(sorted(glob.items()))"),
+        r#"[("k", 3), ("m", 2), ("n", 1)]"#
+    );
+    assert_eq!(
+        glob_text("glob.update([(\"m\", 2)])\nstr(glob[\"m\"])"),
+        "2"
+    );
+}
+
+#[test]
+fn test_dict_copies_glob() {
+    assert_eq!(
+        glob_text(
+            "d = dict(glob)\nd[\"m\"] = 2\nstr(d) + \" \" + type(d) + \" \" + str(len(glob))"
+        ),
+        r#"{"n": 1, "m": 2} dict 1"#
+    );
+}
+
+#[test]
+fn test_glob_equality_and_iteration() {
+    assert_eq!(
+        glob_text(r#"str([glob == {"n": 1}, glob == {"n": 2}, glob == {}])"#),
+        "[True, False, False]"
+    );
+    assert_eq!(glob_text("str([key for key in glob])"), r#"["n"]"#);
+}
+
+#[test]
+fn test_glob_converts_to_a_json_object() {
+    assert_eq!(glob_text("dump_json(glob)"), r#"{"n":1}"#);
+
+    let output = run_with_stored_glob(
+        "glob[\"m\"] = 2\ndata = glob",
+        RecordData::structured(serde_json::json!({"a": 1})),
+    );
+    assert_eq!(
+        output.as_structured(),
+        Some(&serde_json::json!({"n": 1, "m": 2}))
+    );
+}
@@ -0,0 +1,80 @@
+// tests/http_parse_tests.rs
+use assert_cmd::Command;
+use serde_json::Value;
+
+const ACCESS_LOG: &str = concat!(
+    r#"203.0.113.9 - - [10/Oct/2024:13:55:36 +0000] "GET /search?q=rust+logs&page=2 HTTP/1.1" 200 512 "-" "Mozilla/5.0 (Linux; Android 14; Pixel 8) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Mobile Safari/537.36""#,
+    "\n",
+    r#"66.249.66.1 - - [10/Oct/2024:13:55:37 +0000] "GET /robots.txt HTTP/1.1" 200 64 "-" "Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)""#,
+    "\n",
+);
+
+#[test]
+fn test_parse_url_and_qs_builtins() {
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args([
+            "-e",
+            r#"u = parse_url(line); f"{u['host']} {u['port']} {u['path']} {u['query']['tag']} {parse_qs('a=1&b=x%20y')['b']}""#,
+        ])
+        .write_stdin("https://Example.org:8080/items?tag=a&tag=b#frag\n")
+        .assert()
+        .success()
+        .stdout("example.org 8080 /items [\"a\", \"b\"] x y\n");
+}
+
+#[test]
+fn test_parse_user_agent_builtin() {
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args([
+            "-e",
+            r#"ua = parse_user_agent(line); f"{ua['browser']}/{ua['version']} {ua['os']} {ua['device']} {ua['is_bot']}""#,
+        ])
+        .write_stdin(
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:121.0) Gecko/20100101 Firefox/121.0\n",
+        )
+        .assert()
+        .success()
+        .stdout("Firefox/121.0 Windows desktop False\n");
+}
+
+#[test]
+fn test_combined_expand() {
+    let output = Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-f", "combined", "-F", "jsonl", "--combined-expand"])
+        .write_stdin(ACCESS_LOG)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let records: Vec<Value> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(records[0]["request"]["path"], "/search");
+    assert_eq!(records[0]["request"]["query"]["q"], "rust logs");
+    assert_eq!(records[0]["user_agent"]["os"], "Android");
+    assert_eq!(records[0]["user_agent"]["device"], "mobile");
+    assert_eq!(records[1]["user_agent"]["browser"], "Googlebot");
+    assert_eq!(records[1]["user_agent"]["is_bot"], true);
+
+    // Without the flag the records keep their original shape
+    let output = Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-f", "combined", "-F", "jsonl"])
+        .write_stdin(ACCESS_LOG)
+        .output()
+        .unwrap();
+    let first: Value = serde_json::from_str(
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .next()
+            .unwrap(),
+    )
+    .unwrap();
+    assert!(first.get("request").is_none() && first.get("user_agent").is_none());
+}
@@ -1,4 +1,6 @@
 // tests/integration_tests.rs
+#![allow(clippy::field_reassign_with_default)]
+
 use std::io::Cursor;
 use stelp::config::{ErrorStrategy, PipelineConfig};
 use stelp::context::{RecordContext, RecordData};
@@ -0,0 +1,285 @@
+// tests/ip_tests.rs
+use assert_cmd::Command;
+use predicates::prelude::*;
+use serde_json::Value;
+use std::fs;
+use tempfile::TempDir;
+
+/// Just enough of the MaxMind DB data format to build test databases
+enum Mm {
+    Str(&'static str),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    F64(f64),
+    Map(Vec<(&'static str, Mm)>),
+    Arr(Vec<Mm>),
+}
+
+fn control(kind: u8, size: usize, out: &mut Vec<u8>) {
+    assert!(size < 285);
+    let size_bits = size.min(29) as u8;
+    if kind <= 7 {
+        out.push(kind << 5 | size_bits);
+    } else {
+        out.extend([size_bits, kind - 7]);
+    }
+    if size >= 29 {
+        out.push((size - 29) as u8);
+    }
+}
+
+impl Mm {
+    fn encode(&self, out: &mut Vec<u8>) {
+        let uint = |kind: u8, value: u64, out: &mut Vec<u8>| {
+            let bytes = value.to_be_bytes();
+            let skip = bytes.iter().take_while(|&&b| b == 0).count();
+            control(kind, 8 - skip, out);
+            out.extend(&bytes[skip..]);
+        };
+        match self {
+            Mm::Str(s) => {
+                control(2, s.len(), out);
+                out.extend(s.as_bytes());
+            }
+            Mm::F64(f) => {
+                control(3, 8, out);
+                out.extend(f.to_be_bytes());
+            }
+            Mm::U16(v) => uint(5, *v as u64, out),
+            Mm::U32(v) => uint(6, *v as u64, out),
+            Mm::U64(v) => uint(9, *v, out),
+            Mm::Map(entries) => {
+                control(7, entries.len(), out);
+                for (key, value) in entries {
+                    Mm::Str(key).encode(out);
+                    value.encode(out);
+                }
+            }
+            Mm::Arr(items) => {
+                control(11, items.len(), out);
+                for item in items {
+                    item.encode(out);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Record {
+    Empty,
+    Node(usize),
+    Data(usize),
+}
+
+/// IPv4 database (24-bit records) mapping each network to a record
+fn write_mmdb(path: &std::path::Path, database_type: &'static str, networks: &[(&str, u8, Mm)]) {
+    let mut nodes = vec![[Record::Empty; 2]];
+    let mut data = Vec::new();
+    for (addr, prefix, record) in networks {
+        let addr = u32::from(addr.parse::<std::net::Ipv4Addr>().unwrap());
+        let offset = data.len();
+        record.encode(&mut data);
+        let mut node = 0;
+        for i in 0..*prefix {
+            let bit = ((addr >> (31 - i)) & 1) as usize;
+            if i + 1 == *prefix {
+                nodes[node][bit] = Record::Data(offset);
+            } else {
+                node = match nodes[node][bit] {
+                    Record::Node(next) => next,
+                    _ => {
+                        nodes.push([Record::Empty; 2]);
+                        nodes[node][bit] = Record::Node(nodes.len() - 1);
+                        nodes.len() - 1
+                    }
+                };
+            }
+        }
+    }
+
+    let node_count = nodes.len();
+    let mut db = Vec::new();
+    for node in &nodes {
+        for record in node {
+            let value = match *record {
+                Record::Empty => node_count,
+                Record::Node(next) => next,
+                Record::Data(offset) => node_count + 16 + offset,
+            } as u32;
+            db.extend(&value.to_be_bytes()[1..]);
+        }
+    }
+    db.extend([0u8; 16]);
+    db.extend(data);
+    db.extend(b"\xAB\xCD\xEFMaxMind.com");
+    Mm::Map(vec![
+        ("binary_format_major_version", Mm::U16(2)),
+        ("binary_format_minor_version", Mm::U16(0)),
+        ("build_epoch", Mm::U64(1_700_000_000)),
+        ("database_type", Mm::Str(database_type)),
+        ("description", Mm::Map(vec![("en", Mm::Str("test"))])),
+        ("ip_version", Mm::U16(4)),
+        ("languages", Mm::Arr(vec![Mm::Str("en")])),
+        ("node_count", Mm::U32(node_count as u32)),
+        ("record_size", Mm::U16(24)),
+    ])
+    .encode(&mut db);
+    fs::write(path, db).unwrap();
+}
+
+fn write_databases(dir: &TempDir) -> (std::path::PathBuf, std::path::PathBuf) {
+    let city = dir.path().join("city.mmdb");
+    let asn = dir.path().join("asn.mmdb");
+    write_mmdb(
+        &city,
+        "GeoLite2-City",
+        &[(
+            "203.0.113.0",
+            24,
+            Mm::Map(vec![
+                (
+                    "country",
+                    Mm::Map(vec![
+                        ("iso_code", Mm::Str("AU")),
+                        ("names", Mm::Map(vec![("en", Mm::Str("Australia"))])),
+                    ]),
+                ),
+                (
+                    "city",
+                    Mm::Map(vec![("names", Mm::Map(vec![("en", Mm::Str("Sydney"))]))]),
+                ),
+                (
+                    "location",
+                    Mm::Map(vec![
+                        ("latitude", Mm::F64(-33.86)),
+                        ("longitude", Mm::F64(151.2)),
+                    ]),
+                ),
+            ]),
+        )],
+    );
+    write_mmdb(
+        &asn,
+        "GeoLite2-ASN",
+        &[(
+            "203.0.0.0",
+            16,
+            Mm::Map(vec![
+                ("autonomous_system_number", Mm::U32(64500)),
+                ("autonomous_system_organization", Mm::Str("Example Net")),
+            ]),
+        )],
+    );
+    (city, asn)
+}
+
+#[test]
+fn test_ip_builtins() {
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args([
+            "-e",
+            r#"f"{ip_in(line, '10.0.0.0/8')} {ip_in(line, ['192.168.0.0/16', '10.1.0.0/16'])} {ip_version(line)} {is_private(line)} {ip_to_int(line)}""#,
+        ])
+        .write_stdin("10.1.2.3\n2001:db8::1\n")
+        .assert()
+        .success()
+        .stdout(
+            "True True 4 True 167838211\nFalse False 6 False 42540766411282592856903984951653826561\n",
+        );
+
+    // Non-addresses are simply not in any network
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args([
+            "-e",
+            r#"f"{ip_in(line, '0.0.0.0/0')} {ip_version(line)} {is_private(line)}""#,
+        ])
+        .write_stdin("-\n")
+        .assert()
+        .success()
+        .stdout("False None False\n");
+
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-e", "ip_to_int(line)"])
+        .write_stdin("-\n")
+        .assert()
+        .stderr(predicate::str::contains(
+            "ip_to_int(): invalid IP address '-'",
+        ));
+}
+
+#[test]
+fn test_cidr_set() {
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args([
+            "--filter",
+            r#"internal = cidr_set(["10.0.0.0/8", "172.16.0.0/12", "fd00::/8"]); not internal.contains(line) and line not in internal"#,
+        ])
+        .write_stdin("10.9.9.9\n8.8.8.8\nfd00::5\n172.31.0.1\n1.1.1.1\n")
+        .assert()
+        .success()
+        .stdout("8.8.8.8\n1.1.1.1\n");
+}
+
+#[test]
+fn test_geoip_lookup_merges_databases() {
+    let dir = TempDir::new().unwrap();
+    let (city, asn) = write_databases(&dir);
+
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .arg("--geoip")
+        .arg(&city)
+        .arg("--geoip")
+        .arg(&asn)
+        .args([
+            "-e",
+            r#"g = geoip(line); f"{g.get('country')} {g.get('city')} {g['asn']} {g['as_org']}" if g else "unknown""#,
+        ])
+        .write_stdin("203.0.113.7\n203.0.200.1\n198.51.100.1\n")
+        .assert()
+        .success()
+        // Only the ASN database covers 203.0.200.1
+        .stdout("AU Sydney 64500 Example Net\nNone None 64500 Example Net\nunknown\n");
+}
+
+#[test]
+fn test_combined_geoip_enrichment() {
+    let dir = TempDir::new().unwrap();
+    let (city, _) = write_databases(&dir);
+
+    let output = Command::cargo_bin("stelp")
+        .unwrap()
+        .args([
+            "-f",
+            "combined",
+            "-F",
+            "jsonl",
+            "--combined-geoip",
+            "--geoip",
+        ])
+        .arg(&city)
+        .write_stdin(concat!(
+            r#"203.0.113.9 - - [10/Oct/2024:13:55:36 +0000] "GET / HTTP/1.1" 200 5 "-" "curl/8.0""#,
+            "\n",
+        ))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let record: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(record["geo"]["country"], "AU");
+    assert_eq!(record["geo"]["country_name"], "Australia");
+    assert_eq!(record["geo"]["latitude"], -33.86);
+
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-e", "geoip(line)"])
+        .write_stdin("1.2.3.4\n")
+        .assert()
+        .stderr(predicate::str::contains("no GeoIP database loaded"));
+}
@@ -0,0 +1,119 @@
+// tests/load_tests.rs
+use assert_cmd::Command;
+use predicates::prelude::*;
+use std::fs;
+use tempfile::TempDir;
+
+fn write_lib(dir: &TempDir) {
+    let lib = dir.path().join("lib");
+    fs::create_dir_all(&lib).unwrap();
+    fs::write(
+        lib.join("http.star"),
+        r#"load("text.star", "first_word")
+
+print("loading http.star")
+
+def classify(status):
+    if status >= 500:
+        return "server_error"
+    return "ok"
+
+def parse_ua(ua):
+    return first_word(ua).lower()
+"#,
+    )
+    .unwrap();
+    fs::write(
+        lib.join("text.star"),
+        "def first_word(text):\n    return text.split(\"/\")[0]\n",
+    )
+    .unwrap();
+}
+
+const INPUT: &str = "{\"status\":503,\"ua\":\"Mozilla/5.0\"}\n{\"status\":200,\"ua\":\"curl/8.0\"}\n{\"status\":200,\"ua\":\"Go/1.2\"}\n";
+
+#[test]
+fn test_load_from_lib_path_is_cached() {
+    let dir = TempDir::new().unwrap();
+    write_lib(&dir);
+
+    let output = Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-f", "jsonl", "-F", "logfmt", "-k", "class,agent"])
+        .arg("--lib-path")
+        .arg(dir.path().join("lib"))
+        .args([
+            "-e",
+            "load(\"//http.star\", \"parse_ua\", \"classify\")\ndata[\"class\"] = classify(data[\"status\"])\ndata[\"agent\"] = parse_ua(data[\"ua\"])",
+        ])
+        .write_stdin(INPUT)
+        .assert()
+        .success()
+        .stdout("class=server_error agent=mozilla\nclass=ok agent=curl\nclass=ok agent=go\n")
+        .get_output()
+        .clone();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr.matches("loading http.star").count(), 1);
+}
+
+#[test]
+fn test_load_from_stelp_path_and_script_dir() {
+    let dir = TempDir::new().unwrap();
+    write_lib(&dir);
+
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .env("STELP_PATH", dir.path().join("lib"))
+        .args(["-f", "jsonl", "-F", "jsonl", "--filter"])
+        .arg("load(\"//http.star\", \"classify\")\nclassify(data[\"status\"]) == \"ok\"")
+        .write_stdin(INPUT)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("curl"))
+        .stdout(predicate::str::contains("Mozilla").not());
+
+    // Relative loads in a script file start at the script's directory
+    let script = dir.path().join("lib").join("main.star");
+    fs::write(
+        &script,
+        "load(\"text.star\", \"first_word\")\nfirst_word(line)\n",
+    )
+    .unwrap();
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .arg("-s")
+        .arg(&script)
+        .write_stdin("Mozilla/5.0\n")
+        .assert()
+        .success()
+        .stdout("Mozilla\n");
+}
+
+#[test]
+fn test_load_errors() {
+    let dir = TempDir::new().unwrap();
+    let lib = dir.path().join("lib");
+    fs::create_dir_all(&lib).unwrap();
+    fs::write(lib.join("a.star"), "load(\"b.star\", \"b\")\na = 1\n").unwrap();
+    fs::write(lib.join("b.star"), "load(\"a.star\", \"a\")\nb = 1\n").unwrap();
+    fs::write(lib.join("broken.star"), "def f(x):\n    return x +\n").unwrap();
+
+    let run = |script: &str| {
+        Command::cargo_bin("stelp")
+            .unwrap()
+            .arg("--lib-path")
+            .arg(&lib)
+            .args(["-e", script])
+            .write_stdin("x\n")
+            .assert()
+            .failure()
+    };
+
+    run("load(\"//a.star\", \"a\")\nline")
+        .stderr(predicate::str::contains("load cycle:"))
+        .stderr(predicate::str::contains("a.star -> "));
+    run("load(\"//broken.star\", \"f\")\nline").stderr(predicate::str::contains("broken.star:2:"));
+    run("load(\"//missing.star\", \"f\")\nline")
+        .stderr(predicate::str::contains("cannot load '//missing.star'"));
+}
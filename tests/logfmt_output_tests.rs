@@ -77,6 +77,7 @@ fn test_field_priority_ordering() {
         .expect("Failed to execute stelp");
 
     let result = String::from_utf8(output.stdout).unwrap();
+    #[allow(clippy::trim_split_whitespace)]
     let parts: Vec<&str> = result.trim().split_whitespace().collect();
 
     // With --common flag: timestamp should come first, then level, then message
@@ -100,6 +101,7 @@ fn test_original_field_order_preservation() {
         .expect("Failed to execute stelp");
 
     let result = String::from_utf8(output.stdout).unwrap();
+    #[allow(clippy::trim_split_whitespace)]
     let parts: Vec<&str> = result.trim().split_whitespace().collect();
 
     // Without any flags, should preserve original JSON field order: zebra, timestamp, level, message, alpha
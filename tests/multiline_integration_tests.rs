@@ -21,6 +21,7 @@ java.lang.RuntimeException: Something went wrong
         ..Default::default()
     };
 
+    #[allow(clippy::needless_update)]
     let chunk_config = ChunkConfig {
         strategy: ChunkStrategy::StartPattern(
             Regex::new(r"^\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2}").unwrap(),
@@ -89,6 +90,7 @@ line7";
         ..Default::default()
     };
 
+    #[allow(clippy::needless_update)]
     let chunk_config = ChunkConfig {
         strategy: ChunkStrategy::FixedLines(3),
         ..Default::default()
@@ -151,6 +153,7 @@ data3";
         ..Default::default()
     };
 
+    #[allow(clippy::needless_update)]
     let chunk_config = ChunkConfig {
         strategy: ChunkStrategy::Delimiter("---".to_string()),
         ..Default::default()
@@ -215,6 +218,7 @@ item5";
         ..Default::default()
     };
 
+    #[allow(clippy::needless_update)]
     let chunk_config = ChunkConfig {
         strategy: ChunkStrategy::Delimiter("---".to_string()),
         ..Default::default()
@@ -0,0 +1,107 @@
+// tests/output_template_tests.rs
+use assert_cmd::Command;
+use std::fs;
+use tempfile::TempDir;
+
+const INPUT: &str = r#"{"service":"api","ts":"2024-01-01T10:00:00Z","msg":"a"}
+{"service":"web","ts":"2024-01-01T11:00:00Z","msg":"b"}
+{"service":"api","ts":"2024-01-02T09:00:00Z","msg":"c"}
+{"service":"api","ts":"2024-01-01T12:00:00Z","msg":"d"}
+"#;
+
+#[test]
+fn test_output_template_partitions_by_field_and_day() {
+    let dir = TempDir::new().unwrap();
+    let template = format!("{}/{{service}}/{{ts:%Y-%m-%d}}.jsonl", dir.path().display());
+
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-f", "jsonl", "--output-template", &template])
+        .write_stdin(INPUT)
+        .assert()
+        .success()
+        .stdout("");
+
+    let api_day1 = fs::read_to_string(dir.path().join("api/2024-01-01.jsonl")).unwrap();
+    assert_eq!(api_day1.lines().count(), 2);
+    assert!(api_day1.contains(r#""msg":"a""#));
+    assert!(api_day1.contains(r#""msg":"d""#));
+
+    let api_day2 = fs::read_to_string(dir.path().join("api/2024-01-02.jsonl")).unwrap();
+    assert!(api_day2.contains(r#""msg":"c""#));
+
+    let web = fs::read_to_string(dir.path().join("web/2024-01-01.jsonl")).unwrap();
+    assert!(web.contains(r#""msg":"b""#));
+}
+
+#[test]
+fn test_output_template_csv_headers_per_file_with_eviction() {
+    let dir = TempDir::new().unwrap();
+    let template = format!("{}/{{service}}.csv", dir.path().display());
+
+    // With only one open file, every service switch evicts and reopens
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-f", "jsonl", "-k", "service,msg"])
+        .args(["--output-template", &template, "--output-max-open", "1"])
+        .write_stdin(INPUT)
+        .assert()
+        .success();
+
+    let api = fs::read_to_string(dir.path().join("api.csv")).unwrap();
+    assert_eq!(api, "service,msg\napi,a\napi,c\napi,d\n");
+
+    let web = fs::read_to_string(dir.path().join("web.csv")).unwrap();
+    assert_eq!(web, "service,msg\nweb,b\n");
+}
+
+#[test]
+fn test_output_template_reopened_csv_warns_once() {
+    let dir = TempDir::new().unwrap();
+    let template = format!("{}/{{service}}.csv", dir.path().display());
+    let input = r#"{"service":"api","msg":"a"}
+{"service":"web","msg":"b"}
+{"msg":"c","service":"api","extra":1}
+{"service":"web","msg":"d"}
+{"service":"api","msg":"e","extra":2}
+"#;
+
+    let output = Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-f", "jsonl"])
+        .args(["--output-template", &template, "--output-max-open", "1"])
+        .write_stdin(input)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    // api.csv is closed and reopened twice but warned about once
+    let api = fs::read_to_string(dir.path().join("api.csv")).unwrap();
+    assert!(api.starts_with("service,msg\n"));
+    assert_eq!(api.matches("service").count(), 1);
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        stderr.matches("keys 'extra' found").count(),
+        1,
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_output_template_conflicts_with_output_file() {
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args([
+            "-f",
+            "jsonl",
+            "--output-template",
+            "{service}.jsonl",
+            "-o",
+            "x.jsonl",
+        ])
+        .write_stdin(INPUT)
+        .assert()
+        .failure();
+}
@@ -0,0 +1,115 @@
+// tests/patterns_tests.rs
+mod common;
+
+use assert_cmd::Command;
+use common::stelp;
+
+const LOG: &str = "Connection to 10.0.0.1 timed out after 30s
+user alice logged in
+Connection to 10.0.0.2 timed out after 5s
+user bob logged in
+Connection to 192.168.1.1 timed out after 12s
+disk full
+";
+
+fn report(args: &[&str], input: &str) -> Vec<serde_json::Value> {
+    stelp(args, input)
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_patterns_report() {
+    let templates = report(&["--patterns", "-F", "jsonl"], LOG);
+    assert_eq!(templates.len(), 3);
+    assert_eq!(
+        templates[0]["template"],
+        "Connection to <IP> timed out after <NUM>s"
+    );
+    assert_eq!(templates[0]["count"], 3);
+    assert_eq!(templates[0]["samples"].as_array().unwrap().len(), 3);
+    assert_eq!(templates[1]["template"], "user <*> logged in");
+    assert_eq!(
+        templates[1]["samples"],
+        serde_json::json!(["user alice logged in", "user bob logged in"])
+    );
+    assert_eq!(templates[2]["template"], "disk full");
+
+    let top = report(&["--patterns", "--patterns-top", "1", "-F", "jsonl"], LOG);
+    assert_eq!(top.len(), 1);
+    assert_eq!(top[0]["template_id"], templates[0]["template_id"]);
+}
+
+#[test]
+fn test_patterns_use_message_field_and_steps() {
+    let input = r#"{"level":"info","msg":"took 5ms"}
+{"level":"warn","msg":"took 7ms"}
+{"level":"info","msg":"cache miss for key 42"}
+{"level":"info","count":1}
+"#;
+    let templates = report(
+        &[
+            "-f",
+            "jsonl",
+            "--filter",
+            "data[\"level\"] == \"info\"",
+            "--patterns",
+            "-F",
+            "jsonl",
+        ],
+        input,
+    );
+    let summary: Vec<(String, u64)> = templates
+        .iter()
+        .map(|t| {
+            (
+                t["template"].as_str().unwrap().to_string(),
+                t["count"].as_u64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            ("took <NUM>ms".to_string(), 1),
+            ("cache miss for key <NUM>".to_string(), 1),
+        ]
+    );
+}
+
+#[test]
+fn test_pattern_field_tags_records() {
+    let templates = report(&["--patterns", "-F", "jsonl"], LOG);
+    let user_id = templates[1]["template_id"].as_str().unwrap();
+
+    // Text lines become records, and later steps can use the id
+    let filter = format!("data[\"tid\"] == \"{}\"", user_id);
+    assert_eq!(
+        stelp(&["--pattern-field", "tid", "--filter", &filter], LOG),
+        format!(
+            "line=\"user alice logged in\" tid={}\nline=\"user bob logged in\" tid={}\n",
+            user_id, user_id
+        )
+    );
+
+    // Records without a message pass through untouched
+    let output = report(
+        &["-f", "jsonl", "--pattern-field", "tid", "-F", "jsonl"],
+        "{\"msg\":\"user carol logged in\"}\n{\"x\":1}\n{\"msg\":\"user dave logged in\"}\n",
+    );
+    assert_eq!(output.len(), 3);
+    assert!(output[0]["tid"].is_string());
+    assert_eq!(output[1], serde_json::json!({"x": 1}));
+    assert_eq!(output[2]["tid"], output[0]["tid"]);
+}
+
+#[test]
+fn test_patterns_conflicts_with_pattern_field() {
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["--patterns", "--pattern-field", "tid"])
+        .write_stdin(LOG)
+        .assert()
+        .failure();
+}
@@ -0,0 +1,114 @@
+// tests/pipeline_file_tests.rs
+use assert_cmd::Command;
+use std::fs;
+use tempfile::TempDir;
+
+const INPUT: &str = "{\"status\":500,\"duration\":0.5,\"path\":\"/a\"}\n{\"status\":200,\"duration\":1,\"path\":\"/b\"}\n{\"status\":503,\"duration\":2,\"path\":\"/c\"}\n";
+
+fn setup() -> TempDir {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("input.jsonl"), INPUT).unwrap();
+    fs::write(
+        dir.path().join("errors.yaml"),
+        r#"input-format: jsonl
+steps:
+  - filter: 'data["status"] >= 500'
+  - derive: 'ms = duration * 1000'
+  - script: tag.star
+keys: [status, ms, tag]
+output-format: logfmt
+"#,
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("tag.star"),
+        "data[\"tag\"] = \"err\" + data[\"path\"]\n",
+    )
+    .unwrap();
+    dir
+}
+
+#[test]
+fn test_yaml_pipeline_file() {
+    let dir = setup();
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .arg("-P")
+        .arg(dir.path().join("errors.yaml"))
+        .arg(dir.path().join("input.jsonl"))
+        .assert()
+        .success()
+        .stdout("status=500 ms=500.0 tag=err/a\nstatus=503 ms=2000 tag=err/c\n");
+}
+
+#[test]
+fn test_cli_flags_override_pipeline_file() {
+    let dir = setup();
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .arg("-P")
+        .arg(dir.path().join("errors.yaml"))
+        .args(["-F", "jsonl", "-k", "path"])
+        .args(["--filter", "data[\"status\"] != 503"])
+        .arg(dir.path().join("input.jsonl"))
+        .assert()
+        .success()
+        .stdout("{\"path\":\"/a\"}\n");
+}
+
+#[test]
+fn test_no_plain_overrides_pipeline_file() {
+    let dir = setup();
+    fs::write(
+        dir.path().join("plain.yaml"),
+        "input-format: jsonl\nkeys: [status]\nplain: true\n",
+    )
+    .unwrap();
+    let run = |extra: &[&str]| {
+        let output = Command::cargo_bin("stelp")
+            .unwrap()
+            .arg("-P")
+            .arg(dir.path().join("plain.yaml"))
+            .args(extra)
+            .arg(dir.path().join("input.jsonl"))
+            .output()
+            .unwrap();
+        String::from_utf8(output.stdout).unwrap()
+    };
+    assert_eq!(run(&[]), "500\n200\n503\n");
+    assert_eq!(run(&["--no-plain"]), "status=500\nstatus=200\nstatus=503\n");
+}
+
+#[test]
+fn test_starlark_pipeline_file() {
+    let dir = setup();
+    fs::write(
+        dir.path().join("ok.star"),
+        r#"settings(input_format = "jsonl", keys = ["path"], plain = True)
+stage("filter", 'data["status"] < 500')
+"#,
+    )
+    .unwrap();
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .arg("-P")
+        .arg(dir.path().join("ok.star"))
+        .arg(dir.path().join("input.jsonl"))
+        .assert()
+        .success()
+        .stdout("/b\n");
+}
+
+#[test]
+fn test_invalid_pipeline_file() {
+    let dir = setup();
+    fs::write(dir.path().join("bad.yaml"), "steps:\n  - transform: line\n").unwrap();
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .arg("-P")
+        .arg(dir.path().join("bad.yaml"))
+        .arg(dir.path().join("input.jsonl"))
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("unknown variant `transform`"));
+}
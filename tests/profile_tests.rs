@@ -0,0 +1,35 @@
+// tests/profile_tests.rs
+use assert_cmd::Command;
+use predicates::prelude::*;
+
+#[test]
+fn test_profile_reports_every_step() {
+    let input: String = (1..=30).map(|n| format!("{{\"n\":{}}}\n", n)).collect();
+
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-f", "jsonl", "-F", "jsonl"])
+        .args(["--filter", "data[\"n\"] % 3 == 0"])
+        .args(["-e", "data[\"sq\"] = data[\"n\"] * data[\"n\"]"])
+        .args(["--profile", "--profile-top", "2"])
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stderr(predicate::str::is_match(r"parse:jsonl\s+30 ").unwrap())
+        .stderr(predicate::str::is_match(r"filter_1\s+30 .*33\.3%").unwrap())
+        .stderr(predicate::str::is_match(r"eval_2\s+10 .*100\.0%").unwrap())
+        .stderr(predicate::str::is_match(r"format:jsonl\s+10 ").unwrap())
+        .stderr(predicate::str::contains("Slowest records:"))
+        .stderr(predicate::str::is_match(r"ms  <stdin>:\d+\n.*ms  <stdin>:\d+\n$").unwrap());
+}
+
+#[test]
+fn test_no_profile_output_by_default() {
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-e", "line"])
+        .write_stdin("a\n")
+        .assert()
+        .success()
+        .stderr("");
+}
@@ -0,0 +1,52 @@
+// tests/pseudonymize_tests.rs
+use assert_cmd::Command;
+use std::fs;
+use tempfile::TempDir;
+
+fn run(files: &[&std::path::Path]) -> String {
+    let output = Command::cargo_bin("stelp")
+        .unwrap()
+        .args([
+            "-f",
+            "jsonl",
+            "-F",
+            "jsonl",
+            "-e",
+            r#"data["user"] = pseudonymize(data["user"], "vendor-2024")
+data["ip"] = pseudonymize(data["ip"], "vendor-2024")"#,
+        ])
+        .args(files)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_pseudonyms_match_across_files_and_runs() {
+    let dir = TempDir::new().unwrap();
+    let a = dir.path().join("a.jsonl");
+    let b = dir.path().join("b.jsonl");
+    fs::write(
+        &a,
+        "{\"user\":\"alice@corp.example.org\",\"ip\":\"10.0.0.7\"}\n",
+    )
+    .unwrap();
+    fs::write(
+        &b,
+        "{\"user\":\"alice@corp.example.org\",\"ip\":\"10.0.0.7\"}\n",
+    )
+    .unwrap();
+
+    let both = run(&[&a, &b]);
+    let lines: Vec<&str> = both.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0], lines[1]);
+    assert_eq!(run(&[&b]).trim_end(), lines[0]);
+
+    let record: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+    let user = record["user"].as_str().unwrap();
+    let ip: std::net::Ipv4Addr = record["ip"].as_str().unwrap().parse().unwrap();
+    assert!(!user.contains("alice") && user.ends_with(".org"));
+    assert_eq!(ip.octets()[0], 10);
+}
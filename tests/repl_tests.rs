@@ -0,0 +1,66 @@
+// tests/repl_tests.rs
+use assert_cmd::Command;
+use predicates::prelude::*;
+use std::fs;
+use tempfile::TempDir;
+
+fn setup() -> TempDir {
+    let dir = TempDir::new().unwrap();
+    fs::write(
+        dir.path().join("app.jsonl"),
+        "{\"status\":200,\"ms\":12}\n{\"status\":503,\"ms\":900}\n{\"status\":500,\"ms\":40}\n",
+    )
+    .unwrap();
+    dir
+}
+
+#[test]
+fn test_repl_navigation_and_expressions() {
+    let dir = setup();
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .current_dir(dir.path())
+        .args(["repl", "app.jsonl"])
+        .write_stdin(":next\ndata[\"status\"] * 2\n:goto 3\nLINENUM\nglob[\"n\"] = 5\nglob[\"n\"] + 1\n:keys\n:q\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("loaded 3 records"))
+        .stdout(predicate::str::contains("record 2 (line 2): {\"status\":503,\"ms\":900}\n1006\n"))
+        .stdout(predicate::str::contains("record 3 (line 3)"))
+        .stdout(predicate::str::contains("\n3\n"))
+        .stdout(predicate::str::contains("\n6\n"))
+        .stdout(predicate::str::contains("ms status\n"));
+}
+
+#[test]
+fn test_repl_steps_and_command_line() {
+    let dir = setup();
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .current_dir(dir.path())
+        .args(["repl", "-f", "jsonl", "app.jsonl"])
+        .write_stdin("data[\"status\"] >= 500\n:add filter\n:add derive slow = ms > 100\n:next\n:steps\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("record 1 (line 1): record dropped by step 1"))
+        .stdout(predicate::str::contains("{\"status\":503,\"ms\":900,\"slow\":true}"))
+        .stdout(predicate::str::contains("2. derive slow = ms > 100"))
+        .stdout(predicate::str::ends_with(
+            "stelp -f jsonl --filter 'data[\"status\"] >= 500' --derive 'slow = ms > 100' app.jsonl\n",
+        ));
+}
+
+#[test]
+fn test_repl_errors_keep_session() {
+    let dir = setup();
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .current_dir(dir.path())
+        .args(["repl", "app.jsonl"])
+        .write_stdin("data[\"missing\"]\n:frobnicate\ndata[\"ms\"]\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("error:"))
+        .stdout(predicate::str::contains("unknown command ':frobnicate'"))
+        .stdout(predicate::str::contains("\n12\n"));
+}
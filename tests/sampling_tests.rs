@@ -0,0 +1,158 @@
+// tests/sampling_tests.rs
+mod common;
+
+use assert_cmd::Command;
+use common::stelp;
+use predicates::prelude::*;
+use std::fs;
+use tempfile::TempDir;
+
+fn numbers(count: usize) -> String {
+    (1..=count).map(|i| format!("{}\n", i)).collect()
+}
+
+fn events() -> String {
+    (1..=60)
+        .map(|i| format!("{{\"user\":\"u{}\",\"n\":{}}}\n", i % 6, i))
+        .collect()
+}
+
+#[test]
+fn test_sample_is_repeatable_with_seed() {
+    let input = numbers(2000);
+    let first = stelp(&["--sample", "5%", "--seed", "7"], &input);
+    assert_eq!(first, stelp(&["--sample", "0.05", "--seed", "7"], &input));
+    let kept = first.lines().count();
+    assert!((50..160).contains(&kept), "kept {}", kept);
+    assert_ne!(first, stelp(&["--sample", "0.05", "--seed", "8"], &input));
+
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["--sample", "2"])
+        .write_stdin("x\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "must be greater than 0 and at most 1",
+        ));
+}
+
+#[test]
+fn test_sample_by_keeps_whole_keys() {
+    let output = stelp(
+        &["-f", "jsonl", "-F", "jsonl", "--sample-by", "user:0.5"],
+        &events(),
+    );
+    let mut kept: Vec<(String, usize)> = Vec::new();
+    for line in output.lines() {
+        let record: serde_json::Value = serde_json::from_str(line).unwrap();
+        let user = record["user"].as_str().unwrap().to_string();
+        match kept.iter_mut().find(|(u, _)| *u == user) {
+            Some((_, count)) => *count += 1,
+            None => kept.push((user, 1)),
+        }
+    }
+    assert!(!kept.is_empty() && kept.len() < 6, "{:?}", kept);
+    // Every user that is kept is kept completely
+    assert!(kept.iter().all(|(_, count)| *count == 10), "{:?}", kept);
+    // and the choice does not change between runs
+    assert_eq!(
+        output,
+        stelp(
+            &["-f", "jsonl", "-F", "jsonl", "--sample-by", "user:0.5"],
+            &events()
+        )
+    );
+}
+
+#[test]
+fn test_reservoir_emits_before_end_and_composes_with_steps() {
+    let output = stelp(
+        &[
+            "--filter",
+            "int(line) % 2 == 0",
+            "--reservoir",
+            "5",
+            "-e",
+            r#"line + "!""#,
+            "--end",
+            r#""done""#,
+        ],
+        &numbers(100),
+    );
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 6);
+    assert_eq!(lines[5], "done");
+    let sample: Vec<usize> = lines[..5]
+        .iter()
+        .map(|l| l.strip_suffix('!').unwrap().parse().unwrap())
+        .collect();
+    assert!(sample.iter().all(|n| n % 2 == 0), "{:?}", sample);
+    assert!(sample.windows(2).all(|w| w[0] < w[1]), "{:?}", sample);
+
+    // Fewer records than the reservoir size: all of them
+    assert_eq!(
+        stelp(
+            &["-f", "jsonl", "--reservoir", "100", "-k", "n"],
+            "{\"n\":1}\n{\"n\":2}\n{\"n\":3}\n"
+        ),
+        "n=1\nn=2\nn=3\n"
+    );
+}
+
+#[test]
+fn test_reservoir_samples_across_input_files() {
+    let dir = TempDir::new().unwrap();
+    let first = dir.path().join("first.log");
+    let second = dir.path().join("second.log");
+    fs::write(&first, numbers(10)).unwrap();
+    fs::write(&second, numbers(10)).unwrap();
+
+    let output = stelp(
+        &[
+            "--reservoir",
+            "3",
+            first.to_str().unwrap(),
+            second.to_str().unwrap(),
+        ],
+        "",
+    );
+    assert_eq!(output.lines().count(), 3, "{}", output);
+}
+
+#[test]
+fn test_rate_limit_per_key() {
+    let output = stelp(
+        &["-f", "jsonl", "--rate-limit", "2/h:user", "-k", "n"],
+        &events(),
+    );
+    assert_eq!(output.lines().count(), 12);
+    assert!(output.starts_with("n=1\nn=2\n"));
+
+    let output = stelp(&["--rate-limit", "3/h"], &numbers(10));
+    assert_eq!(output, "1\n2\n3\n");
+}
+
+#[test]
+fn test_sampling_steps_in_pipeline_file() {
+    let dir = TempDir::new().unwrap();
+    let pipeline = dir.path().join("sample.yaml");
+    fs::write(
+        &pipeline,
+        "input-format: jsonl\nseed: 3\nkeys: [n]\nsteps:\n  - filter: 'data[\"n\"] <= 30'\n  - reservoir: 4\n",
+    )
+    .unwrap();
+    let output = Command::cargo_bin("stelp")
+        .unwrap()
+        .arg("--pipeline")
+        .arg(&pipeline)
+        .write_stdin(events())
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 4);
+    assert!(stdout
+        .lines()
+        .all(|l| l.trim_start_matches("n=").parse::<usize>().unwrap() <= 30));
+}
@@ -0,0 +1,131 @@
+// tests/schema_tests.rs
+mod common;
+
+use common::stelp;
+use serde_json::{json, Value};
+
+const EVENTS: &str = r#"{"ts":"2024-01-01T00:00:00Z","level":"info","status":200,"user":{"id":"u1"}}
+{"ts":"2024-01-01T00:00:03Z","level":"error","status":500,"user":{"id":"u2"},"took":"2s"}
+{"ts":"2024-01-01T00:00:01Z","level":"info","status":200,"user":{"id":"u1","roles":["admin"]}}
+"#;
+
+fn json_lines(output: &str) -> Vec<Value> {
+    output
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_schema_report() {
+    let fields = json_lines(&stelp(&["-f", "jsonl", "--schema", "-F", "jsonl"], EVENTS));
+    let paths: Vec<&str> = fields
+        .iter()
+        .map(|f| f["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        paths,
+        vec![
+            "ts",
+            "level",
+            "status",
+            "user",
+            "user.id",
+            "user.roles",
+            "user.roles[]",
+            "took"
+        ]
+    );
+    assert_eq!(fields[0]["min"], "2024-01-01T00:00:00Z");
+    assert_eq!(fields[0]["max"], "2024-01-01T00:00:03Z");
+    assert_eq!(fields[1]["cardinality"], 2);
+    assert_eq!(fields[1]["top"], json!({"info": 2, "error": 1}));
+    assert_eq!(fields[2]["type"], "int");
+    assert_eq!(fields[2]["min"], 200);
+    assert_eq!(fields[2]["max"], 500);
+    assert_eq!(fields[5]["present"], 33.3);
+    assert_eq!(fields[7]["type"], "duration");
+    assert_eq!(fields[7]["examples"], json!(["2s"]));
+
+    // The profile covers the records the steps kept
+    let errors = json_lines(&stelp(
+        &[
+            "-f",
+            "jsonl",
+            "--filter",
+            "data[\"level\"] == \"error\"",
+            "--schema",
+            "-F",
+            "jsonl",
+        ],
+        EVENTS,
+    ));
+    assert_eq!(errors[1]["top"], json!({"error": 1}));
+}
+
+#[test]
+fn test_schema_top_does_not_inflate_distinct_values() {
+    let input: String = (1..=40)
+        .map(|i| format!("{{\"id\":\"r{}\",\"level\":\"info\"}}\n", i))
+        .collect();
+    let fields = json_lines(&stelp(&["-f", "jsonl", "--schema", "-F", "jsonl"], &input));
+    // No id occurs more than once, so none is known to be more frequent
+    assert_eq!(fields[0]["top"], json!({}));
+    assert_eq!(fields[1]["top"], json!({"info": 40}));
+}
+
+#[test]
+fn test_schema_json_schema() {
+    let schema = json_lines(&stelp(&["-f", "jsonl", "--schema=json-schema"], EVENTS));
+    assert_eq!(schema.len(), 1);
+    assert_eq!(
+        schema[0]["$schema"],
+        "https://json-schema.org/draft/2020-12/schema"
+    );
+    assert_eq!(
+        schema[0]["required"],
+        json!(["ts", "level", "status", "user"])
+    );
+    assert_eq!(
+        schema[0]["properties"]["status"],
+        json!({"type": "integer"})
+    );
+    assert_eq!(
+        schema[0]["properties"]["user"],
+        json!({
+            "type": "object",
+            "properties": {
+                "id": {"type": "string"},
+                "roles": {"type": "array", "items": {"type": "string"}}
+            },
+            "required": ["id"]
+        })
+    );
+}
+
+#[test]
+fn test_schema_types_declaration() {
+    let input = "time=2024-01-01T00:00:00Z status=200 bytes=12 path=/ ok=true\n\
+                 time=2024-01-01T00:00:01Z status=404 bytes=1.5 path=/x ok=false\n";
+    let types = stelp(&["-f", "logfmt", "--schema=types"], input);
+    assert_eq!(types, "time:timestamp,status:int,bytes:float,ok:bool\n");
+
+    // The declaration can be used as is
+    let typed = stelp(
+        &[
+            "-f",
+            "logfmt",
+            "--types",
+            types.trim(),
+            "-F",
+            "jsonl",
+            "-k",
+            "status,bytes,ok",
+        ],
+        input,
+    );
+    assert_eq!(
+        typed.lines().next().unwrap(),
+        r#"{"status":200,"bytes":12.0,"ok":true}"#
+    );
+}
@@ -0,0 +1,74 @@
+// tests/state_file_tests.rs
+use assert_cmd::Command;
+use predicates::prelude::*;
+use serde_json::Value;
+use std::fs;
+use tempfile::TempDir;
+
+fn run_slice(state: &std::path::Path, input: &str) -> String {
+    let output = Command::cargo_bin("stelp")
+        .unwrap()
+        .arg("--state-file")
+        .arg(state)
+        .args([
+            "--state-keys",
+            "total,last_",
+            "--begin",
+            r#"f"previous total: {glob.get('total', 0)}""#,
+            "-e",
+            r#"inc("total"); glob["last_seen"] = line; glob["scratch"] = [line]; line"#,
+            "--end",
+            r#"f"total: {glob['total']}""#,
+        ])
+        .write_stdin(input)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_state_carries_over_between_runs() {
+    let dir = TempDir::new().unwrap();
+    let state = dir.path().join("state.json");
+
+    assert_eq!(
+        run_slice(&state, "a\nb\n"),
+        "previous total: 0\na\nb\ntotal: 2\n"
+    );
+    assert_eq!(run_slice(&state, "c\n"), "previous total: 2\nc\ntotal: 3\n");
+
+    let saved: Value = serde_json::from_str(&fs::read_to_string(&state).unwrap()).unwrap();
+    assert_eq!(saved["version"], 1);
+    assert_eq!(saved["glob"]["total"], 3);
+    assert_eq!(saved["glob"]["last_seen"], "c");
+    // Not matched by --state-keys
+    assert!(saved["glob"].get("scratch").is_none());
+    // No temporary files left behind
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn test_state_file_errors() {
+    let dir = TempDir::new().unwrap();
+    let state = dir.path().join("state.json");
+    fs::write(&state, r#"{"version": 2, "glob": {}}"#).unwrap();
+
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .arg("--state-file")
+        .arg(&state)
+        .args(["-e", "line"])
+        .write_stdin("a\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("newer than this stelp supports"));
+
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["--state-keys", "x", "-e", "line"])
+        .write_stdin("a\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--state-file"));
+}
@@ -0,0 +1,85 @@
+// tests/stats_report_tests.rs
+use assert_cmd::Command;
+use predicates::prelude::*;
+use serde_json::Value;
+use std::fs;
+use tempfile::TempDir;
+
+fn write_inputs(dir: &TempDir) -> (String, String) {
+    let first = dir.path().join("first.jsonl");
+    let second = dir.path().join("second.jsonl");
+    fs::write(
+        &first,
+        "{\"level\":\"info\",\"msg\":\"a\"}\nnot json\n{\"level\":\"error\",\"msg\":\"b\"}\n",
+    )
+    .unwrap();
+    fs::write(&second, "{\"level\":\"warn\",\"msg\":\"c\"}\n").unwrap();
+    (
+        first.to_string_lossy().to_string(),
+        second.to_string_lossy().to_string(),
+    )
+}
+
+#[test]
+fn test_stats_json_has_per_file_and_per_processor_counters() {
+    let dir = TempDir::new().unwrap();
+    let (first, second) = write_inputs(&dir);
+    let stats_file = dir.path().join("stats.json");
+
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-f", "jsonl", "--filter", "data[\"level\"] != \"warn\""])
+        .arg("--stats-file")
+        .arg(&stats_file)
+        .args([&first, &second])
+        .assert()
+        .code(1); // the parse error makes the run fail
+
+    let report: Value = serde_json::from_str(&fs::read_to_string(&stats_file).unwrap()).unwrap();
+
+    assert_eq!(report["lines_seen"], 4);
+    assert_eq!(report["records_processed"], 3);
+    assert_eq!(report["records_output"], 2);
+    assert_eq!(report["records_skipped"], 1);
+    assert_eq!(report["parse_errors"], 1);
+    assert_eq!(report["keys_seen"], serde_json::json!(["level", "msg"]));
+
+    let files = report["files"].as_array().unwrap();
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0]["file"], first.as_str());
+    assert_eq!(files[0]["records_processed"], 2);
+    assert_eq!(files[1]["records_processed"], 1);
+    assert_eq!(files[1]["records_output"], 0);
+
+    let processors = report["processors"].as_array().unwrap();
+    assert_eq!(processors[0]["name"], "filter_1");
+    assert_eq!(processors[0]["records_in"], 3);
+    assert_eq!(processors[0]["records_out"], 2);
+    assert_eq!(processors[0]["records_skipped"], 1);
+
+    let samples = &report["parse_error_samples"]["jsonl"];
+    assert_eq!(samples["count"], 1);
+    assert_eq!(samples["samples"][0]["line_number"], 2);
+}
+
+#[test]
+fn test_stats_formats_on_stderr() {
+    let dir = TempDir::new().unwrap();
+    let (first, _) = write_inputs(&dir);
+
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-f", "jsonl", "--stats", &first])
+        .assert()
+        .stderr(predicate::str::contains("Records shown: 2"))
+        .stderr(predicate::str::contains("Performance: 2 records processed"));
+
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-f", "jsonl", "--stats=logfmt", &first])
+        .assert()
+        .stderr(predicate::str::contains("scope=total lines_seen=3"))
+        .stderr(predicate::str::contains(
+            "scope=parse_errors format=jsonl count=1",
+        ));
+}
@@ -0,0 +1,72 @@
+// tests/tee_tests.rs
+use assert_cmd::Command;
+use std::fs;
+use tempfile::TempDir;
+
+const INPUT: &str = r#"{"ts":"10:00","level":"info","msg":"start"}
+{"ts":"10:01","level":"error","msg":"boom"}
+{"ts":"10:02","level":"info","msg":"done"}
+"#;
+
+#[test]
+fn test_tee_to_multiple_formats_and_filters() {
+    let dir = TempDir::new().unwrap();
+    let errors = dir.path().join("errors.jsonl");
+    let summary = dir.path().join("summary.csv");
+
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-f", "jsonl", "-F", "logfmt"])
+        .arg("--tee")
+        .arg(format!(
+            "{}:jsonl:data[\"level\"] == \"error\"",
+            errors.display()
+        ))
+        .arg("--tee")
+        .arg(format!("{}:csv[ts,msg]", summary.display()))
+        .write_stdin(INPUT)
+        .assert()
+        .success()
+        .stdout(
+            "ts=10:00 level=info msg=start\nts=10:01 level=error msg=boom\nts=10:02 level=info msg=done\n",
+        );
+
+    assert_eq!(
+        fs::read_to_string(&errors).unwrap(),
+        "{\"ts\":\"10:01\",\"level\":\"error\",\"msg\":\"boom\"}\n"
+    );
+    assert_eq!(
+        fs::read_to_string(&summary).unwrap(),
+        "ts,msg\n10:00,start\n10:01,boom\n10:02,done\n"
+    );
+}
+
+#[test]
+fn test_tee_receives_only_records_that_reach_output() {
+    let dir = TempDir::new().unwrap();
+    let all = dir.path().join("all.jsonl");
+
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-f", "jsonl", "--filter", "data[\"level\"] == \"info\""])
+        .arg("--tee")
+        .arg(format!("{}:jsonl", all.display()))
+        .write_stdin(INPUT)
+        .assert()
+        .success();
+
+    let written = fs::read_to_string(&all).unwrap();
+    assert_eq!(written.lines().count(), 2);
+    assert!(!written.contains("boom"));
+}
+
+#[test]
+fn test_tee_rejects_bad_spec() {
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-f", "jsonl", "--tee", "out.xml:xml"])
+        .write_stdin(INPUT)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Invalid --tee"));
+}
@@ -0,0 +1,196 @@
+// tests/test_runner_tests.rs
+use assert_cmd::Command;
+use predicates::prelude::*;
+use std::fs;
+use tempfile::TempDir;
+
+fn write_case(root: &std::path::Path, expected: Option<&str>) {
+    let case = root.join("errors");
+    fs::create_dir_all(&case).unwrap();
+    fs::write(
+        case.join("input.jsonl"),
+        "{\"status\":200,\"path\":\"/a\"}\n{\"status\":503,\"path\":\"/b\"}\n",
+    )
+    .unwrap();
+    fs::write(
+        case.join("pipeline.yaml"),
+        "steps:\n  - filter: 'data[\"status\"] >= 500'\nkeys: [path, status]\n",
+    )
+    .unwrap();
+    if let Some(expected) = expected {
+        fs::write(case.join("expected.out"), expected).unwrap();
+    }
+}
+
+#[test]
+fn test_golden_case_passes_and_reports_diff() {
+    let dir = TempDir::new().unwrap();
+    write_case(dir.path(), Some("path=/b status=503\n"));
+
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .arg("test")
+        .arg(dir.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("PASS"))
+        .stdout(predicate::str::contains("1 passed, 0 failed"));
+
+    fs::write(
+        dir.path().join("errors/expected.out"),
+        "path=/c status=504\n",
+    )
+    .unwrap();
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .arg("test")
+        .arg(dir.path())
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("output differs"))
+        .stdout(predicate::str::contains(
+            "-path=/c status=504\n+path=/b status=503\n",
+        ));
+}
+
+#[test]
+fn test_update_writes_expected_output() {
+    let dir = TempDir::new().unwrap();
+    write_case(dir.path(), None);
+
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .arg("test")
+        .arg(dir.path())
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("run with --update"));
+
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["test", "--update"])
+        .arg(dir.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1 updated"));
+    assert_eq!(
+        fs::read_to_string(dir.path().join("errors/expected.out")).unwrap(),
+        "path=/b status=503\n"
+    );
+}
+
+#[test]
+fn test_command_line_case() {
+    let dir = TempDir::new().unwrap();
+    let case = dir.path().join("slow");
+    fs::create_dir_all(&case).unwrap();
+    fs::write(
+        case.join("input.jsonl"),
+        "{\"ms\":20,\"path\":\"/a\"}\n{\"ms\":900,\"path\":\"/b\"}\n",
+    )
+    .unwrap();
+    fs::write(
+        case.join("cmd"),
+        "stelp --filter 'data[\"ms\"] > 100' -F jsonl -k path\n",
+    )
+    .unwrap();
+    fs::write(case.join("expected.jsonl"), "{\"path\":\"/b\"}\n").unwrap();
+
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .arg("test")
+        .arg(dir.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1 passed, 0 failed"));
+
+    fs::write(
+        case.join("cmd"),
+        "--filter 'data[\"ms\"] > 100' input.jsonl\n",
+    )
+    .unwrap();
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .arg("test")
+        .arg(dir.path())
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains(
+            "unsupported argument 'input.jsonl'",
+        ));
+}
+
+#[test]
+fn test_starlark_test_functions() {
+    let dir = TempDir::new().unwrap();
+    fs::write(
+        dir.path().join("helpers.star"),
+        "def double(x):\n    return x * 2\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("helpers_test.star"),
+        r#"load("helpers.star", "double")
+
+def test_double():
+    assert_eq(double(2), 4)
+    assert_ne(double(2), 5)
+    assert_true(double(1) == 2)
+
+def test_broken():
+    assert_eq(double(3), 7, msg="three")
+
+def not_a_test():
+    fail("never called")
+"#,
+    )
+    .unwrap();
+
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .arg("test")
+        .arg(dir.path().join("helpers_test.star"))
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("helpers_test.star::test_double"))
+        .stdout(predicate::str::contains("three: assert_eq failed: 6 != 7"))
+        .stdout(predicate::str::contains("1 passed, 1 failed"))
+        .stdout(predicate::str::contains("never called").not());
+}
+
+#[test]
+fn test_no_tests_found() {
+    let dir = TempDir::new().unwrap();
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .arg("test")
+        .arg(dir.path())
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("no tests found"));
+}
+
+#[test]
+fn test_subcommands_in_help_and_input_file_named_test() {
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .arg("--help")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "test  Run pipeline regression tests",
+        ))
+        .stdout(predicate::str::contains(
+            "repl  Explore records interactively",
+        ));
+
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("test"), "hello\n").unwrap();
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .current_dir(dir.path())
+        .args(["-e", "line.upper()", "test"])
+        .assert()
+        .success()
+        .stdout("HELLO\n");
+}
@@ -0,0 +1,92 @@
+// tests/types_tests.rs
+use assert_cmd::Command;
+use predicates::prelude::*;
+use serde_json::Value;
+
+fn jsonl(stdout: &[u8]) -> Vec<Value> {
+    String::from_utf8(stdout.to_vec())
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_types_flow_into_starlark_and_json() {
+    let output = Command::cargo_bin("stelp")
+        .unwrap()
+        .args([
+            "-f",
+            "csv",
+            "-F",
+            "jsonl",
+            "--types",
+            "status:int,latency:duration,ok:bool,ts:timestamp",
+            "--filter",
+            r#"data["status"] >= 500 and data["latency"] > 1"#,
+        ])
+        .write_stdin(concat!(
+            "status,latency,ok,ts\n",
+            "200,2s,yes,2024-01-15T10:30:00Z\n",
+            "503,1m30s,no,2024-01-15T10:31:00Z\n",
+            "500,500ms,no,2024-01-15T10:32:00Z\n",
+        ))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let records = jsonl(&output.stdout);
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0]["status"], 503);
+    assert_eq!(records[0]["latency"], 90.0);
+    assert_eq!(records[0]["ok"], false);
+    assert_eq!(records[0]["ts"], 1705314660);
+}
+
+#[test]
+fn test_conversion_failures_use_error_strategy() {
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-f", "logfmt", "--types", "status:int", "-k", "status"])
+        .write_stdin("status=200\nstatus=oops\nstatus=404\n")
+        .assert()
+        // Bad records are skipped, like any other processing error
+        .stdout("status=200\nstatus=404\n")
+        .stderr(predicate::str::contains(
+            "field 'status': cannot convert 'oops' to int",
+        ));
+
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-f", "logfmt", "--types", "status:int", "--fail-fast"])
+        .write_stdin("status=200\nstatus=oops\nstatus=404\n")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("404").not())
+        .stderr(predicate::str::contains("cannot convert 'oops' to int"));
+
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-f", "logfmt", "--types", "status:decimal"])
+        .write_stdin("status=200\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("unknown type 'decimal'"));
+}
+
+#[test]
+fn test_infer_types() {
+    let output = Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-f", "csv", "-F", "jsonl", "--infer-types"])
+        .write_stdin("id,zip,load,name\n42,02134,0.75,web\n")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let records = jsonl(&output.stdout);
+    assert_eq!(records[0]["id"], 42);
+    assert_eq!(records[0]["zip"], "02134");
+    assert_eq!(records[0]["load"], 0.75);
+    assert_eq!(records[0]["name"], "web");
+}
@@ -0,0 +1,181 @@
+// tests/validate_tests.rs
+use assert_cmd::Command;
+use serde_json::{json, Value};
+use std::fs;
+use tempfile::TempDir;
+
+const SCHEMA: &str = r#"{
+  "$schema": "https://json-schema.org/draft/2020-12/schema",
+  "type": "object",
+  "required": ["level", "status"],
+  "properties": {
+    "level": {"enum": ["info", "warn", "error"]},
+    "status": {"type": "integer", "minimum": 100, "maximum": 599},
+    "user": {
+      "type": "object",
+      "required": ["id"],
+      "properties": {"id": {"type": "string", "pattern": "^u[0-9]+$"}}
+    }
+  }
+}"#;
+
+const EVENTS: &str = r#"{"level":"info","status":200,"user":{"id":"u1"}}
+{"level":"debug","status":"200"}
+{"status":900,"user":{"id":"x"}}
+{"level":"warn","status":404}
+"#;
+
+fn setup() -> (TempDir, std::path::PathBuf) {
+    let dir = TempDir::new().unwrap();
+    let schema = dir.path().join("schema.json");
+    fs::write(&schema, SCHEMA).unwrap();
+    (dir, schema)
+}
+
+#[test]
+fn test_validate_rejects_to_dead_letter() {
+    let (dir, schema) = setup();
+    let dead_letter = dir.path().join("rejected.jsonl");
+
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-f", "jsonl", "-F", "jsonl", "-k", "status"])
+        .arg("--validate")
+        .arg(&schema)
+        .arg("--dead-letter")
+        .arg(&dead_letter)
+        .write_stdin(EVENTS)
+        .assert()
+        .code(1)
+        .stdout("{\"status\":200}\n{\"status\":404}\n");
+
+    let entries: Vec<Value> = fs::read_to_string(&dead_letter)
+        .unwrap()
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0]["step"], "validate");
+    assert_eq!(entries[0]["line_number"], 2);
+    assert_eq!(
+        entries[0]["error"],
+        "schema violation: level: \"debug\" is not one of the allowed values; \
+         status: expected integer, got string"
+    );
+    assert_eq!(
+        entries[1]["line"],
+        "{\"status\":900,\"user\":{\"id\":\"x\"}}"
+    );
+
+    // --fail-fast stops at the first invalid record
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-f", "jsonl", "--fail-fast", "--validate"])
+        .arg(&schema)
+        .write_stdin(EVENTS)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "level: \"debug\" is not one of the allowed values",
+        ));
+}
+
+#[test]
+fn test_validate_annotate_and_stats() {
+    let (_dir, schema) = setup();
+    let output = Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-f", "jsonl", "-F", "jsonl", "--validate-mode", "annotate"])
+        .arg("--validate")
+        .arg(&schema)
+        .arg("--stats=json")
+        .write_stdin(EVENTS)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+
+    let records: Vec<Value> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(records.len(), 4);
+    assert!(records[0].get("_errors").is_none());
+    assert_eq!(
+        records[2]["_errors"],
+        json!([
+            "level: required field is missing",
+            "status: 900 is above the maximum 599",
+            "user.id: \"x\" does not match pattern '^u[0-9]+$'"
+        ])
+    );
+
+    let stats: Value = serde_json::from_slice(&output.stderr).unwrap();
+    let validate = stats["processors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|p| p["name"] == "validate")
+        .unwrap();
+    assert_eq!(
+        validate["counters"],
+        json!({
+            "invalid": 2,
+            "level/enum": 1,
+            "level/required": 1,
+            "status/maximum": 1,
+            "status/type": 1,
+            "user.id/pattern": 1
+        })
+    );
+}
+
+#[test]
+fn test_validate_against_generated_schema() {
+    let dir = TempDir::new().unwrap();
+    let v1 = "{\"level\":\"info\",\"status\":200}\n{\"level\":\"warn\",\"status\":404}\n";
+    let schema = Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-f", "jsonl", "--schema=json-schema"])
+        .write_stdin(v1)
+        .output()
+        .unwrap();
+    assert!(schema.status.success());
+    let path = dir.path().join("v1.schema.json");
+    fs::write(&path, schema.stdout).unwrap();
+
+    // A later version that renamed a field and changed a type
+    let v2 = "{\"level\":\"info\",\"code\":\"200\"}\n";
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args([
+            "-f",
+            "jsonl",
+            "--validate-mode",
+            "count",
+            "--stats",
+            "--validate",
+        ])
+        .arg(&path)
+        .write_stdin(v2)
+        .assert()
+        .success()
+        .stderr(predicates::str::contains(
+            "validate: invalid=1, status/required=1",
+        ));
+}
+
+#[test]
+fn test_validate_bad_schema() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("bad.json");
+    fs::write(&path, r#"{"type": "text"}"#).unwrap();
+    Command::cargo_bin("stelp")
+        .unwrap()
+        .args(["-f", "jsonl", "--validate"])
+        .arg(&path)
+        .write_stdin("{}\n")
+        .assert()
+        .code(1)
+        .stderr(predicates::str::contains("unknown type 'text'"));
+}
@@ -1,5 +1,7 @@
 // tests/window_tests.rs
 use std::io::Cursor;
+use stelp::input_format::{InputFormat, InputFormatWrapper};
+use stelp::processors::WindowConfig;
 use stelp::{config::PipelineConfig, StarlarkProcessor, StreamPipeline, WindowProcessor};
 
 #[test]
@@ -199,3 +201,64 @@ f"Size: {size}, Values: {count}""#,
     let output_str = String::from_utf8(output).unwrap();
     assert!(output_str.contains("Size: 0, Values: 0"));
 }
+
+fn run_jsonl_window(window: WindowConfig, script: &str, input: &str) -> String {
+    let mut pipeline = StreamPipeline::new(PipelineConfig::default());
+    let inner = StarlarkProcessor::from_script("window_keyed", script).unwrap();
+    pipeline.add_processor(Box::new(WindowProcessor::with_config(
+        window,
+        Box::new(inner),
+    )));
+
+    let mut output = Vec::new();
+    InputFormatWrapper::new(Some(&InputFormat::Jsonl))
+        .process_with_pipeline(
+            Cursor::new(input.to_string()),
+            &mut pipeline,
+            &mut output,
+            None,
+        )
+        .unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+const LOGINS: &str = r#"{"ts": "2024-01-15T10:00:00Z", "user": "alice"}
+{"ts": "2024-01-15T10:01:00Z", "user": "bob"}
+{"ts": "2024-01-15T10:04:00Z", "user": "alice"}
+{"ts": "2024-01-15T10:09:00Z", "user": "alice"}
+{"ts": "2024-01-15T10:16:00Z", "user": "alice"}
+"#;
+
+#[test]
+fn test_time_window_per_key() {
+    let window = WindowConfig::from_options(None, Some("10m"), Some("user"), None)
+        .unwrap()
+        .unwrap();
+    let output = run_jsonl_window(
+        window,
+        r#"data = {"user": data["user"], "n": window_size(), "oldest": window_values("ts")[0]}"#,
+        LOGINS,
+    );
+    assert_eq!(
+        output,
+        "user=alice n=1 oldest=2024-01-15T10:00:00Z\n\
+         user=bob n=1 oldest=2024-01-15T10:01:00Z\n\
+         user=alice n=2 oldest=2024-01-15T10:00:00Z\n\
+         user=alice n=3 oldest=2024-01-15T10:00:00Z\n\
+         user=alice n=2 oldest=2024-01-15T10:09:00Z\n"
+    );
+}
+
+#[test]
+fn test_window_keys_are_bounded() {
+    // With room for one key, bob's record evicts alice's window
+    let window = WindowConfig::from_options(Some(5), None, Some("user"), Some(1))
+        .unwrap()
+        .unwrap();
+    let output = run_jsonl_window(window, r#"data = {"n": window_size()}"#, LOGINS);
+    assert_eq!(output, "n=1\nn=1\nn=1\nn=2\nn=3\n");
+
+    assert!(WindowConfig::from_options(None, None, Some("user"), None).is_err());
+    assert!(WindowConfig::from_options(None, Some("soon"), None, None).is_err());
+    assert_eq!(WindowConfig::from_options(None, None, None, None), Ok(None));
+}